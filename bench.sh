@@ -0,0 +1,39 @@
+#!/bin/bash
+
+# Compares the core library allocator against the one in glibc
+# The results are written to bench_output.txt
+
+cargo build --release
+
+idac="`pwd`/target/release/idac"
+
+if [[ -f bench_output.txt ]] ; then
+    rm bench_output.txt
+fi
+
+function run_bench() {
+    for entry in $1
+    do
+        name=`basename $entry .ida`
+        
+        # Build against the core library
+        $idac $entry -o ${name}_core
+        
+        # Build against glibc
+        $idac $entry --no-link --use-c
+        gcc -no-pie $name.o -o ${name}_libc
+        rm $name.o
+        
+        for lib in core libc
+        do
+            echo "$name ($lib):" >> bench_output.txt
+            { time ./${name}_$lib > /dev/null ; } 2>> bench_output.txt
+            echo "" >> bench_output.txt
+            rm ./${name}_$lib
+        done
+    done
+}
+
+run_bench 'bench/*.ida'
+
+cat bench_output.txt
@@ -8,6 +8,63 @@ cargo build --release
 cwd=`pwd`
 export idac="$cwd/target/release/idac"
 
+# Cross-build the libraries for AArch64 (./build-stdlib.sh aarch64)
+# These are static, and go in target/aarch64
+if [[ $1 == "aarch64" ]] ; then
+    flags="-march=aarch64 --no-link"
+    prefix=""
+    
+    if [[ `uname -m` != "aarch64" ]] ; then
+        prefix="aarch64-linux-gnu-"
+    fi
+    
+    mkdir -p target/aarch64
+    cd target/aarch64
+    
+    # Build the core library
+    $idac ../../corelib/aarch64.ida -o aarch64.o $flags --no-corelib
+    $idac ../../corelib/mem.ida -o mem.o $flags --no-corelib
+    $idac ../../corelib/string.ida -o string.o $flags --no-corelib
+    $idac ../../corelib/io.ida -o io.o $flags --no-corelib
+    ${prefix}as ../../corelib/mem_aarch64.asm -o mem_aarch64.o
+    ${prefix}as ../../corelib/panic_aarch64.asm -o panic_aarch64.o
+    
+    ${prefix}ar -rc libida_core.a \
+        aarch64.o \
+        mem.o \
+        mem_aarch64.o \
+        panic_aarch64.o \
+        string.o \
+        io.o
+        
+    rm *.o
+    
+    # Build the standard library
+    $idac ../../stdlib/string.ida -o string.o $flags
+    $idac ../../stdlib/io.ida -o io.o $flags
+    $idac ../../stdlib/os.ida -o os.o $flags
+    $idac ../../stdlib/file_io.ida -o file_io.o $flags
+    $idac ../../stdlib/text_utils.ida -o text_utils.o $flags
+    $idac ../../stdlib/text_io.ida -o text_io.o $flags
+    
+    ${prefix}ar -rc libida.a \
+        string.o \
+        io.o \
+        os.o \
+        file_io.o \
+        text_utils.o \
+        text_io.o
+        
+    rm *.o
+    
+    ${prefix}as ../../stdlib/aarch64_start.asm -o irt.o
+    
+    cd ../..
+    
+    echo "Done"
+    exit 0
+fi
+
 cd target
 
 if [[ -d ./std ]] ; then
@@ -24,10 +81,14 @@ $idac ../corelib/x86_64.ida -o x86_64.o --no-link --pic --no-corelib
 $idac ../corelib/mem.ida -o mem.o --no-link --pic --no-corelib
 $idac ../corelib/string.ida -o string.o --no-link --pic --no-corelib
 $idac ../corelib/io.ida -o io.o --no-link --pic --no-corelib
+as ../corelib/mem_x86_64.asm -o mem_x86_64.o
+as ../corelib/panic_x86_64.asm -o panic_x86_64.o
 
 ar -rc libida_core.a \
     x86_64.o \
     mem.o \
+    mem_x86_64.o \
+    panic_x86_64.o \
     string.o \
     io.o
     
@@ -17,16 +17,20 @@ pub enum Arm64Type {
     Stp,
     Adrp,
     Mov,
+    Movk,
     
-    Str,
-    Ldr,
-    LdrSW,
+    Str,        Strb,       Strh,
+    Ldr,        Ldrb,       Ldrh,
+    LdrSB,      LdrSH,      LdrSW,
     
-    Add,
-    Sub,
-    Mul,
-    SDiv,
-    MSub,
+    Add,        Adds,
+    Sub,        Subs,
+    Mul,        SMull,      UMull,
+    SMulh,      UMulh,
+    SDiv,       UDiv,
+    MSub,       MAdd,
+    Sxtb,       Sxth,       Sxtw,
+    Uxtb,       Uxth,
     
     And,
     Orr,
@@ -34,13 +38,29 @@ pub enum Arm64Type {
     Lsl,
     Lsr,
     
+    // Floating-point instructions
+    FMov,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FCmp,
+    FCvt,
+    SCvtf,
+    FCvtzs,     // Truncates
+    
     Cmp,
     B,
     Beq,    Bne,
-    Bl,     Ble,
-    Bg,     Bge,
+    Bvs,    Bcs,
+    Blt,    Ble,
+    Bgt,    Bge,
+    Blo,    Bls,    // Unsigned less than (or equal)
+    Bhi,    Bhs,    // Unsigned greater than (or equal)
+    Bmi,            // Float less than
     
     Call,
+    Blr,
     Ret,
     Svc
 }
@@ -51,6 +71,8 @@ pub enum Arm64Arg {
     Empty,
     
     Mem(Arm64Reg, i32),
+    MemLcl(Arm64Reg, String),           // [reg, :lo12:label]
+    MemIdx(Arm64Reg, Arm64Reg, i32),    // [base, index, sxtw shift]
     RegRef(Arm64Reg),
     
     Imm32(i32),
@@ -59,7 +81,13 @@ pub enum Arm64Arg {
     PtrLcl(String),
     PtrLclLow(String),
     
-    Reg(Arm64Reg)
+    Reg(Arm64Reg),
+    
+    // Shifted and extended register operands
+    Lsl(i32),
+    Asr(i32),
+    Sxtw,
+    Uxtw,
 }
 
 #[derive(Clone, PartialEq)]
@@ -82,7 +110,11 @@ pub enum Arm64Reg {
     W18,
     W19, W20, W21, W22, W23, W24, W25, W26, W27, W28,
     W29,
-    W30
+    W30,
+    
+    // The floating-point registers, as single and double
+    S(i32),
+    D(i32),
 }
 
 #[derive(Clone)]
@@ -105,3 +137,78 @@ pub fn create_arm64_instr(instr_type : Arm64Type) -> Arm64Instr{
         arg4 : Arm64Arg::Empty,
     }
 }
+
+// The registers used for operations
+// x16 and x17 are scratch registers for addresses and immediates. 7 and up are the
+// callee-saved x19-x28, which the register allocator uses for values kept across calls.
+pub fn arm64_op_reg32(pos : i32) -> Arm64Reg {
+    match pos {
+        0 => Arm64Reg::W9,
+        1 => Arm64Reg::W10,
+        2 => Arm64Reg::W11,
+        3 => Arm64Reg::W12,
+        4 => Arm64Reg::W13,
+        5 => Arm64Reg::W14,
+        6 => Arm64Reg::W15,
+        7 => Arm64Reg::W19,
+        8 => Arm64Reg::W20,
+        9 => Arm64Reg::W21,
+        10 => Arm64Reg::W22,
+        11 => Arm64Reg::W23,
+        12 => Arm64Reg::W24,
+        13 => Arm64Reg::W25,
+        14 => Arm64Reg::W26,
+        15 => Arm64Reg::W27,
+        _ => Arm64Reg::W28,
+    }
+}
+
+pub fn arm64_op_reg64(pos : i32) -> Arm64Reg {
+    match pos {
+        0 => Arm64Reg::X9,
+        1 => Arm64Reg::X10,
+        2 => Arm64Reg::X11,
+        3 => Arm64Reg::X12,
+        4 => Arm64Reg::X13,
+        5 => Arm64Reg::X14,
+        6 => Arm64Reg::X15,
+        7 => Arm64Reg::X19,
+        8 => Arm64Reg::X20,
+        9 => Arm64Reg::X21,
+        10 => Arm64Reg::X22,
+        11 => Arm64Reg::X23,
+        12 => Arm64Reg::X24,
+        13 => Arm64Reg::X25,
+        14 => Arm64Reg::X26,
+        15 => Arm64Reg::X27,
+        _ => Arm64Reg::X28,
+    }
+}
+
+// v0-v7 hold arguments and the return value, so the operations use v16 and up
+// v24 is the scratch register for float literals
+pub fn arm64_op_flt(pos : i32, is_double : bool) -> Arm64Reg {
+    if is_double {
+        Arm64Reg::D(16 + pos)
+    } else {
+        Arm64Reg::S(16 + pos)
+    }
+}
+
+// Returns true if a register is 64 bits wide
+pub fn arm64_is_reg64(reg : &Arm64Reg) -> bool {
+    match reg {
+        Arm64Reg::SP | Arm64Reg::XZR
+        | Arm64Reg::X0 | Arm64Reg::X1 | Arm64Reg::X2 | Arm64Reg::X3
+        | Arm64Reg::X4 | Arm64Reg::X5 | Arm64Reg::X6 | Arm64Reg::X7
+        | Arm64Reg::X8 | Arm64Reg::X9 | Arm64Reg::X10 | Arm64Reg::X11
+        | Arm64Reg::X12 | Arm64Reg::X13 | Arm64Reg::X14 | Arm64Reg::X15
+        | Arm64Reg::X16 | Arm64Reg::X17 | Arm64Reg::X18 | Arm64Reg::X19
+        | Arm64Reg::X20 | Arm64Reg::X21 | Arm64Reg::X22 | Arm64Reg::X23
+        | Arm64Reg::X24 | Arm64Reg::X25 | Arm64Reg::X26 | Arm64Reg::X27
+        | Arm64Reg::X28 | Arm64Reg::X29 | Arm64Reg::X30
+        | Arm64Reg::D(_) => true,
+        
+        _ => false,
+    }
+}
@@ -6,6 +6,7 @@
 
 use parser::ltac::{LtacInstr, LtacType, LtacArg};
 use crate::asm::*;
+use crate::instr::*;
 
 // Builds a function declaration
 pub fn arm64_build_func(code : &mut Vec<Arm64Instr>, instr : &LtacInstr) {
@@ -48,19 +49,195 @@ pub fn arm64_build_ret(code : &mut Vec<Arm64Instr>, stack_size : i32) {
     code.push(ret);
 }
 
+// Saves and restores the callee-saved registers a function uses, and drops the frame pointer if asked
+// This runs after the whole function is translated, since only then do we know which registers it uses.
+// The registers are saved in extra slots above the locals.
+// Params: start -> the position of the function's first instruction
+pub fn arm64_build_frame(code : &mut Vec<Arm64Instr>, start : usize, omit_fp : bool) {
+    let body = code.split_off(start);
+    let saved = arm64_callee_saved(&body);
+    
+    let stack_size = match body.iter().find(|ln| ln.instr_type == Arm64Type::Stp) {
+        Some(ln) => match ln.arg3 {
+            Arm64Arg::Mem(_, size) => -size,
+            _ => 0,
+        },
+        
+        None => 0,
+    };
+    
+    // Keep the stack 16-byte aligned
+    let mut frame_size = stack_size + (saved.len() as i32) * 8;
+    if frame_size % 16 != 0 {
+        frame_size += 8;
+    }
+    
+    for mut ln in body {
+        match ln.instr_type {
+            // Without a frame pointer, we only need to keep the link register
+            // sub sp, sp, frame_size
+            // str x30, [sp, 8]
+            Arm64Type::Stp if omit_fp => {
+                arm64_adjust_sp(code, Arm64Type::Sub, frame_size);
+                
+                let mut st = create_arm64_instr(Arm64Type::Str);
+                st.arg1 = Arm64Arg::Reg(Arm64Reg::X30);
+                st.arg2 = Arm64Arg::Mem(Arm64Reg::SP, 8);
+                code.push(st);
+                
+                arm64_save_regs(code, &saved, stack_size, Arm64Type::Str);
+            },
+            
+            // sub sp, sp, frame_size
+            // stp x29, x30, [sp]
+            Arm64Type::Stp if frame_size > 504 => {
+                arm64_adjust_sp(code, Arm64Type::Sub, frame_size);
+                
+                ln.arg3 = Arm64Arg::RegRef(Arm64Reg::SP);
+                code.push(ln);
+            },
+            
+            Arm64Type::Stp => {
+                ln.arg3 = Arm64Arg::Mem(Arm64Reg::SP, -frame_size);
+                code.push(ln);
+            },
+            
+            // mov x29, sp
+            Arm64Type::Mov if ln.arg1 == Arm64Arg::Reg(Arm64Reg::X29) => {
+                if !omit_fp {
+                    code.push(ln);
+                    arm64_save_regs(code, &saved, stack_size, Arm64Type::Str);
+                }
+            },
+            
+            Arm64Type::Ldp => {
+                arm64_save_regs(code, &saved, stack_size, Arm64Type::Ldr);
+                
+                if omit_fp {
+                    let mut ldr = create_arm64_instr(Arm64Type::Ldr);
+                    ldr.arg1 = Arm64Arg::Reg(Arm64Reg::X30);
+                    ldr.arg2 = Arm64Arg::Mem(Arm64Reg::SP, 8);
+                    code.push(ldr);
+                    
+                    arm64_adjust_sp(code, Arm64Type::Add, frame_size);
+                    
+                // The pre-index offset of ldp and stp only goes up to 504
+                // ldp x29, x30, [sp]
+                // add sp, sp, frame_size
+                } else if frame_size > 504 {
+                    ln.arg4 = Arm64Arg::Empty;
+                    code.push(ln);
+                    arm64_adjust_sp(code, Arm64Type::Add, frame_size);
+                } else {
+                    ln.arg4 = Arm64Arg::Imm32(frame_size);
+                    code.push(ln);
+                }
+            },
+            
+            _ => code.push(ln),
+        }
+    }
+}
+
+// Moves the stack pointer
+// add and sub only take 12-bit immediates, so bigger frames go through x16
+fn arm64_adjust_sp(code : &mut Vec<Arm64Instr>, instr_type : Arm64Type, size : i32) {
+    let mut ln = create_arm64_instr(instr_type);
+    ln.arg1 = Arm64Arg::Reg(Arm64Reg::SP);
+    ln.arg2 = Arm64Arg::Reg(Arm64Reg::SP);
+    ln.arg3 = Arm64Arg::Imm32(size);
+    
+    if size > 4095 {
+        arm64_build_li(code, Arm64Reg::X16, size as i64);
+        ln.arg3 = Arm64Arg::Reg(Arm64Reg::X16);
+    }
+    
+    code.push(ln);
+}
+
+// Stores (or loads) the saved registers to their slots
+fn arm64_save_regs(code : &mut Vec<Arm64Instr>, saved : &[Arm64Reg], stack_size : i32, instr_type : Arm64Type) {
+    for (i, reg) in saved.iter().enumerate() {
+        let mut ln = create_arm64_instr(instr_type.clone());
+        ln.arg1 = Arm64Arg::Reg(reg.clone());
+        ln.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size + (i as i32) * 8);
+        code.push(ln);
+    }
+}
+
+// Returns the callee-saved registers (x19-x28) used by a block of code
+fn arm64_callee_saved(code : &[Arm64Instr]) -> Vec<Arm64Reg> {
+    let all = [
+        (Arm64Reg::X19, Arm64Reg::W19), (Arm64Reg::X20, Arm64Reg::W20),
+        (Arm64Reg::X21, Arm64Reg::W21), (Arm64Reg::X22, Arm64Reg::W22),
+        (Arm64Reg::X23, Arm64Reg::W23), (Arm64Reg::X24, Arm64Reg::W24),
+        (Arm64Reg::X25, Arm64Reg::W25), (Arm64Reg::X26, Arm64Reg::W26),
+        (Arm64Reg::X27, Arm64Reg::W27), (Arm64Reg::X28, Arm64Reg::W28),
+    ];
+    let mut used : Vec<Arm64Reg> = Vec::new();
+    
+    for ln in code.iter() {
+        for arg in [&ln.arg1, &ln.arg2, &ln.arg3, &ln.arg4] {
+            match arg {
+                Arm64Arg::Reg(reg) | Arm64Arg::RegRef(reg)
+                | Arm64Arg::Mem(reg, _) => used.push(reg.clone()),
+                
+                _ => {},
+            }
+        }
+    }
+    
+    all.iter()
+        .filter(|(x, w)| used.contains(x) || used.contains(w))
+        .map(|(x, _)| x.clone())
+        .collect()
+}
+
 // Builds a function pusharg statement
+// Integer arguments go in x0-x7 (system calls take the number in x8 first), and floats in v0-v7
 pub fn arm64_build_pusharg(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stack_size : i32, karg : bool) {
-    let mut dest = arm64_arg_reg(instr.arg2_val);
-    let mut dest32 = arm64_arg_reg32(instr.arg2_val);
-    if karg {
-        dest = arm64_karg_reg(instr.arg2_val);
-        dest32 = arm64_karg_reg32(instr.arg2_val);
-    }
+    let is_float = match (&instr.arg1, &instr.arg2) {
+        (LtacArg::F32(_), _) | (LtacArg::F64(_), _)
+        | (LtacArg::FltReg(_), _) | (LtacArg::FltReg64(_), _)
+        | (_, LtacArg::FltReg(_)) | (_, LtacArg::FltReg64(_)) => true,
+        _ => false,
+    };
+    
+    // Registers keep their own size; otherwise it comes from the type hint
+    let is_64 = match arm64_build_reg(&instr.arg1) {
+        Some(reg) => arm64_is_reg64(&reg),
+        None => match (&instr.arg1, &instr.arg2) {
+            (LtacArg::F64(_), _) | (_, LtacArg::FltReg64(_))
+            | (LtacArg::Ptr(_), _) | (LtacArg::PtrLcl(_), _) | (LtacArg::PtrFunc(_), _)
+            | (LtacArg::I64(_), _) | (LtacArg::U64(_), _)
+            | (_, LtacArg::I64(_)) | (_, LtacArg::U64(_)) => true,
+            _ => false,
+        },
+    };
+    
+    let pos = instr.arg2_val;
+    let dest = if is_float {
+        if is_64 { Arm64Reg::D(pos - 1) } else { Arm64Reg::S(pos - 1) }
+    } else if karg {
+        if is_64 { arm64_karg_reg(pos) } else { arm64_karg_reg32(pos) }
+    } else if is_64 {
+        arm64_arg_reg(pos)
+    } else {
+        arm64_arg_reg32(pos)
+    };
     
     match instr.arg1 {
         LtacArg::Mem(pos) => {
-            let mut ld = create_arm64_instr(Arm64Type::Ldr);
-            ld.arg1 = Arm64Arg::Reg(dest32);
+            let ld_type = match instr.arg2 {
+                LtacArg::Byte(_) => Arm64Type::LdrSB,
+                LtacArg::UByte(_) => Arm64Type::Ldrb,
+                LtacArg::I16(_) => Arm64Type::LdrSH,
+                LtacArg::U16(_) => Arm64Type::Ldrh,
+                _ => Arm64Type::Ldr,
+            };
+            
+            let mut ld = create_arm64_instr(ld_type);
+            ld.arg1 = Arm64Arg::Reg(dest);
             ld.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
             code.push(ld);
         },
@@ -72,50 +249,76 @@ pub fn arm64_build_pusharg(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stac
             code.push(ld);
         },
         
-        LtacArg::I32(val) => {
-            let mut mov = create_arm64_instr(Arm64Type::Mov);
-            mov.arg1 = Arm64Arg::Reg(dest32);
-            mov.arg2 = Arm64Arg::Imm32(val);
-            code.push(mov);
-        },
+        LtacArg::PtrLcl(ref val) | LtacArg::PtrFunc(ref val) => arm64_build_adr(code, dest, val),
         
-        LtacArg::U32(val) => {
+        LtacArg::F32(ref val) | LtacArg::F64(ref val) => arm64_build_flt_lit(code, dest, val),
+        
+        _ => {
+            if let Some(val) = arm64_imm_val(&instr.arg1) {
+                arm64_build_li(code, dest, val);
+                return;
+            }
+            
+            let src = match arm64_build_reg(&instr.arg1) {
+                Some(reg) => reg,
+                None => return,
+            };
+            
             let mut mov = create_arm64_instr(Arm64Type::Mov);
-            mov.arg1 = Arm64Arg::Reg(dest32);
-            mov.arg2 = Arm64Arg::Imm32(val as i32);
+            if is_float {
+                mov.instr_type = Arm64Type::FMov;
+            }
+            
+            mov.arg1 = Arm64Arg::Reg(dest);
+            mov.arg2 = Arm64Arg::Reg(src);
             code.push(mov);
         },
-        
-        LtacArg::PtrLcl(ref val) => {
-            let mut instr1 = create_arm64_instr(Arm64Type::Adrp);
-            instr1.arg1 = Arm64Arg::Reg(dest.clone());
-            instr1.arg2 = Arm64Arg::PtrLcl(val.clone());
-            code.push(instr1);
-            
-            let mut instr2 = create_arm64_instr(Arm64Type::Add);
-            instr2.arg1 = Arm64Arg::Reg(dest.clone());
-            instr2.arg2 = Arm64Arg::Reg(dest);
-            instr2.arg3 = Arm64Arg::PtrLclLow(val.clone());
-            code.push(instr2);
+    }
+}
+
+// Builds an indirect function call
+// The address is either in a register, or a variable on the stack
+pub fn arm64_build_call_reg(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stack_size : i32) {
+    let mut blr = create_arm64_instr(Arm64Type::Blr);
+    blr.arg1 = Arm64Arg::Reg(Arm64Reg::X16);
+    
+    match instr.arg1 {
+        LtacArg::Mem(pos) => {
+            let mut ld = create_arm64_instr(Arm64Type::Ldr);
+            ld.arg1 = Arm64Arg::Reg(Arm64Reg::X16);
+            ld.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
+            code.push(ld);
         },
         
-        _ => {},
+        LtacArg::Reg64(pos) => blr.arg1 = Arm64Arg::Reg(arm64_op_reg64(pos)),
+        
+        _ => return,
     }
+    
+    code.push(blr);
 }
 
 // Builds a ldarg statement
 // Syntax: ldarg <mem> <reg>
 pub fn arm64_build_ldarg(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stack_size : i32) {
-    let mut line : Arm64Instr;
+    let pos = instr.arg2_val;
     
-    match instr.instr_type {
-        LtacType::LdArgI32 => line = create_arm64_instr(Arm64Type::Str),
+    let (instr_type, reg) = match instr.instr_type {
+        LtacType::LdArgI8 | LtacType::LdArgU8 => (Arm64Type::Strb, arm64_arg_reg32(pos)),
+        LtacType::LdArgI16 | LtacType::LdArgU16 => (Arm64Type::Strh, arm64_arg_reg32(pos)),
+        LtacType::LdArgI32 | LtacType::LdArgU32 => (Arm64Type::Str, arm64_arg_reg32(pos)),
+        
+        LtacType::LdArgI64 | LtacType::LdArgU64
+        | LtacType::LdArgPtr => (Arm64Type::Str, arm64_arg_reg(pos)),
+        
+        LtacType::LdArgF32 => (Arm64Type::Str, Arm64Reg::S(pos - 1)),
+        LtacType::LdArgF64 => (Arm64Type::Str, Arm64Reg::D(pos - 1)),
         
         _ => return,
-    }
+    };
     
-    // Register
-    line.arg1 = Arm64Arg::Reg(arm64_arg_reg32(instr.arg2_val));
+    let mut line = create_arm64_instr(instr_type);
+    line.arg1 = Arm64Arg::Reg(reg);
     
     // Memory
     match instr.arg1 {
@@ -5,359 +5,639 @@
 //
 
 use parser::ltac::{LtacInstr, LtacType, LtacArg};
-use crate::asm::*; 
+use crate::asm::*;
 
-// Builds a load or store instruction
-pub fn arm64_build_ld_str(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stack_size : i32) {
-    let mut line : Arm64Instr;
-    let is_store : bool;
-    
-    match instr.instr_type {
-        LtacType::Str | LtacType::StrQ => {
-            line = create_arm64_instr(Arm64Type::Str);
-            is_store = true;
-        },
+// Returns the register for an operand
+// This covers the operation registers and the return registers
+pub fn arm64_build_reg(arg : &LtacArg) -> Option<Arm64Reg> {
+    match arg {
+        LtacArg::Reg8(pos) | LtacArg::Reg16(pos)
+        | LtacArg::Reg32(pos) => Some(arm64_op_reg32(*pos)),
+        LtacArg::Reg64(pos) => Some(arm64_op_reg64(*pos)),
+        LtacArg::FltReg(pos) => Some(arm64_op_flt(*pos, false)),
+        LtacArg::FltReg64(pos) => Some(arm64_op_flt(*pos, true)),
         
-        LtacType::Ld => {
-            line = create_arm64_instr(Arm64Type::Ldr);
-            is_store = false;
-        },
+        LtacArg::RetRegI8 | LtacArg::RetRegU8
+        | LtacArg::RetRegI16 | LtacArg::RetRegU16
+        | LtacArg::RetRegI32 | LtacArg::RetRegU32 => Some(Arm64Reg::W0),
         
-        _ => return,
+        LtacArg::RetRegI64 | LtacArg::RetRegU64 => Some(Arm64Reg::X0),
+        LtacArg::RetRegF32 => Some(Arm64Reg::S(0)),
+        LtacArg::RetRegF64 => Some(Arm64Reg::D(0)),
+        
+        _ => None,
+    }
+}
+
+// Returns the value of an integer literal
+pub fn arm64_imm_val(arg : &LtacArg) -> Option<i64> {
+    match arg {
+        LtacArg::Byte(val) => Some(*val as i64),
+        LtacArg::UByte(val) => Some(*val as i64),
+        LtacArg::I16(val) => Some(*val as i64),
+        LtacArg::U16(val) => Some(*val as i64),
+        LtacArg::I32(val) => Some(*val as i64),
+        LtacArg::U32(val) => Some(*val as i64),
+        LtacArg::I64(val) => Some(*val),
+        LtacArg::U64(val) => Some(*val as i64),
+        _ => None,
     }
+}
+
+// Loads an immediate into a register
+// mov only takes 16 bits at a time, so bigger values are built up with movk
+pub fn arm64_build_li(code : &mut Vec<Arm64Instr>, reg : Arm64Reg, val : i64) {
+    let is_64 = arm64_is_reg64(&reg);
     
-    // Build store arguments
-    if is_store {
-        match instr.arg1 {
-            LtacArg::Mem(val) => line.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - val),
-            
-            LtacArg::MemOffsetImm(pos, offset) => {
-                let mut line1 = create_arm64_instr(Arm64Type::Ldr);
-                line1.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line1.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
-                code.push(line1);
-                
-                let mut line2 = create_arm64_instr(Arm64Type::Add);
-                line2.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line2.arg2 = line2.arg1.clone();
-                line2.arg3 = Arm64Arg::Imm32(offset);
-                code.push(line2);
-                
-                line.arg2 = Arm64Arg::RegRef(Arm64Reg::X0);
-            },
-            
-            LtacArg::MemOffsetMem(pos, var, size) => {
-                //ldrsw x0, [sp, var]
-                let mut line1 = create_arm64_instr(Arm64Type::LdrSW);
-                line1.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line1.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - var);
-                code.push(line1);
-                
-                //lsl x0, x0, sqrt(size)
-                let mut line2 = create_arm64_instr(Arm64Type::Lsl);
-                line2.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line2.arg2 = line2.arg1.clone();
-                line2.arg3 = Arm64Arg::Imm32(size / 2);
-                code.push(line2);
-                
-                //ldr x1, [sp, pos]
-                let mut line3 = create_arm64_instr(Arm64Type::Ldr);
-                line3.arg1 = Arm64Arg::Reg(Arm64Reg::X1);
-                line3.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
-                code.push(line3);
-                
-                // add x0, x0, x1
-                let mut line4 = create_arm64_instr(Arm64Type::Add);
-                line4.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line4.arg2 = line4.arg1.clone();
-                line4.arg3 = Arm64Arg::Reg(Arm64Reg::X1);
-                code.push(line4);
-                
-                // Final store
-                line.arg2 = Arm64Arg::RegRef(Arm64Reg::X0);
-            },
-            
-            LtacArg::MemOffsetReg(pos, reg, size) => {
-                //mov x0, reg
-                let mut line1 = create_arm64_instr(Arm64Type::Mov);
-                line1.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line1.arg2 = Arm64Arg::Reg(arm64_arg_reg64(reg));
-                code.push(line1);
-                
-                //lsl x0, x0, sqrt(size)
-                let mut line2 = create_arm64_instr(Arm64Type::Lsl);
-                line2.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line2.arg2 = line2.arg1.clone();
-                line2.arg3 = Arm64Arg::Imm32(size / 2);
-                code.push(line2);
-                
-                //ldr x1, [sp, pos]
-                let mut line3 = create_arm64_instr(Arm64Type::Ldr);
-                line3.arg1 = Arm64Arg::Reg(Arm64Reg::X1);
-                line3.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
-                code.push(line3);
-                
-                // add x0, x0, x1
-                let mut line4 = create_arm64_instr(Arm64Type::Add);
-                line4.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line4.arg2 = line4.arg1.clone();
-                line4.arg3 = Arm64Arg::Reg(Arm64Reg::X1);
-                code.push(line4);
-                
-                // Final store
-                line.arg2 = Arm64Arg::RegRef(Arm64Reg::X0);
-            },
-            
-            _ => return,
+    let mut mov = create_arm64_instr(Arm64Type::Mov);
+    mov.arg1 = Arm64Arg::Reg(reg.clone());
+    
+    if val > -65536 && val < 65536 {
+        mov.arg2 = Arm64Arg::Imm64(val);
+        code.push(mov);
+        return;
+    }
+    
+    let bits = if is_64 { val as u64 } else { val as u32 as u64 };
+    mov.arg2 = Arm64Arg::Imm64((bits & 0xFFFF) as i64);
+    code.push(mov);
+    
+    let top = if is_64 { 64 } else { 32 };
+    
+    for shift in (16 .. top).step_by(16) {
+        let part = (bits >> shift) & 0xFFFF;
+        if part == 0 {
+            continue;
         }
         
-        match instr.arg2 {
-            LtacArg::Reg8(val) | LtacArg::Reg16(val)
-            | LtacArg::Reg32(val) => line.arg1 = Arm64Arg::Reg(arm64_arg_reg32(val)),
-            
-            LtacArg::Reg64(val) => line.arg1 = Arm64Arg::Reg(arm64_arg_reg64(val)),
-            
-            LtacArg::RetRegI64 | LtacArg::RetRegU64 => line.arg1 = Arm64Arg::Reg(Arm64Reg::X0),
-            
-            _ => return,
-        }
-    // Build load arguments
-    } else {
-        match instr.arg1 {
-            LtacArg::Reg8(val) | LtacArg::Reg16(val)
-            | LtacArg::Reg32(val) => line.arg1 = Arm64Arg::Reg(arm64_arg_reg32(val)),
-            
-            LtacArg::Reg64(val) => line.arg1 = Arm64Arg::Reg(arm64_arg_reg64(val)),
-            
-            LtacArg::RetRegI8 | LtacArg::RetRegU8
-            | LtacArg::RetRegI16 | LtacArg::RetRegU16
-            | LtacArg::RetRegI32 | LtacArg::RetRegU32 => {
-                line.arg1 = Arm64Arg::Reg(Arm64Reg::W0);
-            },
-            
-            LtacArg::RetRegI64 | LtacArg::RetRegU64 => line.arg1 = Arm64Arg::Reg(Arm64Reg::X0),
-            
-            _ => return,
-        }
+        let mut movk = create_arm64_instr(Arm64Type::Movk);
+        movk.arg1 = Arm64Arg::Reg(reg.clone());
+        movk.arg2 = Arm64Arg::Imm64(part as i64);
+        movk.arg3 = Arm64Arg::Lsl(shift);
+        code.push(movk);
+    }
+}
+
+// Loads the address of a label
+// adrp reg, name
+// add reg, reg, :lo12:name
+pub fn arm64_build_adr(code : &mut Vec<Arm64Instr>, reg : Arm64Reg, name : &String) {
+    let mut instr1 = create_arm64_instr(Arm64Type::Adrp);
+    instr1.arg1 = Arm64Arg::Reg(reg.clone());
+    instr1.arg2 = Arm64Arg::PtrLcl(name.clone());
+    code.push(instr1);
+    
+    let mut instr2 = create_arm64_instr(Arm64Type::Add);
+    instr2.arg1 = Arm64Arg::Reg(reg.clone());
+    instr2.arg2 = Arm64Arg::Reg(reg);
+    instr2.arg3 = Arm64Arg::PtrLclLow(name.clone());
+    code.push(instr2);
+}
+
+// Loads a float literal from the data section
+// adrp x16, name
+// ldr reg, [x16, :lo12:name]
+pub fn arm64_build_flt_lit(code : &mut Vec<Arm64Instr>, reg : Arm64Reg, name : &String) {
+    let mut instr1 = create_arm64_instr(Arm64Type::Adrp);
+    instr1.arg1 = Arm64Arg::Reg(Arm64Reg::X16);
+    instr1.arg2 = Arm64Arg::PtrLcl(name.clone());
+    code.push(instr1);
+    
+    let mut instr2 = create_arm64_instr(Arm64Type::Ldr);
+    instr2.arg1 = Arm64Arg::Reg(reg);
+    instr2.arg2 = Arm64Arg::MemLcl(Arm64Reg::X16, name.clone());
+    code.push(instr2);
+}
+
+// Loads a register from the stack
+fn arm64_build_ldr(code : &mut Vec<Arm64Instr>, reg : Arm64Reg, offset : i32) {
+    let mut ld = create_arm64_instr(Arm64Type::Ldr);
+    ld.arg1 = Arm64Arg::Reg(reg);
+    ld.arg2 = Arm64Arg::Mem(Arm64Reg::SP, offset);
+    code.push(ld);
+}
+
+// Builds a memory operand
+// For array elements, the address of the array goes in x17 and the index in w16
+pub fn arm64_build_mem(code : &mut Vec<Arm64Instr>, arg : &LtacArg, stack_size : i32) -> Arm64Arg {
+    match arg {
+        LtacArg::Mem(pos) | LtacArg::Ptr(pos) => Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos),
         
-        match instr.arg2 {
-            LtacArg::Mem(val) => line.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - val),
-            
-            LtacArg::MemOffsetImm(pos, offset) => {
-                let mut line1 = create_arm64_instr(Arm64Type::Ldr);
-                line1.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line1.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
-                code.push(line1);
-            
-                line.arg2 = Arm64Arg::Mem(Arm64Reg::X0, offset);
-            },
-            
-            LtacArg::MemOffsetMem(pos, var, size) => {
-                //ldrsw x0, [sp, var]
-                let mut line1 = create_arm64_instr(Arm64Type::LdrSW);
-                line1.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line1.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - var);
-                code.push(line1);
-                
-                //lsl x0, x0, sqrt(size)
-                let mut line2 = create_arm64_instr(Arm64Type::Lsl);
-                line2.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line2.arg2 = line2.arg1.clone();
-                line2.arg3 = Arm64Arg::Imm32(size / 2);
-                code.push(line2);
-                
-                //ldr x1, [sp, pos]
-                let mut line3 = create_arm64_instr(Arm64Type::Ldr);
-                line3.arg1 = Arm64Arg::Reg(Arm64Reg::X1);
-                line3.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
-                code.push(line3);
-                
-                // add x0, x0, x1
-                let mut line4 = create_arm64_instr(Arm64Type::Add);
-                line4.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line4.arg2 = line4.arg1.clone();
-                line4.arg3 = Arm64Arg::Reg(Arm64Reg::X1);
-                code.push(line4);
-                
-                // Final store
-                line.arg2 = Arm64Arg::RegRef(Arm64Reg::X0);
-            },
+        LtacArg::MemOffsetImm(pos, offset) => {
+            arm64_build_ldr(code, Arm64Reg::X17, stack_size - pos);
+            Arm64Arg::Mem(Arm64Reg::X17, *offset)
+        },
+        
+        LtacArg::MemOffsetMem(pos, var, size) => {
+            arm64_build_ldr(code, Arm64Reg::W16, stack_size - var);
+            arm64_build_ldr(code, Arm64Reg::X17, stack_size - pos);
+            Arm64Arg::MemIdx(Arm64Reg::X17, Arm64Reg::W16, size.trailing_zeros() as i32)
+        },
+        
+        LtacArg::MemOffsetReg(pos, reg, size) => {
+            arm64_build_ldr(code, Arm64Reg::X17, stack_size - pos);
+            Arm64Arg::MemIdx(Arm64Reg::X17, arm64_op_reg32(*reg), size.trailing_zeros() as i32)
+        },
+        
+        // [pos] + ([row] * stride + reg) * size
+        LtacArg::MemOffsetStride(pos, row, reg, stride, size) => {
+            arm64_build_ldr(code, Arm64Reg::W16, stack_size - row);
+            arm64_build_li(code, Arm64Reg::W17, *stride as i64);
             
-            LtacArg::MemOffsetReg(pos, reg, size) => {
-                //mov x0, reg
-                let mut line1 = create_arm64_instr(Arm64Type::Mov);
-                line1.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line1.arg2 = Arm64Arg::Reg(arm64_arg_reg64(reg));
-                code.push(line1);
-                
-                //lsl x0, x0, sqrt(size)
-                let mut line2 = create_arm64_instr(Arm64Type::Lsl);
-                line2.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line2.arg2 = line2.arg1.clone();
-                line2.arg3 = Arm64Arg::Imm32(size / 2);
-                code.push(line2);
-                
-                //ldr x1, [sp, pos]
-                let mut line3 = create_arm64_instr(Arm64Type::Ldr);
-                line3.arg1 = Arm64Arg::Reg(Arm64Reg::X1);
-                line3.arg2 = Arm64Arg::Mem(Arm64Reg::SP, stack_size - pos);
-                code.push(line3);
-                
-                // add x0, x0, x1
-                let mut line4 = create_arm64_instr(Arm64Type::Add);
-                line4.arg1 = Arm64Arg::Reg(Arm64Reg::X0);
-                line4.arg2 = line4.arg1.clone();
-                line4.arg3 = Arm64Arg::Reg(Arm64Reg::X1);
-                code.push(line4);
-                
-                // Final store
-                line.arg2 = Arm64Arg::RegRef(Arm64Reg::X0);
-            },
+            let mut madd = create_arm64_instr(Arm64Type::MAdd);
+            madd.arg1 = Arm64Arg::Reg(Arm64Reg::W16);
+            madd.arg2 = Arm64Arg::Reg(Arm64Reg::W16);
+            madd.arg3 = Arm64Arg::Reg(Arm64Reg::W17);
+            madd.arg4 = Arm64Arg::Reg(arm64_op_reg32(*reg));
+            code.push(madd);
             
-            _ => return,
-        }
+            arm64_build_ldr(code, Arm64Reg::X17, stack_size - pos);
+            Arm64Arg::MemIdx(Arm64Reg::X17, Arm64Reg::W16, size.trailing_zeros() as i32)
+        },
+        
+        _ => Arm64Arg::Empty,
     }
+}
+
+// Builds a load or store instruction
+// Syntax: ld <mem>, <reg> and str <mem>, <reg>
+// Signed bytes and shorts are sign-extended when they are loaded
+pub fn arm64_build_ld_str(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stack_size : i32) {
+    let instr_type = match instr.instr_type {
+        LtacType::LdB => Arm64Type::LdrSB,
+        LtacType::LdUB => Arm64Type::Ldrb,
+        LtacType::LdW => Arm64Type::LdrSH,
+        LtacType::LdUW => Arm64Type::Ldrh,
+        
+        LtacType::Ld | LtacType::LdU | LtacType::LdQ | LtacType::LdUQ
+        | LtacType::LdF32 | LtacType::LdF64 => Arm64Type::Ldr,
+        
+        LtacType::StrB | LtacType::StrUB => Arm64Type::Strb,
+        LtacType::StrW | LtacType::StrUW => Arm64Type::Strh,
+        
+        _ => Arm64Type::Str,
+    };
+    
+    let reg = match arm64_build_reg(&instr.arg2) {
+        Some(reg) => reg,
+        None => return,
+    };
     
+    let mut line = create_arm64_instr(instr_type);
+    line.arg2 = arm64_build_mem(code, &instr.arg1, stack_size);
+    line.arg1 = Arm64Arg::Reg(reg);
     code.push(line);
 }
 
 // Builds a move instruction
 pub fn arm64_build_mov(code : &mut Vec<Arm64Instr>, instr : &LtacInstr) {
-    let mut mov = create_arm64_instr(Arm64Type::Mov);
-    mov.arg1 = arm64_build_operand(&instr.arg1, code, false);
-    mov.arg2 = arm64_build_operand(&instr.arg2, code, false);
-    code.push(mov);
+    let dest = match arm64_build_reg(&instr.arg1) {
+        Some(reg) => reg,
+        None => return,
+    };
+    
+    match &instr.arg2 {
+        // Addresses are loaded relative to the page
+        LtacArg::PtrLcl(ref val) | LtacArg::PtrFunc(ref val) => arm64_build_adr(code, dest, val),
+        
+        LtacArg::F32(ref val) | LtacArg::F64(ref val) => arm64_build_flt_lit(code, dest, val),
+        
+        _ => {
+            if let Some(val) = arm64_imm_val(&instr.arg2) {
+                arm64_build_li(code, dest, val);
+                return;
+            }
+            
+            let src = match arm64_build_reg(&instr.arg2) {
+                Some(reg) => reg,
+                None => return,
+            };
+            
+            let mut mov = create_arm64_instr(Arm64Type::Mov);
+            if instr.instr_type == LtacType::MovF32 || instr.instr_type == LtacType::MovF64 {
+                mov.instr_type = Arm64Type::FMov;
+            }
+            
+            mov.arg1 = Arm64Arg::Reg(dest);
+            mov.arg2 = Arm64Arg::Reg(src);
+            code.push(mov);
+        },
+    }
+}
+
+// Loads the address of a variable
+pub fn arm64_build_ldaddr(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stack_size : i32) {
+    let mut ln = create_arm64_instr(Arm64Type::Add);
+    
+    ln.arg1 = match arm64_build_reg(&instr.arg1) {
+        Some(reg) => Arm64Arg::Reg(reg),
+        None => return,
+    };
+    
+    ln.arg2 = Arm64Arg::Reg(Arm64Reg::SP);
+    
+    match instr.arg2 {
+        LtacArg::Mem(pos) => ln.arg3 = Arm64Arg::Imm32(stack_size - pos),
+        _ => return,
+    }
+    
+    code.push(ln);
+}
+
+// Returns the second operand of an instruction
+// Literals the instruction can't encode (anything above max_imm) are loaded into w17/x17 first
+fn arm64_build_operand(code : &mut Vec<Arm64Instr>, arg : &LtacArg, dest : &Arm64Reg, max_imm : i64) -> Arm64Arg {
+    if let Some(val) = arm64_imm_val(arg) {
+        if val >= 0 && val <= max_imm {
+            return Arm64Arg::Imm64(val);
+        }
+        
+        let reg = if arm64_is_reg64(dest) { Arm64Reg::X17 } else { Arm64Reg::W17 };
+        arm64_build_li(code, reg.clone(), val);
+        return Arm64Arg::Reg(reg);
+    }
+    
+    match arm64_build_reg(arg) {
+        Some(reg) => Arm64Arg::Reg(reg),
+        None => Arm64Arg::Empty,
+    }
 }
 
 // Builds common 3-operand instructions
 pub fn arm64_build_instr(code : &mut Vec<Arm64Instr>, instr : &LtacInstr) {
-    let mut ln : Arm64Instr;
-    
-    match instr.instr_type {
-        LtacType::I32Add => ln = create_arm64_instr(Arm64Type::Add),
-        LtacType::I32Sub => ln = create_arm64_instr(Arm64Type::Sub),
-        LtacType::I32Mul => ln = create_arm64_instr(Arm64Type::Mul),
-        LtacType::I32Div => ln = create_arm64_instr(Arm64Type::SDiv),
-        LtacType::I32Mod => ln = create_arm64_instr(Arm64Type::SDiv),
+    // The instruction, the size of the type, and whether it is signed
+    // The bitwise instructions aren't typed, so they don't have a size
+    let (instr_type, bits, signed) = match instr.instr_type {
+        LtacType::I8Add => (Arm64Type::Add, 8, true),
+        LtacType::U8Add => (Arm64Type::Add, 8, false),
+        LtacType::I8Sub => (Arm64Type::Sub, 8, true),
+        LtacType::I8Mul => (Arm64Type::Mul, 8, true),
+        LtacType::U8Mul => (Arm64Type::Mul, 8, false),
+        LtacType::I8Div | LtacType::I8Mod => (Arm64Type::SDiv, 8, true),
+        LtacType::U8Div | LtacType::U8Mod => (Arm64Type::UDiv, 8, false),
         
-        LtacType::And => ln = create_arm64_instr(Arm64Type::And),
-        LtacType::Or => ln = create_arm64_instr(Arm64Type::Orr),
-        LtacType::Xor => ln = create_arm64_instr(Arm64Type::Eor),
-        LtacType::Lsh => ln = create_arm64_instr(Arm64Type::Lsl),
-        LtacType::Rsh => ln = create_arm64_instr(Arm64Type::Lsr),
+        LtacType::I16Add => (Arm64Type::Add, 16, true),
+        LtacType::U16Add => (Arm64Type::Add, 16, false),
+        LtacType::I16Sub => (Arm64Type::Sub, 16, true),
+        LtacType::I16Mul => (Arm64Type::Mul, 16, true),
+        LtacType::U16Mul => (Arm64Type::Mul, 16, false),
+        LtacType::I16Div | LtacType::I16Mod => (Arm64Type::SDiv, 16, true),
+        LtacType::U16Div | LtacType::U16Mod => (Arm64Type::UDiv, 16, false),
         
-        LtacType::I32Cmp => ln = create_arm64_instr(Arm64Type::Cmp),
+        LtacType::I32Add => (Arm64Type::Add, 32, true),
+        LtacType::U32Add => (Arm64Type::Add, 32, false),
+        LtacType::I32Sub => (Arm64Type::Sub, 32, true),
+        LtacType::I32Mul => (Arm64Type::Mul, 32, true),
+        LtacType::U32Mul => (Arm64Type::Mul, 32, false),
+        LtacType::I32Div | LtacType::I32Mod => (Arm64Type::SDiv, 32, true),
+        LtacType::U32Div | LtacType::U32Mod => (Arm64Type::UDiv, 32, false),
+        
+        LtacType::I64Add => (Arm64Type::Add, 64, true),
+        LtacType::U64Add => (Arm64Type::Add, 64, false),
+        LtacType::I64Sub => (Arm64Type::Sub, 64, true),
+        LtacType::I64Mul => (Arm64Type::Mul, 64, true),
+        LtacType::U64Mul => (Arm64Type::Mul, 64, false),
+        LtacType::I64Div | LtacType::I64Mod => (Arm64Type::SDiv, 64, true),
+        LtacType::U64Div | LtacType::U64Mod => (Arm64Type::UDiv, 64, false),
+        
+        LtacType::And => (Arm64Type::And, 0, true),
+        LtacType::Or => (Arm64Type::Orr, 0, true),
+        LtacType::Xor => (Arm64Type::Eor, 0, true),
+        LtacType::Lsh => (Arm64Type::Lsl, 0, true),
+        LtacType::Rsh => (Arm64Type::Lsr, 0, true),
         
         _ => return,
-    }
+    };
     
-    let mut separate_li = false;
-    if ln.instr_type != Arm64Type::Add && ln.instr_type != Arm64Type::Sub {
-        separate_li = true;
-    }
+    let is_mod = match instr.instr_type {
+        LtacType::I8Mod | LtacType::U8Mod | LtacType::I16Mod | LtacType::U16Mod
+        | LtacType::I32Mod | LtacType::U32Mod | LtacType::I64Mod | LtacType::U64Mod => true,
+        _ => false,
+    };
     
-    ln.arg1 = arm64_build_operand(&instr.arg1, code, false);
+    let dest = match arm64_build_reg(&instr.arg1) {
+        Some(reg) => reg,
+        None => return,
+    };
     
-    if ln.instr_type == Arm64Type::Cmp {
-        ln.arg2 = arm64_build_operand(&instr.arg2, code, separate_li);
-    } else {
-        ln.arg2 = ln.arg1.clone();
-        ln.arg3 = arm64_build_operand(&instr.arg2, code, separate_li);
+    let max_imm = match instr_type {
+        Arm64Type::Add | Arm64Type::Sub => 4095,
+        Arm64Type::Lsl | Arm64Type::Lsr => 63,
+        _ => -1,
+    };
+    
+    let src = arm64_build_operand(code, &instr.arg2, &dest, max_imm);
+    let is_div = instr_type == Arm64Type::SDiv || instr_type == Arm64Type::UDiv;
+    
+    if !instr.name.is_empty() && !is_div {
+        arm64_build_checked_instr(code, instr, instr_type, bits, signed, dest, src);
+        return;
     }
     
-    if instr.instr_type == LtacType::I32Mod {
-        let dest2 = Arm64Arg::Reg(Arm64Reg::W1);
-        ln.arg1 = dest2;
-        code.push(ln.clone());
-        
-        let mut ln2 = create_arm64_instr(Arm64Type::MSub);
-        ln2.arg1 = ln.arg2.clone();
-        ln2.arg2 = ln.arg1.clone();
-        ln2.arg3 = ln.arg3.clone();
-        ln2.arg4 = ln.arg2.clone();
-        code.push(ln2);
+    if is_div {
+        arm64_build_zero_check(code, instr, &src);
+    }
+    
+    // The remainder is dest - (dest / src) * src
+    if is_mod {
+        let quot = if arm64_is_reg64(&dest) { Arm64Reg::X16 } else { Arm64Reg::W16 };
+        
+        let mut div = create_arm64_instr(instr_type);
+        div.arg1 = Arm64Arg::Reg(quot.clone());
+        div.arg2 = Arm64Arg::Reg(dest.clone());
+        div.arg3 = src.clone();
+        code.push(div);
+        
+        let mut msub = create_arm64_instr(Arm64Type::MSub);
+        msub.arg1 = Arm64Arg::Reg(dest.clone());
+        msub.arg2 = Arm64Arg::Reg(quot);
+        msub.arg3 = src;
+        msub.arg4 = Arm64Arg::Reg(dest.clone());
+        code.push(msub);
     } else {
+        let mut ln = create_arm64_instr(instr_type);
+        ln.arg1 = Arm64Arg::Reg(dest.clone());
+        ln.arg2 = Arm64Arg::Reg(dest.clone());
+        ln.arg3 = src;
         code.push(ln);
     }
+    
+    // Bytes and shorts are kept extended to 32 bits, so they wrap around the same way they do in memory
+    if bits == 8 || bits == 16 {
+        arm64_build_ext(code, dest.clone(), dest, bits, signed);
+    }
 }
 
-// Translates an operand
-fn arm64_build_operand(arg : &LtacArg, code : &mut Vec<Arm64Instr>, separate_li : bool) -> Arm64Arg {
-    let mut to_ret = match arg {
-        LtacArg::Reg8(val) | LtacArg::Reg16(val)
-        | LtacArg::Reg32(val) => Arm64Arg::Reg(arm64_arg_reg32(*val)),
+// Sign or zero-extends a byte or short
+fn arm64_build_ext(code : &mut Vec<Arm64Instr>, dest : Arm64Reg, src : Arm64Reg, bits : i32, signed : bool) {
+    let instr_type = match (bits, signed) {
+        (8, true) => Arm64Type::Sxtb,
+        (8, false) => Arm64Type::Uxtb,
+        (16, true) => Arm64Type::Sxth,
+        _ => Arm64Type::Uxth,
+    };
+    
+    let mut ext = create_arm64_instr(instr_type);
+    ext.arg1 = Arm64Arg::Reg(dest);
+    ext.arg2 = Arm64Arg::Reg(src);
+    code.push(ext);
+}
+
+// Builds checked integer math, which branches to the overflow panic
+// 32 and 64-bit adds and subtracts use the flags. Bytes and shorts are done at 32 bits, and
+// overflowed if extending the result changes it. Multiplication checks the upper half.
+fn arm64_build_checked_instr(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, instr_type : Arm64Type,
+                             bits : i32, signed : bool, dest : Arm64Reg, src : Arm64Arg) {
+    let mut jmp = create_arm64_instr(Arm64Type::Bne);
+    jmp.name = instr.name.clone();
+    
+    if bits == 8 || bits == 16 {
+        let mut ln = create_arm64_instr(instr_type);
+        ln.arg1 = Arm64Arg::Reg(dest.clone());
+        ln.arg2 = Arm64Arg::Reg(dest.clone());
+        ln.arg3 = src;
+        code.push(ln);
         
-        LtacArg::RetRegI8 | LtacArg::RetRegU8
-        | LtacArg::RetRegI16 | LtacArg::RetRegU16
-        | LtacArg::RetRegI32 | LtacArg::RetRegU32 => {
-            Arm64Arg::Reg(Arm64Reg::W0)
-        },
+        arm64_build_ext(code, Arm64Reg::W16, dest.clone(), bits, signed);
         
-        LtacArg::RetRegI64 | LtacArg::RetRegU64 => Arm64Arg::Reg(Arm64Reg::X0),
+        let mut cmp = create_arm64_instr(Arm64Type::Cmp);
+        cmp.arg1 = Arm64Arg::Reg(Arm64Reg::W16);
+        cmp.arg2 = Arm64Arg::Reg(dest);
+        code.push(cmp);
+    
+    // The product is done at 64 bits, and overflows if it doesn't extend from 32
+    } else if instr_type == Arm64Type::Mul && bits == 32 {
+        let mut mull = create_arm64_instr(if signed { Arm64Type::SMull } else { Arm64Type::UMull });
+        mull.arg1 = Arm64Arg::Reg(Arm64Reg::X16);
+        mull.arg2 = Arm64Arg::Reg(dest.clone());
+        mull.arg3 = src;
+        code.push(mull);
         
-        LtacArg::I32(val) => Arm64Arg::Imm32(*val),
-        LtacArg::U32(val) => Arm64Arg::Imm32(*val as i32),
+        let mut cmp = create_arm64_instr(Arm64Type::Cmp);
+        cmp.arg1 = Arm64Arg::Reg(Arm64Reg::X16);
+        cmp.arg2 = Arm64Arg::Reg(Arm64Reg::W16);
+        cmp.arg3 = if signed { Arm64Arg::Sxtw } else { Arm64Arg::Uxtw };
+        code.push(cmp);
+        
+        code.push(jmp);
         
-        _ => Arm64Arg::Empty,
-    };
-    
-    if separate_li {
         let mut mov = create_arm64_instr(Arm64Type::Mov);
-        mov.arg1 = Arm64Arg::Reg(Arm64Reg::W0);
-        mov.arg2 = to_ret;
+        mov.arg1 = Arm64Arg::Reg(dest);
+        mov.arg2 = Arm64Arg::Reg(Arm64Reg::W16);
         code.push(mov);
+        return;
+    
+    // The upper half has to be the sign of the lower half (or zero if unsigned)
+    } else if instr_type == Arm64Type::Mul {
+        let mut mulh = create_arm64_instr(if signed { Arm64Type::SMulh } else { Arm64Type::UMulh });
+        mulh.arg1 = Arm64Arg::Reg(Arm64Reg::X16);
+        mulh.arg2 = Arm64Arg::Reg(dest.clone());
+        mulh.arg3 = src.clone();
+        code.push(mulh);
+        
+        let mut mul = create_arm64_instr(Arm64Type::Mul);
+        mul.arg1 = Arm64Arg::Reg(dest.clone());
+        mul.arg2 = Arm64Arg::Reg(dest.clone());
+        mul.arg3 = src;
+        code.push(mul);
+        
+        let mut cmp = create_arm64_instr(Arm64Type::Cmp);
+        cmp.arg1 = Arm64Arg::Reg(Arm64Reg::X16);
+        
+        if signed {
+            cmp.arg2 = Arm64Arg::Reg(dest);
+            cmp.arg3 = Arm64Arg::Asr(63);
+        } else {
+            cmp.arg2 = Arm64Arg::Imm32(0);
+        }
+        
+        code.push(cmp);
+    
+    // Signed math overflows into the V flag, and unsigned math carries
+    } else {
+        let ln_type = if instr_type == Arm64Type::Add { Arm64Type::Adds } else { Arm64Type::Subs };
+        
+        let mut ln = create_arm64_instr(ln_type);
+        ln.arg1 = Arm64Arg::Reg(dest.clone());
+        ln.arg2 = Arm64Arg::Reg(dest);
+        ln.arg3 = src;
+        code.push(ln);
         
-        to_ret = Arm64Arg::Reg(Arm64Reg::W0);
+        jmp.instr_type = if signed { Arm64Type::Bvs } else { Arm64Type::Bcs };
+    }
+    
+    code.push(jmp);
+}
+
+// Branches to the panic of a checked division if the divisor is zero
+// sdiv doesn't trap on this; the result would just be 0
+fn arm64_build_zero_check(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, divisor : &Arm64Arg) {
+    if instr.name.is_empty() {
+        return;
     }
     
-    to_ret
+    let mut cmp = create_arm64_instr(Arm64Type::Cmp);
+    cmp.arg1 = divisor.clone();
+    cmp.arg2 = Arm64Arg::Imm32(0);
+    code.push(cmp);
+    
+    let mut jmp = create_arm64_instr(Arm64Type::Beq);
+    jmp.name = instr.name.clone();
+    code.push(jmp);
 }
 
-// Returns a register given a numerical value
-fn arm64_arg_reg32(val : i32) -> Arm64Reg {
-    match val {
-        0 => Arm64Reg::W9,
-        1 => Arm64Reg::W10,
-        2 => Arm64Reg::W11,
-        3 => Arm64Reg::W12,
-        4 => Arm64Reg::W13,
-        5 => Arm64Reg::W14,
-        6 => Arm64Reg::W15,
-        7 => Arm64Reg::W16,
-        _ => Arm64Reg::W17,
+// Returns the operand of a float instruction
+// Literals are loaded into v24
+fn arm64_build_flt_operand(code : &mut Vec<Arm64Instr>, arg : &LtacArg) -> Arm64Arg {
+    match arg {
+        LtacArg::F32(ref val) => {
+            arm64_build_flt_lit(code, Arm64Reg::S(24), val);
+            Arm64Arg::Reg(Arm64Reg::S(24))
+        },
+        
+        LtacArg::F64(ref val) => {
+            arm64_build_flt_lit(code, Arm64Reg::D(24), val);
+            Arm64Arg::Reg(Arm64Reg::D(24))
+        },
+        
+        _ => match arm64_build_reg(arg) {
+            Some(reg) => Arm64Arg::Reg(reg),
+            None => Arm64Arg::Empty,
+        },
     }
 }
 
-fn arm64_arg_reg64(val : i32) -> Arm64Reg {
-    match val {
-        0 => Arm64Reg::X9,
-        1 => Arm64Reg::X10,
-        2 => Arm64Reg::X11,
-        3 => Arm64Reg::X12,
-        4 => Arm64Reg::X13,
-        5 => Arm64Reg::X14,
-        6 => Arm64Reg::X15,
-        7 => Arm64Reg::X16,
-        _ => Arm64Reg::X17,
+// Builds float math
+pub fn arm64_build_flt_instr(code : &mut Vec<Arm64Instr>, instr : &LtacInstr) {
+    let instr_type = match instr.instr_type {
+        LtacType::F32Add | LtacType::F64Add => Arm64Type::FAdd,
+        LtacType::F32Sub | LtacType::F64Sub => Arm64Type::FSub,
+        LtacType::F32Mul | LtacType::F64Mul => Arm64Type::FMul,
+        LtacType::F32Div | LtacType::F64Div => Arm64Type::FDiv,
+        _ => return,
+    };
+    
+    let dest = match arm64_build_reg(&instr.arg1) {
+        Some(reg) => reg,
+        None => return,
+    };
+    
+    let mut ln = create_arm64_instr(instr_type);
+    ln.arg3 = arm64_build_flt_operand(code, &instr.arg2);
+    ln.arg1 = Arm64Arg::Reg(dest.clone());
+    ln.arg2 = Arm64Arg::Reg(dest);
+    code.push(ln);
+}
+
+// Builds a comparison
+// The bounds checks compare straight from memory, so that gets loaded into w16/x16
+pub fn arm64_build_cmp(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, stack_size : i32) {
+    let mut ln = create_arm64_instr(Arm64Type::Cmp);
+    
+    let dest = match arm64_build_reg(&instr.arg1) {
+        Some(reg) => reg,
+        None => {
+            let (ld_type, reg) = match instr.instr_type {
+                LtacType::I8Cmp => (Arm64Type::LdrSB, Arm64Reg::W16),
+                LtacType::U8Cmp => (Arm64Type::Ldrb, Arm64Reg::W16),
+                LtacType::I16Cmp => (Arm64Type::LdrSH, Arm64Reg::W16),
+                LtacType::U16Cmp => (Arm64Type::Ldrh, Arm64Reg::W16),
+                LtacType::I32Cmp | LtacType::U32Cmp => (Arm64Type::Ldr, Arm64Reg::W16),
+                LtacType::I64Cmp | LtacType::U64Cmp => (Arm64Type::Ldr, Arm64Reg::X16),
+                _ => return,
+            };
+            
+            let mut ld = create_arm64_instr(ld_type);
+            ld.arg2 = arm64_build_mem(code, &instr.arg1, stack_size);
+            if ld.arg2 == Arm64Arg::Empty {
+                return;
+            }
+            
+            ld.arg1 = Arm64Arg::Reg(reg.clone());
+            code.push(ld);
+            reg
+        },
+    };
+    
+    if instr.instr_type == LtacType::F32Cmp || instr.instr_type == LtacType::F64Cmp {
+        ln.instr_type = Arm64Type::FCmp;
+        ln.arg2 = arm64_build_flt_operand(code, &instr.arg2);
+    } else {
+        ln.arg2 = arm64_build_operand(code, &instr.arg2, &dest, 4095);
     }
+    
+    ln.arg1 = Arm64Arg::Reg(dest);
+    code.push(ln);
+}
+
+// Builds a string comparison
+// strcmp returns 0 if the strings are equal, so the result is compared against zero
+pub fn arm64_build_strcmp(code : &mut Vec<Arm64Instr>) {
+    let mut call = create_arm64_instr(Arm64Type::Call);
+    call.name = "strcmp".to_string();
+    code.push(call);
+    
+    let mut cmp = create_arm64_instr(Arm64Type::Cmp);
+    cmp.arg1 = Arm64Arg::Reg(Arm64Reg::W0);
+    cmp.arg2 = Arm64Arg::Imm32(0);
+    code.push(cmp);
+}
+
+// Builds a type conversion
+// The integer side can be either 32 or 64 bits
+pub fn arm64_build_cvt(code : &mut Vec<Arm64Instr>, instr : &LtacInstr) {
+    let instr_type = match instr.instr_type {
+        LtacType::CvtF32F64 | LtacType::CvtF64F32 => Arm64Type::FCvt,
+        LtacType::CvtIntF32 | LtacType::CvtIntF64 => Arm64Type::SCvtf,
+        LtacType::CvtF32Int | LtacType::CvtF64Int => Arm64Type::FCvtzs,
+        _ => Arm64Type::FMov,
+    };
+    
+    // The bits of a double only fit in a 64-bit register
+    let dest = match instr.arg1 {
+        LtacArg::Reg32(pos) if instr.instr_type == LtacType::MovF64Int => Some(arm64_op_reg64(pos)),
+        _ => arm64_build_reg(&instr.arg1),
+    };
+    
+    let (dest, src) = match (dest, arm64_build_reg(&instr.arg2)) {
+        (Some(dest), Some(src)) => (dest, src),
+        _ => return,
+    };
+    
+    let mut ln = create_arm64_instr(instr_type);
+    ln.arg1 = Arm64Arg::Reg(dest);
+    ln.arg2 = Arm64Arg::Reg(src);
+    code.push(ln);
 }
 
 // Translates a jump instruction
-pub fn arm64_build_jump(code : &mut Vec<Arm64Instr>, instr : &LtacInstr) {
-    let mut ln : Arm64Instr;
-    
-    match instr.instr_type {
-        LtacType::Br => ln = create_arm64_instr(Arm64Type::B),
-        LtacType::Be => ln = create_arm64_instr(Arm64Type::Beq),
-        LtacType::Bne => ln = create_arm64_instr(Arm64Type::Bne),
-        LtacType::Bl => ln = create_arm64_instr(Arm64Type::Bl),
-        LtacType::Ble => ln = create_arm64_instr(Arm64Type::Ble),
-        LtacType::Bg => ln = create_arm64_instr(Arm64Type::Bg),
-        LtacType::Bge => ln = create_arm64_instr(Arm64Type::Bge),
+// Unsigned comparisons use the carry flag instead of the sign
+pub fn arm64_build_jump(code : &mut Vec<Arm64Instr>, instr : &LtacInstr, is_unsigned : bool) {
+    let instr_type = match instr.instr_type {
+        LtacType::Br => Arm64Type::B,
+        LtacType::Be => Arm64Type::Beq,
+        LtacType::Bne => Arm64Type::Bne,
+        
+        LtacType::Bl if is_unsigned => Arm64Type::Blo,
+        LtacType::Ble if is_unsigned => Arm64Type::Bls,
+        LtacType::Bg if is_unsigned => Arm64Type::Bhi,
+        LtacType::Bge if is_unsigned => Arm64Type::Bhs,
+        
+        LtacType::Bl => Arm64Type::Blt,
+        LtacType::Ble => Arm64Type::Ble,
+        LtacType::Bg => Arm64Type::Bgt,
+        LtacType::Bge => Arm64Type::Bge,
+        
+        // These are false if either float is NaN
+        LtacType::Bfl => Arm64Type::Bmi,
+        LtacType::Bfle => Arm64Type::Bls,
+        LtacType::Bfg => Arm64Type::Bgt,
+        LtacType::Bfge => Arm64Type::Bge,
         
         _ => return,
-    }
+    };
     
+    let mut ln = create_arm64_instr(instr_type);
     ln.name = instr.name.clone();
     code.push(ln);
 }
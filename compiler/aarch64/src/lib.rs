@@ -19,18 +19,13 @@ use asm::*;
 use func::*;
 use instr::*;
 
-pub fn compile(ltac_file : &LtacFile) -> io::Result<()> {
+pub fn compile(ltac_file : &LtacFile, name : &String, omit_fp : bool) -> io::Result<()> {
     // Translate the code
     let mut code : Vec<Arm64Instr> = Vec::new();
-    translate_code(&mut code, &ltac_file.code);
+    translate_code(&mut code, &ltac_file.code, omit_fp);
 
-    // Create the file
-    let mut name = "/tmp/".to_string();
-    name.push_str(&ltac_file.name);
-    name.push_str(".asm");
-    
     // Write it out
-    let file = File::create(&name)?;
+    let file = File::create(name)?;
     let mut writer = BufWriter::new(file);
     
     write_data(&mut writer, &ltac_file.data);
@@ -54,6 +49,7 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>) {
             },
             
             LtacDataType::FloatL => {
+                line.push_str(".balign 4\n");
                 line.push_str(&data.name);
                 line.push_str(": .long ");
                 line.push_str(&data.val);
@@ -61,10 +57,26 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>) {
             },
             
             LtacDataType::DoubleL => {
+                line.push_str(".balign 8\n");
                 line.push_str(&data.name);
                 line.push_str(": .quad ");
                 line.push_str(&data.val);
                 line.push_str("\n");
+            },            
+            // Array literals are read-only, so they go in .rodata
+            LtacDataType::ArrayL(size) => {
+                line.push_str(".pushsection .rodata\n.balign 8\n");
+                line.push_str(&data.name);
+                
+                match size {
+                    1 => line.push_str(": .byte "),
+                    2 => line.push_str(": .2byte "),
+                    8 => line.push_str(": .8byte "),
+                    _ => line.push_str(": .4byte "),
+                }
+                
+                line.push_str(&data.val);
+                line.push_str("\n.popsection\n");
             },
         }
     }
@@ -76,8 +88,10 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>) {
 }
 
 // Translate the code section
-fn translate_code(code : &mut Vec<Arm64Instr>, input : &Vec<LtacInstr>) {
+fn translate_code(code : &mut Vec<Arm64Instr>, input : &Vec<LtacInstr>, omit_fp : bool) {
     let mut stack_size = 0;
+    let mut func_start : Option<usize> = None;
+    let mut is_unsigned = false;
     
     for ln in input {
         match ln.instr_type {
@@ -93,7 +107,13 @@ fn translate_code(code : &mut Vec<Arm64Instr>, input : &Vec<LtacInstr>) {
                 code.push(instr);
             },
             
+            // The frame of the previous function can be finished now that we have all its code
             LtacType::Func => {
+                if let Some(start) = func_start {
+                    arm64_build_frame(code, start, omit_fp);
+                }
+                
+                func_start = Some(code.len());
                 arm64_build_func(code, &ln);
                 stack_size = ln.arg1_val + 16;
             },
@@ -104,6 +124,8 @@ fn translate_code(code : &mut Vec<Arm64Instr>, input : &Vec<LtacInstr>) {
                 code.push(instr);
             },
             
+            LtacType::CallReg => arm64_build_call_reg(code, &ln, stack_size),
+            
             LtacType::Ret => arm64_build_ret(code, stack_size),
             
             LtacType::PushArg => arm64_build_pusharg(code, &ln, stack_size, false),
@@ -116,34 +138,89 @@ fn translate_code(code : &mut Vec<Arm64Instr>, input : &Vec<LtacInstr>) {
             
             LtacType::LdArgI8 | LtacType::LdArgU8 | LtacType::LdArgI16 | LtacType::LdArgU16
             | LtacType::LdArgI32 | LtacType::LdArgU32 | LtacType::LdArgI64 | LtacType::LdArgU64
+            | LtacType::LdArgF32 | LtacType::LdArgF64
             | LtacType::LdArgPtr => arm64_build_ldarg(code, &ln, stack_size),
             
             LtacType::StrB | LtacType::StrUB | LtacType::StrW | LtacType::StrUW
             | LtacType::Str | LtacType::StrU | LtacType::StrQ | LtacType::StrUQ
+            | LtacType::StrF32 | LtacType::StrF64
             | LtacType::StrPtr => arm64_build_ld_str(code, &ln, stack_size),
             
             LtacType::LdB | LtacType::LdUB | LtacType::LdW | LtacType::LdUW
             | LtacType::Ld | LtacType::LdU | LtacType::LdQ | LtacType::LdUQ
+            | LtacType::LdF32 | LtacType::LdF64
             => arm64_build_ld_str(code, &ln, stack_size),
             
             LtacType::MovB | LtacType::MovUB | LtacType::MovW | LtacType::MovUW
             | LtacType::Mov | LtacType::MovU | LtacType::MovQ | LtacType::MovUQ
+            | LtacType::MovF32 | LtacType::MovF64
             => arm64_build_mov(code, &ln),
             
-            LtacType::I32Add | LtacType::I32Sub | LtacType::I32Mul
-            | LtacType::I32Div | LtacType::I32Mod
+            LtacType::LdAddr => arm64_build_ldaddr(code, &ln, stack_size),
+            
+            LtacType::I8Add | LtacType::U8Add | LtacType::I8Sub
+            | LtacType::I8Mul | LtacType::U8Mul | LtacType::I8Div | LtacType::U8Div
+            | LtacType::I8Mod | LtacType::U8Mod
+            | LtacType::I16Add | LtacType::U16Add | LtacType::I16Sub
+            | LtacType::I16Mul | LtacType::U16Mul | LtacType::I16Div | LtacType::U16Div
+            | LtacType::I16Mod | LtacType::U16Mod
+            | LtacType::I32Add | LtacType::U32Add | LtacType::I32Sub
+            | LtacType::I32Mul | LtacType::U32Mul | LtacType::I32Div | LtacType::U32Div
+            | LtacType::I32Mod | LtacType::U32Mod
+            | LtacType::I64Add | LtacType::U64Add | LtacType::I64Sub
+            | LtacType::I64Mul | LtacType::U64Mul | LtacType::I64Div | LtacType::U64Div
+            | LtacType::I64Mod | LtacType::U64Mod
             | LtacType::And | LtacType::Or | LtacType::Xor
             | LtacType::Lsh | LtacType::Rsh
-            | LtacType::I32Cmp
             => arm64_build_instr(code, &ln),
             
+            LtacType::F32Add | LtacType::F32Sub | LtacType::F32Mul | LtacType::F32Div
+            | LtacType::F64Add | LtacType::F64Sub | LtacType::F64Mul | LtacType::F64Div
+            => arm64_build_flt_instr(code, &ln),
+            
+            // The branches after a compare depend on whether it was signed
+            LtacType::I8Cmp | LtacType::I16Cmp | LtacType::I32Cmp | LtacType::I64Cmp
+            | LtacType::F32Cmp | LtacType::F64Cmp => {
+                is_unsigned = false;
+                arm64_build_cmp(code, &ln, stack_size);
+            },
+            
+            LtacType::U8Cmp | LtacType::U16Cmp | LtacType::U32Cmp | LtacType::U64Cmp => {
+                is_unsigned = true;
+                arm64_build_cmp(code, &ln, stack_size);
+            },
+            
+            LtacType::StrCmp => {
+                is_unsigned = false;
+                arm64_build_strcmp(code);
+            },
+            
             LtacType::Br | LtacType::Be | LtacType::Bne
             | LtacType::Bl | LtacType::Ble
-            | LtacType::Bg | LtacType::Bge => arm64_build_jump(code, &ln),
+            | LtacType::Bg | LtacType::Bge
+            | LtacType::Bfl | LtacType::Bfle
+            | LtacType::Bfg | LtacType::Bfge => arm64_build_jump(code, &ln, is_unsigned),
+            
+            LtacType::CvtF32F64 | LtacType::CvtF64F32
+            | LtacType::CvtIntF32 | LtacType::CvtIntF64
+            | LtacType::CvtF32Int | LtacType::CvtF64Int
+            | LtacType::MovF64Int => arm64_build_cvt(code, &ln),
+            
+            // The transform layer turns these into calls
+            LtacType::Malloc | LtacType::Calloc | LtacType::Free
+            | LtacType::Resize | LtacType::Exit => {},
+            
+            // Vectors aren't supported yet, and push/pop are only used on stack machines
+            LtacType::MovI32Vec | LtacType::I32VAdd
+            | LtacType::Push | LtacType::Pop => {},
             
-            _ => {},
+            LtacType::None => {},
         }
     }
+    
+    if let Some(start) = func_start {
+        arm64_build_frame(code, start, omit_fp);
+    }
 }
 
 // Write the code section
@@ -195,6 +272,15 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<Arm64Instr>) {
                     .expect("[AArch64_call] Write failed.");
             },
             
+            Arm64Type::Blr => {
+                let mut line = "  blr ".to_string();
+                line.push_str(&write_operand(&ln.arg1, false));
+                line.push_str("\n");
+                
+                writer.write(&line.into_bytes())
+                    .expect("[AArch64_call_reg] Write failed.");
+            },
+            
             Arm64Type::Svc => {
                 writer.write(b"  svc 0\n")
                     .expect("[AArch64_syscall] Write failed.");
@@ -202,8 +288,12 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<Arm64Instr>) {
             
             Arm64Type::B
             | Arm64Type::Beq | Arm64Type::Bne
-            | Arm64Type::Bl | Arm64Type::Ble
-            | Arm64Type::Bg | Arm64Type::Bge => write_jump(writer, &ln),
+            | Arm64Type::Bvs | Arm64Type::Bcs
+            | Arm64Type::Blt | Arm64Type::Ble
+            | Arm64Type::Bgt | Arm64Type::Bge
+            | Arm64Type::Blo | Arm64Type::Bls
+            | Arm64Type::Bhi | Arm64Type::Bhs
+            | Arm64Type::Bmi => write_jump(writer, &ln),
             
             _ => write_instr(writer, &ln),
         }
@@ -218,19 +308,48 @@ fn write_instr(writer : &mut BufWriter<File>, ln : &Arm64Instr) {
         Arm64Type::Stp => line.push_str("stp "),
         Arm64Type::Adrp => line.push_str("adrp "),
         Arm64Type::Mov => line.push_str("mov "),
+        Arm64Type::Movk => line.push_str("movk "),
         Arm64Type::Str => line.push_str("str "),
+        Arm64Type::Strb => line.push_str("strb "),
+        Arm64Type::Strh => line.push_str("strh "),
         Arm64Type::Ldr => line.push_str("ldr "),
+        Arm64Type::Ldrb => line.push_str("ldrb "),
+        Arm64Type::Ldrh => line.push_str("ldrh "),
+        Arm64Type::LdrSB => line.push_str("ldrsb "),
+        Arm64Type::LdrSH => line.push_str("ldrsh "),
         Arm64Type::LdrSW => line.push_str("ldrsw "),
         Arm64Type::Add => line.push_str("add "),
+        Arm64Type::Adds => line.push_str("adds "),
         Arm64Type::Sub => line.push_str("sub "),
+        Arm64Type::Subs => line.push_str("subs "),
         Arm64Type::Mul => line.push_str("mul "),
+        Arm64Type::SMull => line.push_str("smull "),
+        Arm64Type::UMull => line.push_str("umull "),
+        Arm64Type::SMulh => line.push_str("smulh "),
+        Arm64Type::UMulh => line.push_str("umulh "),
         Arm64Type::SDiv => line.push_str("sdiv "),
+        Arm64Type::UDiv => line.push_str("udiv "),
         Arm64Type::MSub => line.push_str("msub "),
+        Arm64Type::MAdd => line.push_str("madd "),
+        Arm64Type::Sxtb => line.push_str("sxtb "),
+        Arm64Type::Sxth => line.push_str("sxth "),
+        Arm64Type::Sxtw => line.push_str("sxtw "),
+        Arm64Type::Uxtb => line.push_str("uxtb "),
+        Arm64Type::Uxth => line.push_str("uxth "),
         Arm64Type::And => line.push_str("and "),
         Arm64Type::Orr => line.push_str("orr "),
         Arm64Type::Eor => line.push_str("eor "),
         Arm64Type::Lsl => line.push_str("lsl "),
         Arm64Type::Lsr => line.push_str("lsr "),
+        Arm64Type::FMov => line.push_str("fmov "),
+        Arm64Type::FAdd => line.push_str("fadd "),
+        Arm64Type::FSub => line.push_str("fsub "),
+        Arm64Type::FMul => line.push_str("fmul "),
+        Arm64Type::FDiv => line.push_str("fdiv "),
+        Arm64Type::FCmp => line.push_str("fcmp "),
+        Arm64Type::FCvt => line.push_str("fcvt "),
+        Arm64Type::SCvtf => line.push_str("scvtf "),
+        Arm64Type::FCvtzs => line.push_str("fcvtzs "),
         Arm64Type::Cmp => line.push_str("cmp "),
         _ => {},
     }
@@ -261,12 +380,19 @@ fn write_jump(writer : &mut BufWriter<File>, ln : &Arm64Instr) {
     
     match ln.instr_type {
         Arm64Type::B => line.push_str("b"),
-        Arm64Type::Beq => line.push_str("beq"),
-        Arm64Type::Bne => line.push_str("bne"),
-        Arm64Type::Bl => line.push_str("bl"),
-        Arm64Type::Ble => line.push_str("ble"),
-        Arm64Type::Bg => line.push_str("bg"),
-        Arm64Type::Bge => line.push_str("bge"),
+        Arm64Type::Beq => line.push_str("b.eq"),
+        Arm64Type::Bne => line.push_str("b.ne"),
+        Arm64Type::Bvs => line.push_str("b.vs"),
+        Arm64Type::Bcs => line.push_str("b.cs"),
+        Arm64Type::Blt => line.push_str("b.lt"),
+        Arm64Type::Ble => line.push_str("b.le"),
+        Arm64Type::Bgt => line.push_str("b.gt"),
+        Arm64Type::Bge => line.push_str("b.ge"),
+        Arm64Type::Blo => line.push_str("b.lo"),
+        Arm64Type::Bls => line.push_str("b.ls"),
+        Arm64Type::Bhi => line.push_str("b.hi"),
+        Arm64Type::Bhs => line.push_str("b.hs"),
+        Arm64Type::Bmi => line.push_str("b.mi"),
         
         _ => return,
     }
@@ -303,7 +429,40 @@ fn write_operand(arg : &Arm64Arg, flag_mem : bool) -> String {
             return line;
         },
         
+        // [x16, :lo12:label]
+        Arm64Arg::MemLcl(reg, ref val) => {
+            let mut line = "[".to_string();
+            line.push_str(&write_register(reg));
+            line.push_str(", :lo12:");
+            line.push_str(val);
+            line.push_str("]");
+            return line;
+        },
+        
+        // [x17, w16, sxtw 2]
+        Arm64Arg::MemIdx(base, index, shift) => {
+            let mut line = "[".to_string();
+            line.push_str(&write_register(base));
+            line.push_str(", ");
+            line.push_str(&write_register(index));
+            line.push_str(", sxtw");
+            
+            if *shift > 0 {
+                line.push_str(" ");
+                line.push_str(&shift.to_string());
+            }
+            
+            line.push_str("]");
+            return line;
+        },
+        
         Arm64Arg::Imm32(val) => val.to_string(),
+        Arm64Arg::Imm64(val) => val.to_string(),
+        
+        Arm64Arg::Lsl(val) => format!("lsl {}", val),
+        Arm64Arg::Asr(val) => format!("asr {}", val),
+        Arm64Arg::Sxtw => "sxtw".to_string(),
+        Arm64Arg::Uxtw => "uxtw".to_string(),
         
         Arm64Arg::PtrLcl(ref val) => val.to_string(),
         
@@ -323,6 +482,7 @@ fn write_operand(arg : &Arm64Arg, flag_mem : bool) -> String {
 fn write_register(reg : &Arm64Reg) -> String {
     match reg {
         Arm64Reg::SP => "sp".to_string(),
+        Arm64Reg::XZR => "xzr".to_string(),
         
         Arm64Reg::X0 => "x0".to_string(),
         Arm64Reg::X1 => "x1".to_string(),
@@ -344,6 +504,17 @@ fn write_register(reg : &Arm64Reg) -> String {
         Arm64Reg::X16 => "x16".to_string(),
         Arm64Reg::X17 => "x17".to_string(),
         
+        Arm64Reg::X19 => "x19".to_string(),
+        Arm64Reg::X20 => "x20".to_string(),
+        Arm64Reg::X21 => "x21".to_string(),
+        Arm64Reg::X22 => "x22".to_string(),
+        Arm64Reg::X23 => "x23".to_string(),
+        Arm64Reg::X24 => "x24".to_string(),
+        Arm64Reg::X25 => "x25".to_string(),
+        Arm64Reg::X26 => "x26".to_string(),
+        Arm64Reg::X27 => "x27".to_string(),
+        Arm64Reg::X28 => "x28".to_string(),
+        
         Arm64Reg::X29 => "x29".to_string(),
         Arm64Reg::X30 => "x30".to_string(),
         
@@ -367,6 +538,20 @@ fn write_register(reg : &Arm64Reg) -> String {
         Arm64Reg::W16 => "w16".to_string(),
         Arm64Reg::W17 => "w17".to_string(),
         
+        Arm64Reg::W19 => "w19".to_string(),
+        Arm64Reg::W20 => "w20".to_string(),
+        Arm64Reg::W21 => "w21".to_string(),
+        Arm64Reg::W22 => "w22".to_string(),
+        Arm64Reg::W23 => "w23".to_string(),
+        Arm64Reg::W24 => "w24".to_string(),
+        Arm64Reg::W25 => "w25".to_string(),
+        Arm64Reg::W26 => "w26".to_string(),
+        Arm64Reg::W27 => "w27".to_string(),
+        Arm64Reg::W28 => "w28".to_string(),
+        
+        Arm64Reg::S(num) => format!("s{}", num),
+        Arm64Reg::D(num) => format!("d{}", num),
+        
         _ => String::new(),
     }
 }
@@ -45,6 +45,7 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>) {
             LtacDataType::StringL => {},
             LtacDataType::FloatL => {},
             LtacDataType::DoubleL => {},
+            LtacDataType::ArrayL(_) => {},
         }
     }
     
@@ -105,6 +106,7 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<LtacInstr>) {
             LtacType::PushArg => {},
             LtacType::KPushArg => {},
             LtacType::Call => {},
+            LtacType::CallReg => {},
             LtacType::Syscall => {},
             
             // Comparison instructons
@@ -210,7 +212,9 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<LtacInstr>) {
             // These are intrinsics if you will; they should never get down to a code generation layer
             LtacType::Exit => {},
             LtacType::Malloc => {},
+            LtacType::Calloc => {},
             LtacType::Free => {},
+            LtacType::Resize => {},
             
             // These are specific to RISC machines
             // RISC Load instructions
@@ -240,6 +244,11 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<LtacInstr>) {
             
             // Misc instructions
             LtacType::CvtF32F64 => {},
+            LtacType::CvtF64F32 => {},
+            LtacType::CvtIntF32 => {},
+            LtacType::CvtIntF64 => {},
+            LtacType::CvtF32Int => {},
+            LtacType::CvtF64Int => {},
             LtacType::MovF64Int => {},
             
             // Unknown
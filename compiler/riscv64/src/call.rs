@@ -23,7 +23,7 @@ pub fn riscv64_build_pusharg(writer : &mut BufWriter<File>, code : &LtacInstr, i
 
     match &code.arg1 {
 
-        LtacArg::Reg32(pos) => {
+        LtacArg::Reg32(pos) | LtacArg::Reg64(pos) => {
             let src_reg = riscv64_op_reg(*pos);
             
             line.push_str("  mv ");
@@ -32,6 +32,18 @@ pub fn riscv64_build_pusharg(writer : &mut BufWriter<File>, code : &LtacInstr, i
             line.push_str(&src_reg);
             line.push_str("\n");
         },
+        
+        LtacArg::FltReg(pos) | LtacArg::FltReg64(pos) => {
+            match &code.arg1 {
+                LtacArg::FltReg64(_) => line.push_str("  fmv.d "),
+                _ => line.push_str("  fmv.s "),
+            }
+            
+            line.push_str(&freg);
+            line.push_str(", ");
+            line.push_str(&riscv64_op_freg(*pos));
+            line.push_str("\n");
+        },
 
         LtacArg::Mem(val) => {
             let mut pos = stack_top - (*val);
@@ -172,6 +184,14 @@ pub fn riscv64_build_pusharg(writer : &mut BufWriter<File>, code : &LtacInstr, i
             line.push_str(")\n");
         },
 
+        LtacArg::PtrFunc(ref val) => {
+            line.push_str("  la ");
+            line.push_str(&reg);
+            line.push_str(", ");
+            line.push_str(val);
+            line.push_str("\n");
+        },
+
         _ => {},
     }
 
@@ -190,6 +210,38 @@ pub fn riscv64_build_call(writer : &mut BufWriter<File>, code : &LtacInstr) {
         .expect("[RISCV64_build_call] Write failed.");
 }
 
+// Builds an indirect function call
+// The address is either in a register, or a variable on the stack
+pub fn riscv64_build_call_reg(writer : &mut BufWriter<File>, code : &LtacInstr, stack_top : i32) {
+    let mut line = String::new();
+
+    match &code.arg1 {
+        LtacArg::Reg64(pos) => {
+            line.push_str("  jalr ");
+            line.push_str(&riscv64_op_reg(*pos));
+        },
+
+        LtacArg::Mem(val) => {
+            let mut pos = stack_top - (*val);
+            if pos + 8 == stack_top {
+                pos += 8;
+            }
+
+            line.push_str("  ld s2, -");
+            line.push_str(&pos.to_string());
+            line.push_str("(s0)\n");
+            line.push_str("  jalr s2");
+        },
+
+        _ => return,
+    }
+
+    line.push_str("\n\n");
+
+    writer.write(&line.into_bytes())
+        .expect("[RISCV64_build_call_reg] Write failed.");
+}
+
 // Builds a system call
 pub fn riscv64_build_syscall(writer : &mut BufWriter<File>) {
     let line = "  scall\n\n".to_string();
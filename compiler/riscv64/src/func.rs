@@ -31,9 +31,47 @@ pub fn riscv64_build_label(writer : &mut BufWriter<File>, code : &LtacInstr) {
         .expect("[RISCV64_build_label] Write failed.");
 }
 
+// Returns the callee-saved registers used by a function
+// S2 and S3 are used internally by a lot of instructions, so we always save those
+// Float literals are loaded into ft0, which isn't callee-saved
+pub fn riscv64_callee_saved(code : &[LtacInstr]) -> Vec<String> {
+    let mut saved : Vec<String> = vec!["s2".to_string(), "s3".to_string()];
+    
+    for ln in code.iter() {
+        if ln.instr_type == LtacType::Func {
+            break;
+        }
+        
+        for arg in [&ln.arg1, &ln.arg2] {
+            let reg = match arg {
+                LtacArg::Reg8(pos) | LtacArg::Reg16(pos)
+                | LtacArg::Reg32(pos) | LtacArg::Reg64(pos)
+                | LtacArg::MemOffsetReg(_, pos, _)
+                | LtacArg::MemOffsetStride(_, _, pos, _, _) => riscv64_op_reg(*pos),
+                
+                LtacArg::FltReg(pos) | LtacArg::FltReg64(pos) => riscv64_op_freg(*pos),
+                
+                _ => continue,
+            };
+            
+            if !reg.is_empty() && !saved.contains(&reg) {
+                saved.push(reg);
+            }
+        }
+    }
+    
+    saved
+}
+
 // Builds a function
-pub fn riscv64_build_func(writer : &mut BufWriter<File>, code : &LtacInstr) {
-    let stack_size = code.arg1_val + 16;
+// The callee-saved registers go below the return address and frame pointer
+// Returns the size of the stack frame
+pub fn riscv64_build_func(writer : &mut BufWriter<File>, code : &LtacInstr, saved : &[String]) -> i32 {
+    let mut stack_size = code.arg1_val + 16 + (saved.len() as i32) * 8;
+    if stack_size % 16 != 0 {
+        stack_size += 8;
+    }
+    
     let ra = stack_size - 8;
     let s0 = stack_size - 16;
 
@@ -57,20 +95,47 @@ pub fn riscv64_build_func(writer : &mut BufWriter<File>, code : &LtacInstr) {
     line.push_str(&s0.to_string());
     line.push_str("(sp)\n");
     
+    riscv64_save_regs(&mut line, saved, s0, true);
+    
     line.push_str("  addi s0, sp, ");
     line.push_str(&stack_size.to_string());
     line.push_str("\n\n");
 
     writer.write(&line.into_bytes())
         .expect("[RISCV64_build_func] Write failed.");
+    
+    stack_size
+}
+
+// Stores (or loads) the saved registers to their slots
+fn riscv64_save_regs(line : &mut String, saved : &[String], s0 : i32, is_store : bool) {
+    for (i, reg) in saved.iter().enumerate() {
+        let pos = s0 - (i as i32 + 1) * 8;
+        
+        if reg.starts_with('f') && is_store {
+            line.push_str("  fsd ");
+        } else if reg.starts_with('f') {
+            line.push_str("  fld ");
+        } else if is_store {
+            line.push_str("  sd ");
+        } else {
+            line.push_str("  ld ");
+        }
+        
+        line.push_str(reg);
+        line.push_str(", ");
+        line.push_str(&pos.to_string());
+        line.push_str("(sp)\n");
+    }
 }
 
 // Builds a return statement
-pub fn riscv64_build_ret(writer : &mut BufWriter<File>, stack_size : i32) {
+pub fn riscv64_build_ret(writer : &mut BufWriter<File>, stack_size : i32, saved : &[String]) {
     let ra = stack_size - 8;
     let s0 = stack_size - 16;
 
     let mut line = String::new();
+    riscv64_save_regs(&mut line, saved, s0, false);
 
     // Restore the return address and stack pointer
     line.push_str("  ld ra, ");
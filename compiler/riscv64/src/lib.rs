@@ -8,7 +8,6 @@ use std::io;
 use std::io::prelude::*;
 use std::io::BufWriter;
 use std::fs::File;
-use std::process::Command;
 
 use parser::ltac::{LtacFile, LtacData, LtacDataType, LtacType, LtacInstr, LtacArg};
 
@@ -24,13 +23,9 @@ use func::*;
 use mov::*;
 use utils::*;
 
-pub fn compile(ltac_file : &LtacFile) -> io::Result<()> {
-    let mut name = "/tmp/".to_string();
-    name.push_str(&ltac_file.name);
-    name.push_str(".asm");
-    
+pub fn compile(ltac_file : &LtacFile, name : &String) -> io::Result<()> {
     // Write it out
-    let file = File::create(&name)?;
+    let file = File::create(name)?;
     let mut writer = BufWriter::new(file);
     
     write_data(&mut writer, &ltac_file.data);
@@ -39,95 +34,6 @@ pub fn compile(ltac_file : &LtacFile) -> io::Result<()> {
     Ok(())
 }
 
-// Assemble a file
-pub fn build_asm(name : &String, no_link : bool) {
-    // Create all the names
-    let mut asm_name = "/tmp/".to_string();
-    asm_name.push_str(name);
-    asm_name.push_str(".asm");
-    
-    let mut obj_name = "/tmp/".to_string();
-    if no_link {
-        obj_name = "./".to_string();
-    }
-    
-    obj_name.push_str(name);
-    obj_name.push_str(".o");
-
-    // Assemble
-    let asm = Command::new("as")
-        .args(&[&asm_name, "-o", &obj_name])
-        .output()
-        .expect("Fatal: Assembly failed.");
-        
-    if !asm.status.success() {
-        io::stdout().write_all(&asm.stdout).unwrap();
-        io::stderr().write_all(&asm.stderr).unwrap();
-    }
-}
- 
-// Link everything
-pub fn link(all_names : &Vec<String>, output : &String, use_c : bool, is_lib : bool) {
-    let mut names : Vec<String> = Vec::new();
-    let mut libs : Vec<String> = Vec::new();
-    
-    for name in all_names.iter() {
-        if name.ends_with(".o") {
-            names.push(name.clone());
-        } else if name.starts_with("-l") {
-            libs.push(name.clone());
-        } else {
-            let mut obj_name = "/tmp/".to_string();
-            obj_name.push_str(&name);
-            obj_name.push_str(".o");
-            names.push(obj_name);
-        }
-    }
-    
-    // Link
-    //let ld : Output;
-    let mut args : Vec<&str> = Vec::new();
-    args.push("-L./");
-    
-    if use_c {
-        if !is_lib {
-            args.push("/usr/lib64/crti.o");
-            args.push("/usr/lib64/crtn.o");
-            args.push("/usr/lib64/crt1.o");
-        }
-        
-        args.push("-lc");
-    }
-    
-    args.push("-dynamic-linker");
-    args.push("/lib64/ld-linux-riscv64-lp64d.so.1");
-        
-    for name in names.iter() {
-        args.push(&name);
-    }
-        
-    if is_lib {
-        args.push("-shared");
-    }
-    
-    for lib in libs.iter() {
-        args.push(lib);
-    }
-        
-    args.push("-o");
-    args.push(output);
-    
-    let ld = Command::new("ld")
-        .args(args.as_slice())
-        .output()
-        .expect("Fatal: Linking failed.");
-    
-    if !ld.status.success() {
-        io::stdout().write_all(&ld.stdout).unwrap();
-        io::stderr().write_all(&ld.stderr).unwrap();
-    }
-}
-
 // Write the data section
 fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>) {
     let mut line = String::new();
@@ -154,6 +60,21 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>) {
                 line.push_str(": .long ");
                 line.push_str(&data.val);
                 line.push_str("\n");
+            },            
+            // Array literals are read-only, so they go in .rodata
+            LtacDataType::ArrayL(size) => {
+                line.push_str(".pushsection .rodata\n.balign 8\n");
+                line.push_str(&data.name);
+                
+                match size {
+                    1 => line.push_str(": .byte "),
+                    2 => line.push_str(": .2byte "),
+                    8 => line.push_str(": .8byte "),
+                    _ => line.push_str(": .4byte "),
+                }
+                
+                line.push_str(&data.val);
+                line.push_str("\n.popsection\n");
             },
         }
     }
@@ -165,25 +86,26 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>) {
 }
 
 // Write the code section
-fn write_code(writer : &mut BufWriter<File>, code : &Vec<LtacInstr>) {
+fn write_code(writer : &mut BufWriter<File>, input : &Vec<LtacInstr>) {
     let line = ".text\n".to_string();
     writer.write(&line.into_bytes())
         .expect("[RISCV_code] Write failed");
         
     let mut stack_size = 0;
-    let mut cmp_instr : &LtacInstr = code.first().unwrap();
+    let mut saved : Vec<String> = Vec::new();
+    let mut cmp_instr : &LtacInstr = input.first().unwrap();
 
-    for code in code.iter() {
+    for (i, code) in input.iter().enumerate() {
         match &code.instr_type {
         
             // Basic function instructions
             LtacType::Extern => riscv64_build_extern(writer, &code),
             LtacType::Label => riscv64_build_label(writer, &code),
-            LtacType::Ret => riscv64_build_ret(writer, stack_size),
+            LtacType::Ret => riscv64_build_ret(writer, stack_size, &saved),
             
             LtacType::Func => {
-                riscv64_build_func(writer, &code);
-                stack_size = code.arg1_val + 16;
+                saved = riscv64_callee_saved(&input[i+1..]);
+                stack_size = riscv64_build_func(writer, &code, &saved);
             },
             
             // Used to load function arguments
@@ -213,6 +135,7 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<LtacInstr>) {
             LtacType::PushArg => riscv64_build_pusharg(writer, &code, false, stack_size),
             LtacType::KPushArg => riscv64_build_pusharg(writer, &code, true, stack_size),
             LtacType::Call => riscv64_build_call(writer, &code),
+            LtacType::CallReg => riscv64_build_call_reg(writer, &code, stack_size),
             LtacType::Syscall => riscv64_build_syscall(writer),
             
             // Comparison instructons
@@ -240,7 +163,9 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<LtacInstr>) {
             // These are intrinsics if you will; they should never get down to a code generation layer
             LtacType::Exit => {},
             LtacType::Malloc => {},
+            LtacType::Calloc => {},
             LtacType::Free => {},
+            LtacType::Resize => {},
             
             // These are specific to RISC machines
             // RISC Load instructions
@@ -263,7 +188,9 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<LtacInstr>) {
             LtacType::StrF32 => riscv64_build_ld_str(writer, &code, stack_size, false),
 
             // Misc instructions
-            LtacType::CvtF32F64 => riscv64_build_cvt(writer, &code),
+            LtacType::CvtF32F64 | LtacType::CvtF64F32 |
+            LtacType::CvtIntF32 | LtacType::CvtIntF64 |
+            LtacType::CvtF32Int | LtacType::CvtF64Int => riscv64_build_cvt(writer, &code),
             
             // All else
             _ => riscv64_build_instr(writer, &code),
@@ -294,6 +221,11 @@ fn riscv64_is_muldiv(instr_type : &LtacType) -> bool {
 
 // Builds the base integer instructions
 fn riscv64_build_instr(writer : &mut BufWriter<File>, code : &LtacInstr) {
+    if !code.name.is_empty() {
+        riscv64_build_checked_instr(writer, code);
+        return;
+    }
+    
     let mut line = String::new();
     let mut instr = String::new();
     let mut suffix = 'w';
@@ -315,15 +247,17 @@ fn riscv64_build_instr(writer : &mut BufWriter<File>, code : &LtacInstr) {
         | LtacType::I32Mul | LtacType::U32Mul 
         | LtacType::I64Mul | LtacType::U64Mul => instr = "mul".to_string(),
 
-        LtacType::I8Div | LtacType::U8Div
-        | LtacType::I16Div | LtacType::U16Div
-        | LtacType::I32Div | LtacType::U32Div 
-        | LtacType::I64Div | LtacType::U64Div => instr = "div".to_string(),
+        LtacType::I8Div | LtacType::I16Div
+        | LtacType::I32Div | LtacType::I64Div => instr = "div".to_string(),
 
-        LtacType::I8Mod | LtacType::U8Mod
-        | LtacType::I16Mod | LtacType::U16Mod
-        | LtacType::I32Mod | LtacType::U32Mod 
-        | LtacType::I64Mod | LtacType::U64Mod => instr = "rem".to_string(),
+        LtacType::U8Div | LtacType::U16Div
+        | LtacType::U32Div | LtacType::U64Div => instr = "divu".to_string(),
+
+        LtacType::I8Mod | LtacType::I16Mod
+        | LtacType::I32Mod | LtacType::I64Mod => instr = "rem".to_string(),
+
+        LtacType::U8Mod | LtacType::U16Mod
+        | LtacType::U32Mod | LtacType::U64Mod => instr = "remu".to_string(),
 
         LtacType::F32Add => {
             instr = "fadd.s".to_string();
@@ -442,7 +376,7 @@ fn riscv64_build_instr(writer : &mut BufWriter<File>, code : &LtacInstr) {
             line.push_str(val);
             line.push_str(")\n");
 
-            line.push_str("  flw fs2, %lo(");
+            line.push_str("  flw ft0, %lo(");
             line.push_str(val);
             line.push_str(")(s2)\n");
         },
@@ -577,7 +511,7 @@ fn riscv64_build_instr(writer : &mut BufWriter<File>, code : &LtacInstr) {
         LtacArg::U32(val) => line.push_str(&val.to_string()),
         LtacArg::U64(val) => line.push_str(&val.to_string()),
         
-        LtacArg::F32(_v) => line.push_str("fs2"),
+        LtacArg::F32(_v) => line.push_str("ft0"),
 
         _ => {},
     }
@@ -588,3 +522,161 @@ fn riscv64_build_instr(writer : &mut BufWriter<File>, code : &LtacInstr) {
     writer.write(&line.into_bytes())
         .expect("[RISCV64_write_instr] Write failed.");
 }
+
+// Builds checked integer math
+// RISC-V has no overflow flags, so narrow types are done on values extended to
+// 64 bits, and the result is overflowed if it changes when extended again.
+// 64-bit operations compare the result against the operands instead.
+fn riscv64_build_checked_instr(writer : &mut BufWriter<File>, code : &LtacInstr) {
+    let mut line = String::new();
+    let label = &code.name;
+    
+    let (op, bits, signed) = match &code.instr_type {
+        LtacType::I8Add => ("add", 8, true),
+        LtacType::I8Sub => ("sub", 8, true),
+        LtacType::I8Mul => ("mul", 8, true),
+        LtacType::U8Add => ("add", 8, false),
+        LtacType::U8Mul => ("mul", 8, false),
+        
+        LtacType::I16Add => ("add", 16, true),
+        LtacType::I16Sub => ("sub", 16, true),
+        LtacType::I16Mul => ("mul", 16, true),
+        LtacType::U16Add => ("add", 16, false),
+        LtacType::U16Mul => ("mul", 16, false),
+        
+        LtacType::I32Add => ("add", 32, true),
+        LtacType::I32Sub => ("sub", 32, true),
+        LtacType::I32Mul => ("mul", 32, true),
+        LtacType::U32Add => ("add", 32, false),
+        LtacType::U32Mul => ("mul", 32, false),
+        
+        LtacType::I64Add => ("add", 64, true),
+        LtacType::I64Sub => ("sub", 64, true),
+        LtacType::I64Mul => ("mul", 64, true),
+        LtacType::U64Add => ("add", 64, false),
+        LtacType::U64Mul => ("mul", 64, false),
+        
+        // Division only checks the divisor, so the rest is built normally
+        LtacType::I8Div | LtacType::U8Div | LtacType::I8Mod | LtacType::U8Mod
+        | LtacType::I16Div | LtacType::U16Div | LtacType::I16Mod | LtacType::U16Mod
+        | LtacType::I32Div | LtacType::U32Div | LtacType::I32Mod | LtacType::U32Mod
+        | LtacType::I64Div | LtacType::U64Div | LtacType::I64Mod | LtacType::U64Mod => {
+            riscv64_build_zero_check(writer, code);
+            
+            let mut instr = code.clone();
+            instr.name = String::new();
+            riscv64_build_instr(writer, &instr);
+            return;
+        },
+        
+        _ => return,
+    };
+    
+    let dest = match &code.arg1 {
+        LtacArg::Reg8(pos) | LtacArg::Reg16(pos)
+        | LtacArg::Reg32(pos) | LtacArg::Reg64(pos) => riscv64_op_reg(*pos),
+        _ => return,
+    };
+    
+    // Immediates are loaded into a scratch register first
+    let src = match &code.arg2 {
+        LtacArg::Reg8(pos) | LtacArg::Reg16(pos)
+        | LtacArg::Reg32(pos) | LtacArg::Reg64(pos) => riscv64_op_reg(*pos),
+        
+        LtacArg::Byte(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        LtacArg::UByte(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        LtacArg::I16(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        LtacArg::U16(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        LtacArg::I32(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        LtacArg::U32(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        LtacArg::I64(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        LtacArg::U64(val) => riscv64_checked_imm(&mut line, val.to_string()),
+        
+        _ => return,
+    };
+    
+    if bits < 64 {
+        let shift = (64 - bits).to_string();
+        let shift_right = if signed { "srai" } else { "srli" };
+        
+        // t0 = dest, t1 = src, both extended
+        for (reg, val) in [("t0", &dest), ("t1", &src)] {
+            line.push_str(&format!("  slli {}, {}, {}\n", reg, val, shift));
+            line.push_str(&format!("  {} {}, {}, {}\n", shift_right, reg, reg, shift));
+        }
+        
+        line.push_str(&format!("  {} t0, t0, t1\n", op));
+        line.push_str(&format!("  slli t2, t0, {}\n", shift));
+        line.push_str(&format!("  {} t2, t2, {}\n", shift_right, shift));
+        line.push_str(&format!("  bne t2, t0, {}\n", label));
+        line.push_str(&format!("  mv {}, t0\n", dest));
+    } else if op == "mul" && signed {
+        // The upper half has to be the sign of the lower half
+        line.push_str(&format!("  mulh t1, {}, {}\n", dest, src));
+        line.push_str(&format!("  mul {}, {}, {}\n", dest, dest, src));
+        line.push_str(&format!("  srai t2, {}, 63\n", dest));
+        line.push_str(&format!("  bne t1, t2, {}\n", label));
+    } else if op == "mul" {
+        line.push_str(&format!("  mulhu t1, {}, {}\n", dest, src));
+        line.push_str(&format!("  mul {}, {}, {}\n", dest, dest, src));
+        line.push_str(&format!("  bnez t1, {}\n", label));
+    } else if signed {
+        // Adding a negative number has to make the result smaller, and subtracting
+        // a positive one has to as well
+        line.push_str(&format!("  {} t0, {}, {}\n", op, dest, src));
+        line.push_str(&format!("  slt t1, t0, {}\n", dest));
+        
+        if op == "add" {
+            line.push_str(&format!("  slti t2, {}, 0\n", src));
+        } else {
+            line.push_str(&format!("  slt t2, zero, {}\n", src));
+        }
+        
+        line.push_str(&format!("  bne t1, t2, {}\n", label));
+        line.push_str(&format!("  mv {}, t0\n", dest));
+    } else {
+        // An unsigned sum wraps around if it ends up smaller
+        line.push_str(&format!("  add t0, {}, {}\n", dest, src));
+        line.push_str(&format!("  bltu t0, {}, {}\n", dest, label));
+        line.push_str(&format!("  mv {}, t0\n", dest));
+    }
+    
+    writer.write(&line.into_bytes())
+        .expect("[RISCV64_write_checked_instr] Write failed.");
+}
+
+// Branches to the panic of a checked division if the divisor is zero
+// RISC-V doesn't trap on this; the quotient would be -1 and the remainder the dividend
+fn riscv64_build_zero_check(writer : &mut BufWriter<File>, code : &LtacInstr) {
+    let mut line = String::new();
+    
+    match &code.arg2 {
+        LtacArg::Reg8(pos) | LtacArg::Reg16(pos)
+        | LtacArg::Reg32(pos) | LtacArg::Reg64(pos) => {
+            line.push_str("  beqz ");
+            line.push_str(&riscv64_op_reg(*pos));
+            line.push_str(", ");
+        },
+        
+        LtacArg::Byte(0) | LtacArg::UByte(0)
+        | LtacArg::I16(0) | LtacArg::U16(0)
+        | LtacArg::I32(0) | LtacArg::U32(0)
+        | LtacArg::I64(0) | LtacArg::U64(0) => line.push_str("  j "),
+        
+        _ => return,
+    }
+    
+    line.push_str(&code.name);
+    line.push_str("\n");
+    
+    writer.write(&line.into_bytes())
+        .expect("[RISCV64_write_zero_check] Write failed.");
+}
+
+// Loads the immediate operand of a checked instruction
+fn riscv64_checked_imm(line : &mut String, val : String) -> String {
+    line.push_str("  li s2, ");
+    line.push_str(&val);
+    line.push_str("\n");
+    "s2".to_string()
+}
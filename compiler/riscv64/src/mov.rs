@@ -14,8 +14,20 @@ use crate::utils::*;
 pub fn riscv64_build_cvt(writer : &mut BufWriter<File>, code : &LtacInstr) {
     let mut line = String::new();
 
+    // The integer side can be a word or a double word
+    let is_long = matches!(code.arg1, LtacArg::Reg64(_)) || matches!(code.arg2, LtacArg::Reg64(_));
+
     match &code.instr_type {
         LtacType::CvtF32F64 => line = "  fcvt.d.s ".to_string(),
+        LtacType::CvtF64F32 => line = "  fcvt.s.d ".to_string(),
+        LtacType::CvtIntF32 if is_long => line = "  fcvt.s.l ".to_string(),
+        LtacType::CvtIntF32 => line = "  fcvt.s.w ".to_string(),
+        LtacType::CvtIntF64 if is_long => line = "  fcvt.d.l ".to_string(),
+        LtacType::CvtIntF64 => line = "  fcvt.d.w ".to_string(),
+        LtacType::CvtF32Int if is_long => line = "  fcvt.l.s ".to_string(),
+        LtacType::CvtF32Int => line = "  fcvt.w.s ".to_string(),
+        LtacType::CvtF64Int if is_long => line = "  fcvt.l.d ".to_string(),
+        LtacType::CvtF64Int => line = "  fcvt.w.d ".to_string(),
         _ => {},
     }
 
@@ -24,6 +36,11 @@ pub fn riscv64_build_cvt(writer : &mut BufWriter<File>, code : &LtacInstr) {
             let reg = riscv64_op_freg(*pos);
             line.push_str(&reg);
         },
+        
+        LtacArg::Reg32(pos) | LtacArg::Reg64(pos) => {
+            let reg = riscv64_op_reg(*pos);
+            line.push_str(&reg);
+        },
 
         _ => {},
     }
@@ -35,9 +52,19 @@ pub fn riscv64_build_cvt(writer : &mut BufWriter<File>, code : &LtacInstr) {
             let reg = riscv64_op_freg(*pos);
             line.push_str(&reg);
         },
+        
+        LtacArg::Reg32(pos) | LtacArg::Reg64(pos) => {
+            let reg = riscv64_op_reg(*pos);
+            line.push_str(&reg);
+        },
 
         _ => {},
     }
+    
+    // Float to integer conversions truncate
+    if code.instr_type == LtacType::CvtF32Int || code.instr_type == LtacType::CvtF64Int {
+        line.push_str(", rtz");
+    }
 
     line.push_str("\n");
 
@@ -70,6 +97,7 @@ pub fn riscv64_build_ld_str(writer : &mut BufWriter<File>, code : &LtacInstr, st
     }
 
     // Write the registers
+    // Values are loaded straight into the return register when a function returns a variable
     match &code.arg2 {
         LtacArg::Reg8(pos) | LtacArg::Reg16(pos)
         | LtacArg::Reg32(pos) | LtacArg::Reg64(pos) => {
@@ -81,6 +109,13 @@ pub fn riscv64_build_ld_str(writer : &mut BufWriter<File>, code : &LtacInstr, st
             let reg = riscv64_op_freg(*pos);
             line.push_str(&reg);
         },
+        
+        LtacArg::RetRegI8 | LtacArg::RetRegU8
+        | LtacArg::RetRegI16 | LtacArg::RetRegU16
+        | LtacArg::RetRegI32 | LtacArg::RetRegU32
+        | LtacArg::RetRegI64 | LtacArg::RetRegU64 => line.push_str("a0"),
+        
+        LtacArg::RetRegF32 => line.push_str("fa0"),
 
         _ => {},
     }
@@ -164,21 +199,17 @@ pub fn riscv64_build_ld_str(writer : &mut BufWriter<File>, code : &LtacInstr, st
             full_line.push_str("(s0)\n");
 
             // Now for the offset
-            let reg = riscv64_op_reg(*reg_pos);
+            // The index is scaled in s3, since the register may still be needed
+            let mut reg = riscv64_op_reg(*reg_pos);
 
             if (*size) > 1 {
-                full_line.push_str("  slli ");
+                full_line.push_str("  slli s3, ");
                 full_line.push_str(&reg);
                 full_line.push_str(", ");
-                full_line.push_str(&reg);
-
-                if (*size) == 2 {
-                    full_line.push_str(", 1\n");
-                } else if (*size) == 4 {
-                    full_line.push_str(", 2\n");
-                } else if (*size) == 8 {
-                    full_line.push_str(", 3\n");
-                }
+                full_line.push_str(&size.trailing_zeros().to_string());
+                full_line.push_str("\n");
+                
+                reg = "s3".to_string();
             }
 
             full_line.push_str("  add s2, s2, ");
@@ -189,6 +220,41 @@ pub fn riscv64_build_ld_str(writer : &mut BufWriter<File>, code : &LtacInstr, st
             line.push_str("0(s2)");
         },
 
+        // [pos] + ([row] * stride + reg) * size
+        LtacArg::MemOffsetStride(pos, row, reg_pos, stride, size) => {
+            // Load the row and scale it by the stride
+            let row_pos = stack_top - (*row);
+            full_line.push_str("  lw s3, -");
+            full_line.push_str(&row_pos.to_string());
+            full_line.push_str("(s0)\n");
+
+            full_line.push_str("  li s2, ");
+            full_line.push_str(&stride.to_string());
+            full_line.push_str("\n");
+            full_line.push_str("  mul s3, s3, s2\n");
+
+            // Add the column
+            full_line.push_str("  add s3, s3, ");
+            full_line.push_str(&riscv64_op_reg(*reg_pos));
+            full_line.push_str("\n");
+
+            if (*size) > 1 {
+                full_line.push_str("  slli s3, s3, ");
+                full_line.push_str(&size.trailing_zeros().to_string());
+                full_line.push_str("\n");
+            }
+
+            // Load the array and add the offset
+            let array_pos = stack_top - (*pos) + 8;
+            full_line.push_str("  ld s2, -");
+            full_line.push_str(&array_pos.to_string());
+            full_line.push_str("(s0)\n");
+            full_line.push_str("  add s2, s2, s3\n");
+
+            // Store the result
+            line.push_str("0(s2)");
+        },
+
         _ => {},
     }
 
@@ -235,6 +301,7 @@ pub fn riscv64_build_mov(writer : &mut BufWriter<File>, code : &LtacInstr) {
             match &code.arg2 {
                 LtacArg::I64(_v) => line.push_str("  li "),
                 LtacArg::U64(_v) => line.push_str("  li "),
+                LtacArg::PtrLcl(_v) | LtacArg::PtrFunc(_v) => line.push_str("  la "),
                 _ => line.push_str("  mv "),
             }
         },
@@ -249,7 +316,7 @@ pub fn riscv64_build_mov(writer : &mut BufWriter<File>, code : &LtacInstr) {
                     line.push_str("  flw ");
                 },
 
-                _ => line.push_str("  fmv.s.s "),
+                _ => line.push_str("  fmv.s "),
             }
         },
 
@@ -314,6 +381,8 @@ pub fn riscv64_build_mov(writer : &mut BufWriter<File>, code : &LtacInstr) {
         LtacArg::I64(val) => line.push_str(&val.to_string()),
         LtacArg::U64(val) => line.push_str(&val.to_string()),
 
+        LtacArg::PtrLcl(ref val) | LtacArg::PtrFunc(ref val) => line.push_str(val),
+
         LtacArg::F32(ref val) => {
             line.push_str("%lo(");
             line.push_str(&val);
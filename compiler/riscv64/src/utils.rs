@@ -53,11 +53,14 @@ pub fn riscv64_op_reg(pos : i32) -> String {
         2 => return "s6".to_string(),
         3 => return "s7".to_string(),
         4 => return "s8".to_string(),
+        5 => return "s9".to_string(),
+        6 => return "s10".to_string(),
+        7 => return "s11".to_string(),
         _ => return String::new(),
     };
 }
 
-// FS2 and FS3 are for internal operations
+// Float literals are loaded into FT0, which is a temporary
 pub fn riscv64_op_freg(pos : i32) -> String {
     match pos {
         0 => return "fs4".to_string(),
@@ -65,6 +68,9 @@ pub fn riscv64_op_freg(pos : i32) -> String {
         2 => return "fs6".to_string(),
         3 => return "fs7".to_string(),
         4 => return "fs8".to_string(),
+        5 => return "fs9".to_string(),
+        6 => return "fs10".to_string(),
+        7 => return "fs11".to_string(),
         _ => return String::new(),
     };
 }
@@ -31,6 +31,10 @@ pub enum X86Type {
     Mul,    Mul8,
     IDiv,
     Div,
+    Neg,
+    Cwd,        // Sign-extend ax into dx
+    Cdq,        // Sign-extend eax into edx
+    Cqo,        // Sign-extend rax into rdx
     
     And,
     Or,
@@ -48,6 +52,13 @@ pub enum X86Type {
     MulSD,
     DivSD,
     
+    Cvtss2sd,
+    Cvtsd2ss,
+    Cvtsi2ss,
+    Cvtsi2sd,
+    Cvttss2si,      // Truncates
+    Cvttsd2si,
+    
     Cmp,
     Ucomiss,
     Ucomisd,
@@ -58,8 +69,10 @@ pub enum X86Type {
     Jg, Jge,
     Ja, Jae,
     Jb, Jbe,
+    Jo,
     
     Call,
+    CallReg,
     Syscall,
     Leave,
     Ret
@@ -75,6 +88,7 @@ pub enum X86Arg {
     WordMem(X86Reg, i32, bool),
     QwordMem(X86Reg, i32, bool),
     LclMem(String, bool),
+    FuncMem(String, bool),
     ScaleMem(i32, X86Reg, i32, bool),
     
     Imm32(i32),
@@ -319,7 +333,7 @@ pub fn amd64_arg_reg64(pos : i32) -> X86Arg {
     };
 }
 
-/*pub fn amd64_arg_flt(pos : i32) -> X86Arg {
+pub fn amd64_arg_flt(pos : i32) -> X86Arg {
     match pos {
         1 => return X86Arg::Xmm(0),
         2 => return X86Arg::Xmm(1),
@@ -331,7 +345,7 @@ pub fn amd64_arg_reg64(pos : i32) -> X86Arg {
         8 => return X86Arg::Xmm(7),
         _ => return X86Arg::Empty,
     };
-}*/
+}
 
 // Operation registers
 // EAX -> Return register
@@ -343,6 +357,7 @@ pub fn amd64_op_reg8(pos : i32) -> X86Arg {
         2 => return X86Arg::Reg8(X86Reg::R10),
         3 => return X86Arg::Reg8(X86Reg::R11),
         4 => return X86Arg::Reg8(X86Reg::R12),
+        5 => return X86Arg::Reg8(X86Reg::R13),
         _ => return X86Arg::Empty,
     };
 }
@@ -354,6 +369,7 @@ pub fn amd64_op_reg16(pos : i32) -> X86Arg {
         2 => return X86Arg::Reg16(X86Reg::R10),
         3 => return X86Arg::Reg16(X86Reg::R11),
         4 => return X86Arg::Reg16(X86Reg::R12),
+        5 => return X86Arg::Reg16(X86Reg::R13),
         _ => return X86Arg::Empty,
     };
 }
@@ -365,6 +381,7 @@ pub fn amd64_op_reg32(pos : i32) -> X86Arg {
         2 => return X86Arg::Reg32(X86Reg::R10),
         3 => return X86Arg::Reg32(X86Reg::R11),
         4 => return X86Arg::Reg32(X86Reg::R12),
+        5 => return X86Arg::Reg32(X86Reg::R13),
         _ => return X86Arg::Empty,
     };
 }
@@ -376,26 +393,27 @@ pub fn amd64_op_reg64(pos : i32) -> X86Arg {
         2 => return X86Arg::Reg64(X86Reg::R10),
         3 => return X86Arg::Reg64(X86Reg::R11),
         4 => return X86Arg::Reg64(X86Reg::R12),
+        5 => return X86Arg::Reg64(X86Reg::R13),
         _ => return X86Arg::Empty,
     };
 }
 
-// xmm0 and xmm1 are reserved for internal operations
-/*pub fn amd64_op_flt(pos : i32) -> String {
+// xmm0-xmm7 hold arguments and the return value, so the operations use the upper registers
+pub fn amd64_op_flt(pos : i32) -> X86Arg {
     match pos {
-        0 => return "xmm10".to_string(),
-        1 => return "xmm11".to_string(),
-        2 => return "xmm12".to_string(),
-        3 => return "xmm13".to_string(),
-        4 => return "xmm14".to_string(),
-        5 => return "xmm15".to_string(),
-        _ => return String::new(),
-    }
+        0 => return X86Arg::Xmm(10),
+        1 => return X86Arg::Xmm(11),
+        2 => return X86Arg::Xmm(12),
+        3 => return X86Arg::Xmm(13),
+        4 => return X86Arg::Xmm(14),
+        5 => return X86Arg::Xmm(15),
+        _ => return X86Arg::Empty,
+    };
 }
 
 // Vector registers
 // ymm0 and ymm1 are reserved for internal operations
-pub fn amd64_vector_i32(pos : i32) -> String {
+/*pub fn amd64_vector_i32(pos : i32) -> String {
     match pos {
         0 => return "ymm3".to_string(),
         1 => return "ymm4".to_string(),
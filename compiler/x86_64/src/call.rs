@@ -12,7 +12,7 @@ pub fn amd64_build_pusharg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_
     // Get the argument registers
     let mut reg32 = amd64_arg_reg32(code.arg2_val);
     let mut reg64 = amd64_arg_reg64(code.arg2_val);
-    //let mut reg_flt = amd64_arg_flt(code.arg2_val);
+    let reg_flt = amd64_arg_flt(code.arg2_val);
     
     if is_karg {
         reg32 = amd64_karg_reg32(code.arg2_val);
@@ -24,8 +24,8 @@ pub fn amd64_build_pusharg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_
     
     match &code.arg1 {
         LtacArg::Reg8(_p) => mov_type = X86Type::MovZX,
-        LtacArg::F32(_p) => mov_type = X86Type::MovSS,
-        LtacArg::F64(_p) => mov_type = X86Type::MovSD,
+        LtacArg::F32(_) | LtacArg::FltReg(_) => mov_type = X86Type::MovSS,
+        LtacArg::F64(_) | LtacArg::FltReg64(_) => mov_type = X86Type::MovSD,
         LtacArg::PtrLcl(_p) if is_pic => mov_type = X86Type::Lea,
         _ => {},
     }
@@ -35,18 +35,8 @@ pub fn amd64_build_pusharg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_
         LtacArg::UByte(_v) => mov_type = X86Type::MovZX,
         LtacArg::I16(_v) => mov_type = X86Type::MovSX,
         LtacArg::U16(_v) => mov_type = X86Type::MovZX,
-        
-        // TODO: What do those second lines for the float registers do?
-        LtacArg::FltReg(_pos) => {
-            mov_type = X86Type::MovSS;
-            //reg_flt = amd64_arg_flt(pos);
-        },
-        
-        LtacArg::FltReg64(_pos) => {
-            mov_type = X86Type::MovSD;
-            //reg_flt = amd64_arg_flt(pos);
-        },
-        
+        LtacArg::FltReg(_v) => mov_type = X86Type::MovSS,
+        LtacArg::FltReg64(_v) => mov_type = X86Type::MovSD,
         _ => {},
     }
     
@@ -76,8 +66,18 @@ pub fn amd64_build_pusharg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_
             instr.arg2 = amd64_op_reg64(*pos);
         },
         
+        LtacArg::FltReg(pos) | LtacArg::FltReg64(pos) => {
+            instr.arg1 = reg_flt;
+            instr.arg2 = amd64_op_flt(*pos);
+        },
+        
         LtacArg::Mem(pos) => {
             match code.arg2 {
+                LtacArg::FltReg(_v) | LtacArg::FltReg64(_v) => {
+                    instr.arg1 = reg_flt;
+                    instr.arg2 = X86Arg::Mem(X86Reg::RBP, *pos, is_pic);
+                },
+                
                 LtacArg::Byte(_v) => {
                     instr.arg1 = reg32;
                     instr.arg2 = X86Arg::BwordMem(X86Reg::RBP, *pos, is_pic);
@@ -146,21 +146,64 @@ pub fn amd64_build_pusharg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_
             instr.arg2 = X86Arg::LclMem(val.to_string(), is_pic);
         },
         
+        LtacArg::PtrFunc(ref val) => {
+            instr.arg1 = reg64;
+            instr.arg2 = X86Arg::FuncMem(val.to_string(), is_pic);
+        },
+        
+        // Float literals are read from the data section
+        LtacArg::F32(ref val) | LtacArg::F64(ref val) => {
+            instr.arg1 = reg_flt;
+            instr.arg2 = X86Arg::LclMem(val.to_string(), true);
+        },
+        
         _ => {},
     }
         
     x86_code.push(instr);
 }
 
+// Returns true if an argument goes in a vector register
+pub fn amd64_is_float_arg(code : &LtacInstr) -> bool {
+    match (&code.arg1, &code.arg2) {
+        (LtacArg::F32(_), _) | (LtacArg::F64(_), _) => true,
+        (_, LtacArg::FltReg(_)) | (_, LtacArg::FltReg64(_)) => true,
+        _ => false,
+    }
+}
+
 // Builds a function call
 // Param: name
-pub fn amd64_build_call(x86_code : &mut Vec<X86Instr>, code : &LtacInstr) {
+// Variadic functions need the number of vector registers used in al, so we always set it
+// if there are float arguments.
+pub fn amd64_build_call(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, flt_args : i32) {
+    if flt_args > 0 {
+        let mut instr = create_x86instr(X86Type::Mov);
+        instr.arg1 = X86Arg::Reg32(X86Reg::RAX);
+        instr.arg2 = X86Arg::Imm32(flt_args);
+        x86_code.push(instr);
+    }
+    
     let mut instr = create_x86instr(X86Type::Call);
     instr.name = code.name.clone();
     
     x86_code.push(instr);
 }
 
+// Builds an indirect function call
+// The address can either be in a register or a variable
+pub fn amd64_build_call_reg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pic : bool) {
+    let mut instr = create_x86instr(X86Type::CallReg);
+    
+    match &code.arg1 {
+        LtacArg::Reg64(pos) => instr.arg1 = amd64_op_reg64(*pos),
+        LtacArg::Mem(pos) => instr.arg1 = X86Arg::QwordMem(X86Reg::RBP, *pos, is_pic),
+        _ => {},
+    }
+    
+    x86_code.push(instr);
+}
+
 // Builds a system call
 pub fn amd64_build_syscall(x86_code : &mut Vec<X86Instr>) {
     let instr = create_x86instr(X86Type::Syscall);
@@ -62,6 +62,128 @@ pub fn amd64_build_func(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pic
     x86_code.push(instr);
 }
 
+// Saves and restores the callee-saved registers a function uses, and drops the frame pointer if asked
+// This runs after the whole function is translated, since only then do we know which registers it uses.
+// The registers are saved in extra slots below the locals.
+// Params: start -> the position of the function's first instruction
+pub fn amd64_build_frame(x86_code : &mut Vec<X86Instr>, start : usize, omit_fp : bool, is_pic : bool) {
+    let body = x86_code.split_off(start);
+    let saved = amd64_callee_saved(&body);
+    
+    let stack_size = match body.iter().find(|ln| ln.instr_type == X86Type::Sub) {
+        Some(ln) => match ln.arg2 {
+            X86Arg::Imm32(val) => val,
+            _ => 0,
+        },
+        
+        None => 0,
+    };
+    
+    // Keep the stack 16-byte aligned
+    let mut frame_size = stack_size + (saved.len() as i32) * 8;
+    if frame_size % 16 != 0 {
+        frame_size += 8;
+    }
+    
+    for mut ln in body {
+        match ln.instr_type {
+            X86Type::Push if omit_fp && ln.arg1 == X86Arg::Reg64(X86Reg::RBP) => continue,
+            X86Type::Mov if omit_fp && ln.arg1 == X86Arg::Reg64(X86Reg::RBP) => continue,
+            
+            // Without rbp, the 8 bytes it would take are needed to keep the alignment
+            X86Type::Sub if ln.arg1 == X86Arg::Reg64(X86Reg::RSP) => {
+                ln.arg2 = X86Arg::Imm32(frame_size);
+                if omit_fp {
+                    ln.arg2 = X86Arg::Imm32(frame_size + 8);
+                }
+                
+                x86_code.push(ln);
+                
+                for (i, reg) in saved.iter().enumerate() {
+                    let pos = stack_size + (i as i32 + 1) * 8;
+                    
+                    let mut instr = create_x86instr(X86Type::Mov);
+                    instr.arg1 = X86Arg::QwordMem(X86Reg::RBP, pos, is_pic);
+                    instr.arg2 = X86Arg::Reg64(reg.clone());
+                    x86_code.push(instr);
+                }
+            },
+            
+            X86Type::Leave => {
+                for (i, reg) in saved.iter().enumerate() {
+                    let pos = stack_size + (i as i32 + 1) * 8;
+                    
+                    let mut instr = create_x86instr(X86Type::Mov);
+                    instr.arg1 = X86Arg::Reg64(reg.clone());
+                    instr.arg2 = X86Arg::QwordMem(X86Reg::RBP, pos, is_pic);
+                    x86_code.push(instr);
+                }
+                
+                if omit_fp {
+                    let mut instr = create_x86instr(X86Type::Add);
+                    instr.arg1 = X86Arg::Reg64(X86Reg::RSP);
+                    instr.arg2 = X86Arg::Imm32(frame_size + 8);
+                    x86_code.push(instr);
+                } else {
+                    x86_code.push(ln);
+                }
+            },
+            
+            _ => x86_code.push(ln),
+        }
+    }
+    
+    // Without a frame pointer, everything on the stack is relative to rsp
+    // rbp would have been at rsp + frame_size, so [rbp-pos] becomes [rsp+(frame_size-pos)]
+    if omit_fp {
+        for ln in x86_code[start..].iter_mut() {
+            ln.arg1 = amd64_rebase_mem(&ln.arg1, frame_size);
+            ln.arg2 = amd64_rebase_mem(&ln.arg2, frame_size);
+        }
+    }
+}
+
+// Returns the callee-saved registers used by a block of code
+// We never use rbp as a general register, so the frame handles that one
+fn amd64_callee_saved(code : &[X86Instr]) -> Vec<X86Reg> {
+    let all = [X86Reg::RBX, X86Reg::R12, X86Reg::R13, X86Reg::R14, X86Reg::R15];
+    let mut used : Vec<X86Reg> = Vec::new();
+    
+    for ln in code.iter() {
+        for arg in [&ln.arg1, &ln.arg2, &ln.arg3] {
+            let reg = match arg {
+                X86Arg::Reg64(reg) | X86Arg::Reg32(reg)
+                | X86Arg::Reg16(reg) | X86Arg::Reg8(reg) => reg,
+                
+                X86Arg::Mem(reg, _, _) | X86Arg::BwordMem(reg, _, _)
+                | X86Arg::WordMem(reg, _, _) | X86Arg::DwordMem(reg, _, _)
+                | X86Arg::QwordMem(reg, _, _) | X86Arg::ScaleMem(_, reg, _, _) => reg,
+                
+                _ => continue,
+            };
+            
+            if !used.contains(reg) {
+                used.push(reg.clone());
+            }
+        }
+    }
+    
+    all.iter().filter(|reg| used.contains(reg)).cloned().collect()
+}
+
+// Moves an rbp-relative memory operand to rsp
+fn amd64_rebase_mem(arg : &X86Arg, frame_size : i32) -> X86Arg {
+    match arg {
+        X86Arg::Mem(X86Reg::RBP, pos, pic) => X86Arg::Mem(X86Reg::RSP, pos - frame_size, *pic),
+        X86Arg::BwordMem(X86Reg::RBP, pos, pic) => X86Arg::BwordMem(X86Reg::RSP, pos - frame_size, *pic),
+        X86Arg::WordMem(X86Reg::RBP, pos, pic) => X86Arg::WordMem(X86Reg::RSP, pos - frame_size, *pic),
+        X86Arg::DwordMem(X86Reg::RBP, pos, pic) => X86Arg::DwordMem(X86Reg::RSP, pos - frame_size, *pic),
+        X86Arg::QwordMem(X86Reg::RBP, pos, pic) => X86Arg::QwordMem(X86Reg::RSP, pos - frame_size, *pic),
+        
+        _ => arg.clone(),
+    }
+}
+
 // Builds a return statement
 pub fn amd64_build_ret(x86_code : &mut Vec<X86Instr>) {
     let mut instr = create_x86instr(X86Type::Leave);
@@ -76,7 +198,11 @@ pub fn amd64_build_ret(x86_code : &mut Vec<X86Instr>) {
 //      -> arg1_val = memory location
 //      -> arg2_val = register position
 pub fn amd64_build_ldarg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pic : bool) {
-    let mut instr = create_x86instr(X86Type::Mov);
+    let mut instr = match code.instr_type {
+        LtacType::LdArgF32 => create_x86instr(X86Type::MovSS),
+        LtacType::LdArgF64 => create_x86instr(X86Type::MovSD),
+        _ => create_x86instr(X86Type::Mov),
+    };
     
     match &code.arg1 {
         LtacArg::Reg8(pos) => instr.arg1 = amd64_op_reg8(*pos),
@@ -95,6 +221,7 @@ pub fn amd64_build_ldarg(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
         LtacType::LdArgI32 | LtacType::LdArgU32 => instr.arg2 = amd64_arg_reg32(code.arg2_val),
         LtacType::LdArgI64 | LtacType::LdArgU64 
         | LtacType::LdArgPtr => instr.arg2 = amd64_arg_reg64(code.arg2_val),
+        LtacType::LdArgF32 | LtacType::LdArgF64 => instr.arg2 = amd64_arg_flt(code.arg2_val),
         
         _ => {},
     }
@@ -31,6 +31,7 @@ pub fn amd64_build_jump(x86_code : &mut Vec<X86Instr>, code : &LtacInstr) {
 }
 
 // Builds a string comparison
+// strcmp returns 0 if the strings are equal, so the result is compared against zero
 pub fn amd64_build_strcmp(x86_code : &mut Vec<X86Instr>) {
     let mut instr2 = create_x86instr(X86Type::Call);
     instr2.name = "strcmp".to_string();
@@ -38,7 +39,7 @@ pub fn amd64_build_strcmp(x86_code : &mut Vec<X86Instr>) {
     
     instr2 = create_x86instr(X86Type::Cmp);
     instr2.arg1 = X86Arg::Reg32(X86Reg::RAX);
-    instr2.arg2 = X86Arg::Imm32(1);
+    instr2.arg2 = X86Arg::Imm32(0);
     x86_code.push(instr2);
 }
 
@@ -78,6 +79,7 @@ fn amd64_build_offset_reg(x86_code : &mut Vec<X86Instr>, pos : i32, reg : i32, s
             2 => src_reg = X86Reg::R10,
             3 => src_reg = X86Reg::R11,
             4 => src_reg = X86Reg::R12,
+            5 => src_reg = X86Reg::R13,
             _ => src_reg = X86Reg::RAX,
         };
     
@@ -100,7 +102,54 @@ fn amd64_build_offset_reg(x86_code : &mut Vec<X86Instr>, pos : i32, reg : i32, s
     x86_code.push(instr2.clone());
 }
 
+// Builds the address of an element in a multi-dimensional array
+// The row number comes from memory and the column from a register:
+// [pos] + ([row] * stride + reg) * size
+fn amd64_build_offset_stride(x86_code : &mut Vec<X86Instr>, pos : i32, row : i32, reg : i32, stride : i32, size : i32, is_pic : bool) {
+    // Load the row and scale it by the stride
+    let mut instr2 = create_x86instr(X86Type::Mov);
+    instr2.arg1 = X86Arg::Reg32(X86Reg::R15);
+    instr2.arg2 = X86Arg::DwordMem(X86Reg::RBP, row, is_pic);
+    x86_code.push(instr2.clone());
+    
+    instr2 = create_x86instr(X86Type::IMul);
+    instr2.arg1 = X86Arg::Reg64(X86Reg::R15);
+    instr2.arg2 = X86Arg::Imm32(stride);
+    x86_code.push(instr2.clone());
+    
+    // Add the column
+    instr2 = create_x86instr(X86Type::Add);
+    instr2.arg1 = X86Arg::Reg64(X86Reg::R15);
+    instr2.arg2 = amd64_op_reg64(reg);
+    x86_code.push(instr2.clone());
+    
+    // Load the effective address
+    instr2 = create_x86instr(X86Type::Lea);
+    instr2.arg1 = X86Arg::Reg64(X86Reg::R14);
+    instr2.arg2 = X86Arg::ScaleMem(0, X86Reg::R15, size, is_pic);
+    x86_code.push(instr2.clone());
+    
+    // Load the array
+    instr2 = create_x86instr(X86Type::Mov);
+    instr2.arg1 = X86Arg::Reg64(X86Reg::R15);
+    instr2.arg2 = X86Arg::QwordMem(X86Reg::RBP, pos, is_pic);
+    x86_code.push(instr2.clone());
+    
+    // Add to get the proper offset
+    instr2 = create_x86instr(X86Type::Add);
+    instr2.arg1 = X86Arg::Reg64(X86Reg::R15);
+    instr2.arg2 = X86Arg::Reg64(X86Reg::R14);
+    x86_code.push(instr2.clone());
+}
+
 fn amd64_check_arg1(x86_code : &mut Vec<X86Instr>, arg1 : &LtacArg, offset : i32, is_pic : bool) -> X86Arg {
+    // The SSE instructions can read the element directly
+    match &arg1 {
+        LtacArg::FltReg(_p) => return X86Arg::DwordMem(X86Reg::R15, offset * -1, is_pic),
+        LtacArg::FltReg64(_p) => return X86Arg::QwordMem(X86Reg::R15, offset * -1, is_pic),
+        _ => {},
+    }
+    
     // Store
     let mut instr2 = create_x86instr(X86Type::Mov);
     let arg2 : X86Arg;
@@ -161,6 +210,9 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
             instr = create_x86instr(X86Type::Mov);
         },
         
+        LtacType::LdF32 | LtacType::StrF32 => instr = create_x86instr(X86Type::MovSS),
+        LtacType::LdF64 | LtacType::StrF64 => instr = create_x86instr(X86Type::MovSD),
+        
         LtacType::Mov | LtacType::MovU |
         LtacType::MovB | LtacType::MovUB |
         LtacType::MovW | LtacType::MovUW |
@@ -173,6 +225,7 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
                 LtacArg::MemOffsetImm(_p, _o) => instr = create_x86instr(X86Type::Mov),
                 LtacArg::MemOffsetMem(_p, _o, _s) |
                 LtacArg::MemOffsetReg(_p, _o, _s) => instr = create_x86instr(X86Type::Mov),
+                LtacArg::MemOffsetStride(_p, _r, _o, _t, _s) => instr = create_x86instr(X86Type::Mov),
                 _ => instr = create_x86instr(X86Type::MovSS),
             }
         },
@@ -181,6 +234,7 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
                 LtacArg::MemOffsetImm(_p, _o) => instr = create_x86instr(X86Type::Mov),
                 LtacArg::MemOffsetMem(_p, _o, _s) |
                 LtacArg::MemOffsetReg(_p, _o, _s) => instr = create_x86instr(X86Type::Mov),
+                LtacArg::MemOffsetStride(_p, _r, _o, _t, _s) => instr = create_x86instr(X86Type::Mov),
                 _ => instr = create_x86instr(X86Type::MovSD),
             }
         },
@@ -210,6 +264,13 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
         LtacType::F64Mul => instr = create_x86instr(X86Type::MulSD),
         LtacType::F64Div => instr = create_x86instr(X86Type::DivSD),
         
+        LtacType::CvtF32F64 => instr = create_x86instr(X86Type::Cvtss2sd),
+        LtacType::CvtF64F32 => instr = create_x86instr(X86Type::Cvtsd2ss),
+        LtacType::CvtIntF32 => instr = create_x86instr(X86Type::Cvtsi2ss),
+        LtacType::CvtIntF64 => instr = create_x86instr(X86Type::Cvtsi2sd),
+        LtacType::CvtF32Int => instr = create_x86instr(X86Type::Cvttss2si),
+        LtacType::CvtF64Int => instr = create_x86instr(X86Type::Cvttsd2si),
+        
         LtacType::And => instr = create_x86instr(X86Type::And),
         LtacType::Or => instr = create_x86instr(X86Type::Or),
         LtacType::Xor => instr = create_x86instr(X86Type::Xor),
@@ -233,6 +294,7 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
         LtacArg::Reg16(pos) => instr.arg1 = amd64_op_reg16(*pos),
         LtacArg::Reg32(pos) => instr.arg1 = amd64_op_reg32(*pos),
         LtacArg::Reg64(pos) => instr.arg1 = amd64_op_reg64(*pos),
+        LtacArg::FltReg(pos) | LtacArg::FltReg64(pos) => instr.arg1 = amd64_op_flt(*pos),
         
         LtacArg::RetRegI8 | LtacArg::RetRegU8 => instr.arg1 = X86Arg::Reg8(X86Reg::RAX),
         LtacArg::RetRegI16 | LtacArg::RetRegU16 => instr.arg1 = X86Arg::Reg16(X86Reg::RAX),
@@ -279,6 +341,7 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
                 LtacArg::I64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 LtacArg::U64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 LtacArg::Reg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::FltReg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 _ => instr.arg1 = X86Arg::DwordMem(X86Reg::R15, 0, is_pic),
             };
         },
@@ -297,6 +360,7 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
                 LtacArg::I64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 LtacArg::U64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 LtacArg::Reg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::FltReg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 _ => instr.arg1 = X86Arg::DwordMem(X86Reg::R15, 0, is_pic),
             }
         },
@@ -315,6 +379,26 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
                 LtacArg::I64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 LtacArg::U64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 LtacArg::Reg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::FltReg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
+                _ => instr.arg1 = X86Arg::DwordMem(X86Reg::R15, 0, is_pic),
+            }
+        },
+        
+        LtacArg::MemOffsetStride(pos, row, reg, stride, size) => {
+            amd64_build_offset_stride(x86_code, *pos, *row, *reg, *stride, *size, is_pic);
+            
+            // Now set up for the final move
+            match &code.arg2 {
+                LtacArg::Reg8(_v) => instr.arg1 = X86Arg::BwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::Reg16(_v) => instr.arg1 = X86Arg::WordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::Byte(_v) => instr.arg1 = X86Arg::BwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::UByte(_v) => instr.arg1 = X86Arg::BwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::I16(_v) => instr.arg1 = X86Arg::WordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::U16(_v) => instr.arg1 = X86Arg::WordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::I64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::U64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::Reg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
+                LtacArg::FltReg64(_v) => instr.arg1 = X86Arg::QwordMem(X86Reg::R15, 0, is_pic),
                 _ => instr.arg1 = X86Arg::DwordMem(X86Reg::R15, 0, is_pic),
             }
         },
@@ -328,6 +412,7 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
         LtacArg::Reg16(pos) => instr.arg2 = amd64_op_reg16(*pos),
         LtacArg::Reg32(pos) => instr.arg2 = amd64_op_reg32(*pos),
         LtacArg::Reg64(pos) => instr.arg2 = amd64_op_reg64(*pos),
+        LtacArg::FltReg(pos) | LtacArg::FltReg64(pos) => instr.arg2 = amd64_op_flt(*pos),
         
         LtacArg::RetRegI8 | LtacArg::RetRegU8 => instr.arg2 = X86Arg::Reg8(X86Reg::RAX),
         LtacArg::RetRegI16 | LtacArg::RetRegU16 => instr.arg2 = X86Arg::Reg16(X86Reg::RAX),
@@ -357,6 +442,11 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
             instr.arg2 = amd64_check_arg1(x86_code, &code.arg1, 0, is_pic);
         },
         
+        LtacArg::MemOffsetStride(pos, row, reg, stride, size) => {
+            amd64_build_offset_stride(x86_code, *pos, *row, *reg, *stride, *size, is_pic);
+            instr.arg2 = amd64_check_arg1(x86_code, &code.arg1, 0, is_pic);
+        },
+        
         LtacArg::Byte(val) => instr.arg2 = X86Arg::Imm32(*val as i32),
         LtacArg::UByte(val) => instr.arg2 = X86Arg::Imm32(*val as i32),
         
@@ -382,6 +472,11 @@ pub fn amd64_build_instr(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pi
             }
         },
         
+        LtacArg::PtrFunc(ref val) => instr.arg2 = X86Arg::FuncMem(val.to_string(), is_pic),
+        
+        // Float literals are read from the data section
+        LtacArg::F32(ref val) | LtacArg::F64(ref val) => instr.arg2 = X86Arg::LclMem(val.to_string(), true),
+        
         _ => {},
     }
     
@@ -436,65 +531,39 @@ pub fn amd64_build_imm(x86_code : &mut Vec<X86Instr>, val : i32, size : i32) ->
 
 // Builds the integer and modulus instructions
 // On x86 these are a little weird...
+// The dividend goes in rdx:rax (or ax for bytes), which has to be sign-extended for signed
+// division. idiv also traps on MIN / -1, so a divisor of -1 is handled without it: the
+// quotient is the negated dividend (wrapping), and the remainder is 0.
 pub fn amd64_build_div(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pic : bool) {
-    //Clear the RDX register
-    let mut xor = create_x86instr(X86Type::Xor);
-    xor.arg1 = X86Arg::Reg64(X86Reg::RDX);
-    xor.arg2 = X86Arg::Reg64(X86Reg::RDX);
-    x86_code.push(xor);
-
+    let is_signed = match code.instr_type {
+        LtacType::I8Div | LtacType::I8Mod
+        | LtacType::I16Div | LtacType::I16Mod
+        | LtacType::I32Div | LtacType::I32Mod
+        | LtacType::I64Div | LtacType::I64Mod => true,
+        
+        _ => false,
+    };
+    
+    let is_mod = match code.instr_type {
+        LtacType::I8Mod | LtacType::U8Mod
+        | LtacType::I16Mod | LtacType::U16Mod
+        | LtacType::I32Mod | LtacType::U32Mod
+        | LtacType::I64Mod | LtacType::U64Mod => true,
+        
+        _ => false,
+    };
+    
     // Create and build the instruction
     let mut instr = create_x86instr(X86Type::IDiv);
     let mut dest_instr = create_x86instr(X86Type::Mov);
     
-    match code.instr_type {
-        LtacType::U8Div | LtacType::U8Mod
-        | LtacType::U16Div | LtacType::U16Mod
-        | LtacType::U32Div | LtacType::U32Mod
-        | LtacType::U64Div | LtacType::U64Mod => instr = create_x86instr(X86Type::Div),
-        
-        _ => {},
+    if !is_signed {
+        instr = create_x86instr(X86Type::Div);
     }
     
-    match &code.arg1 {
-        LtacArg::Reg8(pos) => {
-            let mut instr2 = create_x86instr(X86Type::Mov);
-            instr2.arg1 = X86Arg::Reg8(X86Reg::RAX);
-            instr2.arg2 = amd64_op_reg8(*pos);
-            x86_code.push(instr2);
-            
-            dest_instr.arg1 = amd64_op_reg8(*pos);
-        },
-        
-        LtacArg::Reg16(pos) => {
-            let mut instr2 = create_x86instr(X86Type::Mov);
-            instr2.arg1 = X86Arg::Reg16(X86Reg::RAX);
-            instr2.arg2 = amd64_op_reg16(*pos);
-            x86_code.push(instr2);
-            
-            dest_instr.arg1 = amd64_op_reg16(*pos);
-        },
-        
-        LtacArg::Reg32(pos) => {
-            let mut instr2 = create_x86instr(X86Type::Mov);
-            instr2.arg1 = X86Arg::Reg32(X86Reg::RAX);
-            instr2.arg2 = amd64_op_reg32(*pos);
-            x86_code.push(instr2);
-            
-            dest_instr.arg1 = amd64_op_reg32(*pos);
-        },
-        
-        LtacArg::Reg64(pos) => {
-            let mut instr2 = create_x86instr(X86Type::Mov);
-            instr2.arg1 = X86Arg::Reg64(X86Reg::RAX);
-            instr2.arg2 = amd64_op_reg64(*pos);
-            x86_code.push(instr2);
-            
-            dest_instr.arg1 = amd64_op_reg64(*pos);
-        },
-        
-        _ => {},
-    }
+    // Find the divisor
+    // Immediates are known, so they are kept to decide what to build below
+    let mut imm : Option<i64> = None;
     
     match &code.arg2 {
         LtacArg::Reg8(pos) => instr.arg1 = amd64_op_reg8(*pos),
@@ -503,47 +572,170 @@ pub fn amd64_build_div(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pic
         LtacArg::Reg64(pos) => instr.arg1 = amd64_op_reg64(*pos),
         
         LtacArg::Mem(pos) => {
-            if code.instr_type == LtacType::I8Div || code.instr_type == LtacType::I8Mod {
-                instr.arg1 = X86Arg::BwordMem(X86Reg::RBP, *pos, is_pic);
-            } else if code.instr_type == LtacType::I16Div || code.instr_type == LtacType::I16Mod {
-                instr.arg1 = X86Arg::WordMem(X86Reg::RBP, *pos, is_pic);
-            } else if code.instr_type == LtacType::I64Div || code.instr_type == LtacType::I64Mod {
-                instr.arg1 = X86Arg::QwordMem(X86Reg::RBP, *pos, is_pic);
-            } else {
-                instr.arg1 = X86Arg::DwordMem(X86Reg::RBP, *pos, is_pic);
+            match code.instr_type {
+                LtacType::I8Div | LtacType::I8Mod
+                | LtacType::U8Div | LtacType::U8Mod => instr.arg1 = X86Arg::BwordMem(X86Reg::RBP, *pos, is_pic),
+                
+                LtacType::I16Div | LtacType::I16Mod
+                | LtacType::U16Div | LtacType::U16Mod => instr.arg1 = X86Arg::WordMem(X86Reg::RBP, *pos, is_pic),
+                
+                LtacType::I64Div | LtacType::I64Mod
+                | LtacType::U64Div | LtacType::U64Mod => instr.arg1 = X86Arg::QwordMem(X86Reg::RBP, *pos, is_pic),
+                
+                _ => instr.arg1 = X86Arg::DwordMem(X86Reg::RBP, *pos, is_pic),
             }
         },
         
-        LtacArg::Byte(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 8),
-        LtacArg::UByte(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 8),
-        
-        LtacArg::I16(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 16),
-        LtacArg::U16(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 16),
-        
-        LtacArg::I32(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 32),
-        LtacArg::U32(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 32),
-        
-        LtacArg::I64(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 64),
-        LtacArg::U64(val) => instr.arg1 = amd64_build_imm(x86_code, *val as i32, 64),
+        LtacArg::Byte(val) => imm = Some(*val as i64),
+        LtacArg::UByte(val) => imm = Some(*val as i64),
+        LtacArg::I16(val) => imm = Some(*val as i64),
+        LtacArg::U16(val) => imm = Some(*val as i64),
+        LtacArg::I32(val) => imm = Some(*val as i64),
+        LtacArg::U32(val) => imm = Some(*val as i64),
+        LtacArg::I64(val) => imm = Some(*val),
+        LtacArg::U64(val) => imm = Some(*val as i64),
         
         _ => {},
     }
     
-    match &code.instr_type {
-        LtacType::I8Div | LtacType::U8Div => dest_instr.arg2 = X86Arg::Reg8(X86Reg::AL),
-        LtacType::I16Div | LtacType::U16Div => dest_instr.arg2 = X86Arg::Reg16(X86Reg::RAX),
-        LtacType::I32Div | LtacType::U32Div => dest_instr.arg2 = X86Arg::Reg32(X86Reg::RAX),
-        LtacType::I64Div | LtacType::U64Div => dest_instr.arg2 = X86Arg::Reg64(X86Reg::RAX),
+    // With division checks, a zero divisor jumps to the panic
+    if !code.name.is_empty() {
+        if imm == Some(0) {
+            let mut jmp = create_x86instr(X86Type::Jmp);
+            jmp.name = code.name.clone();
+            x86_code.push(jmp);
+        } else if imm.is_none() {
+            let mut cmp = create_x86instr(X86Type::Cmp);
+            cmp.arg1 = instr.arg1.clone();
+            cmp.arg2 = X86Arg::Imm32(0);
+            x86_code.push(cmp);
+            
+            let mut je = create_x86instr(X86Type::Je);
+            je.name = code.name.clone();
+            x86_code.push(je);
+        }
+    }
+    
+    // Load the dividend
+    let (dest, size) = match &code.arg1 {
+        LtacArg::Reg8(pos) => (amd64_op_reg8(*pos), 8),
+        LtacArg::Reg16(pos) => (amd64_op_reg16(*pos), 16),
+        LtacArg::Reg32(pos) => (amd64_op_reg32(*pos), 32),
+        LtacArg::Reg64(pos) => (amd64_op_reg64(*pos), 64),
+        _ => return,
+    };
+    
+    dest_instr.arg1 = dest.clone();
+    
+    if size == 8 {
+        let mut instr2 = create_x86instr(X86Type::MovZX);
+        if is_signed {
+            instr2 = create_x86instr(X86Type::MovSX);
+        }
+        instr2.arg1 = X86Arg::Reg16(X86Reg::RAX);
+        instr2.arg2 = dest;
+        x86_code.push(instr2);
+    } else {
+        let mut instr2 = create_x86instr(X86Type::Mov);
+        instr2.arg2 = dest;
+        
+        instr2.arg1 = match size {
+            16 => X86Arg::Reg16(X86Reg::RAX),
+            32 => X86Arg::Reg32(X86Reg::RAX),
+            _ => X86Arg::Reg64(X86Reg::RAX),
+        };
+        x86_code.push(instr2);
+        
+        if is_signed {
+            match size {
+                16 => x86_code.push(create_x86instr(X86Type::Cwd)),
+                32 => x86_code.push(create_x86instr(X86Type::Cdq)),
+                _ => x86_code.push(create_x86instr(X86Type::Cqo)),
+            }
+        } else {
+            let mut xor = create_x86instr(X86Type::Xor);
+            xor.arg1 = X86Arg::Reg32(X86Reg::RDX);
+            xor.arg2 = X86Arg::Reg32(X86Reg::RDX);
+            x86_code.push(xor);
+        }
+    }
+    
+    // The byte remainder is in ah, which can't be moved to every register, so it is
+    // shifted down into al
+    let result = match size {
+        8 => X86Arg::Reg8(X86Reg::AL),
+        16 if is_mod => X86Arg::Reg16(X86Reg::RDX),
+        16 => X86Arg::Reg16(X86Reg::RAX),
+        32 if is_mod => X86Arg::Reg32(X86Reg::RDX),
+        32 => X86Arg::Reg32(X86Reg::RAX),
+        _ if is_mod => X86Arg::Reg64(X86Reg::RDX),
+        _ => X86Arg::Reg64(X86Reg::RAX),
+    };
+    dest_instr.arg2 = result.clone();
+    
+    let mut minus_one = create_x86instr(X86Type::Neg);
+    minus_one.arg1 = result.clone();
+    
+    if is_mod {
+        minus_one = create_x86instr(X86Type::Mov);
+        minus_one.arg1 = result;
+        minus_one.arg2 = X86Arg::Imm32(0);
+    }
+    
+    if is_signed && imm == Some(-1) {
+        x86_code.push(minus_one);
+        x86_code.push(dest_instr);
+        return;
+    }
+    
+    if let Some(val) = imm {
+        instr.arg1 = amd64_build_imm(x86_code, val as i32, size);
+    }
+    
+    // cmp divisor, -1
+    // jne 1f
+    // neg rax / mov rdx, 0
+    // jmp 2f
+    // 1: idiv divisor
+    // 2: mov dest, rax / rdx
+    let is_checked = is_signed && imm.is_none();
+    
+    if is_checked {
+        let mut cmp = create_x86instr(X86Type::Cmp);
+        cmp.arg1 = instr.arg1.clone();
+        cmp.arg2 = X86Arg::Imm32(-1);
+        x86_code.push(cmp);
         
-        LtacType::I8Mod | LtacType::U8Mod => dest_instr.arg2 = X86Arg::Reg8(X86Reg::AH),
-        LtacType::I16Mod | LtacType::U16Mod => dest_instr.arg2 = X86Arg::Reg16(X86Reg::RDX),
-        LtacType::I32Mod | LtacType::U32Mod => dest_instr.arg2 = X86Arg::Reg32(X86Reg::RDX),
-        LtacType::I64Mod | LtacType::U64Mod => dest_instr.arg2 = X86Arg::Reg64(X86Reg::RDX),
+        let mut jne = create_x86instr(X86Type::Jne);
+        jne.name = "1f".to_string();
+        x86_code.push(jne);
         
-        _ => {},
+        x86_code.push(minus_one);
+        
+        let mut jmp = create_x86instr(X86Type::Jmp);
+        jmp.name = "2f".to_string();
+        x86_code.push(jmp);
+        
+        let mut lbl = create_x86instr(X86Type::Label);
+        lbl.name = "1".to_string();
+        x86_code.push(lbl);
     }
     
     x86_code.push(instr);
+    
+    if size == 8 && is_mod {
+        let mut shr = create_x86instr(X86Type::Shr);
+        shr.arg1 = X86Arg::Reg16(X86Reg::RAX);
+        shr.arg2 = X86Arg::Imm32(8);
+        x86_code.push(shr);
+    }
+    
+    if is_checked {
+        let mut lbl = create_x86instr(X86Type::Label);
+        lbl.name = "2".to_string();
+        x86_code.push(lbl);
+    }
+    
     x86_code.push(dest_instr);
 }
 
@@ -594,3 +786,55 @@ pub fn amd64_build_byte_mul(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is
     x86_code.push(dest_instr);
 }
 
+// Builds a checked unsigned multiplication
+// mul leaves the upper half of the result in rdx and sets the carry if it isn't zero
+pub fn amd64_build_unsigned_mul(x86_code : &mut Vec<X86Instr>, code : &LtacInstr, is_pic : bool) {
+    let (ret_reg, dest) = match &code.arg1 {
+        LtacArg::Reg16(pos) => (LtacArg::RetRegU16, amd64_op_reg16(*pos)),
+        LtacArg::Reg32(pos) => (LtacArg::RetRegU32, amd64_op_reg32(*pos)),
+        LtacArg::Reg64(pos) => (LtacArg::RetRegU64, amd64_op_reg64(*pos)),
+        _ => return,
+    };
+    
+    // Load the second operand into rax
+    let mut mov = code.clone();
+    mov.instr_type = LtacType::MovU;
+    mov.arg1 = ret_reg;
+    mov.name = String::new();
+    amd64_build_instr(x86_code, &mov, is_pic);
+    
+    let mut instr = create_x86instr(X86Type::Mul);
+    instr.arg1 = dest.clone();
+    x86_code.push(instr);
+    
+    let mut instr2 = create_x86instr(X86Type::Mov);
+    instr2.arg1 = dest.clone();
+    instr2.arg2 = match dest {
+        X86Arg::Reg16(_) => X86Arg::Reg16(X86Reg::RAX),
+        X86Arg::Reg32(_) => X86Arg::Reg32(X86Reg::RAX),
+        _ => X86Arg::Reg64(X86Reg::RAX),
+    };
+    x86_code.push(instr2);
+}
+
+// Builds the jump to the overflow panic after checked arithmetic
+// Signed overflow sets the overflow flag, and unsigned overflow sets the carry
+pub fn amd64_build_overflow_jump(x86_code : &mut Vec<X86Instr>, code : &LtacInstr) {
+    let instr_type = match &code.instr_type {
+        LtacType::I8Add | LtacType::I8Sub | LtacType::I8Mul |
+        LtacType::I16Add | LtacType::I16Sub | LtacType::I16Mul |
+        LtacType::I32Add | LtacType::I32Sub | LtacType::I32Mul |
+        LtacType::I64Add | LtacType::I64Sub | LtacType::I64Mul => X86Type::Jo,
+        
+        LtacType::U8Add | LtacType::U8Mul |
+        LtacType::U16Add | LtacType::U16Mul |
+        LtacType::U32Add | LtacType::U32Mul |
+        LtacType::U64Add | LtacType::U64Mul => X86Type::Jb,
+        
+        _ => return,
+    };
+    
+    let mut instr = create_x86instr(instr_type);
+    instr.name = code.name.clone();
+    x86_code.push(instr);
+}
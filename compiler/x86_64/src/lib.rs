@@ -23,17 +23,13 @@ use func::*;
 use instr::*;
 
 // The entry point
-pub fn compile(ltac_file : &LtacFile, pic : bool) -> io::Result<()> {
+pub fn compile(ltac_file : &LtacFile, name : &String, pic : bool, omit_fp : bool) -> io::Result<()> {
     // First, translate
     let mut x86_code : Vec<X86Instr> = Vec::new();
-    translate_code(&mut x86_code, &ltac_file.code, pic);
+    translate_code(&mut x86_code, &ltac_file.code, pic, omit_fp);
     
     // Write it out
-    let mut name = "/tmp/".to_string();
-    name.push_str(&ltac_file.name);
-    name.push_str(".asm");
-    
-    let file = File::create(&name)?;
+    let file = File::create(name)?;
     let mut writer = BufWriter::new(file);
     
     //GNU AS specific
@@ -75,6 +71,21 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>, pic : bool)
                 line.push_str(": .quad ");
                 line.push_str(&data.val);
                 line.push_str("\n");
+            },            
+            // Array literals are read-only, so they go in .rodata
+            LtacDataType::ArrayL(size) => {
+                line.push_str(".pushsection .rodata\n.balign 8\n");
+                line.push_str(&data.name);
+                
+                match size {
+                    1 => line.push_str(": .byte "),
+                    2 => line.push_str(": .2byte "),
+                    8 => line.push_str(": .8byte "),
+                    _ => line.push_str(": .4byte "),
+                }
+                
+                line.push_str(&data.val);
+                line.push_str("\n.popsection\n");
             },
         }
     }
@@ -86,20 +97,32 @@ fn write_data(writer : &mut BufWriter<File>, data : &Vec<LtacData>, pic : bool)
 }
 
 // Translates the LTAC code section to x86 code
-fn translate_code(x86_code : &mut Vec<X86Instr>, code : &Vec<LtacInstr>, is_pic : bool) {
+fn translate_code(x86_code : &mut Vec<X86Instr>, code : &Vec<LtacInstr>, is_pic : bool, omit_fp : bool) {
+    let mut func_start : Option<usize> = None;
+    let mut flt_args = 0;
+    
     for code in code.iter() {
         match &code.instr_type {
             LtacType::Extern => amd64_build_extern(x86_code, &code),
             LtacType::Label => amd64_build_label(x86_code, &code),
-            LtacType::Func => amd64_build_func(x86_code, &code, is_pic),
+            
+            // The frame of the previous function can be finished now that we have all its code
+            LtacType::Func => {
+                if let Some(start) = func_start {
+                    amd64_build_frame(x86_code, start, omit_fp, is_pic);
+                }
+                
+                func_start = Some(x86_code.len());
+                amd64_build_func(x86_code, &code, is_pic);
+            },
+            
             LtacType::Ret => amd64_build_ret(x86_code),
             
             LtacType::LdArgI8 | LtacType::LdArgU8 => amd64_build_ldarg(x86_code, &code, is_pic),
             LtacType::LdArgI16 | LtacType::LdArgU16 => amd64_build_ldarg(x86_code, &code, is_pic),
             LtacType::LdArgI32 | LtacType::LdArgU32 => amd64_build_ldarg(x86_code, &code, is_pic),
             LtacType::LdArgI64 | LtacType::LdArgU64 => amd64_build_ldarg(x86_code, &code, is_pic),
-            //LtacType::LdArgF32 => amd64_build_ldarg_float(writer, &code),
-            //LtacType::LdArgF64 => amd64_build_ldarg_float(writer, &code),
+            LtacType::LdArgF32 | LtacType::LdArgF64 => amd64_build_ldarg(x86_code, &code, is_pic),
             LtacType::LdArgPtr => amd64_build_ldarg(x86_code, &code, is_pic),
             
             // TODO: Combine this to reduce lines
@@ -110,14 +133,32 @@ fn translate_code(x86_code : &mut Vec<X86Instr>, code : &Vec<LtacInstr>, is_pic
             LtacType::Bg | LtacType::Bge => amd64_build_jump(x86_code, &code),
             LtacType::Bfg | LtacType::Bfge => amd64_build_jump(x86_code, &code),
             
-            LtacType::PushArg => amd64_build_pusharg(x86_code, &code, false, is_pic),
+            LtacType::PushArg => {
+                if amd64_is_float_arg(&code) {
+                    flt_args = code.arg2_val;
+                }
+                
+                amd64_build_pusharg(x86_code, &code, false, is_pic);
+            },
+            
             LtacType::KPushArg => amd64_build_pusharg(x86_code, &code, true, is_pic),
-            LtacType::Call => amd64_build_call(x86_code, &code),
+            
+            LtacType::Call => {
+                amd64_build_call(x86_code, &code, flt_args);
+                flt_args = 0;
+            },
+            
+            LtacType::CallReg => amd64_build_call_reg(x86_code, &code, is_pic),
             LtacType::Syscall => amd64_build_syscall(x86_code),
             
             LtacType::StrCmp => amd64_build_strcmp(x86_code),
             
             LtacType::I8Mul | LtacType::U8Mul => amd64_build_byte_mul(x86_code, &code, is_pic),
+            
+            // imul only sets the carry for signed overflow, so checked unsigned multiplication uses mul
+            LtacType::U16Mul | LtacType::U32Mul |
+            LtacType::U64Mul if !code.name.is_empty() => amd64_build_unsigned_mul(x86_code, &code, is_pic),
+            
             LtacType::I8Div | LtacType::I8Mod |
             LtacType::U8Div | LtacType::U8Mod => amd64_build_div(x86_code, &code, is_pic),
             
@@ -133,6 +174,15 @@ fn translate_code(x86_code : &mut Vec<X86Instr>, code : &Vec<LtacInstr>, is_pic
             // Everything else uses the common build instruction function
             _ => amd64_build_instr(x86_code, &code, is_pic),
         }
+        
+        // Checked arithmetic jumps to the overflow panic
+        if !code.name.is_empty() {
+            amd64_build_overflow_jump(x86_code, &code);
+        }
+    }
+    
+    if let Some(start) = func_start {
+        amd64_build_frame(x86_code, start, omit_fp, is_pic);
     }
 }
 
@@ -152,14 +202,17 @@ fn write_code(writer : &mut BufWriter<File>, code : &Vec<X86Instr>) {
             | X86Type::Jg | X86Type::Jge
             | X86Type::Ja | X86Type::Jae
             | X86Type::Jb | X86Type::Jbe
+            | X86Type::Jo
             | X86Type::Call => amd64_write_named(writer, &code),
             
             X86Type::Leave | X86Type::Ret 
-            | X86Type::Syscall => amd64_write_instr(writer, &code, 0),
+            | X86Type::Syscall
+            | X86Type::Cwd | X86Type::Cdq | X86Type::Cqo => amd64_write_instr(writer, &code, 0),
             
-            X86Type::Push
+            X86Type::Push | X86Type::CallReg
             | X86Type::IMul8 | X86Type::Mul8
-            | X86Type::IDiv | X86Type::Div => amd64_write_instr(writer, &code, 1),
+            | X86Type::Mul | X86Type::IDiv | X86Type::Div
+            | X86Type::Neg => amd64_write_instr(writer, &code, 1),
             
             _ => amd64_write_instr(writer, &code, 2),
         }
@@ -188,6 +241,7 @@ fn amd64_write_named(writer : &mut BufWriter<File>, code : &X86Instr) {
         X86Type::Jae => line.push_str("  jae "),
         X86Type::Jb => line.push_str("  jb "),
         X86Type::Jbe => line.push_str("  jbe "),
+        X86Type::Jo => line.push_str("  jo "),
         
         _ => {},
     }
@@ -216,10 +270,13 @@ fn amd64_write_instr(writer : &mut BufWriter<File>, code : &X86Instr, op_count :
         X86Type::Syscall => line.push_str("syscall"),
         
         X86Type::Push => line.push_str("push"),
+        X86Type::CallReg => line.push_str("call"),
         X86Type::Lea => line.push_str("lea"),
         X86Type::Mov => line.push_str("mov"),
         X86Type::MovZX => line.push_str("movzx"),
         X86Type::MovSX => line.push_str("movsx"),
+        X86Type::MovSS => line.push_str("movss"),
+        X86Type::MovSD => line.push_str("movsd"),
         
         X86Type::Add => line.push_str("add"),
         X86Type::Sub => line.push_str("sub"),
@@ -227,6 +284,10 @@ fn amd64_write_instr(writer : &mut BufWriter<File>, code : &X86Instr, op_count :
         X86Type::Mul | X86Type::Mul8 => line.push_str("mul"),
         X86Type::IDiv => line.push_str("idiv"),
         X86Type::Div => line.push_str("div"),
+        X86Type::Neg => line.push_str("neg"),
+        X86Type::Cwd => line.push_str("cwd"),
+        X86Type::Cdq => line.push_str("cdq"),
+        X86Type::Cqo => line.push_str("cqo"),
         
         X86Type::And => line.push_str("and"),
         X86Type::Or => line.push_str("or"),
@@ -234,7 +295,26 @@ fn amd64_write_instr(writer : &mut BufWriter<File>, code : &X86Instr, op_count :
         X86Type::Shl => line.push_str("shl"),
         X86Type::Shr => line.push_str("shr"),
         
+        X86Type::AddSS => line.push_str("addss"),
+        X86Type::SubSS => line.push_str("subss"),
+        X86Type::MulSS => line.push_str("mulss"),
+        X86Type::DivSS => line.push_str("divss"),
+        
+        X86Type::AddSD => line.push_str("addsd"),
+        X86Type::SubSD => line.push_str("subsd"),
+        X86Type::MulSD => line.push_str("mulsd"),
+        X86Type::DivSD => line.push_str("divsd"),
+        
+        X86Type::Cvtss2sd => line.push_str("cvtss2sd"),
+        X86Type::Cvtsd2ss => line.push_str("cvtsd2ss"),
+        X86Type::Cvtsi2ss => line.push_str("cvtsi2ss"),
+        X86Type::Cvtsi2sd => line.push_str("cvtsi2sd"),
+        X86Type::Cvttss2si => line.push_str("cvttss2si"),
+        X86Type::Cvttsd2si => line.push_str("cvttsd2si"),
+        
         X86Type::Cmp => line.push_str("cmp"),
+        X86Type::Ucomiss => line.push_str("ucomiss"),
+        X86Type::Ucomisd => line.push_str("ucomisd"),
         
         _ => {},
     }
@@ -324,6 +404,11 @@ fn amd64_write_operand(arg : &X86Arg) -> String {
              line.push_str(&reg_str);
         },
         
+        X86Arg::Xmm(pos) => {
+            line.push_str("xmm");
+            line.push_str(&pos.to_string());
+        },
+        
         X86Arg::Imm32(val) => line.push_str(&val.to_string()),
         X86Arg::Imm64(val) => line.push_str(&val.to_string()),
         
@@ -362,6 +447,18 @@ fn amd64_write_operand(arg : &X86Arg) -> String {
             }
         },
         
+        // With PIC, function addresses have to be loaded from the GOT
+        X86Arg::FuncMem(ref val, is_pic) => {
+            if *is_pic {
+                line.push_str("QWORD PTR ");
+                line.push_str(&val);
+                line.push_str("@GOTPCREL[rip]");
+            } else {
+                line.push_str("OFFSET FLAT:");
+                line.push_str(&val);
+            }
+        },
+        
         X86Arg::ScaleMem(base, reg, scale, is_pic) => {
             let reg_str = reg2str(&reg, 64);
             
@@ -0,0 +1,240 @@
+//
+// The memory allocator for the core library (AArch64)
+//
+// This is the same allocator as mem_x86_64.asm. Small requests are served
+// from eight size classes, from 16 to 2048 bytes (including the header). Each
+// class has a free list, and new blocks are carved out of 1MB chunks that we
+// get from mmap. Larger requests are mapped directly.
+//
+// Every block begins with an 8-byte header that holds the size of the block.
+// Free blocks keep the next pointer of their free list right after the header.
+//
+// memcpy is also here, since realloc uses it, and the compiler uses it to copy
+// array literals.
+//
+
+.set CHUNK_SIZE, 1048576
+.set MAX_SMALL, 2048
+
+.bss
+.balign 8
+free_lists: .zero 64
+heap_cur: .zero 8
+heap_end: .zero 8
+
+.text
+
+// Maps a block of memory
+// x0 -> size
+// Returns the address, or 0 if the system call failed
+mem_map:
+    mov x1, x0
+    mov x0, 0
+    mov x2, 3
+    mov x3, 34
+    mov x4, -1
+    mov x5, 0
+    mov x8, 222
+    svc 0
+    cmn x0, 4096
+    b.ls 1f
+    mov x0, 0
+1:
+    ret
+
+// Allocate memory
+// x0 -> size
+.global malloc
+malloc:
+ida_malloc:
+    adds x9, x0, 8
+    b.cs .Lmalloc_fail
+    cmp x9, MAX_SMALL
+    b.hi .Lmalloc_large
+
+    // Find the size class
+    mov x10, 16
+    mov x11, 0
+.Lmalloc_class:
+    cmp x10, x9
+    b.hs .Lmalloc_small
+    lsl x10, x10, 1
+    add x11, x11, 1
+    b .Lmalloc_class
+
+    // Take a block from the free list if we can
+.Lmalloc_small:
+    adrp x12, free_lists
+    add x12, x12, :lo12:free_lists
+    ldr x0, [x12, x11, lsl 3]
+    cbz x0, .Lmalloc_carve
+    ldr x13, [x0, 8]
+    str x13, [x12, x11, lsl 3]
+    add x0, x0, 8
+    ret
+
+    // Otherwise, carve one out of the current chunk
+    // heap_end comes right after heap_cur, so they are loaded together
+.Lmalloc_carve:
+    adrp x12, heap_cur
+    add x12, x12, :lo12:heap_cur
+    ldp x0, x13, [x12]
+    add x14, x0, x10
+    cmp x14, x13
+    b.hi .Lmalloc_grow
+    str x14, [x12]
+    str x10, [x0]
+    add x0, x0, 8
+    ret
+
+    // The chunk is full (or we don't have one yet), so map a new one
+.Lmalloc_grow:
+    stp x29, x30, [sp, -32]!
+    str x10, [sp, 16]
+    mov x0, CHUNK_SIZE
+    bl mem_map
+    ldr x10, [sp, 16]
+    ldp x29, x30, [sp], 32
+    cbz x0, .Lmalloc_done
+    adrp x12, heap_cur
+    add x12, x12, :lo12:heap_cur
+    mov x13, CHUNK_SIZE
+    add x13, x0, x13
+    add x14, x0, x10
+    stp x14, x13, [x12]
+    str x10, [x0]
+    add x0, x0, 8
+    ret
+
+    // Large blocks are rounded to the page size and mapped directly
+.Lmalloc_large:
+    adds x9, x9, 4095
+    b.cs .Lmalloc_fail
+    and x9, x9, -4096
+    stp x29, x30, [sp, -32]!
+    str x9, [sp, 16]
+    mov x0, x9
+    bl mem_map
+    ldr x9, [sp, 16]
+    ldp x29, x30, [sp], 32
+    cbz x0, .Lmalloc_done
+    str x9, [x0]
+    add x0, x0, 8
+.Lmalloc_done:
+    ret
+
+    // The size was too big to add the header or round up
+.Lmalloc_fail:
+    mov x0, 0
+    ret
+
+// Free memory
+// x0 -> address
+.global free
+free:
+ida_free:
+    cbz x0, .Lfree_done
+    sub x9, x0, 8
+    ldr x1, [x9]
+    cmp x1, MAX_SMALL
+    b.hi .Lfree_large
+
+    // Push the block onto the free list of its class
+    // The class is log2(size) - 4, which is 59 - clz(size)
+    clz x10, x1
+    mov x11, 59
+    sub x10, x11, x10
+    adrp x12, free_lists
+    add x12, x12, :lo12:free_lists
+    ldr x13, [x12, x10, lsl 3]
+    str x13, [x9, 8]
+    str x9, [x12, x10, lsl 3]
+.Lfree_done:
+    ret
+
+.Lfree_large:
+    mov x0, x9
+    mov x8, 215
+    svc 0
+    ret
+
+// Resize memory
+// x0 -> address
+// x1 -> new size
+// If the block is already big enough, it is returned as is
+.global realloc
+realloc:
+    cbnz x0, 1f
+    mov x0, x1
+    b ida_malloc
+1:
+    ldur x9, [x0, -8]
+    sub x9, x9, 8
+    cmp x1, x9
+    b.hi .Lrealloc_move
+    ret
+
+.Lrealloc_move:
+    stp x29, x30, [sp, -48]!
+    stp x19, x20, [sp, 16]
+    str x21, [sp, 32]
+    mov x19, x0
+    mov x20, x9
+    mov x0, x1
+    bl ida_malloc
+    cbz x0, .Lrealloc_done
+    mov x21, x0
+    mov x1, x19
+    mov x2, x20
+    bl memcpy
+    mov x0, x19
+    bl ida_free
+    mov x0, x21
+.Lrealloc_done:
+    ldr x21, [sp, 32]
+    ldp x19, x20, [sp, 16]
+    ldp x29, x30, [sp], 48
+    ret
+
+// Allocate zeroed memory
+// x0 -> number of elements
+// x1 -> element size
+.global calloc
+calloc:
+    umulh x9, x0, x1
+    cbnz x9, .Lcalloc_fail
+    mul x0, x0, x1
+    stp x29, x30, [sp, -32]!
+    str x0, [sp, 16]
+    bl ida_malloc
+    ldr x2, [sp, 16]
+    ldp x29, x30, [sp], 32
+    cbz x0, .Lcalloc_done
+    mov x9, x0
+1:
+    cbz x2, .Lcalloc_done
+    strb wzr, [x9], 1
+    sub x2, x2, 1
+    b 1b
+.Lcalloc_done:
+    ret
+
+.Lcalloc_fail:
+    mov x0, 0
+    ret
+
+// Copy memory
+// x0 -> destination
+// x1 -> source
+// x2 -> number of bytes
+.global memcpy
+memcpy:
+    mov x9, x0
+1:
+    cbz x2, 2f
+    ldrb w10, [x1], 1
+    strb w10, [x9], 1
+    sub x2, x2, 1
+    b 1b
+2:
+    ret
@@ -0,0 +1,230 @@
+#
+# The memory allocator for the core library (x86-64)
+#
+# Small requests are served from eight size classes, from 16 to 2048 bytes
+# (including the header). Each class has a free list, and new blocks are
+# carved out of 1MB chunks that we get from mmap. Larger requests are mapped
+# directly.
+#
+# Every block begins with an 8-byte header that holds the size of the block.
+# Free blocks keep the next pointer of their free list right after the header.
+#
+# memcpy is also here, since the compiler uses it to copy array literals.
+#
+.intel_syntax noprefix
+
+.set CHUNK_SIZE, 1048576
+.set MAX_SMALL, 2048
+
+.bss
+.align 8
+free_lists: .zero 64
+heap_cur: .zero 8
+heap_end: .zero 8
+
+.text
+
+# Maps a block of memory
+# rdi -> size
+# Returns the address, or 0 if the system call failed
+mem_map:
+    mov rsi, rdi
+    mov eax, 9
+    xor edi, edi
+    mov edx, 3
+    mov r10d, 34
+    mov r8, -1
+    xor r9d, r9d
+    syscall
+    cmp rax, -4096
+    jbe 1f
+    xor eax, eax
+1:
+    ret
+
+# Allocate memory
+# rdi -> size
+.global malloc
+malloc:
+ida_malloc:
+    mov rax, rdi
+    add rax, 8
+    jc .Lmalloc_fail
+    cmp rax, MAX_SMALL
+    ja .Lmalloc_large
+
+    # Find the size class
+    mov ecx, 16
+    xor edx, edx
+.Lmalloc_class:
+    cmp rcx, rax
+    jae .Lmalloc_small
+    shl rcx, 1
+    inc edx
+    jmp .Lmalloc_class
+
+    # Take a block from the free list if we can
+.Lmalloc_small:
+    lea r8, [rip+free_lists]
+    mov rax, [r8+rdx*8]
+    test rax, rax
+    jz .Lmalloc_carve
+    mov r9, [rax+8]
+    mov [r8+rdx*8], r9
+    add rax, 8
+    ret
+
+    # Otherwise, carve one out of the current chunk
+.Lmalloc_carve:
+    mov rax, [rip+heap_cur]
+    lea r9, [rax+rcx]
+    cmp r9, [rip+heap_end]
+    ja .Lmalloc_grow
+    mov [rip+heap_cur], r9
+    mov [rax], rcx
+    add rax, 8
+    ret
+
+    # The chunk is full (or we don't have one yet), so map a new one
+.Lmalloc_grow:
+    push rcx
+    mov edi, CHUNK_SIZE
+    call mem_map
+    pop rcx
+    test rax, rax
+    jz .Lmalloc_done
+    lea r9, [rax+CHUNK_SIZE]
+    mov [rip+heap_end], r9
+    lea r9, [rax+rcx]
+    mov [rip+heap_cur], r9
+    mov [rax], rcx
+    add rax, 8
+    ret
+
+    # Large blocks are rounded to the page size and mapped directly
+.Lmalloc_large:
+    add rax, 4095
+    jc .Lmalloc_fail
+    and rax, -4096
+    push rax
+    mov rdi, rax
+    call mem_map
+    pop rcx
+    test rax, rax
+    jz .Lmalloc_done
+    mov [rax], rcx
+    add rax, 8
+.Lmalloc_done:
+    ret
+
+    # The size was too big to add the header or round up
+.Lmalloc_fail:
+    xor eax, eax
+    ret
+
+# Free memory
+# rdi -> address
+.global free
+free:
+ida_free:
+    test rdi, rdi
+    jz .Lfree_done
+    lea rax, [rdi-8]
+    mov rsi, [rax]
+    cmp rsi, MAX_SMALL
+    ja .Lfree_large
+
+    # Push the block onto the free list of its class
+    bsr rcx, rsi
+    sub ecx, 4
+    lea r8, [rip+free_lists]
+    mov rdx, [r8+rcx*8]
+    mov [rax+8], rdx
+    mov [r8+rcx*8], rax
+.Lfree_done:
+    ret
+
+.Lfree_large:
+    mov rdi, rax
+    mov eax, 11
+    syscall
+    ret
+
+# Resize memory
+# rdi -> address
+# rsi -> new size
+# If the block is already big enough, it is returned as is
+.global realloc
+realloc:
+    test rdi, rdi
+    jnz 1f
+    mov rdi, rsi
+    jmp ida_malloc
+1:
+    mov rax, [rdi-8]
+    sub rax, 8
+    cmp rsi, rax
+    ja .Lrealloc_move
+    mov rax, rdi
+    ret
+
+.Lrealloc_move:
+    push rbx
+    push r12
+    push r13
+    mov rbx, rdi
+    mov r12, rax
+    mov rdi, rsi
+    call ida_malloc
+    test rax, rax
+    jz .Lrealloc_done
+    mov r13, rax
+    mov rdi, rax
+    mov rsi, rbx
+    mov rcx, r12
+    rep movsb
+    mov rdi, rbx
+    call ida_free
+    mov rax, r13
+.Lrealloc_done:
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+# Allocate zeroed memory
+# rdi -> number of elements
+# rsi -> element size
+.global calloc
+calloc:
+    mov rax, rdi
+    mul rsi
+    jo .Lcalloc_fail
+    push rax
+    mov rdi, rax
+    call ida_malloc
+    pop rcx
+    test rax, rax
+    jz .Lcalloc_done
+    mov rdx, rax
+    mov rdi, rax
+    xor eax, eax
+    rep stosb
+    mov rax, rdx
+.Lcalloc_done:
+    ret
+
+.Lcalloc_fail:
+    xor eax, eax
+    ret
+
+# Copy memory
+# rdi -> destination
+# rsi -> source
+# rdx -> number of bytes
+.global memcpy
+memcpy:
+    mov rax, rdi
+    mov rcx, rdx
+    rep movsb
+    ret
@@ -0,0 +1,170 @@
+//
+// Runtime error handlers for the core library (AArch64)
+//
+// The compiler emits calls to these for the panic statement, failed asserts,
+// and failed runtime checks. They print the source location and what happened
+// to stderr, then exit with code 101.
+//
+
+.set STDERR, 2
+.set PANIC_CODE, 101
+
+.section .rodata
+msg_colon: .string ":"
+msg_sep: .string ": "
+msg_assert: .string "assertion failed: "
+msg_bounds1: .string "index out of bounds: the index is "
+msg_bounds2: .string " but the length is "
+msg_overflow: .string "arithmetic overflow"
+msg_div_zero: .string "division by zero"
+msg_newline: .string "\n"
+msg_empty: .string ""
+
+.text
+
+// Loads the address of a message
+.macro load_msg reg, msg
+    adrp \reg, \msg
+    add \reg, \reg, :lo12:\msg
+.endm
+
+// Writes a string to stderr
+// x0 -> string
+write_str:
+    mov x1, x0
+    mov x2, 0
+1:
+    ldrb w3, [x1, x2]
+    cbz w3, 2f
+    add x2, x2, 1
+    b 1b
+2:
+    mov x0, STDERR
+    mov x8, 64
+    svc 0
+    ret
+
+// Writes a signed integer to stderr
+// w0 -> value
+write_int:
+    sub sp, sp, 32
+    sxtw x3, w0
+    cmp x3, 0
+    cneg x4, x3, lt
+    add x1, sp, 32
+    mov x5, 10
+1:
+    udiv x6, x4, x5
+    msub x7, x6, x5, x4
+    add w7, w7, '0'
+    sub x1, x1, 1
+    strb w7, [x1]
+    mov x4, x6
+    cbnz x4, 1b
+
+    cmp x3, 0
+    b.ge 2f
+    mov w7, '-'
+    sub x1, x1, 1
+    strb w7, [x1]
+2:
+    add x2, sp, 32
+    sub x2, x2, x1
+    mov x0, STDERR
+    mov x8, 64
+    svc 0
+    add sp, sp, 32
+    ret
+
+// Writes "file:line: " to stderr
+// x0 -> source file
+// w1 -> line
+write_location:
+    stp x29, x30, [sp, -32]!
+    str x19, [sp, 16]
+    mov w19, w1
+    bl write_str
+    load_msg x0, msg_colon
+    bl write_str
+    mov w0, w19
+    bl write_int
+    load_msg x0, msg_sep
+    bl write_str
+    ldr x19, [sp, 16]
+    ldp x29, x30, [sp], 32
+    ret
+
+// Writes a message and a new line to stderr, then exits
+// x0 -> message
+panic_exit:
+    bl write_str
+    load_msg x0, msg_newline
+    bl write_str
+    mov x0, PANIC_CODE
+    mov x8, 93
+    svc 0
+
+// Called by the panic statement
+// x0 -> source file
+// w1 -> line
+// x2 -> message
+.global ida_panic
+ida_panic:
+    mov x20, x2
+    bl write_location
+    mov x0, x20
+    b panic_exit
+
+// Called when the condition of an assert statement is false
+// x0 -> source file
+// w1 -> line
+// x2 -> message
+.global ida_assert_fail
+ida_assert_fail:
+    mov x20, x2
+    bl write_location
+    load_msg x0, msg_assert
+    bl write_str
+    mov x0, x20
+    b panic_exit
+
+// Called when an array index is out of bounds (--bounds-check)
+// x0 -> source file
+// w1 -> line
+// w2 -> index
+// w3 -> length
+.global ida_bounds_panic
+ida_bounds_panic:
+    mov w20, w2
+    mov w21, w3
+
+    // file:line: index out of bounds: the index is N but the length is M
+    bl write_location
+    load_msg x0, msg_bounds1
+    bl write_str
+    mov w0, w20
+    bl write_int
+    load_msg x0, msg_bounds2
+    bl write_str
+    mov w0, w21
+    bl write_int
+    load_msg x0, msg_empty
+    b panic_exit
+
+// Called when checked arithmetic overflows (--overflow-checks)
+// x0 -> source file
+// w1 -> line
+.global ida_overflow_panic
+ida_overflow_panic:
+    bl write_location
+    load_msg x0, msg_overflow
+    b panic_exit
+
+// Called when an integer is divided by zero (--div-checks)
+// x0 -> source file
+// w1 -> line
+.global ida_div_panic
+ida_div_panic:
+    bl write_location
+    load_msg x0, msg_div_zero
+    b panic_exit
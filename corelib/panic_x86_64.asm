@@ -0,0 +1,165 @@
+#
+# Runtime error handlers for the core library (x86-64)
+#
+# The compiler emits calls to these for the panic statement, failed asserts,
+# and failed runtime checks. They print the source location and what happened
+# to stderr, then exit with code 101.
+#
+.intel_syntax noprefix
+
+.set STDERR, 2
+.set PANIC_CODE, 101
+
+.section .rodata
+msg_colon: .string ":"
+msg_sep: .string ": "
+msg_assert: .string "assertion failed: "
+msg_bounds1: .string "index out of bounds: the index is "
+msg_bounds2: .string " but the length is "
+msg_overflow: .string "arithmetic overflow"
+msg_div_zero: .string "division by zero"
+msg_newline: .string "\n"
+msg_empty: .string ""
+
+.text
+
+# Writes a string to stderr
+# rdi -> string
+write_str:
+    mov rsi, rdi
+    xor edx, edx
+1:
+    cmp byte ptr [rsi+rdx], 0
+    je 2f
+    inc rdx
+    jmp 1b
+2:
+    mov eax, 1
+    mov edi, STDERR
+    syscall
+    ret
+
+# Writes a signed integer to stderr
+# edi -> value
+write_int:
+    sub rsp, 24
+    movsxd rax, edi
+    mov r8, rax
+    test rax, rax
+    jns 1f
+    neg rax
+1:
+    lea rsi, [rsp+24]
+    mov ecx, 10
+2:
+    xor edx, edx
+    div rcx
+    add dl, '0'
+    dec rsi
+    mov [rsi], dl
+    test rax, rax
+    jnz 2b
+
+    test r8, r8
+    jns 3f
+    dec rsi
+    mov byte ptr [rsi], '-'
+3:
+    lea rdx, [rsp+24]
+    sub rdx, rsi
+    mov eax, 1
+    mov edi, STDERR
+    syscall
+    add rsp, 24
+    ret
+
+# Writes "file:line: " to stderr
+# rdi -> source file
+# esi -> line
+write_location:
+    push rbx
+    mov ebx, esi
+    call write_str
+    lea rdi, [rip+msg_colon]
+    call write_str
+    mov edi, ebx
+    call write_int
+    lea rdi, [rip+msg_sep]
+    call write_str
+    pop rbx
+    ret
+
+# Writes a message and a new line to stderr, then exits
+# rdi -> message
+panic_exit:
+    call write_str
+    lea rdi, [rip+msg_newline]
+    call write_str
+    mov eax, 60
+    mov edi, PANIC_CODE
+    syscall
+
+# Called by the panic statement
+# rdi -> source file
+# esi -> line
+# rdx -> message
+.global ida_panic
+ida_panic:
+    mov r12, rdx
+    call write_location
+    mov rdi, r12
+    jmp panic_exit
+
+# Called when the condition of an assert statement is false
+# rdi -> source file
+# esi -> line
+# rdx -> message
+.global ida_assert_fail
+ida_assert_fail:
+    mov r12, rdx
+    call write_location
+    lea rdi, [rip+msg_assert]
+    call write_str
+    mov rdi, r12
+    jmp panic_exit
+
+# Called when an array index is out of bounds (--bounds-check)
+# rdi -> source file
+# esi -> line
+# edx -> index
+# ecx -> length
+.global ida_bounds_panic
+ida_bounds_panic:
+    mov r12d, edx
+    mov r13d, ecx
+
+    # file:line: index out of bounds: the index is N but the length is M
+    call write_location
+    lea rdi, [rip+msg_bounds1]
+    call write_str
+    mov edi, r12d
+    call write_int
+    lea rdi, [rip+msg_bounds2]
+    call write_str
+    mov edi, r13d
+    call write_int
+    lea rdi, [rip+msg_empty]
+    jmp panic_exit
+
+# Called when checked arithmetic overflows (--overflow-checks)
+# rdi -> source file
+# esi -> line
+.global ida_overflow_panic
+ida_overflow_panic:
+    call write_location
+    lea rdi, [rip+msg_overflow]
+    jmp panic_exit
+
+# Called when an integer is divided by zero (--div-checks)
+# rdi -> source file
+# esi -> line
+.global ida_div_panic
+ida_div_panic:
+    call write_location
+    lea rdi, [rip+msg_div_zero]
+    jmp panic_exit
@@ -44,4 +44,11 @@ if [[ -f target/irt.o ]] ; then
     sudo cp target/irt.o /usr/lib/ida
 fi
 
+# Install the AArch64 libraries, if they were built (./build-stdlib.sh aarch64)
+if [[ -d target/aarch64 ]] ; then
+    sudo mkdir -p /usr/lib/ida/aarch64
+    sudo cp target/aarch64/libida_core.a target/aarch64/libida.a target/aarch64/irt.o /usr/lib/ida/aarch64
+    sudo cp -r target/aarch64/core target/aarch64/std /usr/lib/ida
+fi
+
 echo "Done"
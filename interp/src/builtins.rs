@@ -0,0 +1,212 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The functions and system calls built into the interpreter
+// These stand in for the core library, the C library, and the kernel. A function
+// defined in the program takes the place of a built-in one with the same name.
+
+use std::io;
+use std::io::prelude::*;
+
+use parser::Arch;
+
+use crate::*;
+
+// Returns true if a function is built in
+pub fn is_builtin(name : &str) -> bool {
+    match name {
+        // The C library
+        "printf" | "puts" | "putchar"
+        | "strlen" | "strcmp"
+        | "malloc" | "calloc" | "realloc" | "free"
+        | "memcpy" | "memmove" | "memset"
+        | "exit" | "abort" => true,
+        
+        // The core library
+        "print" | "println" | "sys_exit"
+        | "str_concat" | "str_slice"
+        | "ida_panic" | "ida_assert_fail" | "ida_bounds_panic"
+        | "ida_overflow_panic" | "ida_div_panic" => true,
+        
+        _ => false,
+    }
+}
+
+impl Interp {
+    fn read_str(&mut self, addr : u64) -> Result<Vec<u8>, Stop> {
+        self.mem.read_cstr(addr)
+    }
+    
+    // Copies a string to the heap
+    fn new_str(&mut self, bytes : &[u8]) -> Result<u64, Stop> {
+        let addr = self.mem.malloc(bytes.len() as u64 + 1);
+        if addr == 0 {
+            return Err(Stop::Fault("Out of memory".to_string(), 134));
+        }
+        
+        self.mem.write_bytes(addr, bytes)?;
+        self.mem.write(addr + bytes.len() as u64, 1, 0)?;
+        Ok(addr)
+    }
+    
+    // Prints "file:line: message" to stderr, and exits with the panic code
+    fn panic(&mut self, msg : &[u8]) -> Result<(), Stop> {
+        let mut line = self.read_str(self.args[1])?;
+        line.extend_from_slice(format!(":{}: ", self.args[2] as u32 as i32).as_bytes());
+        line.extend_from_slice(msg);
+        line.push(b'\n');
+        
+        self.write_fd(2, &line);
+        Err(Stop::Exit(101))
+    }
+    
+    // Calls a built-in function
+    // The arguments are in the argument registers, and the result goes in the return register.
+    pub fn call_builtin(&mut self, name : &str) -> Result<(), Stop> {
+        let args = self.args;
+        
+        match name {
+            "printf" => {
+                let text = self.printf()?;
+                self.write_fd(1, &text);
+                self.ret = text.len() as u64;
+            },
+            
+            "puts" | "println" => {
+                let mut text = self.read_str(args[1])?;
+                text.push(b'\n');
+                
+                self.write_fd(1, &text);
+                self.ret = text.len() as u64;
+            },
+            
+            "print" => {
+                let text = self.read_str(args[1])?;
+                self.write_fd(1, &text);
+                self.ret = text.len() as u64;
+            },
+            
+            "putchar" => {
+                self.write_fd(1, &[args[1] as u8]);
+                self.ret = args[1] & 0xFF;
+            },
+            
+            "strlen" => self.ret = self.read_str(args[1])?.len() as u64,
+            
+            // Only the sign of the result matters
+            "strcmp" => {
+                let s1 = self.read_str(args[1])?;
+                let s2 = self.read_str(args[2])?;
+                
+                self.ret = match s1.cmp(&s2) {
+                    std::cmp::Ordering::Less => -1i64 as u64,
+                    std::cmp::Ordering::Equal => 0,
+                    std::cmp::Ordering::Greater => 1,
+                };
+            },
+            
+            "str_concat" => {
+                let mut text = self.read_str(args[1])?;
+                text.extend(self.read_str(args[2])?);
+                self.ret = self.new_str(&text)?;
+            },
+            
+            // Copies the characters from start up to (but not including) stop
+            "str_slice" => {
+                let text = self.read_str(args[1])?;
+                let stop = (args[3] as u32 as i32).max(0).min(text.len() as i32) as usize;
+                let start = (args[2] as u32 as i32).max(0).min(stop as i32) as usize;
+                self.ret = self.new_str(&text[start .. stop])?;
+            },
+            
+            "malloc" => self.ret = self.mem.malloc(args[1]),
+            "calloc" => self.ret = self.mem.calloc(args[1].wrapping_mul(args[2]))?,
+            "realloc" => self.ret = self.mem.realloc(args[1], args[2])?,
+            "free" => self.mem.free(args[1])?,
+            
+            "memcpy" | "memmove" => {
+                let bytes = self.mem.read_bytes(args[2], args[3])?;
+                self.mem.write_bytes(args[1], &bytes)?;
+                self.ret = args[1];
+            },
+            
+            "memset" => {
+                self.mem.write_bytes(args[1], &vec![args[2] as u8; args[3] as usize])?;
+                self.ret = args[1];
+            },
+            
+            "exit" | "sys_exit" => return Err(Stop::Exit(args[1] as i32)),
+            "abort" => return Err(Stop::Fault("Aborted".to_string(), 134)),
+            
+            "ida_panic" => {
+                let msg = self.read_str(args[3])?;
+                self.panic(&msg)?;
+            },
+            
+            "ida_assert_fail" => {
+                let mut msg = b"assertion failed: ".to_vec();
+                msg.extend(self.read_str(args[3])?);
+                self.panic(&msg)?;
+            },
+            
+            "ida_bounds_panic" => {
+                let msg = format!("index out of bounds: the index is {} but the length is {}",
+                    args[3] as u32 as i32, args[4] as u32 as i32);
+                self.panic(msg.as_bytes())?;
+            },
+            
+            "ida_overflow_panic" => self.panic(b"arithmetic overflow")?,
+            "ida_div_panic" => self.panic(b"division by zero")?,
+            
+            _ => return Err(Stop::Fault(format!("Undefined function: {}", name), 134)),
+        }
+        
+        Ok(())
+    }
+    
+    // Runs a system call
+    // The first kernel argument is the call number. Only read, write, and exit
+    // are supported; anything else fails with ENOSYS.
+    pub fn syscall(&mut self) -> Result<(), Stop> {
+        let args = self.kargs;
+        
+        let (sys_read, sys_write, sys_exit, sys_exit_group) = match self.arch {
+            Arch::X86_64 => (0, 1, 60, 231),
+            Arch::AArch64 | Arch::Riscv64 => (63, 64, 93, 94),
+        };
+        
+        let number = args[1];
+        
+        if number == sys_write {
+            let bytes = self.mem.read_bytes(args[3], args[4])?;
+            
+            self.ret = if self.write_fd(args[2], &bytes) {
+                bytes.len() as u64
+            } else {
+                -9i64 as u64        // EBADF
+            };
+        } else if number == sys_read {
+            if args[2] != 0 {
+                self.ret = -9i64 as u64;
+                return Ok(());
+            }
+            
+            self.out.flush().ok();
+            
+            let mut bytes = vec![0; args[4] as usize];
+            let count = io::stdin().read(&mut bytes).unwrap_or(0);
+            
+            self.mem.write_bytes(args[3], &bytes[0 .. count])?;
+            self.ret = count as u64;
+        } else if number == sys_exit || number == sys_exit_group {
+            return Err(Stop::Exit(args[2] as i32));
+        } else {
+            self.ret = -38i64 as u64;       // ENOSYS
+        }
+        
+        Ok(())
+    }
+}
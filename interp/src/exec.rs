@@ -0,0 +1,767 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// Runs the instructions
+// Registers are 64 bits, and are written the way they are on x86-64: a 32-bit
+// write clears the upper half, while 8 and 16-bit writes leave the rest alone.
+// The size of an operation comes from its register or literal operand; the
+// instruction type is only used when both operands are in memory.
+
+use parser::ltac::{LtacType, LtacArg, LtacInstr};
+
+use crate::*;
+use crate::memory::*;
+
+// Integer operations
+#[derive(PartialEq)]
+enum IntOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+fn mask(width : u32) -> u64 {
+    if width >= 64 {
+        return u64::MAX;
+    }
+    
+    (1 << width) - 1
+}
+
+fn sign_extend(val : u64, width : u32) -> i64 {
+    let shift = 64 - width;
+    ((val << shift) as i64) >> shift
+}
+
+// Returns the size of an operand, if it has one
+fn arg_width(arg : &LtacArg) -> Option<u32> {
+    match arg {
+        LtacArg::Reg8(_) | LtacArg::Byte(_) | LtacArg::UByte(_)
+        | LtacArg::RetRegI8 | LtacArg::RetRegU8 => Some(8),
+        
+        LtacArg::Reg16(_) | LtacArg::I16(_) | LtacArg::U16(_)
+        | LtacArg::RetRegI16 | LtacArg::RetRegU16 => Some(16),
+        
+        LtacArg::Reg32(_) | LtacArg::I32(_) | LtacArg::U32(_)
+        | LtacArg::RetRegI32 | LtacArg::RetRegU32
+        | LtacArg::FltReg(_) | LtacArg::F32(_) | LtacArg::RetRegF32 => Some(32),
+        
+        LtacArg::Reg64(_) | LtacArg::I64(_) | LtacArg::U64(_)
+        | LtacArg::RetRegI64 | LtacArg::RetRegU64
+        | LtacArg::FltReg64(_) | LtacArg::F64(_) | LtacArg::RetRegF64
+        | LtacArg::Ptr(_) | LtacArg::PtrLcl(_) | LtacArg::PtrFunc(_) => Some(64),
+        
+        _ => None,
+    }
+}
+
+// Returns the size implied by an instruction
+fn type_width(instr_type : &LtacType) -> u32 {
+    match instr_type {
+        LtacType::MovB | LtacType::MovUB | LtacType::LdB | LtacType::LdUB
+        | LtacType::StrB | LtacType::StrUB
+        | LtacType::I8Add | LtacType::U8Add | LtacType::I8Sub
+        | LtacType::I8Mul | LtacType::U8Mul | LtacType::I8Div | LtacType::U8Div
+        | LtacType::I8Mod | LtacType::U8Mod
+        | LtacType::I8Cmp | LtacType::U8Cmp => 8,
+        
+        LtacType::MovW | LtacType::MovUW | LtacType::LdW | LtacType::LdUW
+        | LtacType::StrW | LtacType::StrUW
+        | LtacType::I16Add | LtacType::U16Add | LtacType::I16Sub
+        | LtacType::I16Mul | LtacType::U16Mul | LtacType::I16Div | LtacType::U16Div
+        | LtacType::I16Mod | LtacType::U16Mod
+        | LtacType::I16Cmp | LtacType::U16Cmp => 16,
+        
+        LtacType::MovQ | LtacType::MovUQ | LtacType::LdQ | LtacType::LdUQ
+        | LtacType::StrQ | LtacType::StrUQ | LtacType::StrPtr
+        | LtacType::MovF64 | LtacType::LdF64 | LtacType::StrF64
+        | LtacType::I64Add | LtacType::U64Add | LtacType::I64Sub
+        | LtacType::I64Mul | LtacType::U64Mul | LtacType::I64Div | LtacType::U64Div
+        | LtacType::I64Mod | LtacType::U64Mod
+        | LtacType::I64Cmp | LtacType::U64Cmp => 64,
+        
+        _ => 32,
+    }
+}
+
+// Returns the operation and signedness of integer math
+fn int_op(instr_type : &LtacType) -> Option<(IntOp, bool)> {
+    match instr_type {
+        LtacType::I8Add | LtacType::I16Add | LtacType::I32Add | LtacType::I64Add => Some((IntOp::Add, true)),
+        LtacType::U8Add | LtacType::U16Add | LtacType::U32Add | LtacType::U64Add => Some((IntOp::Add, false)),
+        LtacType::I8Sub | LtacType::I16Sub | LtacType::I32Sub | LtacType::I64Sub => Some((IntOp::Sub, true)),
+        LtacType::I8Mul | LtacType::I16Mul | LtacType::I32Mul | LtacType::I64Mul => Some((IntOp::Mul, true)),
+        LtacType::U8Mul | LtacType::U16Mul | LtacType::U32Mul | LtacType::U64Mul => Some((IntOp::Mul, false)),
+        LtacType::I8Div | LtacType::I16Div | LtacType::I32Div | LtacType::I64Div => Some((IntOp::Div, true)),
+        LtacType::U8Div | LtacType::U16Div | LtacType::U32Div | LtacType::U64Div => Some((IntOp::Div, false)),
+        LtacType::I8Mod | LtacType::I16Mod | LtacType::I32Mod | LtacType::I64Mod => Some((IntOp::Mod, true)),
+        LtacType::U8Mod | LtacType::U16Mod | LtacType::U32Mod | LtacType::U64Mod => Some((IntOp::Mod, false)),
+        _ => None,
+    }
+}
+
+fn is_mem(arg : &LtacArg) -> bool {
+    match arg {
+        LtacArg::Mem(_) | LtacArg::Ptr(_)
+        | LtacArg::MemOffsetImm(..) | LtacArg::MemOffsetMem(..)
+        | LtacArg::MemOffsetReg(..) | LtacArg::MemOffsetStride(..) => true,
+        _ => false,
+    }
+}
+
+// Converts a float to an integer, truncating toward zero
+// Like cvttsd2si, a value that doesn't fit gives the smallest integer.
+fn float_to_int(val : f64, width : u32) -> u64 {
+    let width = width.max(32);
+    let limit = (1u64 << (width - 1)) as f64;
+    
+    if val.is_nan() || val >= limit || val < -limit {
+        return 1 << (width - 1);
+    }
+    
+    (val.trunc() as i64) as u64 & mask(width)
+}
+
+impl Interp {
+    // Runs from an instruction until the program stops
+    pub fn exec(&mut self, start : usize) -> Stop {
+        let code = self.code.clone();
+        let mut pc = start;
+        
+        loop {
+            let instr = match code.get(pc) {
+                Some(instr) => instr,
+                None => return Stop::Fault("Ran past the end of the program.".to_string(), 134),
+            };
+            
+            match self.step(instr, pc) {
+                Ok(Some(next)) => pc = next,
+                Ok(None) => pc += 1,
+                Err(stop) => return stop,
+            }
+        }
+    }
+    
+    // Runs one instruction
+    // Returns the next instruction if it isn't the one that follows
+    fn step(&mut self, instr : &LtacInstr, pc : usize) -> Result<Option<usize>, Stop> {
+        let target = self.targets[pc];
+        
+        match &instr.instr_type {
+            LtacType::None | LtacType::Extern | LtacType::Label => {},
+            
+            LtacType::Func => self.build_frame(instr.arg1_val)?,
+            LtacType::Ret => return self.ret_from(),
+            
+            LtacType::MovB | LtacType::MovUB
+            | LtacType::MovW | LtacType::MovUW
+            | LtacType::Mov | LtacType::MovU
+            | LtacType::MovQ | LtacType::MovUQ
+            | LtacType::MovF32 | LtacType::MovF64
+            | LtacType::MovF64Int
+            | LtacType::StrB | LtacType::StrUB
+            | LtacType::StrW | LtacType::StrUW
+            | LtacType::Str | LtacType::StrU
+            | LtacType::StrQ | LtacType::StrUQ
+            | LtacType::StrF32 | LtacType::StrF64
+            | LtacType::StrPtr => self.mov(instr, &instr.arg1, &instr.arg2)?,
+            
+            // Loads may be written either way around: the parser puts the register
+            // first, and the RISC transform puts the memory first
+            LtacType::LdB | LtacType::LdUB
+            | LtacType::LdW | LtacType::LdUW
+            | LtacType::Ld | LtacType::LdU
+            | LtacType::LdQ | LtacType::LdUQ
+            | LtacType::LdF32 | LtacType::LdF64 => {
+                if is_mem(&instr.arg1) {
+                    self.mov(instr, &instr.arg2, &instr.arg1)?;
+                } else {
+                    self.mov(instr, &instr.arg1, &instr.arg2)?;
+                }
+            },
+            
+            LtacType::LdAddr => {
+                let addr = match self.mem_addr(&instr.arg2)? {
+                    Some(addr) => addr,
+                    None => self.read(&instr.arg2, 64)?,
+                };
+                
+                self.write(&instr.arg1, 64, addr)?;
+            },
+            
+            LtacType::LdArgI8 | LtacType::LdArgU8
+            | LtacType::LdArgI16 | LtacType::LdArgU16
+            | LtacType::LdArgI32 | LtacType::LdArgU32
+            | LtacType::LdArgI64 | LtacType::LdArgU64
+            | LtacType::LdArgF32 | LtacType::LdArgF64
+            | LtacType::LdArgPtr => self.load_arg(instr)?,
+            
+            LtacType::PushArg => self.push_arg(instr, false)?,
+            LtacType::KPushArg => self.push_arg(instr, true)?,
+            
+            LtacType::Call => return self.call(&instr.name, target, pc),
+            
+            LtacType::CallReg => {
+                let addr = self.read(&instr.arg1, 64)?;
+                let name = self.func_name(addr)?;
+                let target = match self.funcs.get(&name) {
+                    Some(target) => *target,
+                    None => NO_TARGET,
+                };
+                
+                return self.call(&name, target, pc);
+            },
+            
+            LtacType::Syscall => self.syscall()?,
+            
+            // These are normally replaced by the transform layer
+            LtacType::Malloc => self.call_builtin("malloc")?,
+            LtacType::Calloc => self.call_builtin("calloc")?,
+            LtacType::Free => self.call_builtin("free")?,
+            LtacType::Resize => self.call_builtin("realloc")?,
+            LtacType::Exit => return Err(Stop::Exit(self.read(&instr.arg1, 32)? as i32)),
+            
+            LtacType::I8Cmp | LtacType::I16Cmp
+            | LtacType::I32Cmp | LtacType::I64Cmp => self.compare(instr, true)?,
+            
+            LtacType::U8Cmp | LtacType::U16Cmp
+            | LtacType::U32Cmp | LtacType::U64Cmp => self.compare(instr, false)?,
+            
+            LtacType::F32Cmp | LtacType::F64Cmp => self.compare_float(instr)?,
+            
+            // The arguments are already loaded; strcmp returns 0 if the strings are equal
+            LtacType::StrCmp => {
+                self.call_builtin("strcmp")?;
+                
+                let result = self.ret as u32 as i32;
+                self.flags = Flags {
+                    eq : result == 0,
+                    lt : result < 0,
+                    below : false,
+                };
+            },
+            
+            LtacType::Br => return Ok(Some(target)),
+            
+            LtacType::Be | LtacType::Bne
+            | LtacType::Bl | LtacType::Ble
+            | LtacType::Bg | LtacType::Bge
+            | LtacType::Bfl | LtacType::Bfle
+            | LtacType::Bfg | LtacType::Bfge => {
+                if self.branch_taken(&instr.instr_type) {
+                    return Ok(Some(target));
+                }
+            },
+            
+            LtacType::F32Add | LtacType::F32Sub
+            | LtacType::F32Mul | LtacType::F32Div => {
+                let a = f32::from_bits(self.read(&instr.arg1, 32)? as u32);
+                let b = f32::from_bits(self.read(&instr.arg2, 32)? as u32);
+                
+                let result = match &instr.instr_type {
+                    LtacType::F32Add => a + b,
+                    LtacType::F32Sub => a - b,
+                    LtacType::F32Mul => a * b,
+                    _ => a / b,
+                };
+                
+                self.write(&instr.arg1, 32, result.to_bits() as u64)?;
+            },
+            
+            LtacType::F64Add | LtacType::F64Sub
+            | LtacType::F64Mul | LtacType::F64Div => {
+                let a = f64::from_bits(self.read(&instr.arg1, 64)?);
+                let b = f64::from_bits(self.read(&instr.arg2, 64)?);
+                
+                let result = match &instr.instr_type {
+                    LtacType::F64Add => a + b,
+                    LtacType::F64Sub => a - b,
+                    LtacType::F64Mul => a * b,
+                    _ => a / b,
+                };
+                
+                self.write(&instr.arg1, 64, result.to_bits())?;
+            },
+            
+            LtacType::And | LtacType::Or | LtacType::Xor
+            | LtacType::Lsh | LtacType::Rsh => self.bitwise(instr)?,
+            
+            LtacType::CvtF32F64 => {
+                let val = f32::from_bits(self.read(&instr.arg2, 32)? as u32);
+                self.write(&instr.arg1, 64, (val as f64).to_bits())?;
+            },
+            
+            LtacType::CvtF64F32 => {
+                let val = f64::from_bits(self.read(&instr.arg2, 64)?);
+                self.write(&instr.arg1, 32, (val as f32).to_bits() as u64)?;
+            },
+            
+            LtacType::CvtIntF32 | LtacType::CvtIntF64 => {
+                let width = arg_width(&instr.arg2).unwrap_or(32);
+                let val = sign_extend(self.read(&instr.arg2, width)?, width);
+                
+                if instr.instr_type == LtacType::CvtIntF32 {
+                    self.write(&instr.arg1, 32, (val as f32).to_bits() as u64)?;
+                } else {
+                    self.write(&instr.arg1, 64, (val as f64).to_bits())?;
+                }
+            },
+            
+            LtacType::CvtF32Int | LtacType::CvtF64Int => {
+                let val = if instr.instr_type == LtacType::CvtF32Int {
+                    f32::from_bits(self.read(&instr.arg2, 32)? as u32) as f64
+                } else {
+                    f64::from_bits(self.read(&instr.arg2, 64)?)
+                };
+                
+                let width = arg_width(&instr.arg1).unwrap_or(32);
+                self.write(&instr.arg1, width, float_to_int(val, width))?;
+            },
+            
+            LtacType::Push | LtacType::Pop
+            | LtacType::MovI32Vec | LtacType::I32VAdd => {
+                let msg = format!("The interpreter doesn't support {}", parser::ltac::instr_to_text(instr));
+                return Err(Stop::Fault(msg, 134));
+            },
+            
+            // Integer math
+            instr_type => {
+                let (op, signed) = match int_op(instr_type) {
+                    Some(op) => op,
+                    None => return Err(Stop::Fault(format!("Unknown instruction: {:?}", instr_type), 134)),
+                };
+                
+                // Checked math branches to its label
+                if self.int_math(instr, op, signed)? && !instr.name.is_empty() {
+                    return Ok(Some(target));
+                }
+            },
+        }
+        
+        Ok(None)
+    }
+    
+    // Sets up the stack frame of a function
+    fn build_frame(&mut self, size : i32) -> Result<(), Stop> {
+        let size = ((size.max(0) as u64) + 15) & !15;
+        
+        if self.sp < STACK_TOP - STACK_SIZE + size + 16 {
+            return Err(Stop::Fault("Stack overflow".to_string(), 139));
+        }
+        
+        self.bp = self.sp - 16;
+        self.sp = self.bp - size;
+        Ok(())
+    }
+    
+    fn ret_from(&mut self) -> Result<Option<usize>, Stop> {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return Err(Stop::Exit(self.ret as i32)),
+        };
+        
+        self.file = frame.file;
+        self.bp = frame.bp;
+        self.sp = frame.sp;
+        self.regs = frame.regs;
+        self.flt_regs = frame.flt_regs;
+        
+        Ok(Some(frame.return_pc))
+    }
+    
+    // Calls a function, either in the program or built in
+    fn call(&mut self, name : &String, target : usize, pc : usize) -> Result<Option<usize>, Stop> {
+        if target == NO_TARGET {
+            self.call_builtin(name)?;
+            return Ok(None);
+        }
+        
+        self.frames.push(Frame {
+            return_pc : pc + 1,
+            file : self.file,
+            bp : self.bp,
+            sp : self.sp,
+            regs : self.regs,
+            flt_regs : self.flt_regs,
+        });
+        
+        self.file = file_at(&self.files, target);
+        Ok(Some(target))
+    }
+    
+    fn func_name(&self, addr : u64) -> Result<String, Stop> {
+        let index = addr.wrapping_sub(FUNC_BASE) / 16;
+        
+        if addr < FUNC_BASE || addr % 16 != 0 || index >= self.func_names.len() as u64 {
+            return Err(Stop::Fault(format!("Call to an invalid address: 0x{:x}", addr), 139));
+        }
+        
+        Ok(self.func_names[index as usize].clone())
+    }
+    
+    fn reg_index(&self, reg : i32) -> Result<usize, Stop> {
+        if reg < 0 || reg as usize >= REG_COUNT {
+            return Err(Stop::Fault(format!("Invalid register: {}", reg), 134));
+        }
+        
+        Ok(reg as usize)
+    }
+    
+    fn data_addr(&self, name : &String) -> u64 {
+        self.data[self.file][name]
+    }
+    
+    fn local_addr(&self, pos : i32) -> u64 {
+        self.bp.wrapping_sub(pos as i64 as u64)
+    }
+    
+    // Returns the address of a memory operand
+    // Array operands hold the address of the array in a local, and add an offset to it.
+    fn mem_addr(&mut self, arg : &LtacArg) -> Result<Option<u64>, Stop> {
+        let addr = match arg {
+            LtacArg::Mem(pos) | LtacArg::Ptr(pos) => self.local_addr(*pos),
+            
+            LtacArg::MemOffsetImm(pos, offset) => {
+                let base = self.mem.read(self.local_addr(*pos), 8)?;
+                base.wrapping_add(*offset as i64 as u64)
+            },
+            
+            LtacArg::MemOffsetMem(pos, var, size) => {
+                let base = self.mem.read(self.local_addr(*pos), 8)?;
+                let index = self.mem.read(self.local_addr(*var), 4)?;
+                base.wrapping_add(index.wrapping_mul(*size as u64))
+            },
+            
+            LtacArg::MemOffsetReg(pos, reg, size) => {
+                let base = self.mem.read(self.local_addr(*pos), 8)?;
+                let index = self.regs[self.reg_index(*reg)?];
+                base.wrapping_add(index.wrapping_mul(*size as u64))
+            },
+            
+            LtacArg::MemOffsetStride(pos, row, reg, stride, size) => {
+                let base = self.mem.read(self.local_addr(*pos), 8)?;
+                let row = self.mem.read(self.local_addr(*row), 4)?;
+                let index = row.wrapping_mul(*stride as u64).wrapping_add(self.regs[self.reg_index(*reg)?]);
+                base.wrapping_add(index.wrapping_mul(*size as u64))
+            },
+            
+            _ => return Ok(None),
+        };
+        
+        Ok(Some(addr))
+    }
+    
+    // Reads an operand
+    // The value is cut to the width; literals are extended first
+    fn read(&mut self, arg : &LtacArg, width : u32) -> Result<u64, Stop> {
+        let val = match arg {
+            LtacArg::Reg8(reg) | LtacArg::Reg16(reg)
+            | LtacArg::Reg32(reg) | LtacArg::Reg64(reg) => self.regs[self.reg_index(*reg)?],
+            
+            LtacArg::FltReg(reg) | LtacArg::FltReg64(reg) => self.flt_regs[self.reg_index(*reg)?],
+            
+            LtacArg::RetRegI8 | LtacArg::RetRegU8
+            | LtacArg::RetRegI16 | LtacArg::RetRegU16
+            | LtacArg::RetRegI32 | LtacArg::RetRegU32
+            | LtacArg::RetRegI64 | LtacArg::RetRegU64 => self.ret,
+            
+            LtacArg::RetRegF32 | LtacArg::RetRegF64 => self.flt_ret,
+            
+            LtacArg::Byte(val) => *val as i64 as u64,
+            LtacArg::UByte(val) => *val as u64,
+            LtacArg::I16(val) => *val as i64 as u64,
+            LtacArg::U16(val) => *val as u64,
+            LtacArg::I32(val) => *val as i64 as u64,
+            LtacArg::U32(val) => *val as u64,
+            LtacArg::I64(val) => *val as u64,
+            LtacArg::U64(val) => *val,
+            
+            LtacArg::F32(name) => {
+                let addr = self.data_addr(name);
+                self.mem.read(addr, 4)?
+            },
+            
+            LtacArg::F64(name) => {
+                let addr = self.data_addr(name);
+                self.mem.read(addr, 8)?
+            },
+            
+            LtacArg::PtrLcl(name) => self.data_addr(name),
+            LtacArg::PtrFunc(name) => self.func_addrs[name],
+            
+            LtacArg::Empty => return Err(Stop::Fault("Missing operand".to_string(), 134)),
+            
+            _ => match self.mem_addr(arg)? {
+                Some(addr) => self.mem.read(addr, (width / 8) as u64)?,
+                None => 0,
+            },
+        };
+        
+        Ok(val & mask(width))
+    }
+    
+    // Writes an operand
+    fn write(&mut self, arg : &LtacArg, width : u32, val : u64) -> Result<(), Stop> {
+        let val = val & mask(width);
+        
+        // A 32-bit write clears the upper half
+        let merge = |old : u64| {
+            match width {
+                32 | 64 => val,
+                _ => (old & !mask(width)) | val,
+            }
+        };
+        
+        match arg {
+            LtacArg::Reg8(reg) | LtacArg::Reg16(reg)
+            | LtacArg::Reg32(reg) | LtacArg::Reg64(reg) => {
+                let reg = self.reg_index(*reg)?;
+                self.regs[reg] = merge(self.regs[reg]);
+            },
+            
+            LtacArg::FltReg(reg) | LtacArg::FltReg64(reg) => {
+                let reg = self.reg_index(*reg)?;
+                self.flt_regs[reg] = val;
+            },
+            
+            LtacArg::RetRegI8 | LtacArg::RetRegU8
+            | LtacArg::RetRegI16 | LtacArg::RetRegU16
+            | LtacArg::RetRegI32 | LtacArg::RetRegU32
+            | LtacArg::RetRegI64 | LtacArg::RetRegU64 => self.ret = merge(self.ret),
+            
+            LtacArg::RetRegF32 | LtacArg::RetRegF64 => self.flt_ret = val,
+            
+            _ => match self.mem_addr(arg)? {
+                Some(addr) => self.mem.write(addr, (width / 8) as u64, val)?,
+                None => return Err(Stop::Fault(format!("Invalid destination: {:?}", arg), 134)),
+            },
+        }
+        
+        Ok(())
+    }
+    
+    // Returns the size of a two-operand instruction
+    fn op_width(&self, instr : &LtacInstr, dest : &LtacArg, src : &LtacArg) -> u32 {
+        match arg_width(dest) {
+            Some(width) => width,
+            None => arg_width(src).unwrap_or(type_width(&instr.instr_type)),
+        }
+    }
+    
+    // Moves, loads, and stores
+    fn mov(&mut self, instr : &LtacInstr, dest : &LtacArg, src : &LtacArg) -> Result<(), Stop> {
+        let width = self.op_width(instr, dest, src);
+        let val = self.read(src, width)?;
+        self.write(dest, width, val)
+    }
+    
+    // Loads a function argument into a register or local
+    fn load_arg(&mut self, instr : &LtacInstr) -> Result<(), Stop> {
+        let pos = instr.arg2_val as usize;
+        if pos >= ARG_COUNT {
+            return Err(Stop::Fault(format!("Invalid argument number: {}", pos), 134));
+        }
+        
+        let (width, val) = match &instr.instr_type {
+            LtacType::LdArgI8 | LtacType::LdArgU8 => (8, self.args[pos]),
+            LtacType::LdArgI16 | LtacType::LdArgU16 => (16, self.args[pos]),
+            LtacType::LdArgI32 | LtacType::LdArgU32 => (32, self.args[pos]),
+            LtacType::LdArgF32 => (32, self.flt_args[pos]),
+            LtacType::LdArgF64 => (64, self.flt_args[pos]),
+            _ => (64, self.args[pos]),
+        };
+        
+        self.write(&instr.arg1, width, val)
+    }
+    
+    // Loads an argument for a call (or a system call)
+    // Integers are extended to 32 bits the same way the x86 backend does it: arg2
+    // gives the type of a value in memory, and literals are passed as 32 bits.
+    fn push_arg(&mut self, instr : &LtacInstr, is_karg : bool) -> Result<(), Stop> {
+        let pos = instr.arg2_val as usize;
+        if pos >= ARG_COUNT {
+            return Err(Stop::Fault(format!("Invalid argument number: {}", pos), 134));
+        }
+        
+        let is_float = match (&instr.arg1, &instr.arg2) {
+            (LtacArg::F32(_), _) | (LtacArg::F64(_), _)
+            | (LtacArg::FltReg(_), _) | (LtacArg::FltReg64(_), _) => true,
+            (_, LtacArg::FltReg(_)) | (_, LtacArg::FltReg64(_)) => true,
+            _ => false,
+        };
+        
+        if is_float {
+            let width = match (&instr.arg1, &instr.arg2) {
+                (LtacArg::F32(_), _) | (LtacArg::FltReg(_), _) => 32,
+                (LtacArg::Mem(_), LtacArg::FltReg(_)) => 32,
+                _ => 64,
+            };
+            
+            self.flt_args[pos] = self.read(&instr.arg1, width)?;
+            return Ok(());
+        }
+        
+        // The width to read, and whether to sign-extend it to 32 bits
+        let (width, signed) = match (&instr.arg1, &instr.arg2) {
+            (LtacArg::Reg8(_), LtacArg::Byte(_)) => (8, true),
+            (LtacArg::Reg8(_), _) => (8, false),
+            (LtacArg::Reg16(_), LtacArg::I16(_)) => (16, true),
+            (LtacArg::Reg16(_), _) => (16, false),
+            
+            (LtacArg::Mem(_), LtacArg::Byte(_)) => (8, true),
+            (LtacArg::Mem(_), LtacArg::UByte(_)) => (8, false),
+            (LtacArg::Mem(_), LtacArg::I16(_)) => (16, true),
+            (LtacArg::Mem(_), LtacArg::U16(_)) => (16, false),
+            (LtacArg::Mem(_), LtacArg::I64(_)) | (LtacArg::Mem(_), LtacArg::U64(_)) => (64, false),
+            (LtacArg::Mem(_), _) => (32, false),
+            
+            (LtacArg::Byte(_), _) => (8, true),
+            (LtacArg::I16(_), _) => (16, true),
+            
+            (arg, _) => (arg_width(arg).unwrap_or(32), false),
+        };
+        
+        let mut val = self.read(&instr.arg1, width)?;
+        if signed {
+            val = sign_extend(val, width) as u64 & mask(32);
+        }
+        
+        if is_karg {
+            self.kargs[pos] = val;
+        } else {
+            self.args[pos] = val;
+        }
+        
+        Ok(())
+    }
+    
+    fn compare(&mut self, instr : &LtacInstr, signed : bool) -> Result<(), Stop> {
+        let width = self.op_width(instr, &instr.arg1, &instr.arg2);
+        let a = self.read(&instr.arg1, width)?;
+        let b = self.read(&instr.arg2, width)?;
+        
+        let lt = if signed {
+            sign_extend(a, width) < sign_extend(b, width)
+        } else {
+            a < b
+        };
+        
+        self.flags = Flags {
+            eq : a == b,
+            lt : lt,
+            below : a < b,
+        };
+        
+        Ok(())
+    }
+    
+    fn compare_float(&mut self, instr : &LtacInstr) -> Result<(), Stop> {
+        let (a, b) = if instr.instr_type == LtacType::F32Cmp {
+            let a = f32::from_bits(self.read(&instr.arg1, 32)? as u32);
+            let b = f32::from_bits(self.read(&instr.arg2, 32)? as u32);
+            (a as f64, b as f64)
+        } else {
+            (f64::from_bits(self.read(&instr.arg1, 64)?), f64::from_bits(self.read(&instr.arg2, 64)?))
+        };
+        
+        let unordered = a.is_nan() || b.is_nan();
+        
+        self.flags = Flags {
+            eq : unordered || a == b,
+            lt : unordered || a < b,
+            below : unordered || a < b,
+        };
+        
+        Ok(())
+    }
+    
+    fn branch_taken(&self, instr_type : &LtacType) -> bool {
+        let flags = &self.flags;
+        
+        match instr_type {
+            LtacType::Be => flags.eq,
+            LtacType::Bne => !flags.eq,
+            LtacType::Bl => flags.lt,
+            LtacType::Ble => flags.lt || flags.eq,
+            LtacType::Bg => !flags.lt && !flags.eq,
+            LtacType::Bge => !flags.lt,
+            LtacType::Bfl => flags.below,
+            LtacType::Bfle => flags.below || flags.eq,
+            LtacType::Bfg => !flags.below && !flags.eq,
+            LtacType::Bfge => !flags.below,
+            _ => true,
+        }
+    }
+    
+    // Runs integer math
+    // Returns true if the operation overflowed, or if a division was by zero and is checked
+    fn int_math(&mut self, instr : &LtacInstr, op : IntOp, signed : bool) -> Result<bool, Stop> {
+        let width = self.op_width(instr, &instr.arg1, &instr.arg2);
+        let a = self.read(&instr.arg1, width)?;
+        let b = self.read(&instr.arg2, width)?;
+        
+        let (a, b) = if signed {
+            (sign_extend(a, width) as i128, sign_extend(b, width) as i128)
+        } else {
+            (a as i128, b as i128)
+        };
+        
+        if (op == IntOp::Div || op == IntOp::Mod) && b == 0 {
+            if !instr.name.is_empty() {
+                return Ok(true);
+            }
+            
+            return Err(Stop::Fault("Division by zero".to_string(), 136));
+        }
+        
+        // Two large 64-bit values can overflow even an i128 when multiplied; the low bits
+        // are still right, and the result is truncated to the width when written
+        let (result, wrapped) = match op {
+            IntOp::Add => a.overflowing_add(b),
+            IntOp::Sub => a.overflowing_sub(b),
+            IntOp::Mul => a.overflowing_mul(b),
+            IntOp::Div => a.overflowing_div(b),
+            IntOp::Mod => a.overflowing_rem(b),
+        };
+        
+        let overflow = wrapped || if signed {
+            result != sign_extend(result as u64, width) as i128
+        } else {
+            result < 0 || result > mask(width) as i128
+        };
+        
+        self.write(&instr.arg1, width, result as u64)?;
+        
+        // Division only overflows for the smallest value divided by -1, which wraps
+        Ok(overflow && op != IntOp::Div && op != IntOp::Mod)
+    }
+    
+    // Runs the bitwise instructions
+    // Shifts use the count the way x86 does, so only the low 5 (or 6) bits matter
+    fn bitwise(&mut self, instr : &LtacInstr) -> Result<(), Stop> {
+        let width = self.op_width(instr, &instr.arg1, &instr.arg2);
+        let a = self.read(&instr.arg1, width)?;
+        let b = self.read(&instr.arg2, width)?;
+        
+        let count = if width == 64 {
+            b & 63
+        } else {
+            b & 31
+        };
+        
+        let result = match &instr.instr_type {
+            LtacType::And => a & b,
+            LtacType::Or => a | b,
+            LtacType::Xor => a ^ b,
+            LtacType::Lsh => a << count,
+            _ => a >> count,
+        };
+        
+        self.write(&instr.arg1, width, result)
+    }
+}
@@ -0,0 +1,386 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The LTAC interpreter
+// This runs LTAC directly, so programs can be run without an assembler, a linker,
+// or a CPU for the target. The core library, the panic functions, and a few C
+// functions (such as printf and malloc) are built in.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::rc::Rc;
+
+use parser::Arch;
+use parser::ltac::{LtacFile, LtacData, LtacDataType, LtacType, LtacArg, LtacInstr};
+
+// Import any local modules
+mod builtins;
+mod exec;
+mod memory;
+mod printf;
+
+use memory::*;
+
+// Why the program stopped
+pub enum Stop {
+    Exit(i32),
+    Fault(String, i32),     // The message and the exit code
+}
+
+const REG_COUNT : usize = 32;
+const ARG_COUNT : usize = 16;
+
+// The target of an instruction that doesn't branch (or calls a built-in function)
+const NO_TARGET : usize = usize::MAX;
+
+// The state of the caller, which is saved by a call and restored by the return
+struct Frame {
+    return_pc : usize,
+    file : usize,
+    bp : u64,
+    sp : u64,
+    regs : [u64; REG_COUNT],
+    flt_regs : [u64; REG_COUNT],
+}
+
+// The result of the last compare
+// lt is the signed result for signed compares; below is always unsigned.
+// An unordered float compare sets all three, like ucomiss does.
+struct Flags {
+    eq : bool,
+    lt : bool,
+    below : bool,
+}
+
+pub struct Interp {
+    code : Rc<Vec<LtacInstr>>,
+    targets : Vec<usize>,           // The branch or call target of each instruction
+    files : Vec<usize>,             // The first instruction of each file
+    data : Vec<HashMap<String, u64>>,
+    funcs : HashMap<String, usize>,
+    func_addrs : HashMap<String, u64>,
+    func_names : Vec<String>,       // Indexed by address
+    arch : Arch,
+    
+    mem : Memory,
+    file : usize,
+    bp : u64,
+    sp : u64,
+    frames : Vec<Frame>,
+    
+    regs : [u64; REG_COUNT],
+    flt_regs : [u64; REG_COUNT],
+    args : [u64; ARG_COUNT],
+    flt_args : [u64; ARG_COUNT],
+    kargs : [u64; ARG_COUNT],
+    ret : u64,
+    flt_ret : u64,
+    flags : Flags,
+    
+    out : BufWriter<io::Stdout>,
+}
+
+// Runs a program made of one or more LTAC files
+// The arguments are passed to main (the first should be the program name). The
+// syscall numbers are the Linux ones for the architecture the LTAC was built for.
+// Returns the exit code.
+pub fn run(files : &Vec<LtacFile>, arch : Arch, args : &Vec<String>) -> i32 {
+    let mut interp = match load(files, arch) {
+        Ok(interp) => interp,
+        Err(e) => {
+            println!("Fatal: {}", e);
+            return 1;
+        },
+    };
+    
+    let stop = interp.start(args);
+    interp.out.flush().ok();
+    
+    match stop {
+        Stop::Exit(code) => code,
+        Stop::Fault(msg, code) => {
+            eprintln!("Error: {}", msg);
+            code
+        },
+    }
+}
+
+// Lays out the data and code of each file, and resolves the labels and calls
+// Labels and data are local to their file; functions are global.
+fn load(files : &Vec<LtacFile>, arch : Arch) -> Result<Interp, String> {
+    let mut code : Vec<LtacInstr> = Vec::new();
+    let mut starts : Vec<usize> = Vec::new();
+    let mut data : Vec<HashMap<String, u64>> = Vec::new();
+    let mut labels : Vec<HashMap<String, usize>> = Vec::new();
+    let mut funcs : HashMap<String, usize> = HashMap::new();
+    let mut mem = create_memory();
+    
+    for file in files.iter() {
+        starts.push(code.len());
+        
+        let mut file_data : HashMap<String, u64> = HashMap::new();
+        for entry in file.data.iter() {
+            let bytes = data_bytes(entry)?;
+            file_data.insert(entry.name.clone(), mem.add_block(&bytes));
+        }
+        
+        let mut file_labels : HashMap<String, usize> = HashMap::new();
+        for instr in file.code.iter() {
+            match &instr.instr_type {
+                LtacType::Func => {
+                    if funcs.contains_key(&instr.name) {
+                        return Err(format!("The function {} is defined more than once.", instr.name));
+                    }
+                    
+                    funcs.insert(instr.name.clone(), code.len());
+                },
+                
+                LtacType::Label => {
+                    file_labels.insert(instr.name.clone(), code.len());
+                },
+                
+                _ => {},
+            }
+            
+            code.push(instr.clone());
+        }
+        
+        data.push(file_data);
+        labels.push(file_labels);
+    }
+    
+    if !funcs.contains_key("main") && !funcs.contains_key("_start") {
+        return Err("There is no main function.".to_string());
+    }
+    
+    // Every function gets an address, so it can be called through a pointer
+    let mut func_names : Vec<String> = funcs.keys().cloned().collect();
+    func_names.sort();
+    
+    let mut targets = vec![NO_TARGET; code.len()];
+    
+    for (pc, instr) in code.iter().enumerate() {
+        let file = file_at(&starts, pc);
+        
+        for arg in [&instr.arg1, &instr.arg2] {
+            match arg {
+                LtacArg::PtrLcl(name) | LtacArg::F32(name) | LtacArg::F64(name) => {
+                    if !data[file].contains_key(name) {
+                        return Err(format!("Unknown data: {}", name));
+                    }
+                },
+                
+                LtacArg::PtrFunc(name) => {
+                    if !funcs.contains_key(name) && !builtins::is_builtin(name) {
+                        return Err(format!("Undefined function: {}", name));
+                    }
+                    
+                    if !func_names.contains(name) {
+                        func_names.push(name.clone());
+                    }
+                },
+                
+                _ => {},
+            }
+        }
+        
+        match &instr.instr_type {
+            LtacType::Extern | LtacType::Label | LtacType::Func | LtacType::Syscall => {},
+            
+            LtacType::Call => match funcs.get(&instr.name) {
+                Some(target) => targets[pc] = *target,
+                None if builtins::is_builtin(&instr.name) => {},
+                None => return Err(format!("Undefined function: {}", instr.name)),
+            },
+            
+            // Branches, and checked arithmetic
+            _ if !instr.name.is_empty() => match labels[file].get(&instr.name) {
+                Some(target) => targets[pc] = *target,
+                None => return Err(format!("Unknown label: {}", instr.name)),
+            },
+            
+            _ => {},
+        }
+    }
+    
+    let mut func_addrs : HashMap<String, u64> = HashMap::new();
+    for (i, name) in func_names.iter().enumerate() {
+        func_addrs.insert(name.clone(), FUNC_BASE + (i as u64) * 16);
+    }
+    
+    Ok(Interp {
+        code : Rc::new(code),
+        targets : targets,
+        files : starts,
+        data : data,
+        funcs : funcs,
+        func_addrs : func_addrs,
+        func_names : func_names,
+        arch : arch,
+        
+        mem : mem,
+        file : 0,
+        bp : STACK_TOP,
+        sp : STACK_TOP,
+        frames : Vec::new(),
+        
+        regs : [0; REG_COUNT],
+        flt_regs : [0; REG_COUNT],
+        args : [0; ARG_COUNT],
+        flt_args : [0; ARG_COUNT],
+        kargs : [0; ARG_COUNT],
+        ret : 0,
+        flt_ret : 0,
+        flags : Flags { eq : false, lt : false, below : false },
+        
+        out : BufWriter::new(io::stdout()),
+    })
+}
+
+// Returns the file an instruction belongs to
+fn file_at(starts : &Vec<usize>, pc : usize) -> usize {
+    starts.iter().rposition(|start| *start <= pc).unwrap_or_default()
+}
+
+// Returns the bytes of a data entry, as the assembler would lay them out
+fn data_bytes(entry : &LtacData) -> Result<Vec<u8>, String> {
+    let invalid = || format!("Invalid data: {}", entry.name);
+    
+    match &entry.data_type {
+        LtacDataType::StringL => {
+            let mut bytes = unescape(&entry.val);
+            bytes.push(0);
+            Ok(bytes)
+        },
+        
+        LtacDataType::FloatL => match entry.val.parse::<u32>() {
+            Ok(val) => Ok(val.to_le_bytes().to_vec()),
+            Err(_e) => Err(invalid()),
+        },
+        
+        LtacDataType::DoubleL => match entry.val.parse::<u64>() {
+            Ok(val) => Ok(val.to_le_bytes().to_vec()),
+            Err(_e) => Err(invalid()),
+        },
+        
+        LtacDataType::ArrayL(size) => {
+            let mut bytes : Vec<u8> = Vec::new();
+            
+            for val in entry.val.split(',') {
+                let val = match val.trim().parse::<i64>() {
+                    Ok(val) => val,
+                    Err(_e) => return Err(invalid()),
+                };
+                
+                bytes.extend_from_slice(&val.to_le_bytes()[0 .. *size as usize]);
+            }
+            
+            Ok(bytes)
+        },
+    }
+}
+
+// Handles the escape sequences in a string, the same way the assembler does
+fn unescape(val : &str) -> Vec<u8> {
+    let src = val.as_bytes();
+    let mut bytes : Vec<u8> = Vec::new();
+    let mut i = 0;
+    
+    while i < src.len() {
+        let c = src[i];
+        i += 1;
+        
+        if c != b'\\' || i == src.len() {
+            bytes.push(c);
+            continue;
+        }
+        
+        let next = src[i];
+        i += 1;
+        
+        match next {
+            b'n' => bytes.push(b'\n'),
+            b't' => bytes.push(b'\t'),
+            b'r' => bytes.push(b'\r'),
+            b'b' => bytes.push(8),
+            b'f' => bytes.push(12),
+            b'v' => bytes.push(11),
+            b'a' => bytes.push(7),
+            
+            b'x' => {
+                let mut val : u32 = 0;
+                while i < src.len() && (src[i] as char).is_ascii_hexdigit() {
+                    val = val * 16 + (src[i] as char).to_digit(16).unwrap();
+                    i += 1;
+                }
+                bytes.push(val as u8);
+            },
+            
+            b'0' ..= b'7' => {
+                let mut val = (next - b'0') as u32;
+                let mut count = 1;
+                while count < 3 && i < src.len() && (b'0' ..= b'7').contains(&src[i]) {
+                    val = val * 8 + (src[i] - b'0') as u32;
+                    i += 1;
+                    count += 1;
+                }
+                bytes.push(val as u8);
+            },
+            
+            _ => bytes.push(next),
+        }
+    }
+    
+    bytes
+}
+
+impl Interp {
+    // Calls main and runs until the program exits
+    // Like the start-up code, main gets the argument array and the count. Programs
+    // built without the core library start at _start instead.
+    fn start(&mut self, args : &Vec<String>) -> Stop {
+        let mut ptrs : Vec<u8> = Vec::new();
+        
+        for arg in args.iter() {
+            let mut bytes = arg.as_bytes().to_vec();
+            bytes.push(0);
+            
+            let addr = self.mem.add_block(&bytes);
+            ptrs.extend_from_slice(&addr.to_le_bytes());
+        }
+        
+        self.args[1] = self.mem.add_block(&ptrs);
+        self.args[2] = args.len() as u64;
+        
+        let main = match self.funcs.get("main") {
+            Some(main) => *main,
+            None => self.funcs["_start"],
+        };
+        self.file = file_at(&self.files, main);
+        self.exec(main)
+    }
+    
+    // Writes program output to a file descriptor
+    // Only stdout and stderr can be written; returns false for anything else
+    fn write_fd(&mut self, fd : u64, bytes : &[u8]) -> bool {
+        match fd {
+            1 => {
+                self.out.write_all(bytes).ok();
+            },
+            
+            2 => {
+                self.out.flush().ok();
+                io::stderr().write_all(bytes).ok();
+            },
+            
+            _ => return false,
+        }
+        
+        true
+    }
+}
@@ -0,0 +1,182 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The memory of the interpreted program
+// There are two regions: the data section and the heap share one, which starts
+// at LOW_BASE and grows up, and the stack is a fixed block that ends at STACK_TOP.
+// Everything else (including the null page) is invalid.
+
+use std::collections::HashMap;
+
+use crate::Stop;
+
+pub const LOW_BASE : u64 = 0x10000;
+pub const STACK_TOP : u64 = 0x7ff0_0000_0000;
+pub const STACK_SIZE : u64 = 8 * 1024 * 1024;
+
+// Function addresses are below LOW_BASE, so they can't be read or written
+pub const FUNC_BASE : u64 = 0x1000;
+
+// The most the heap can grow to before malloc returns null
+const HEAP_LIMIT : u64 = 1 << 32;
+
+pub struct Memory {
+    low : Vec<u8>,
+    stack : Vec<u8>,
+    
+    // Freed blocks by size, so they can be handed out again
+    free_blocks : HashMap<u64, Vec<u64>>,
+}
+
+pub fn create_memory() -> Memory {
+    Memory {
+        low : Vec::new(),
+        stack : vec![0; STACK_SIZE as usize],
+        free_blocks : HashMap::new(),
+    }
+}
+
+impl Memory {
+    // Returns the region and offset for a block of memory
+    fn find(&mut self, addr : u64, size : u64) -> Result<(&mut Vec<u8>, usize), Stop> {
+        let end = addr.wrapping_add(size);
+        
+        if addr >= LOW_BASE && end >= addr && end <= LOW_BASE + self.low.len() as u64 {
+            return Ok((&mut self.low, (addr - LOW_BASE) as usize));
+        }
+        
+        if addr >= STACK_TOP - STACK_SIZE && end >= addr && end <= STACK_TOP {
+            return Ok((&mut self.stack, (addr - (STACK_TOP - STACK_SIZE)) as usize));
+        }
+        
+        Err(Stop::Fault(format!("Invalid memory access at 0x{:x}", addr), 139))
+    }
+    
+    // Reads a little-endian integer of 1, 2, 4, or 8 bytes
+    pub fn read(&mut self, addr : u64, size : u64) -> Result<u64, Stop> {
+        let (region, pos) = self.find(addr, size)?;
+        let mut val : u64 = 0;
+        
+        for i in (0 .. size as usize).rev() {
+            val = (val << 8) | region[pos + i] as u64;
+        }
+        
+        Ok(val)
+    }
+    
+    pub fn write(&mut self, addr : u64, size : u64, val : u64) -> Result<(), Stop> {
+        let (region, pos) = self.find(addr, size)?;
+        
+        for i in 0 .. size as usize {
+            region[pos + i] = (val >> (i * 8)) as u8;
+        }
+        
+        Ok(())
+    }
+    
+    pub fn read_bytes(&mut self, addr : u64, size : u64) -> Result<Vec<u8>, Stop> {
+        let (region, pos) = self.find(addr, size)?;
+        Ok(region[pos .. pos + size as usize].to_vec())
+    }
+    
+    pub fn write_bytes(&mut self, addr : u64, bytes : &[u8]) -> Result<(), Stop> {
+        let (region, pos) = self.find(addr, bytes.len() as u64)?;
+        region[pos .. pos + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+    
+    // Reads a null-terminated string (without the null)
+    pub fn read_cstr(&mut self, addr : u64) -> Result<Vec<u8>, Stop> {
+        let mut bytes : Vec<u8> = Vec::new();
+        let mut pos = addr;
+        
+        loop {
+            let c = self.read(pos, 1)? as u8;
+            if c == 0 {
+                break;
+            }
+            
+            bytes.push(c);
+            pos += 1;
+        }
+        
+        Ok(bytes)
+    }
+    
+    // Adds a block to the end of the low region, and returns its address
+    // Blocks are 8-byte aligned
+    pub fn add_block(&mut self, bytes : &[u8]) -> u64 {
+        while self.low.len() % 8 != 0 {
+            self.low.push(0);
+        }
+        
+        let addr = LOW_BASE + self.low.len() as u64;
+        self.low.extend_from_slice(bytes);
+        addr
+    }
+    
+    // Allocates a block on the heap
+    // Like the core library, the size is kept in the 8 bytes before the block so
+    // free and realloc know it. Returns 0 if we are out of memory.
+    pub fn malloc(&mut self, size : u64) -> u64 {
+        let size = (size.max(1) + 15) & !15;
+        
+        if let Some(blocks) = self.free_blocks.get_mut(&size) {
+            if let Some(addr) = blocks.pop() {
+                return addr;
+            }
+        }
+        
+        if self.low.len() as u64 + size + 16 > HEAP_LIMIT {
+            return 0;
+        }
+        
+        let header = self.add_block(&size.to_le_bytes());
+        self.low.resize(self.low.len() + size as usize, 0);
+        header + 8
+    }
+    
+    pub fn calloc(&mut self, size : u64) -> Result<u64, Stop> {
+        let addr = self.malloc(size);
+        
+        if addr != 0 {
+            let block_size = self.read(addr - 8, 8)?;
+            self.write_bytes(addr, &vec![0; block_size as usize])?;
+        }
+        
+        Ok(addr)
+    }
+    
+    pub fn free(&mut self, addr : u64) -> Result<(), Stop> {
+        if addr == 0 {
+            return Ok(());
+        }
+        
+        let size = self.read(addr - 8, 8)?;
+        self.free_blocks.entry(size).or_default().push(addr);
+        Ok(())
+    }
+    
+    pub fn realloc(&mut self, addr : u64, size : u64) -> Result<u64, Stop> {
+        if addr == 0 {
+            return Ok(self.malloc(size));
+        }
+        
+        let old_size = self.read(addr - 8, 8)?;
+        if size <= old_size {
+            return Ok(addr);
+        }
+        
+        let new_addr = self.malloc(size);
+        if new_addr != 0 {
+            let bytes = self.read_bytes(addr, old_size)?;
+            self.write_bytes(new_addr, &bytes)?;
+            self.free(addr)?;
+        }
+        
+        Ok(new_addr)
+    }
+}
@@ -0,0 +1,377 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The printf format
+// Like the x86-64 calling convention, integer arguments are taken in order after
+// the format string, and floats are taken in order from the float registers
+// (except on RISC-V, where they are passed with the integers).
+
+use crate::*;
+
+// A parsed conversion specification
+struct Spec {
+    left : bool,
+    plus : bool,
+    space : bool,
+    alt : bool,
+    zero : bool,
+    width : usize,
+    precision : Option<usize>,
+}
+
+// Pads a formatted value to the width
+// Zeros go between the sign (or prefix) and the digits.
+fn pad(spec : &Spec, prefix : &str, digits : &str, zero_ok : bool) -> Vec<u8> {
+    let len = prefix.len() + digits.len();
+    let mut text = String::new();
+    
+    if spec.left || len >= spec.width {
+        text.push_str(prefix);
+        text.push_str(digits);
+    } else if spec.zero && zero_ok {
+        text.push_str(prefix);
+        text.push_str(&"0".repeat(spec.width - len));
+        text.push_str(digits);
+    } else {
+        text.push_str(&" ".repeat(spec.width - len));
+        text.push_str(prefix);
+        text.push_str(digits);
+    }
+    
+    while text.len() < spec.width {
+        text.push(' ');
+    }
+    
+    text.into_bytes()
+}
+
+fn sign_prefix(spec : &Spec, negative : bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+// Formats the digits of an integer with the precision (the least number of digits)
+fn int_digits(spec : &Spec, digits : String) -> String {
+    match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if digits.len() < precision => "0".repeat(precision - digits.len()) + &digits,
+        _ => digits,
+    }
+}
+
+// Formats a number in the %e style, with at least two digits in the exponent
+fn exp_digits(val : f64, precision : usize) -> String {
+    let text = format!("{:.*e}", precision, val);
+    let (mantissa, exp) = text.split_at(text.find('e').unwrap());
+    let exp : i32 = exp[1..].parse().unwrap();
+    
+    let sign = if exp < 0 {
+        '-'
+    } else {
+        '+'
+    };
+    
+    format!("{}e{}{:02}", mantissa, sign, exp.abs())
+}
+
+// Removes the trailing zeros after a decimal point (for %g)
+fn strip_zeros(text : &str) -> String {
+    if !text.contains('.') {
+        return text.to_string();
+    }
+    
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// Formats the digits of a float (without the sign)
+fn float_digits(spec : &Spec, conv : u8, val : f64) -> String {
+    if val.is_nan() {
+        return "nan".to_string();
+    } else if val.is_infinite() {
+        return "inf".to_string();
+    }
+    
+    let precision = spec.precision.unwrap_or(6);
+    
+    let mut text = match conv {
+        b'e' | b'E' => exp_digits(val, precision),
+        
+        // %g uses %e if the exponent is less than -4 or at least the precision
+        b'g' | b'G' => {
+            let precision = precision.max(1);
+            let text = exp_digits(val, precision - 1);
+            let exp : i32 = text[text.find('e').unwrap() + 1 ..].parse().unwrap();
+            
+            if exp < -4 || exp >= precision as i32 {
+                let (mantissa, exp) = text.split_at(text.find('e').unwrap());
+                
+                if spec.alt {
+                    text.clone()
+                } else {
+                    strip_zeros(mantissa) + exp
+                }
+            } else {
+                let text = format!("{:.*}", (precision as i32 - 1 - exp) as usize, val);
+                
+                if spec.alt {
+                    text
+                } else {
+                    strip_zeros(&text)
+                }
+            }
+        },
+        
+        _ => format!("{:.*}", precision, val),
+    };
+    
+    if spec.alt && !text.contains('.') {
+        match text.find('e') {
+            Some(pos) => text.insert(pos, '.'),
+            None => text.push('.'),
+        }
+    }
+    
+    text
+}
+
+impl Interp {
+    fn next_int(&self, pos : &mut usize) -> u64 {
+        let val = match self.args.get(*pos) {
+            Some(val) => *val,
+            None => 0,
+        };
+        
+        *pos += 1;
+        val
+    }
+    
+    fn next_float(&self, pos : &mut usize) -> f64 {
+        let val = match self.flt_args.get(*pos) {
+            Some(val) => f64::from_bits(*val),
+            None => 0.0,
+        };
+        
+        *pos += 1;
+        val
+    }
+    
+    // Formats the arguments of a printf call
+    pub fn printf(&mut self) -> Result<Vec<u8>, Stop> {
+        let format = self.mem.read_cstr(self.args[1])?;
+        let mut out : Vec<u8> = Vec::new();
+        let mut int_pos = 2;
+        let mut flt_pos = 1;
+        let mut i = 0;
+        
+        let peek = |i : usize| -> u8 {
+            match format.get(i) {
+                Some(c) => *c,
+                None => 0,
+            }
+        };
+        
+        while i < format.len() {
+            let c = format[i];
+            i += 1;
+            
+            if c != b'%' {
+                out.push(c);
+                continue;
+            }
+            
+            let start = i - 1;
+            
+            let mut spec = Spec {
+                left : false,
+                plus : false,
+                space : false,
+                alt : false,
+                zero : false,
+                width : 0,
+                precision : None,
+            };
+            
+            // Flags
+            loop {
+                match peek(i) {
+                    b'-' => spec.left = true,
+                    b'+' => spec.plus = true,
+                    b' ' => spec.space = true,
+                    b'#' => spec.alt = true,
+                    b'0' => spec.zero = true,
+                    _ => break,
+                }
+                
+                i += 1;
+            }
+            
+            // The width and precision are either numbers or "*"
+            if peek(i) == b'*' {
+                let width = self.next_int(&mut int_pos) as u32 as i32;
+                spec.left |= width < 0;
+                spec.width = width.unsigned_abs() as usize;
+                i += 1;
+            } else {
+                while peek(i).is_ascii_digit() {
+                    spec.width = spec.width * 10 + (peek(i) - b'0') as usize;
+                    i += 1;
+                }
+            }
+            
+            if peek(i) == b'.' {
+                i += 1;
+                
+                if peek(i) == b'*' {
+                    let precision = self.next_int(&mut int_pos) as u32 as i32;
+                    if precision >= 0 {
+                        spec.precision = Some(precision as usize);
+                    }
+                    i += 1;
+                } else {
+                    let mut precision = 0;
+                    while peek(i).is_ascii_digit() {
+                        precision = precision * 10 + (peek(i) - b'0') as usize;
+                        i += 1;
+                    }
+                    spec.precision = Some(precision);
+                }
+            }
+            
+            // The size of an integer argument
+            let mut width = 32;
+            
+            loop {
+                match peek(i) {
+                    b'h' if width == 16 => width = 8,
+                    b'h' => width = 16,
+                    b'l' | b'z' | b'j' | b't' | b'q' => width = 64,
+                    b'L' => {},
+                    _ => break,
+                }
+                
+                i += 1;
+            }
+            
+            let conv = peek(i);
+            i += 1;
+            
+            match conv {
+                b'%' => out.push(b'%'),
+                
+                b'd' | b'i' => {
+                    let val = self.next_int(&mut int_pos);
+                    let val = ((val << (64 - width)) as i64) >> (64 - width);
+                    
+                    let digits = int_digits(&spec, val.unsigned_abs().to_string());
+                    let prefix = sign_prefix(&spec, val < 0);
+                    out.extend(pad(&spec, prefix, &digits, spec.precision.is_none()));
+                },
+                
+                b'u' | b'x' | b'X' | b'o' => {
+                    let mut val = self.next_int(&mut int_pos);
+                    if width < 64 {
+                        val &= (1 << width) - 1;
+                    }
+                    
+                    let digits = match conv {
+                        b'u' => val.to_string(),
+                        b'x' => format!("{:x}", val),
+                        b'X' => format!("{:X}", val),
+                        _ => format!("{:o}", val),
+                    };
+                    
+                    let mut digits = int_digits(&spec, digits);
+                    let mut prefix = "";
+                    
+                    if spec.alt && val != 0 {
+                        match conv {
+                            b'x' => prefix = "0x",
+                            b'X' => prefix = "0X",
+                            b'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                            _ => {},
+                        }
+                    }
+                    
+                    out.extend(pad(&spec, prefix, &digits, spec.precision.is_none()));
+                },
+                
+                b'c' => {
+                    let val = self.next_int(&mut int_pos) as u8;
+                    let mut text = pad(&spec, "", "_", false);
+                    
+                    let pos = text.iter().position(|c| *c == b'_').unwrap();
+                    text[pos] = val;
+                    out.extend(text);
+                },
+                
+                b's' => {
+                    let addr = self.next_int(&mut int_pos);
+                    let mut text = if addr == 0 {
+                        b"(null)".to_vec()
+                    } else {
+                        self.mem.read_cstr(addr)?
+                    };
+                    
+                    if let Some(precision) = spec.precision {
+                        text.truncate(precision);
+                    }
+                    
+                    // Pad with a placeholder, since the string may not be UTF-8
+                    let fill = "_".repeat(text.len());
+                    let padded = pad(&spec, "", &fill, false);
+                    let pos = padded.iter().position(|c| *c == b'_').unwrap_or(0);
+                    
+                    out.extend_from_slice(&padded[0 .. pos]);
+                    out.extend(text.iter());
+                    out.extend_from_slice(&padded[pos + fill.len() ..]);
+                },
+                
+                b'p' => {
+                    let val = self.next_int(&mut int_pos);
+                    let text = if val == 0 {
+                        "(nil)".to_string()
+                    } else {
+                        format!("0x{:x}", val)
+                    };
+                    
+                    out.extend(pad(&spec, "", &text, false));
+                },
+                
+                b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                    // RISC-V passes variadic doubles in the integer registers
+                    let val = if self.arch == Arch::Riscv64 {
+                        f64::from_bits(self.next_int(&mut int_pos))
+                    } else {
+                        self.next_float(&mut flt_pos)
+                    };
+                    let mut digits = float_digits(&spec, conv, val.abs());
+                    
+                    if conv.is_ascii_uppercase() {
+                        digits = digits.to_uppercase();
+                    }
+                    
+                    let prefix = sign_prefix(&spec, val.is_sign_negative() && !val.is_nan());
+                    out.extend(pad(&spec, prefix, &digits, val.is_finite()));
+                },
+                
+                // Anything else is printed as it is
+                _ => {
+                    i = i.min(format.len());
+                    out.extend_from_slice(&format[start .. i]);
+                },
+            }
+        }
+        
+        Ok(out)
+    }
+}
@@ -25,6 +25,10 @@ pub enum AstStmtType {
     FuncCall,
     Return,
     Exit,
+    Assert,
+    Panic,
+    Delete,
+    Resize,
     End,
 }
 
@@ -41,6 +45,7 @@ pub enum AstArgType {
     Id,
     Sizeof,
     AddrOf,
+    New(DataType),
     OpRParen,
     OpLParen,
     OpNeg,
@@ -62,6 +67,8 @@ pub enum AstArgType {
     OpLeftShift,
     OpRightShift,
     Range,
+    Dim,            // Separates the indices of multi-dimensional arrays
+    ArrayL,
 }
 
 // Represents our data types
@@ -83,6 +90,7 @@ pub enum DataType {
     Str,
     Ptr,
     Enum(String),
+    FuncPtr(Vec<DataType>, Box<DataType>),     // Argument types, return type
 }
 
 // Represents the top of an AST tree
@@ -90,6 +98,7 @@ pub struct AstTree {
     pub file_name : String,
     pub arch : Arch,
     pub module : String,
+    pub c_strings : bool,           // Set with "module <name> c_strings;"
     pub functions : Vec<AstFunc>,
     pub constants : Vec<AstConst>,
 }
@@ -98,6 +107,9 @@ pub struct AstTree {
 pub struct AstFunc {
     pub name : String,
     pub is_extern : bool,
+    pub module : String,            // The module an extern function was declared in, if any
+    pub c_strings : bool,           // The function's module takes and returns plain C strings
+    pub is_variadic : bool,         // Takes any number of arguments after the fixed ones ("..")
     pub statements : Vec<AstStmt>,
     pub args : Vec<AstStmt>,
     pub enums : Vec<AstEnum>,
@@ -262,6 +274,10 @@ impl AstStmt {
             AstStmtType::FuncCall => println!("FUNC CALL {}", self.name),
             AstStmtType::Return => println!("RETURN"),
             AstStmtType::Exit => println!("EXIT"),
+            AstStmtType::Assert => println!("ASSERT"),
+            AstStmtType::Panic => println!("PANIC"),
+            AstStmtType::Delete => println!("DELETE {}", self.name),
+            AstStmtType::Resize => println!("RESIZE {}", self.name),
             AstStmtType::End => println!("END"),
         }
         
@@ -309,6 +325,7 @@ impl AstArg {
             AstArgType::Id => print!("{} ", self.str_val),
             AstArgType::Sizeof => print!("SIZEOF "),
             AstArgType::AddrOf => print!("ADDR_OF "),
+            AstArgType::New(t) => print!("NEW {:?} ", t),
             AstArgType::OpLParen => print!("("),
             AstArgType::OpRParen => print!(")"),
             AstArgType::OpNeg => print!("-"),
@@ -330,6 +347,8 @@ impl AstArg {
             AstArgType::OpLeftShift => print!("<< "),
             AstArgType::OpRightShift => print!(">> "),
             AstArgType::Range => print!("RANGE "),
+            AstArgType::Dim => print!("DIM "),
+            AstArgType::ArrayL => print!("ARRAY "),
         }
         
         if self.sub_args.len() > 0 {
@@ -347,6 +366,9 @@ pub fn create_extern_func(name : String) -> AstFunc {
     AstFunc {
         name : name,
         is_extern : true,
+        module : String::new(),
+        c_strings : false,
+        is_variadic : false,
         statements : Vec::new(),
         args : Vec::new(),
         enums : Vec::new(),
@@ -359,6 +381,9 @@ pub fn create_func(name : String) -> AstFunc {
     AstFunc {
         name : name,
         is_extern : false,
+        module : String::new(),
+        c_strings : false,
+        is_variadic : false,
         statements : Vec::new(),
         args : Vec::new(),
         enums : Vec::new(),
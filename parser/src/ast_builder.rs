@@ -59,6 +59,7 @@ pub fn build_ast(path : String, arch : Arch, name : String, include_core : bool,
         file_name : name,
         arch : arch,
         module : String::new(),
+        c_strings : false,
         functions : Vec::new(),
         constants : Vec::new(),
     };
@@ -139,6 +140,14 @@ pub fn include_module(name : String, builder : &mut AstBuilder) -> bool {
     let old_scanner = builder.scanner.clone();
     builder.scanner = create_lex();
     
+    let func_count = builder.tree.functions.len();
+    
+    // The header has its own module line
+    let old_module = builder.tree.module.clone();
+    let old_c_strings = builder.tree.c_strings;
+    builder.tree.module = String::new();
+    builder.tree.c_strings = false;
+    
     for line in reader.lines() {
         let mut current = line.unwrap();
         current = current.trim().to_string();
@@ -166,6 +175,17 @@ pub fn include_module(name : String, builder : &mut AstBuilder) -> bool {
     
     builder.scanner = old_scanner;
     
+    let c_strings = builder.tree.c_strings;
+    builder.tree.module = old_module;
+    builder.tree.c_strings = old_c_strings;
+    
+    // Remember where each function came from, since the calling convention depends on it
+    let module = name.split('.').next().unwrap_or("").to_string();
+    for func in builder.tree.functions.iter_mut().skip(func_count) {
+        func.module = module.clone();
+        func.c_strings = c_strings;
+    }
+    
     true
 }
 
@@ -213,6 +233,10 @@ fn build_line(in_begin : bool, builder : &mut AstBuilder) -> (bool, bool, bool)
         
         Token::Return if in_code => code = build_return(builder),
         Token::Exit if in_code => code = build_exit(builder),
+        Token::Assert if in_code => code = build_assert(builder),
+        Token::Panic if in_code => code = build_panic(builder),
+        Token::Delete if in_code => code = build_delete(builder),
+        Token::Resize if in_code => code = build_resize(builder),
         Token::End => build_end(builder),
         Token::Const => code = build_const(builder),
         
@@ -11,6 +11,7 @@ use crate::lex::Token;
 use crate::ast_builder::*;
 use crate::ast_func::*;
 use crate::ast_utils::*;
+use crate::ast_var::*;
 
 // Responsible for building a block in a conditional statement or loop
 fn build_block(builder : &mut AstBuilder, mut cond_stmt : AstStmt) -> bool {
@@ -24,6 +25,10 @@ fn build_block(builder : &mut AstBuilder, mut cond_stmt : AstStmt) -> bool {
         match token {
             Token::Return => code = build_return(builder),
             Token::Exit => code = build_exit(builder),
+            Token::Assert => code = build_assert(builder),
+            Token::Panic => code = build_panic(builder),
+            Token::Delete => code = build_delete(builder),
+            Token::Resize => code = build_resize(builder),
             Token::Id(ref val) => code = build_id(builder, val.to_string()),
             Token::If => code = build_cond(builder, Token::If),
             Token::While => code = build_cond(builder, Token::While),
@@ -30,9 +30,13 @@ fn token_to_mod(token : &Token, is_array : bool) -> (DataType, DataType) {
         Token::Int if is_array => return (DataType::Ptr, DataType::Int),
         Token::Int => return (DataType::Int, DataType::None),
         
+        Token::UInt if is_array => return (DataType::Ptr, DataType::UInt),
         Token::UInt => return (DataType::UInt, DataType::None),
         
+        Token::Int64 if is_array => return (DataType::Ptr, DataType::Int64),
         Token::Int64 => return (DataType::Int64, DataType::None),
+        
+        Token::UInt64 if is_array => return (DataType::Ptr, DataType::UInt64),
         Token::UInt64 => return (DataType::UInt64, DataType::None),
         
         Token::Float if is_array => return (DataType::Ptr, DataType::Float),
@@ -48,10 +52,105 @@ fn token_to_mod(token : &Token, is_array : bool) -> (DataType, DataType) {
     }
 }
 
+// Builds a function pointer type: fn(<types>) -> <type>
+// The "fn" keyword should already be consumed. Because the return type is optional, we
+// have to read past the type, so the token after it is returned as well.
+pub fn build_func_type(builder : &mut AstBuilder) -> (DataType, Token) {
+    let mut token = builder.get_token();
+    
+    if token != Token::LParen {
+        builder.syntax_error("Expected \'(\' after \"fn\".".to_string());
+        return (DataType::None, token);
+    }
+    
+    let mut args : Vec<DataType> = Vec::new();
+    token = builder.get_token();
+    
+    while token != Token::RParen {
+        let arg_type : DataType;
+        
+        if token == Token::Fn {
+            let (val, next) = build_func_type(builder);
+            arg_type = val;
+            token = next;
+        } else {
+            let mut type_token = token.clone();
+            let mut is_array = false;
+            
+            if type_token == Token::Id("slice".to_string()) {
+                type_token = match build_slice_type(builder) {
+                    Some(t) => t,
+                    None => return (DataType::None, token),
+                };
+                is_array = true;
+            }
+            
+            token = builder.get_token();
+            
+            if token == Token::LBracket && !is_array {
+                if builder.get_token() != Token::RBracket {
+                    builder.syntax_error("Expected closing \']\'.".to_string());
+                    return (DataType::None, token);
+                }
+                
+                is_array = true;
+                token = builder.get_token();
+            }
+            
+            let (val, _) = token_to_mod(&type_token, is_array);
+            arg_type = val;
+        }
+        
+        if arg_type == DataType::None {
+            builder.syntax_error("Invalid function pointer argument type.".to_string());
+            return (DataType::None, token);
+        }
+        
+        args.push(arg_type);
+        
+        if token == Token::Comma {
+            token = builder.get_token();
+        } else if token != Token::RParen {
+            builder.syntax_error("Invalid function pointer arguments list.".to_string());
+            return (DataType::None, token);
+        }
+    }
+    
+    // Check for a return type
+    let mut ret = DataType::Void;
+    token = builder.get_token();
+    
+    if token == Token::Arrow {
+        token = builder.get_token();
+        
+        if token == Token::Fn {
+            let (val, next) = build_func_type(builder);
+            ret = val;
+            token = next;
+        } else {
+            let (val, _) = token_to_mod(&token, false);
+            ret = val;
+            token = builder.get_token();
+        }
+        
+        if ret == DataType::None {
+            builder.syntax_error("Invalid function pointer return type.".to_string());
+            return (DataType::None, token);
+        }
+    }
+    
+    (DataType::FuncPtr(args, Box::new(ret)), token)
+}
+
 // A helper function for the function declaration builder
 fn build_func_return(builder : &mut AstBuilder, func : &mut AstFunc) -> bool {
     let token = builder.get_token();
-    let (ret, _) = token_to_mod(&token, false);
+    let (mut ret, _) = token_to_mod(&token, false);
+    
+    if token == Token::Fn {
+        let (val, _) = build_func_type(builder);
+        ret = val;
+    }
     
     if ret == DataType::None {
         builder.syntax_error("Invalid function return type.".to_string());
@@ -112,6 +211,7 @@ pub fn build_func(builder : &mut AstBuilder, is_extern : bool) -> bool {
             Token::Id(ref val) => arg.name = val.to_string(),
             
             Token::Any => {
+                func.is_variadic = true;
                 token = builder.get_token();
                 
                 if token == Token::Comma || token == Token::RParen || token == Token::Eof {
@@ -138,7 +238,8 @@ pub fn build_func(builder : &mut AstBuilder, is_extern : bool) -> bool {
         }
         
         let sym_token = builder.get_token();
-        let type_token = builder.get_token();
+        let mut type_token = builder.get_token();
+        let mut func_type = DataType::None;
         let mut is_array = false;
         
         last_token = name_token.clone();
@@ -148,9 +249,28 @@ pub fn build_func(builder : &mut AstBuilder, is_extern : bool) -> bool {
             return false;
         }
         
-        token = builder.get_token();
+        if type_token == Token::Id("slice".to_string()) {
+            type_token = match build_slice_type(builder) {
+                Some(t) => t,
+                None => return false,
+            };
+            is_array = true;
+        }
+        
+        if type_token == Token::Fn {
+            let (val, next) = build_func_type(builder);
+            
+            if val == DataType::None {
+                return false;
+            }
+            
+            func_type = val;
+            token = next;
+        } else {
+            token = builder.get_token();
+        }
         
-        if token == Token::LBracket {
+        if token == Token::LBracket && !is_array {
             token = builder.get_token();
             is_array = true;
             
@@ -162,7 +282,14 @@ pub fn build_func(builder : &mut AstBuilder, is_extern : bool) -> bool {
             token = builder.get_token();
         }
         
-        let (val, sub_val) = token_to_mod(&type_token, is_array);
+        let (mut val, mut sub_val) = token_to_mod(&type_token, is_array);
+        
+        if type_token == Token::Fn && is_array {
+            val = DataType::Ptr;
+            sub_val = func_type;
+        } else if type_token == Token::Fn {
+            val = func_type;
+        }
     
         if val == DataType::None {
             builder.syntax_error("Invalid or missing function argument type.".to_string());
@@ -221,6 +348,79 @@ pub fn build_exit(builder : &mut AstBuilder) -> bool {
     true
 }
 
+// Builds the message of an assert or panic statement, and the closing ");"
+fn build_panic_msg(builder : &mut AstBuilder, stmt : &mut AstStmt) -> bool {
+    match builder.get_token() {
+        Token::StringL(ref val) => stmt.args.push(ast::create_string(val.to_string())),
+        
+        Token::Id(ref val) => {
+            let mut arg = ast::create_arg(AstArgType::Id);
+            arg.str_val = val.to_string();
+            stmt.args.push(arg);
+        },
+        
+        _ => {
+            builder.syntax_error("Expected message string.".to_string());
+            return false;
+        },
+    }
+    
+    if builder.get_token() != Token::RParen {
+        builder.syntax_error("Expected ')'.".to_string());
+        return false;
+    }
+    
+    if builder.get_token() != Token::Semicolon {
+        builder.syntax_error("Expected terminator".to_string());
+        return false;
+    }
+    
+    true
+}
+
+// Builds the assert statement
+// Syntax: assert(<condition>, <message>);
+// The message is the last argument
+pub fn build_assert(builder : &mut AstBuilder) -> bool {
+    let mut assert = ast::create_stmt(AstStmtType::Assert, &mut builder.scanner);
+    
+    if builder.get_token() != Token::LParen {
+        builder.syntax_error("Expected '('.".to_string());
+        return false;
+    }
+    
+    if !build_args(builder, &mut assert, Token::Comma) {
+        return false;
+    }
+    
+    if !build_panic_msg(builder, &mut assert) {
+        return false;
+    }
+    
+    builder.add_stmt(assert);
+    
+    true
+}
+
+// Builds the panic statement
+// Syntax: panic(<message>);
+pub fn build_panic(builder : &mut AstBuilder) -> bool {
+    let mut panic = ast::create_stmt(AstStmtType::Panic, &mut builder.scanner);
+    
+    if builder.get_token() != Token::LParen {
+        builder.syntax_error("Expected '('.".to_string());
+        return false;
+    }
+    
+    if !build_panic_msg(builder, &mut panic) {
+        return false;
+    }
+    
+    builder.add_stmt(panic);
+    
+    true
+}
+
 // Builds the end statement
 pub fn build_end(builder : &mut AstBuilder) {
     let stmt = ast::create_stmt(AstStmtType::End, &mut builder.scanner);
@@ -256,3 +456,20 @@ pub fn build_func_call(builder : &mut AstBuilder, id_val : String) -> bool {
     true
 }
 
+// Builds the element type of a slice (slice<T>)
+// A slice is the same as an array parameter, so it is passed with its length
+fn build_slice_type(builder : &mut AstBuilder) -> Option<Token> {
+    if builder.get_token() != Token::OpLt {
+        builder.syntax_error("Expected \'<\' after \"slice\".".to_string());
+        return None;
+    }
+    
+    let token = builder.get_token();
+    
+    if builder.get_token() != Token::OpGt {
+        builder.syntax_error("Expected closing \'>\'.".to_string());
+        return None;
+    }
+    
+    Some(token)
+}
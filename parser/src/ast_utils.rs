@@ -144,6 +144,20 @@ pub fn build_args(builder : &mut AstBuilder, stmt : &mut AstStmt, end : Token) -
                 }
             },
             
+            Token::New => {
+                let arg = build_new(&mut builder.scanner, &mut builder.syntax);
+                
+                if arg.arg_type == AstArgType::None {
+                    return false;
+                }
+                
+                if in_array {
+                    current_arg.sub_args.push(arg);
+                } else {
+                    args.push(arg);
+                }
+            },
+            
             Token::AddrOf => {
                 let arg = build_addrof(&mut builder.scanner, &mut builder.syntax);
                 
@@ -270,7 +284,13 @@ pub fn build_args(builder : &mut AstBuilder, stmt : &mut AstStmt, end : Token) -
             
             Token::Range => {
                 let arg = ast::create_arg(AstArgType::Range);
-                args.push(arg);
+                
+                // A range inside brackets is a slice
+                if in_array {
+                    current_arg.sub_args.push(arg);
+                } else {
+                    args.push(arg);
+                }
             },
             
             Token::LBracket | Token::LParen => {
@@ -278,6 +298,11 @@ pub fn build_args(builder : &mut AstBuilder, stmt : &mut AstStmt, end : Token) -
                 
                 let arg = args.pop().unwrap();
                 current_arg = arg;
+                
+                // Another index on the same array (ie, m[i][j])
+                if token == Token::LBracket && last == Token::RBracket {
+                    current_arg.sub_args.push(ast::create_arg(AstArgType::Dim));
+                }
             },
             
             Token::RBracket | Token::RParen => {
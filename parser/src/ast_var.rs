@@ -10,6 +10,7 @@ use crate::lex::{Token, Lex};
 use crate::syntax::ErrorManager;
 
 use crate::ast_builder::AstBuilder;
+use crate::ast_func::build_func_type;
 use crate::ast_utils::*;
 
 // Builds a variable declaration
@@ -50,6 +51,9 @@ pub fn build_var_dec(builder : &mut AstBuilder, name : String) -> bool {
     let mut dtype : DataType;
     let mut sub_type = DataType::None;
     
+    // Function pointer types read one token past the type
+    let mut next_token : Option<Token> = None;
+    
     token = builder.get_token();
     
     match token {
@@ -75,6 +79,17 @@ pub fn build_var_dec(builder : &mut AstBuilder, name : String) -> bool {
             dtype = DataType::Enum(val.to_string());
         },
         
+        Token::Fn => {
+            let (val, next) = build_func_type(builder);
+            
+            if val == DataType::None {
+                return false;
+            }
+            
+            dtype = val;
+            next_token = Some(next);
+        },
+        
         _ => {
             builder.syntax_error("Invalid type.".to_string());
             return false;
@@ -82,7 +97,10 @@ pub fn build_var_dec(builder : &mut AstBuilder, name : String) -> bool {
     }
     
     // Check for arrays
-    token = builder.get_token();
+    token = match next_token {
+        Some(next) => next,
+        None => builder.get_token(),
+    };
     
     match token {
         Token::Assign => {},
@@ -106,9 +124,45 @@ pub fn build_var_dec(builder : &mut AstBuilder, name : String) -> bool {
         sub_type = dtype;
         dtype = DataType::Ptr;
         
-        if builder.get_token() != Token::Semicolon {
-            builder.syntax_error("Expected terminator.".to_string());
-            return false;
+        // Multi-dimensional arrays
+        // The inner dimensions have to be known at compile time
+        token = builder.get_token();
+        
+        while token == Token::LBracket {
+            match builder.get_token() {
+                Token::IntL(val) if !var_dec.sub_args.is_empty() => {
+                    var_dec.sub_args.push(ast::create_arg(AstArgType::Dim));
+                    var_dec.sub_args.push(ast::create_int(val));
+                },
+                
+                _ => {
+                    builder.syntax_error("Inner array dimensions must be integer literals.".to_string());
+                    return false;
+                },
+            }
+            
+            if builder.get_token() != Token::RBracket {
+                builder.syntax_error("Expected \']\' after array dimension.".to_string());
+                return false;
+            }
+            
+            token = builder.get_token();
+        }
+        
+        // Arrays can be initialized from a literal, and unsized arrays from a new expression
+        match token {
+            Token::Semicolon => {},
+            
+            Token::Assign => {
+                if !build_array_init(builder, &mut var_dec) {
+                    return false;
+                }
+            },
+            
+            _ => {
+                builder.syntax_error("Expected terminator.".to_string());
+                return false;
+            },
         }
     } else {
         if !build_args(builder, &mut var_dec, Token::Semicolon) {
@@ -130,6 +184,90 @@ pub fn build_var_dec(builder : &mut AstBuilder, name : String) -> bool {
     true
 }
 
+// Builds the initializer of an array declaration
+// This is either an array literal or a new expression
+fn build_array_init(builder : &mut AstBuilder, var_dec : &mut AstStmt) -> bool {
+    let arg = match builder.get_token() {
+        Token::LBracket => build_array_literal(builder),
+        Token::New if var_dec.sub_args.is_empty() => build_new(&mut builder.scanner, &mut builder.syntax),
+        
+        _ => {
+            builder.syntax_error("Expected terminator.".to_string());
+            return false;
+        },
+    };
+    
+    if arg.arg_type == AstArgType::None {
+        return false;
+    }
+    
+    var_dec.args.push(arg);
+    
+    if builder.get_token() != Token::Semicolon {
+        builder.syntax_error("Expected terminator.".to_string());
+        return false;
+    }
+    
+    true
+}
+
+// Builds an array literal (ie, [1, 2, 3])
+// Nested brackets are flattened, so a multi-dimensional array can be written by rows
+pub fn build_array_literal(builder : &mut AstBuilder) -> AstArg {
+    let mut array = ast::create_arg(AstArgType::ArrayL);
+    let mut layer = 1;
+    let mut negate_next = false;
+    
+    while layer > 0 {
+        let token = builder.get_token();
+        
+        let arg = match token {
+            Token::LBracket => {
+                layer += 1;
+                continue;
+            },
+            
+            Token::RBracket => {
+                layer -= 1;
+                continue;
+            },
+            
+            Token::Comma | Token::Eof => continue,
+            
+            Token::OpSub if !negate_next => {
+                negate_next = true;
+                continue;
+            },
+            
+            Token::ByteL(val) => ast::create_byte(val),
+            Token::ShortL(val) => ast::create_short(val),
+            Token::IntL(val) => ast::create_int(val),
+            Token::FloatL(val) => ast::create_float(val),
+            Token::CharL(val) if !negate_next => ast::create_char(val),
+            Token::StringL(ref val) if !negate_next => ast::create_string(val.to_string()),
+            
+            // Constants are replaced with their values
+            Token::Id(ref val) if builder.global_consts.contains_key(val) => {
+                builder.global_consts.get(val).unwrap().value.clone()
+            },
+            
+            _ => {
+                builder.syntax_error("Array literals can only contain literal values.".to_string());
+                return ast::create_arg(AstArgType::None);
+            },
+        };
+        
+        if negate_next {
+            array.sub_args.push(ast::create_arg(AstArgType::OpNeg));
+            negate_next = false;
+        }
+        
+        array.sub_args.push(arg);
+    }
+    
+    array
+}
+
 // Builds a variable assignment
 fn build_var_assign_stmt(builder : &mut AstBuilder, var_assign : &mut AstStmt, name : String, assign_op : Token) -> bool {
     let mut check_end = false;
@@ -240,8 +378,20 @@ pub fn build_array_assign(builder : &mut AstBuilder, id_val : String) -> bool {
         return false;
     }
     
+    // Each extra index is separated by a dimension marker
+    let mut assign_op = builder.get_token();
+    
+    while assign_op == Token::LBracket {
+        array_assign.sub_args.push(ast::create_arg(AstArgType::Dim));
+        
+        if !build_args(builder, &mut array_assign, Token::RBracket) {
+            return false;
+        }
+        
+        assign_op = builder.get_token();
+    }
+    
     // Build the assignment
-    let assign_op = builder.get_token();
     
     if !build_var_assign_stmt(builder, &mut array_assign, id_val, assign_op) {
         return false;
@@ -260,7 +410,27 @@ pub fn build_sizeof(scanner : &mut Lex, syntax : &mut ErrorManager) -> AstArg {
     
     let token1 = scanner.get_token();   // '('
     let token2 = scanner.get_token();   // ID
-    let token3 = scanner.get_token();   // ')'
+    let mut token3 = scanner.get_token();   // ')'
+    
+    // The size of an inner dimension (ie, sizeof(m[0]))
+    // Each index only marks the dimension, so its value is skipped
+    let mut dims : Vec<AstArg> = Vec::new();
+    
+    while token3 == Token::LBracket {
+        loop {
+            match scanner.get_token() {
+                Token::RBracket => break,
+                Token::Semicolon | Token::Eof | Token::EoI => {
+                    syntax.syntax_error(scanner, "Expected \']\' after array index.".to_string());
+                    return ast::create_arg(AstArgType::None);
+                },
+                _ => {},
+            }
+        }
+        
+        dims.push(ast::create_arg(AstArgType::Dim));
+        token3 = scanner.get_token();
+    }
     
     if token1 != Token::LParen || token3 != Token::RParen {
         syntax.syntax_error(scanner, "Sizeof begins with \'(\' and ends with \')\'".to_string());
@@ -271,6 +441,7 @@ pub fn build_sizeof(scanner : &mut Lex, syntax : &mut ErrorManager) -> AstArg {
         Token::Id(ref val) => {
             let mut arg = ast::create_arg(AstArgType::Id);
             arg.str_val = val.to_string();
+            arg.sub_args = dims;
             sizeof.sub_args.push(arg);
         },
         
@@ -304,3 +475,121 @@ pub fn build_addrof(scanner : &mut Lex, syntax : &mut ErrorManager) -> AstArg {
     addrof
 }
 
+
+// Builds a new operation (allocate an array on the heap)
+pub fn build_new(scanner : &mut Lex, syntax : &mut ErrorManager) -> AstArg {
+    let token1 = scanner.get_token();   // Type
+    let token2 = scanner.get_token();   // '['
+    let token3 = scanner.get_token();   // Size
+    let token4 = scanner.get_token();   // ']'
+    
+    let data_type = match token1 {
+        Token::Byte => DataType::Byte,
+        Token::UByte => DataType::UByte,
+        Token::Short => DataType::Short,
+        Token::UShort => DataType::UShort,
+        Token::Int => DataType::Int,
+        Token::UInt => DataType::UInt,
+        Token::Int64 => DataType::Int64,
+        Token::UInt64 => DataType::UInt64,
+        Token::Float => DataType::Float,
+        Token::Double => DataType::Double,
+        Token::Char => DataType::Char,
+        Token::TStr => DataType::Str,
+        
+        _ => {
+            syntax.syntax_error(scanner, "Invalid type in new expression.".to_string());
+            return ast::create_arg(AstArgType::None);
+        },
+    };
+    
+    if token2 != Token::LBracket || token4 != Token::RBracket {
+        syntax.syntax_error(scanner, "The size of a new array must be enclosed in \'[\' and \']\'".to_string());
+        return ast::create_arg(AstArgType::None);
+    }
+    
+    let mut new = ast::create_arg(AstArgType::New(data_type));
+    
+    match token3 {
+        Token::IntL(val) => new.sub_args.push(ast::create_int(val)),
+        
+        Token::Id(ref val) => {
+            let mut arg = ast::create_arg(AstArgType::Id);
+            arg.str_val = val.to_string();
+            new.sub_args.push(arg);
+        },
+        
+        _ => {
+            syntax.syntax_error(scanner, "Expected array size.".to_string());
+            return ast::create_arg(AstArgType::None);
+        },
+    }
+    
+    new
+}
+
+// Builds a delete statement
+pub fn build_delete(builder : &mut AstBuilder) -> bool {
+    let mut delete = ast::create_stmt(AstStmtType::Delete, &mut builder.scanner);
+    
+    match builder.get_token() {
+        Token::Id(ref val) => delete.name = val.to_string(),
+        
+        _ => {
+            builder.syntax_error("Expected variable name.".to_string());
+            return false;
+        },
+    }
+    
+    if builder.get_token() != Token::Semicolon {
+        builder.syntax_error("Expected terminator.".to_string());
+        return false;
+    }
+    
+    builder.add_stmt(delete);
+    
+    true
+}
+
+// Builds a resize statement
+// Syntax: resize(array, size);
+pub fn build_resize(builder : &mut AstBuilder) -> bool {
+    let mut resize = ast::create_stmt(AstStmtType::Resize, &mut builder.scanner);
+    
+    if builder.get_token() != Token::LParen {
+        builder.syntax_error("Expected \'(\' after resize.".to_string());
+        return false;
+    }
+    
+    match builder.get_token() {
+        Token::Id(ref val) => resize.name = val.to_string(),
+        
+        _ => {
+            builder.syntax_error("Expected variable name.".to_string());
+            return false;
+        },
+    }
+    
+    if builder.get_token() != Token::Comma {
+        builder.syntax_error("Expected new size after the array name.".to_string());
+        return false;
+    }
+    
+    if !build_args(builder, &mut resize, Token::RParen) {
+        return false;
+    }
+    
+    if resize.args.len() != 1 {
+        builder.syntax_error("The new size must be an integer or variable.".to_string());
+        return false;
+    }
+    
+    if builder.get_token() != Token::Semicolon {
+        builder.syntax_error("Expected terminator.".to_string());
+        return false;
+    }
+    
+    builder.add_stmt(resize);
+    
+    true
+}
@@ -17,9 +17,12 @@ pub enum Token {
     
     Extern,
     Func,
+    Fn,
     Begin,
     Return,
     Exit,
+    Assert,
+    Panic,
     End,
     
     If,
@@ -65,6 +68,9 @@ pub enum Token {
     Any,
     Sizeof,
     AddrOf,
+    New,
+    Delete,
+    Resize,
     
     OpAdd,
     OpSub,
@@ -421,9 +427,12 @@ impl Lex {
             "enum" => token = Token::Enum,
             "extern" => token = Token::Extern,
             "func" => token = Token::Func,
+            "fn" => token = Token::Fn,
             "begin" => token = Token::Begin,
             "return" => token = Token::Return,
             "exit" => token = Token::Exit,
+            "assert" => token = Token::Assert,
+            "panic" => token = Token::Panic,
             "end" => token = Token::End,
             "const" => token = Token::Const,
             "byte" => token = Token::Byte,
@@ -447,6 +456,9 @@ impl Lex {
             "break" => token = Token::Break,
             "continue" => token = Token::Continue,
             "sizeof" => token = Token::Sizeof,
+            "new" => token = Token::New,
+            "delete" => token = Token::Delete,
+            "resize" => token = Token::Resize,
             _ => token = Token::Id(current.clone()),
         };
         
@@ -25,6 +25,8 @@ mod ltac_for;
 mod ltac_func;
 mod ltac_utils;
 mod ltac_var;
+mod ltac_text;
+mod ltac_verify;
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum Arch {
@@ -34,6 +36,7 @@ pub enum Arch {
 }
 
 // Import what we need
+use std::fs;
 use std::path::Path;
 
 use ast::AstTree;
@@ -51,7 +54,7 @@ pub fn get_ast(path : &String, arch : Arch, include_core : bool, keep_postfix :
 }
 
 // The main parse function
-pub fn parse(path : String, arch : Arch, include_core : bool) -> Result<LtacFile, ()> {
+pub fn parse(path : String, arch : Arch, include_core : bool, bounds_check : bool, overflow_checks : bool, div_checks : bool, release : bool) -> Result<LtacFile, ()> {
     let tree = match get_ast(&path.to_string(), arch, include_core, false) {
         Ok(tree) => tree,
         Err(_e) => return Err(()),
@@ -71,11 +74,41 @@ pub fn parse(path : String, arch : Arch, include_core : bool) -> Result<LtacFile
     let name = get_name(&path);
     
     let mut ltac_builder = ltac_builder::new_ltac_builder(name.clone(), &mut syntax);
+    ltac_builder.bounds_check = bounds_check;
+    ltac_builder.overflow_checks = overflow_checks;
+    ltac_builder.div_checks = div_checks;
+    ltac_builder.release = release;
+    ltac_builder.src_path = path.clone();
+    
     let ltac = match ltac_builder.build_ltac(&tree) {
         Ok(ltac) => ltac,
         Err(_e) => return Err(()),
     };
     
+    // Debug builds check the builder's output
+    if cfg!(debug_assertions) && ltac::verify(&ltac).is_err() {
+        println!("Error: The LTAC builder generated invalid code.");
+        return Err(());
+    }
+    
+    Ok(ltac)
+}
+
+// Reads an LTAC text file
+pub fn parse_ltac(path : &String) -> Result<LtacFile, ()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("Error: Unable to read {}: {}", path, e);
+            return Err(());
+        },
+    };
+    
+    let ltac = ltac::parse_text(&text, &get_name(path))?;
+    
+    // Hand-written LTAC is always checked, since the backends trust it
+    ltac::verify(&ltac)?;
+    
     Ok(ltac)
 }
 
@@ -4,12 +4,17 @@
 // Ida is licensed under the BSD-3 license. See the COPYING file for more information.
 //
 
+// The text format is in ltac_text.rs, and the verifier is in ltac_verify.rs
+pub use crate::ltac_text::{to_text, instr_to_text, parse_text};
+pub use crate::ltac_verify::verify;
+
 // Represents a data entry type
 #[derive(Clone, PartialEq)]
 pub enum LtacDataType {
     StringL,
     FloatL,
     DoubleL,
+    ArrayL(i32),        // Read-only array data; holds the element size
 }
 
 // Represents an instruction type
@@ -65,10 +70,13 @@ pub enum LtacType {
     PushArg,
     KPushArg,
     Call,
+    CallReg,        // Indirect call through a register
     Syscall,
     
     Malloc,
+    Calloc,
     Free,
+    Resize,
     Exit,
     
     // Comparison and flow instructions
@@ -140,8 +148,14 @@ pub enum LtacType {
     I32VAdd,
     
     // Type conversion instructions
-    // These aren't used by default, but some of the transform layers might
+    // Floats passed to variadic functions are promoted to double with CvtF32F64
+    // On the integer side, the size comes from the register
     CvtF32F64,
+    CvtF64F32,
+    CvtIntF32,
+    CvtIntF64,
+    CvtF32Int,    // Float to integer conversions truncate
+    CvtF64Int,
     MovF64Int,    // Move float-64 register to int register
 }
 
@@ -173,6 +187,7 @@ pub enum LtacArg {
     MemOffsetImm(i32, i32),
     MemOffsetMem(i32, i32, i32),    // Dest, var, size
     MemOffsetReg(i32, i32, i32),    // Dest, reg _no, size
+    MemOffsetStride(i32, i32, i32, i32, i32),   // Dest, row var, column reg_no, stride, size
     
     Byte(i8),       UByte(u8),
     I16(i16),       U16(u16),
@@ -182,7 +197,8 @@ pub enum LtacArg {
     F64(String),
     
     Ptr(i32),
-    PtrLcl(String)
+    PtrLcl(String),
+    PtrFunc(String),    // The address of a function
 }
 
 // Represents an LTAC file
@@ -202,7 +218,10 @@ pub struct LtacData {
 }
 
 // Represents an instruction
-#[derive(Debug, Clone)]
+// On integer add/sub/mul instructions, a name marks the operation as checked; the
+// backend branches to that label if the operation overflows. On div/mod, it branches
+// there if the divisor is zero.
+#[derive(Debug, Clone, PartialEq)]
 pub struct LtacInstr {
     pub instr_type : LtacType,
     pub name : String,
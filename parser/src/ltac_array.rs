@@ -5,11 +5,13 @@
 //
 
 use crate::ltac_builder::*;
+use crate::ast;
 use crate::ltac;
-use crate::ltac::{LtacType, LtacInstr, LtacArg};
-use crate::ast::{DataType, AstStmt, AstArgType};
+use crate::ltac::{LtacType, LtacArg};
+use crate::ast::{DataType, AstStmt, AstArg, AstArgType};
 
 use crate::ltac_expr::*;
+use crate::ltac_utils::*;
 
 // Assigns a value to an array
 pub fn build_array_assign(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
@@ -24,10 +26,102 @@ pub fn build_array_assign(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
     code
 }
 
+// Checks an index against a length at runtime (--bounds-check)
+// Only one of the two can be in memory. If the check fails, the core library
+// prints where it happened and exits.
+//
+// cmp index, 0
+// bl FAIL
+// cmp index, length
+// bl OK
+// FAIL:
+// pusharg "file.ida", line, index, length
+// call ida_bounds_panic
+// OK:
+//
+pub fn build_bounds_check(builder : &mut LtacBuilder, index : LtacArg, length : LtacArg) {
+    let fail_label = create_local_label(builder);
+    let ok_label = create_local_label(builder);
+    
+    let mut cmp = ltac::create_instr(LtacType::I32Cmp);
+    cmp.arg1 = index.clone();
+    cmp.arg2 = LtacArg::I32(0);
+    builder.file.code.push(cmp);
+    
+    let mut br = ltac::create_instr(LtacType::Bl);
+    br.name = fail_label.clone();
+    builder.file.code.push(br);
+    
+    cmp = ltac::create_instr(LtacType::I32Cmp);
+    cmp.arg1 = index.clone();
+    cmp.arg2 = length.clone();
+    builder.file.code.push(cmp);
+    
+    br = ltac::create_instr(LtacType::Bl);
+    br.name = ok_label.clone();
+    builder.file.code.push(br);
+    
+    let mut lbl = ltac::create_instr(LtacType::Label);
+    lbl.name = fail_label;
+    builder.file.code.push(lbl);
+    
+    let path = builder.build_src_path();
+    let line_no = builder.syntax.current_ln_no;
+    let args = [LtacArg::PtrLcl(path), LtacArg::I32(line_no), index, length];
+    
+    for (i, arg) in args.iter().enumerate() {
+        let mut push = ltac::create_instr(LtacType::PushArg);
+        push.arg1 = arg.clone();
+        push.arg2_val = (i as i32) + 1;
+        
+        if let LtacArg::Mem(_) = arg {
+            push.arg2 = LtacArg::I32(0);
+        }
+        
+        builder.file.code.push(push);
+    }
+    
+    let mut call = ltac::create_instr(LtacType::Call);
+    call.name = "ida_bounds_panic".to_string();
+    builder.file.code.push(call);
+    
+    lbl = ltac::create_instr(LtacType::Label);
+    lbl.name = ok_label;
+    builder.file.code.push(lbl);
+}
+
+// Checks a one-dimensional array index against the length stored with the array
+// The index is either a literal, a variable, or is already in a register.
+pub fn build_index_check(builder : &mut LtacBuilder, var : &Var, index : LtacArg) {
+    if !builder.bounds_check || var.data_type != DataType::Ptr {
+        return;
+    }
+    
+    let mut mov = ltac::create_instr(LtacType::Mov);
+    mov.arg1 = LtacArg::Reg32(0);
+    
+    match index {
+        LtacArg::I32(_) => mov.arg2 = index,
+        
+        LtacArg::Mem(_) => {
+            mov.instr_type = LtacType::Ld;
+            mov.arg2 = index;
+        },
+        
+        _ => {
+            build_bounds_check(builder, index, LtacArg::Mem(var.pos - 8));
+            return;
+        },
+    }
+    
+    builder.file.code.push(mov);
+    build_bounds_check(builder, LtacArg::Reg32(0), LtacArg::Mem(var.pos - 8));
+}
+
 // An internal function to free any dynamic arrays in the current context
 pub fn free_arrays(builder : &mut LtacBuilder, ignore : String) {
     for (name, var) in &builder.vars {
-        if var.data_type == DataType::Ptr && !var.is_param && *name != ignore {
+        if var.data_type == DataType::Ptr && var.is_owned && !var.is_param && *name != ignore {
             let mut pusharg = ltac::create_instr(LtacType::PushArg);
             pusharg.arg1 = LtacArg::Ptr(var.pos);
             pusharg.arg2_val = 1;
@@ -39,132 +133,313 @@ pub fn free_arrays(builder : &mut LtacBuilder, ignore : String) {
     }
 }
 
-// Initializes a an array in the heap
-pub fn build_dyn_array(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -> bool {
-    let sub_args = &line.sub_args;
-    let mut code = true;
+// Returns the size of a single array element
+fn array_elem_size(sub_type : &DataType) -> i32 {
+    match sub_type {
+        DataType::Byte | DataType::UByte => 1,
+        DataType::Short | DataType::UShort => 2,
+        DataType::Int64 | DataType::UInt64 => 8,
+        DataType::Double | DataType::Str => 8,
+        DataType::FuncPtr(_, _) => 8,
+        _ => 4,
+    }
+}
+
+// Loads an array element count into the first register
+// The count can either be an integer literal or an integer variable
+fn build_array_count(builder : &mut LtacBuilder, line : &AstStmt, arg : &AstArg) -> bool {
+    let mut instr = ltac::create_instr(LtacType::Mov);
+    instr.arg1 = LtacArg::Reg32(0);
     
-    // Setup the store instruction, which holds the array size
-    let mut size_instr = ltac::create_instr(LtacType::Str);
-    size_instr.arg1 = LtacArg::Mem(var.pos - 8);
+    match &arg.arg_type {
+        AstArgType::IntL => instr.arg2 = LtacArg::I32(arg.u64_val as i32),
+        
+        AstArgType::Id => {
+            let v = match builder.vars.get(&arg.str_val) {
+                Some(v) => v.clone(),
+                None => {
+                    builder.syntax.ltac_error(line, "Invalid identifier".to_string());
+                    return false;
+                },
+            };
+            
+            if v.data_type != DataType::Int && v.data_type != DataType::UInt {
+                builder.syntax.ltac_error(line, "Array size can only be set with integer values or variables.".to_string());
+                return false;
+            }
+            
+            instr.arg2 = LtacArg::Mem(v.pos);
+        },
+        
+        _ => {
+            builder.syntax.ltac_error(line, "Array size can only be set with integer values or variables.".to_string());
+            return false;
+        },
+    }
     
-    // Create the array
-    if sub_args.len() == 1 && sub_args.last().unwrap().arg_type == AstArgType::IntL {
-        let arg = sub_args.last().unwrap();
-        let mut size = 4;
-        
-        if var.sub_type == DataType::Byte || var.sub_type == DataType::UByte {
-            size = 1;
-        } else if var.sub_type == DataType::Short || var.sub_type == DataType::UShort {
-            size = 2;
-        } else if  var.sub_type == DataType::Int64 || var.sub_type == DataType::UInt64
-                || var.sub_type == DataType::Double || var.sub_type == DataType::Str {
-            size = 8;
-        }
+    builder.file.code.push(instr);
+    true
+}
+
+// Stores the array size from the first register
+fn build_store_count(builder : &mut LtacBuilder, var : &Var) {
+    let mut instr = ltac::create_instr(LtacType::Str);
+    instr.arg1 = LtacArg::Mem(var.pos - 8);
+    instr.arg2 = LtacArg::Reg32(0);
+    builder.file.code.push(instr);
+}
+
+// Allocates an array on the heap with a given number of elements
+// Arrays come from calloc, so they always start out zeroed
+// Instruction syntax:
+// mov i32.r0, count
+// str [pos-8], i32.r0
+// pusharg i32.r0
+// pusharg i32 size
+// calloc
+// mov [pos], ret.i64
+//
+fn build_array_alloc(builder : &mut LtacBuilder, line : &AstStmt, var : &Var, arg : &AstArg) -> bool {
+    let size = array_elem_size(&var.sub_type);
+    
+    if !build_array_count(builder, line, arg) {
+        return false;
+    }
+    
+    build_store_count(builder, var);
+    
+    // Only the outer dimension is stored, so multi-dimensional arrays
+    // need the full element count here
+    let inner : i32 = var.dims.iter().product();
+    
+    if inner > 1 {
+        let mut instr = ltac::create_instr(LtacType::I32Mul);
+        instr.arg1 = LtacArg::Reg32(0);
+        instr.arg2 = LtacArg::I32(inner);
+        builder.file.code.push(instr);
+    }
+    
+    let mut instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::Reg32(0);
+    instr.arg2_val = 1;
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::I32(size);
+    instr.arg2_val = 2;
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::Calloc);
+    builder.file.code.push(instr);
+    
+    // Move the return register back to the variable
+    instr = ltac::create_instr(LtacType::MovQ);
+    instr.arg1 = LtacArg::Mem(var.pos);
+    instr.arg2 = LtacArg::RetRegI64;
+    builder.file.code.push(instr);
+    
+    true
+}
+
+// Returns the values of an array literal as they should appear in the data section
+// Floating-point values are stored as their bits
+fn array_literal_vals(builder : &mut LtacBuilder, line : &AstStmt, var : &Var, literal : &AstArg) -> Option<Vec<String>> {
+    let mut vals : Vec<String> = Vec::new();
+    let mut negate_next = false;
+    
+    for arg in literal.sub_args.iter() {
+        let mut val : i64 = match &arg.arg_type {
+            AstArgType::OpNeg => {
+                negate_next = true;
+                continue;
+            },
+            
+            AstArgType::ByteL => arg.u8_val as i64,
+            AstArgType::ShortL => arg.u16_val as i64,
+            AstArgType::IntL => arg.u64_val as i64,
+            AstArgType::CharL => arg.char_val as i64,
+            _ => 0,
+        };
         
-        let mut pusharg = ltac::create_instr(LtacType::PushArg);
-        pusharg.arg1 = LtacArg::I32((arg.u64_val as i32) * size);
-        pusharg.arg2_val = 1;
+        let mut flt_val = match &arg.arg_type {
+            AstArgType::FloatL => arg.f64_val,
+            _ => val as f64,
+        };
         
-        builder.file.code.push(pusharg);
+        if negate_next {
+            val = val.wrapping_neg();
+            flt_val = -flt_val;
+            negate_next = false;
+        }
         
-        let mut instr = ltac::create_instr(LtacType::Malloc);
-        builder.file.code.push(instr);
+        let is_int = matches!(arg.arg_type, AstArgType::ByteL | AstArgType::ShortL | AstArgType::IntL);
+        let is_char = arg.arg_type == AstArgType::CharL;
         
-        // Move the return register back to the variable
-        instr = ltac::create_instr(LtacType::StrQ);
-        instr.arg1 = LtacArg::Mem(var.pos);
-        instr.arg2 = LtacArg::RetRegI64;
-        builder.file.code.push(instr);
+        let data = match &var.sub_type {
+            DataType::Float if is_int || arg.arg_type == AstArgType::FloatL => {
+                Some((flt_val as f32).to_bits().to_string())
+            },
+            
+            DataType::Double if is_int || arg.arg_type == AstArgType::FloatL => {
+                Some(flt_val.to_bits().to_string())
+            },
+            
+            DataType::Char if is_char => Some((val as i8).to_string()),
+            DataType::Byte | DataType::UByte if is_int || is_char => Some((val as i8).to_string()),
+            DataType::Short | DataType::UShort if is_int => Some((val as i16).to_string()),
+            DataType::Int | DataType::UInt if is_int => Some((val as i32).to_string()),
+            DataType::Int64 | DataType::UInt64 if is_int => Some(val.to_string()),
+            
+            _ => None,
+        };
         
-        // Store the size
-        let mut mov_size = ltac::create_instr(LtacType::Mov);
-        mov_size.arg1 = LtacArg::Reg32(0);
-        mov_size.arg2 = LtacArg::I32(arg.u64_val as i32);
-        builder.file.code.push(mov_size);
-        
-        size_instr.arg2 = LtacArg::Reg32(0); 
-        builder.file.code.push(size_instr);
-        
-    // An array with a variable as the size
-    } else if sub_args.len() == 1 && sub_args.last().unwrap().arg_type == AstArgType::Id {
-        let arg = sub_args.last().unwrap();
-        let size = 1;
-        let data_type : DataType;
-        let pos : i32;
-        
-        match &builder.vars.get(&arg.str_val) {
-            Some(v) => {
-                data_type = v.data_type.clone();
-                pos = v.pos;
-            }
+        match data {
+            Some(data) => vals.push(data),
             
             None => {
-                builder.syntax.ltac_error(line, "Invalid identifier".to_string());
-                return false;
+                builder.syntax.ltac_error(line, "Invalid value in array literal.".to_string());
+                return None;
             },
         }
-        
-        if data_type != DataType::Int && data_type != DataType::UInt {
-            builder.syntax.ltac_error(line, "Array size can only be set with integer values or variables.".to_string());
-            return false;
+    }
+    
+    Some(vals)
+}
+
+// Copies an array literal into a newly allocated array
+// The literal is stored as read-only data, so we just need a memcpy
+// Instruction syntax:
+// pusharg ptr [pos]
+// pusharg ptr ARRAY0
+// pusharg i32 (count * size)
+// call memcpy
+//
+// String arrays hold pointers, so those elements are assigned one at a time
+fn build_array_copy(builder : &mut LtacBuilder, line : &AstStmt, var : &Var, literal : &AstArg) -> bool {
+    if var.sub_type == DataType::Str {
+        for (i, arg) in literal.sub_args.iter().enumerate() {
+            if arg.arg_type != AstArgType::StringL {
+                builder.syntax.ltac_error(line, "Invalid value in array literal.".to_string());
+                return false;
+            }
+            
+            let mut assign = line.clone();
+            assign.sub_args = vec![ast::create_int(i as u64)];
+            assign.args = vec![arg.clone()];
+            
+            if !build_array_assign(builder, &assign) {
+                return false;
+            }
         }
         
-        // Instruction syntax:
-        // mov u32.r0, [pos]
-        // imul u32.r0, size
-        // pusharg u32
-        // call malloc
+        return true;
+    }
+    
+    let vals = match array_literal_vals(builder, line, var, literal) {
+        Some(vals) => vals,
+        None => return false,
+    };
+    
+    let size = array_elem_size(&var.sub_type);
+    let name = builder.build_array_data(size, &vals);
+    
+    let mut instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::Ptr(var.pos);
+    instr.arg2_val = 1;
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::PtrLcl(name);
+    instr.arg2_val = 2;
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::I32(vals.len() as i32 * size);
+    instr.arg2_val = 3;
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::Call);
+    instr.name = "memcpy".to_string();
+    builder.file.code.push(instr);
+    
+    true
+}
+
+// Allocates an array and fills it from an array literal
+// If the array has no size, the literal decides it
+fn build_array_init(builder : &mut LtacBuilder, line : &AstStmt, var : &Var, literal : &AstArg) -> bool {
+    let count = literal.sub_args.iter()
+        .filter(|a| a.arg_type != AstArgType::OpNeg)
+        .count() as i32;
+    
+    let size_arg = match line.sub_args.first() {
+        Some(arg) if arg.arg_type == AstArgType::IntL => arg.clone(),
+        None => ast::create_int(count as u64),
+        
+        Some(_) => {
+            builder.syntax.ltac_error(line, "Arrays initialized with a literal need a constant size.".to_string());
+            return false;
+        },
+    };
+    
+    let inner : i32 = var.dims.iter().product();
+    if count > size_arg.u64_val as i32 * inner {
+        builder.syntax.ltac_error(line, "Too many values in array literal.".to_string());
+        return false;
+    }
+    
+    if !build_array_alloc(builder, line, var, &size_arg) {
+        return false;
+    }
+    
+    build_array_copy(builder, line, var, literal)
+}
+
+// Initializes a an array in the heap
+pub fn build_dyn_array(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -> bool {
+    let sub_args = &line.sub_args;
+    let mut code = true;
+    
+    // Create the array
+    // Any extra arguments are the inner dimensions, which are held by the variable
+    if line.args.len() == 1 && line.args[0].arg_type == AstArgType::ArrayL {
+        let literal = line.args.first().unwrap();
+        code = build_array_init(builder, line, var, literal);
+    
+    } else if sub_args.len() == 1 + var.dims.len() * 2 {
+        let arg = sub_args.first().unwrap();
+        code = build_array_alloc(builder, line, var, arg);
         
-        let mut instr : LtacInstr;
+    // Allocated with a new expression
+    } else if line.args.len() == 1 && matches!(line.args[0].arg_type, AstArgType::New(_)) {
+        let arg = line.args.first().unwrap();
         
-        if size > 1 {
-            instr = ltac::create_instr(LtacType::MovU);
-            instr.arg1 = LtacArg::Reg32(0);
-            instr.arg2 = LtacArg::Mem(pos);
-            builder.file.code.push(instr.clone());
-            
-            instr = ltac::create_instr(LtacType::U32Mul);
-            instr.arg1 = LtacArg::Reg32(0);
-            instr.arg2 = LtacArg::U32(size);
-            builder.file.code.push(instr.clone());
-            
-            // Store the size
-            size_instr.arg2 = LtacArg::Reg32(0);
-            builder.file.code.push(size_instr);
-            
-            // Prepare for the call
-            instr = ltac::create_instr(LtacType::PushArg);
-            instr.arg1 = LtacArg::Reg32(0);
-            instr.arg2_val = 1;
-            builder.file.code.push(instr.clone());
-        } else {
-            // Store the size
-            let mut instr2 = ltac::create_instr(LtacType::Mov);
-            instr2.arg1 = LtacArg::Reg32(0);
-            instr2.arg2 = LtacArg::Mem(pos);
-            builder.file.code.push(instr2);
-            
-            size_instr.arg2 = LtacArg::Reg32(0);
-            builder.file.code.push(size_instr);
-        
-            // Prepare for the call
-            instr = ltac::create_instr(LtacType::PushArg);
-            instr.arg1 = LtacArg::Mem(pos);
-            instr.arg2 = LtacArg::Reg32(0);
-            instr.arg2_val = 1;
-            builder.file.code.push(instr.clone());
+        if arg.arg_type != AstArgType::New(var.sub_type.clone()) {
+            builder.syntax.ltac_error(line, "The type of the new expression does not match the array type.".to_string());
+            return false;
         }
         
-        instr = ltac::create_instr(LtacType::Malloc);
+        code = build_array_alloc(builder, line, var, arg.sub_args.first().unwrap());
+        
+    // An unsized array starts out empty
+    } else if sub_args.is_empty() && line.args.is_empty() {
+        let mut instr = ltac::create_instr(LtacType::MovQ);
+        instr.arg1 = LtacArg::Reg64(0);
+        instr.arg2 = LtacArg::I64(0);
         builder.file.code.push(instr);
         
-        // Move the return register back to the variable
-        instr = ltac::create_instr(LtacType::MovQ);
+        instr = ltac::create_instr(LtacType::StrQ);
         instr.arg1 = LtacArg::Mem(var.pos);
-        instr.arg2 = LtacArg::RetRegI64;
+        instr.arg2 = LtacArg::Reg64(0);
         builder.file.code.push(instr);
         
+        instr = ltac::create_instr(LtacType::Mov);
+        instr.arg1 = LtacArg::Reg32(0);
+        instr.arg2 = LtacArg::I32(0);
+        builder.file.code.push(instr);
+        
+        build_store_count(builder, var);
+        
     // Vector math
     } else if sub_args.len() == 0 && line.args.len() > 1 {
         code = build_i32array_vector_math(builder, line, var);
@@ -175,6 +450,114 @@ pub fn build_dyn_array(builder : &mut LtacBuilder, line : &AstStmt, var : &Var)
     code
 }
 
+// Builds a delete statement
+// The array is freed and then cleared so a later free is harmless
+pub fn build_delete(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
+    let var = match builder.vars.get_mut(&line.name) {
+        Some(v) if v.data_type == DataType::Ptr => {
+            v.is_owned = false;
+            v.clone()
+        },
+        
+        _ => {
+            builder.syntax.ltac_error(line, "You can only delete arrays.".to_string());
+            return false;
+        },
+    };
+    
+    let mut instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::Ptr(var.pos);
+    instr.arg2_val = 1;
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::Free);
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::MovQ);
+    instr.arg1 = LtacArg::Reg64(0);
+    instr.arg2 = LtacArg::I64(0);
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::StrQ);
+    instr.arg1 = LtacArg::Mem(var.pos);
+    instr.arg2 = LtacArg::Reg64(0);
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::Mov);
+    instr.arg1 = LtacArg::Reg32(0);
+    instr.arg2 = LtacArg::I32(0);
+    builder.file.code.push(instr);
+    
+    build_store_count(builder, &var);
+    
+    true
+}
+
+// Builds a resize statement
+// Instruction syntax:
+// pusharg ptr [pos]
+// pusharg i32 (count * size)
+// resize
+// str [pos], ret.i64
+//
+// The transform layer turns this into a call to realloc
+pub fn build_resize(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
+    let var = match builder.vars.get(&line.name) {
+        Some(v) if v.data_type == DataType::Ptr => v.clone(),
+        
+        _ => {
+            builder.syntax.ltac_error(line, "You can only resize arrays.".to_string());
+            return false;
+        },
+    };
+    
+    if !var.dims.is_empty() {
+        builder.syntax.ltac_error(line, "Multi-dimensional arrays cannot be resized.".to_string());
+        return false;
+    }
+    
+    let size = array_elem_size(&var.sub_type);
+    let arg = line.args.first().unwrap();
+    
+    let mut instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::Ptr(var.pos);
+    instr.arg2_val = 1;
+    builder.file.code.push(instr);
+    
+    // The new size
+    if !build_array_count(builder, line, arg) {
+        return false;
+    }
+    
+    instr = ltac::create_instr(LtacType::I32Mul);
+    instr.arg1 = LtacArg::Reg32(0);
+    instr.arg2 = LtacArg::I32(size);
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::PushArg);
+    instr.arg1 = LtacArg::Reg32(0);
+    instr.arg2_val = 2;
+    builder.file.code.push(instr);
+    
+    instr = ltac::create_instr(LtacType::Resize);
+    instr.arg1 = LtacArg::Ptr(var.pos);
+    builder.file.code.push(instr);
+    
+    // Store the new pointer and count
+    instr = ltac::create_instr(LtacType::StrQ);
+    instr.arg1 = LtacArg::Mem(var.pos);
+    instr.arg2 = LtacArg::RetRegI64;
+    builder.file.code.push(instr);
+    
+    if !build_array_count(builder, line, arg) {
+        return false;
+    }
+    
+    build_store_count(builder, &var);
+    
+    true
+}
+
 // Builds integer vector math
 pub fn build_i32array_vector_math(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -> bool {
     let mut instr = ltac::create_instr(LtacType::MovI32Vec);
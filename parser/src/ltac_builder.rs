@@ -16,6 +16,7 @@ use crate::ltac_array::*;
 use crate::ltac_flow::*;
 use crate::ltac_for::*;
 use crate::ltac_func::*;
+use crate::ltac_utils::*;
 use crate::ltac_var::*;
 
 #[derive(Clone)]
@@ -24,6 +25,8 @@ pub struct Var {
     pub data_type : DataType,
     pub sub_type : DataType,        // Only in the case of enums and pointers
     pub is_param : bool,
+    pub is_owned : bool,            // Owned arrays are freed when the function returns
+    pub dims : Vec<i32>,            // The inner dimensions of multi-dimensional arrays
 }
 
 #[derive(Clone)]
@@ -33,9 +36,14 @@ pub struct LtacBuilder {
     
     pub str_pos : i32,
     pub flt_pos : i32,
+    pub arr_pos : i32,
     
     // Function-related values
     pub functions : HashMap<String, DataType>,
+    pub signatures : HashMap<String, DataType>,     // Function pointer types for each function
+    pub c_functions : Vec<String>,                  // Extern functions that take plain pointers instead of arrays and strings
+    pub c_str_functions : Vec<String>,              // Library functions that take strings as plain pointers
+    pub variadic : Vec<String>,                     // Functions that take extra arguments ("..")
     pub current_func : String,
     pub current_type : DataType,
     pub current_sub_type : DataType,
@@ -44,6 +52,7 @@ pub struct LtacBuilder {
     pub enums : HashMap<String, AstEnum>,        // HashMap for easier searching
     pub vars : HashMap<String, Var>,
     pub stack_pos : i32,
+    pub c_strings : bool,                        // The core and standard libraries use plain C strings
     
     // For labels and blocks
     pub block_layer : i32,
@@ -56,6 +65,16 @@ pub struct LtacBuilder {
     pub loop_layer : i32,
     pub loop_labels : HashMap<i32, String>,      // Needed for continue
     pub end_labels : HashMap<i32, String>,       // Needed for break
+    
+    // Runtime checks
+    pub bounds_check : bool,
+    pub release : bool,                          // Leaves out asserts
+    pub src_path : String,
+    pub src_label : String,                      // The path in the data section (created on first use)
+    pub overflow_checks : bool,
+    pub div_checks : bool,
+    pub check_labels : HashMap<(i32, String), String>,  // The panic for each line and check of the current function
+    pub check_stubs : Vec<LtacInstr>,                   // Added to the end of the current function
 }
 
 pub fn new_ltac_builder(name : String, syntax : &mut ErrorManager) -> LtacBuilder {
@@ -68,13 +87,19 @@ pub fn new_ltac_builder(name : String, syntax : &mut ErrorManager) -> LtacBuilde
         syntax : syntax.clone(),
         str_pos : 0,
         flt_pos : 0,
+        arr_pos : 0,
         functions : HashMap::new(),
+        signatures : HashMap::new(),
+        c_functions : Vec::new(),
+        c_str_functions : Vec::new(),
+        variadic : Vec::new(),
         current_func : String::new(),
         current_type : DataType::Void,
         current_sub_type : DataType::None,
         enums : HashMap::new(),
         vars : HashMap::new(),
         stack_pos : 0,
+        c_strings : false,
         block_layer : 0,
         label_stack : Vec::new(),
         label_map : HashMap::new(),
@@ -83,6 +108,14 @@ pub fn new_ltac_builder(name : String, syntax : &mut ErrorManager) -> LtacBuilde
         loop_layer : 0,
         loop_labels : HashMap::new(),
         end_labels : HashMap::new(),
+        bounds_check : false,
+        release : false,
+        src_path : String::new(),
+        src_label : String::new(),
+        overflow_checks : false,
+        div_checks : false,
+        check_labels : HashMap::new(),
+        check_stubs : Vec::new(),
     }
 }
 
@@ -91,8 +124,13 @@ impl LtacBuilder {
 
     // Builds the main LTAC file
     pub fn build_ltac(&mut self, tree : &AstTree) -> Result<LtacFile, ()> {
+        self.c_strings = tree.c_strings;
+        
         // Build functions
-        if !self.build_functions(tree) {
+        let code = self.build_functions(tree);
+        self.syntax.print_warnings();
+        
+        if !code {
             self.syntax.print_errors();
             return Err(());
         }
@@ -111,6 +149,29 @@ impl LtacBuilder {
             if func.data_type != DataType::None {
                 func_type = func.data_type.clone();
             }
+            
+            let mut arg_types : Vec<DataType> = Vec::new();
+            
+            for arg in func.args.iter() {
+                arg_types.push(arg.data_type.clone());
+            }
+            
+            let signature = DataType::FuncPtr(arg_types, Box::new(func_type.clone()));
+            self.signatures.insert(name.clone(), signature);
+            
+            if func.is_extern && func.module.is_empty() {
+                self.c_functions.push(name.clone());
+            }
+            
+            // The libraries keep C strings, since some of their functions stand in for the C
+            // ones (and are often declared with extern)
+            if self.c_strings || func.c_strings {
+                self.c_str_functions.push(name.clone());
+            }
+            
+            if func.is_variadic {
+                self.variadic.push(name.clone());
+            }
         
             self.functions.insert(name, func_type);
         }
@@ -158,6 +219,10 @@ impl LtacBuilder {
                     return false;
                 }
                 
+                // The check panics go after the body so they stay out of the way
+                self.file.code.append(&mut self.check_stubs);
+                self.check_labels.clear();
+                
                 if self.vars.len() > 0 {
                     let mut stack_size = 0;
                     while stack_size < (self.stack_pos + 1) {
@@ -180,8 +245,21 @@ impl LtacBuilder {
     // Builds function body
     fn build_block(&mut self, statements : &Vec<AstStmt>) -> bool {
         let mut code = true;
+        let mut done = false;       // Set once a statement leaves the block
     
         for line in statements {
+            self.syntax.set_data(line);
+            
+            // Only warn about the first statement that can't be reached
+            match &line.stmt_type {
+                AstStmtType::End | AstStmtType::Elif | AstStmtType::Else => {},
+                _ if done => {
+                    self.syntax.ltac_warning(&line, "This statement can't be reached.".to_string());
+                    done = false;
+                },
+                _ => {},
+            }
+            
             match &line.stmt_type {
                 AstStmtType::VarDec => code = build_var_dec(self, &line, 0, 0).0,
                 AstStmtType::VarAssign => code = build_var_assign(self, &line),
@@ -196,9 +274,19 @@ impl LtacBuilder {
                 AstStmtType::FuncCall => code = build_func_call(self, &line),
                 AstStmtType::Return => code = build_return(self, &line),
                 AstStmtType::Exit => code = build_exit(self, &line),
+                AstStmtType::Assert => code = build_assert(self, line),
+                AstStmtType::Panic => code = build_panic(self, line),
+                AstStmtType::Delete => code = build_delete(self, line),
+                AstStmtType::Resize => code = build_resize(self, line),
                 AstStmtType::End => code = build_end(self, &line),
             }
             
+            match &line.stmt_type {
+                AstStmtType::Return | AstStmtType::Exit | AstStmtType::Panic
+                | AstStmtType::Break | AstStmtType::Continue => done = true,
+                _ => {},
+            }
+            
             if !code {
                 break;
             }
@@ -244,6 +332,23 @@ impl LtacBuilder {
         }
     }
 
+    pub fn get_signature(&self, name : &String) -> Result<&DataType, ()> {
+        self.signatures.get(name).ok_or(())
+    }
+    
+    // Checks whether there is a function pointer variable with this name
+    pub fn func_ptr_exists(&self, name : &String) -> bool {
+        matches!(self.vars.get(name), Some(v) if matches!(v.data_type, DataType::FuncPtr(_, _)))
+    }
+    
+    // Returns the return type of a function pointer variable
+    pub fn get_func_ptr(&self, name : &String) -> Result<&DataType, ()> {
+        match self.vars.get(name) {
+            Some(Var { data_type : DataType::FuncPtr(_, ret), .. }) => Ok(ret.as_ref()),
+            _ => Err(()),
+        }
+    }
+
     // Builds a string and adds it to the data section
     pub fn build_string(&mut self, val : String) -> String {
         // Create the string name
@@ -265,6 +370,72 @@ impl LtacBuilder {
         name
     }
     
+    // Returns the data label holding the path of the source file
+    pub fn build_src_path(&mut self) -> String {
+        if self.src_label.is_empty() {
+            self.src_label = self.build_string(self.src_path.clone());
+        }
+        
+        self.src_label.clone()
+    }
+    
+    // Returns the label of a runtime check's panic for the current line
+    // The panic itself (a call to the given core library function) is added to the end of the function
+    pub fn build_check_label(&mut self, panic : &str) -> String {
+        let line_no = self.syntax.current_ln_no;
+        let key = (line_no, panic.to_string());
+        
+        if let Some(label) = self.check_labels.get(&key) {
+            return label.clone();
+        }
+        
+        let label = create_local_label(self);
+        let path = self.build_src_path();
+        
+        let mut lbl = ltac::create_instr(LtacType::Label);
+        lbl.name = label.clone();
+        self.check_stubs.push(lbl);
+        
+        let mut push = ltac::create_instr(LtacType::PushArg);
+        push.arg1 = LtacArg::PtrLcl(path);
+        push.arg2_val = 1;
+        self.check_stubs.push(push);
+        
+        push = ltac::create_instr(LtacType::PushArg);
+        push.arg1 = LtacArg::I32(line_no);
+        push.arg2_val = 2;
+        self.check_stubs.push(push);
+        
+        let mut call = ltac::create_instr(LtacType::Call);
+        call.name = panic.to_string();
+        self.check_stubs.push(call);
+        
+        self.check_labels.insert(key, label.clone());
+        label
+    }
+    
+    // Joins string literals at compile time and adds the result to the data section
+    pub fn build_string_concat(&mut self, vals : &[String]) -> String {
+        self.build_string(vals.concat())
+    }
+    
+    // Builds the data for an array literal and adds it to the data section
+    pub fn build_array_data(&mut self, elem_size : i32, vals : &[String]) -> String {
+        let mut name = "ARRAY".to_string();
+        name.push_str(&self.arr_pos.to_string());
+        self.arr_pos += 1;
+        
+        let array = LtacData {
+            data_type : LtacDataType::ArrayL(elem_size),
+            name : name.clone(),
+            val : vals.join(", "),
+        };
+        
+        self.file.data.push(array);
+        
+        name
+    }
+    
     // Builds a float literal and adds it to the data section
     // https://stackoverflow.com/questions/40030551/how-to-decode-and-encode-a-float-in-rust
     pub fn build_float(&mut self, v : f64, is_double : bool, negate_next : bool) -> String {
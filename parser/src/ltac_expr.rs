@@ -14,6 +14,7 @@ use crate::ltac;
 use crate::ltac::{LtacType, LtacArg, LtacInstr};
 
 use crate::ltac_builder::*;
+use crate::ltac_array::*;
 use crate::ltac_func::*;
 use crate::ltac_utils::*;
 
@@ -46,22 +47,33 @@ pub fn build_var_math(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -
         } else if var.sub_type == DataType::Short || var.sub_type == DataType::UShort {
             offset_size = 2;
         } else if var.sub_type == DataType::Int64 || var.sub_type == DataType::UInt64
-            || var.sub_type == DataType::Double || var.sub_type == DataType::Str {
+            || var.sub_type == DataType::Double || var.sub_type == DataType::Str
+            || matches!(var.sub_type, DataType::FuncPtr(_, _)) {
             offset_size = 8;
         }
         
-        if line.sub_args.len() == 1 {
+        if line.sub_args.iter().any(|a| a.arg_type == AstArgType::Dim) {
+            instr.arg1 = match build_array_offset(builder, &line.sub_args, var, reg_no + 1, offset_size) {
+                Some(arg) => arg,
+                None => return false,
+            };
+        } else if line.sub_args.len() == 1 {
             if first_arg.arg_type == AstArgType::IntL {
+                build_index_check(builder, var, LtacArg::I32(first_arg.u64_val as i32));
+                
                 let offset = (first_arg.u64_val as i32) * offset_size;
                 instr.arg1 = LtacArg::MemOffsetImm(var.pos, offset);
             } else if first_arg.arg_type == AstArgType::Id {
-                match builder.vars.get(&first_arg.str_val) {
-                    Some(v) => instr.arg1 = LtacArg::MemOffsetMem(var.pos, v.pos, offset_size),
+                let index_pos = match builder.vars.get(&first_arg.str_val) {
+                    Some(v) => v.pos,
                     None => {
                         builder.syntax.ltac_error2("Invalid offset variable.".to_string());
                         return false;
                     },
-                }
+                };
+                
+                build_index_check(builder, var, LtacArg::Mem(index_pos));
+                instr.arg1 = LtacArg::MemOffsetMem(var.pos, index_pos, offset_size);
             }
         } else {
             // We create a dummy variable so the positional math is done as integers
@@ -70,9 +82,12 @@ pub fn build_var_math(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -
                 data_type : DataType::Int,
                 sub_type : DataType::None,
                 is_param : false,
+                is_owned : false,
+                dims : Vec::new(),
             };
             
             build_var_expr(builder, &line.sub_args, &var2, 0);
+            build_index_check(builder, var, LtacArg::Reg32(0));
             instr.arg1 = LtacArg::MemOffsetReg(var.pos, 0, offset_size);
         }
     }
@@ -82,6 +97,100 @@ pub fn build_var_math(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -
     true
 }
 
+// Builds the location of an element in a multi-dimensional array
+// All indices but the last are folded into a row number, which is stored in a hidden
+// stack slot; the last index is the column, and is left in the given register.
+//
+// For m : int[R][C], the element m[i][j] becomes:
+// mov i32.rN, i
+// str [row], i32.rN
+// mov i32.rN, j
+// [m + ([row] * C + rN) * 4]
+//
+fn build_array_offset(builder : &mut LtacBuilder, sub_args : &[AstArg], var : &Var, reg_no : i32, size : i32) -> Option<LtacArg> {
+    let indices : Vec<&[AstArg]> = sub_args.split(|a| a.arg_type == AstArgType::Dim).collect();
+    
+    if indices.len() != var.dims.len() + 1 {
+        builder.syntax.ltac_error2("Invalid number of array indices.".to_string());
+        return None;
+    }
+    
+    // Indices are evaluated from left to right, so each one only has to be
+    // grouped if it is an expression itself
+    let mut row : Vec<AstArg> = Vec::new();
+    
+    for (i, index) in indices[..indices.len()-1].iter().enumerate() {
+        if i > 0 {
+            row.push(ast::create_arg(AstArgType::OpMul));
+            row.push(ast::create_int(var.dims[i-1] as u64));
+            row.push(ast::create_arg(AstArgType::OpAdd));
+        }
+        
+        if index.len() > 1 {
+            row.push(ast::create_arg(AstArgType::OpLParen));
+            row.extend_from_slice(index);
+            row.push(ast::create_arg(AstArgType::OpRParen));
+        } else {
+            row.extend_from_slice(index);
+        }
+    }
+    
+    // The math is done as integers
+    let index_var = Var {
+        pos : 0,
+        data_type : DataType::Int,
+        sub_type : DataType::None,
+        is_param : false,
+        is_owned : false,
+        dims : Vec::new(),
+    };
+    
+    if !build_var_expr(builder, &row, &index_var, reg_no) {
+        return None;
+    }
+    
+    builder.stack_pos += 4;
+    let row_pos = builder.stack_pos;
+    
+    let mut instr = ltac::create_instr(LtacType::Str);
+    instr.arg1 = LtacArg::Mem(row_pos);
+    instr.arg2 = LtacArg::Reg32(reg_no);
+    builder.file.code.push(instr);
+    
+    // The row is checked against the number of rows, which is the outer count
+    // times the inner dimensions
+    if builder.bounds_check {
+        let mut ld = ltac::create_instr(LtacType::Ld);
+        ld.arg1 = LtacArg::Reg32(0);
+        ld.arg2 = LtacArg::Mem(var.pos - 8);
+        builder.file.code.push(ld);
+        
+        let rows : i32 = var.dims[..var.dims.len()-1].iter().product();
+        
+        if rows > 1 {
+            let mut mul = ltac::create_instr(LtacType::I32Mul);
+            mul.arg1 = LtacArg::Reg32(0);
+            mul.arg2 = LtacArg::I32(rows);
+            builder.file.code.push(mul);
+        }
+        
+        build_bounds_check(builder, LtacArg::Mem(row_pos), LtacArg::Reg32(0));
+    }
+    
+    let column = indices.last().unwrap().to_vec();
+    
+    if !build_var_expr(builder, &column, &index_var, reg_no) {
+        return None;
+    }
+    
+    let stride = *var.dims.last().unwrap();
+    
+    if builder.bounds_check {
+        build_bounds_check(builder, LtacArg::Reg32(reg_no), LtacArg::I32(stride));
+    }
+    Some(LtacArg::MemOffsetStride(var.pos, row_pos, reg_no, stride, size))
+}
+
 // TODO: I would eventually like to get rid of the "line" parameter
 // Doing so may require work in the ltac_builder module.
 fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, reg_no : i32) -> bool {
@@ -344,6 +453,16 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
             // ===============================================================
             // Variables and functions
             
+            // Calls through a function pointer
+            // If we are assigning to another function pointer (or an array of them), this is just a copy
+            AstArgType::Id if builder.func_ptr_exists(&arg.str_val)
+                    && !matches!(var.data_type, DataType::FuncPtr(_, _))
+                    && !matches!(var.sub_type, DataType::FuncPtr(_, _)) => {
+                if !build_expr_func_call(builder, arg, var, reg_no, &mut instr) {
+                    return false;
+                }
+            },
+            
             AstArgType::Id if builder.var_exists(&arg.str_val) => {
                 if !build_expr_var(builder, &arg, &var, reg_no, negate_next, &mut instr) {
                     return false;
@@ -372,6 +491,42 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
                 builder.file.code.push(instr.clone());
             },
             
+            // The length of a string or array
+            // Both keep their length next to the pointer, so this is the same as sizeof
+            AstArgType::Id if arg.str_val == "len" && !builder.function_exists(&arg.str_val) => {
+                let array_pos = match arg.sub_args.first() {
+                    Some(a) if arg.sub_args.len() == 1 => match builder.get_var(&a.str_val) {
+                        Ok(v) if v.data_type == DataType::Ptr => Some(v.pos),
+                        Ok(v) if v.data_type == DataType::Str && !builder.c_strings => Some(v.pos),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                
+                if let Some(pos) = array_pos {
+                    let reg = reg_for_type(&var.data_type, &DataType::None, reg_no + 1);
+                    
+                    let mut instr2 = mov_for_type(&var.data_type, &DataType::None);
+                    instr2.arg1 = reg.clone();
+                    instr2.arg2 = LtacArg::Mem(pos - 8);
+                    builder.file.code.push(instr2);
+                    
+                    instr.arg2 = reg;
+                    builder.file.code.push(instr.clone());
+                } else if !build_expr_len(builder, arg, var, reg_no, &mut instr) {
+                    return false;
+                }
+            },
+            
+            // Overflow intrinsics
+            AstArgType::Id if matches!(arg.str_val.as_str(), "wrapping_add" | "checked_add" | "saturating_add")
+                    && !builder.function_exists(&arg.str_val) => {
+                let built = build_arith_intrinsic(builder, arg, var, reg_no, &mut instr);
+                if !built {
+                    return false;
+                }
+            },
+            
             // Function calls
             AstArgType::Id if builder.function_exists(&arg.str_val) => {
                 if !build_expr_func_call(builder, &arg, &var, reg_no, &mut instr) {
@@ -417,11 +572,20 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
             
             // Sizeof statement
             // To get the size, get the array variable, and the size is stored in the upper 4 bytes
+            // The size of a string is its length
             
             AstArgType::Sizeof => {
                 let name_arg = arg.sub_args.first().unwrap();
                 let array_var = match builder.get_var(&name_arg.str_val) {
                     Ok(v) if v.data_type == DataType::Ptr => v,
+                    Ok(v) if v.data_type == DataType::Str && !builder.c_strings && name_arg.sub_args.is_empty() => v,
+                    
+                    Ok(v) if v.data_type == DataType::Str && name_arg.sub_args.is_empty() => {
+                        if !build_expr_len(builder, arg, var, reg_no, &mut instr) {
+                            return false;
+                        }
+                        continue;
+                    },
                     
                     Ok(_v) => {
                         builder.syntax.ltac_error2("Sizeof can only be used with arrays and strings.".to_string());
@@ -435,11 +599,22 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
                 };
                 
                 let pos = array_var.pos - 8;
-                let reg = reg_for_type(&var.data_type, &DataType::None, reg_no);
+                let reg = reg_for_type(&var.data_type, &DataType::None, reg_no + 1);
                 
                 let mut instr2 = mov_for_type(&var.data_type, &DataType::None);
                 instr2.arg1 = reg.clone();
                 instr2.arg2 = LtacArg::Mem(pos);
+                
+                // The inner dimensions are known at compile time
+                let depth = name_arg.sub_args.len();
+                
+                if depth > array_var.dims.len() {
+                    builder.syntax.ltac_error2("The array does not have that many dimensions.".to_string());
+                    return false;
+                } else if depth > 0 {
+                    instr2.arg2 = LtacArg::I32(array_var.dims[depth-1]);
+                }
+                
                 builder.file.code.push(instr2);
                 
                 instr.arg2 = reg;
@@ -450,6 +625,31 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
             
             AstArgType::AddrOf => {
                 let name_arg = arg.sub_args.first().unwrap();
+                
+                // The address of a function gives us a function pointer
+                if !builder.var_exists(&name_arg.str_val) && builder.function_exists(&name_arg.str_val) {
+                    let ptr_type = match &var.data_type {
+                        DataType::Ptr => &var.sub_type,
+                        _ => &var.data_type,
+                    };
+                    
+                    match ptr_type {
+                        DataType::FuncPtr(_, _) if builder.get_signature(&name_arg.str_val) != Ok(ptr_type) => {
+                            let mut msg = "Function does not match the function pointer type: ".to_string();
+                            msg.push_str(&name_arg.str_val);
+                            
+                            builder.syntax.ltac_error2(msg);
+                            return false;
+                        },
+                        
+                        _ => {},
+                    }
+                    
+                    instr.arg2 = LtacArg::PtrFunc(name_arg.str_val.clone());
+                    builder.file.code.push(instr.clone());
+                    continue;
+                }
+                
                 let ref_var = match builder.get_var(&name_arg.str_val) {
                     Ok(v) => v,
                     
@@ -507,6 +707,7 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
                 }
                 
                 instr.arg1 = reg_for_type(&var.data_type, &var.sub_type, reg_no);
+                check_overflow(builder, &mut instr);
             },
             
             // Subtraction
@@ -534,6 +735,7 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
                 }
                 
                 instr.arg1 = reg_for_type(&var.data_type, &var.sub_type, reg_no);
+                check_overflow(builder, &mut instr);
             },
             
             // Multiplication
@@ -569,6 +771,7 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
                 }
                 
                 instr.arg1 = reg_for_type(&var.data_type, &var.sub_type, reg_no);
+                check_overflow(builder, &mut instr);
             },
             
             // Division
@@ -604,6 +807,7 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
                 }
                 
                 instr.arg1 = reg_for_type(&var.data_type, &var.sub_type, reg_no);
+                check_division(builder, &mut instr);
             },
             
             // Modulo
@@ -635,6 +839,7 @@ fn build_var_expr(builder : &mut LtacBuilder, args : &Vec<AstArg>, var : &Var, r
                 }
                 
                 instr.arg1 = reg_for_type(&var.data_type, &var.sub_type, reg_no);
+                check_division(builder, &mut instr);
             },
             
             // Logical AND
@@ -736,7 +941,22 @@ pub fn build_expr_var(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg
         Err(_e) => return false,    // This really shouldn't happen
     };
     
-    let zero = builder.build_float(0.0, false, false);      // I don't love having this here, but it won't work in the match
+    // Mixing integers and floating-point types needs a conversion
+    let dest_type = if var.data_type == DataType::Ptr { &var.sub_type } else { &var.data_type };
+    
+    if arg.sub_args.is_empty() {
+        if let Some(cvt_type) = cvt_for_types(&v.data_type, dest_type) {
+            if negate_next {
+                builder.syntax.ltac_error2("Cannot negate a variable that needs a conversion.".to_string());
+                return false;
+            }
+            
+            build_expr_cvt(builder, &v, var, cvt_type, instr);
+            return true;
+        }
+    }
+    
+    let zero = builder.build_float(0.0, v.data_type == DataType::Double, false);      // I don't love having this here, but it won't work in the match
     let mut pop_float = true;
     
     // Build the load
@@ -756,30 +976,45 @@ pub fn build_expr_var(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg
         || v.sub_type == DataType::Float {
         size = 4;
     } else if  v.sub_type == DataType::Int64 || v.sub_type == DataType::UInt64
-        || v.sub_type == DataType::Double || var.sub_type == DataType::Str {
+        || v.sub_type == DataType::Double || var.sub_type == DataType::Str
+        || matches!(v.sub_type, DataType::FuncPtr(_, _)) {
         size = 8;
     }
     
-    if arg.sub_args.len() > 0 {
+    if arg.sub_args.iter().any(|a| a.arg_type == AstArgType::Dim) {
+        let mut instr2 = ld_for_type(&v.data_type, &v.sub_type);
+        instr2.arg1 = reg_for_type(&v.data_type, &v.sub_type, 0);
+        instr2.arg2 = match build_array_offset(builder, &arg.sub_args, &v, reg_no + 1, size) {
+            Some(arg) => arg,
+            None => return false,
+        };
+        builder.file.code.push(instr2);
+        
+        instr.arg2 = reg_for_type(&v.data_type, &v.sub_type, 0);
+    } else if !arg.sub_args.is_empty() {
         let first_arg = arg.sub_args.last().unwrap();
         
         if arg.sub_args.len() == 1 {
             if first_arg.arg_type == AstArgType::IntL {
+                build_index_check(builder, &v, LtacArg::I32(first_arg.u64_val as i32));
                 instr.instr_type = ld_for_type(&v.data_type, &v.sub_type).instr_type;
                 
                 let offset = (first_arg.u64_val as i32) * size;
                 instr.arg2 = LtacArg::MemOffsetImm(v.pos, offset);
             } else if first_arg.arg_type == AstArgType::Id {
-                let mut instr2 = ld_for_type(&v.data_type, &v.sub_type);
-                
-                match builder.vars.get(&first_arg.str_val) {
-                    Some(v2) => instr2.arg2 = LtacArg::MemOffsetMem(v.pos, v2.pos, size),
+                let index_pos = match builder.vars.get(&first_arg.str_val) {
+                    Some(v2) => v2.pos,
                     None => {
                         builder.syntax.ltac_error2("Invalid offset variable.".to_string());
                         return false;
                     },
                 };
                 
+                build_index_check(builder, &v, LtacArg::Mem(index_pos));
+                
+                let mut instr2 = ld_for_type(&v.data_type, &v.sub_type);
+                instr2.arg2 = LtacArg::MemOffsetMem(v.pos, index_pos, size);
+                
                 // Choose the proper registers
                 instr2.arg1 = reg_for_type(&v.data_type, &v.sub_type, reg_no);
                 instr.arg2 = reg_for_type(&v.data_type, &v.sub_type, reg_no);
@@ -793,9 +1028,12 @@ pub fn build_expr_var(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg
                 data_type : DataType::Int,
                 sub_type : DataType::None,
                 is_param : false,
+                is_owned : false,
+                dims : Vec::new(),
             };
             
             build_var_expr(builder, &arg.sub_args, &var2, 0);
+            build_index_check(builder, &v, LtacArg::Reg32(0));
             
             let mut instr2 = ld_for_type(&v.data_type, &v.sub_type);
             instr2.arg1 = reg_for_type(&v.data_type, &v.sub_type, 0);
@@ -848,7 +1086,7 @@ pub fn build_expr_var(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg
             },
             
             DataType::Float => {
-                instr2.arg2 = LtacArg::F32(zero);
+                instr2.arg2 = LtacArg::F32(zero.clone());
                 builder.file.code.push(instr2.clone());
                 
                 instr2.instr_type = LtacType::F32Sub;
@@ -856,7 +1094,7 @@ pub fn build_expr_var(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg
             },
             
             DataType::Double => {
-                instr2.arg2 = LtacArg::F64(zero);
+                instr2.arg2 = LtacArg::F64(zero.clone());
                 builder.file.code.push(instr2.clone());
                 
                 instr2.instr_type = LtacType::F64Sub;
@@ -871,12 +1109,13 @@ pub fn build_expr_var(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg
         
         // Set the memory and push the second operand
         instr2.arg2 = LtacArg::Mem(v.pos);
+        check_overflow(builder, &mut instr2);
         builder.file.code.push(instr2);
     }
     
     // Pop the extra float we created at the top if we don't need it
     if pop_float {
-        builder.file.data.pop();
+        builder.file.data.retain(|d| d.name != zero);
     }
     
     // Add the instruction
@@ -884,11 +1123,63 @@ pub fn build_expr_var(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg
     true
 }
 
+// Builds a variable converted to the type of the one we are assigning
+// Integers are loaded first so the backend knows their size
+fn build_expr_cvt(builder : &mut LtacBuilder, v : &Var, var : &Var, cvt_type : LtacType, instr : &mut LtacInstr) {
+    let mut cvt = ltac::create_instr(cvt_type.clone());
+    cvt.arg1 = reg_for_type(&var.data_type, &var.sub_type, 0);
+    cvt.arg2 = LtacArg::Mem(v.pos);
+    
+    if cvt_type == LtacType::CvtIntF32 || cvt_type == LtacType::CvtIntF64 {
+        let mut ld = ld_for_type(&v.data_type, &v.sub_type);
+        ld.arg1 = reg_for_type(&v.data_type, &v.sub_type, 0);
+        ld.arg2 = LtacArg::Mem(v.pos);
+        builder.file.code.push(ld);
+        
+        cvt.arg2 = reg_for_type(&v.data_type, &v.sub_type, 0);
+    }
+    
+    builder.file.code.push(cvt);
+    
+    instr.arg2 = reg_for_type(&var.data_type, &var.sub_type, 0);
+    builder.file.code.push(instr.clone());
+}
+
+// Builds the length of a string in the core library (for len() and sizeof)
+// Strings there are plain pointers, so this becomes a call to strlen
+fn build_expr_len(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg_no : i32, instr : &mut LtacInstr) -> bool {
+    let is_str = match arg.sub_args.first() {
+        Some(s) if arg.sub_args.len() == 1 => match builder.get_var(&s.str_val) {
+            Ok(v) => s.arg_type == AstArgType::Id && v.data_type == DataType::Str,
+            Err(_e) => false,
+        },
+        _ => false,
+    };
+    
+    if !is_str {
+        builder.syntax.ltac_error2("len can only be used with strings and arrays.".to_string());
+        return false;
+    }
+    
+    let mut call = arg.clone();
+    call.str_val = "strlen".to_string();
+    
+    if !builder.function_exists(&call.str_val) {
+        builder.syntax.ltac_error2("len requires the core library.".to_string());
+        return false;
+    }
+    
+    build_expr_func_call(builder, &call, var, reg_no, instr)
+}
+
 // Builds a function call within an expression
 pub fn build_expr_func_call(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg_no : i32, instr : &mut LtacInstr) -> bool {
     let t = match builder.get_function(&arg.str_val) {
         Ok(t) => t.clone(),
-        Err(_e) => return false,
+        Err(_e) => match builder.get_func_ptr(&arg.str_val) {
+            Ok(t) => t.clone(),
+            Err(_e) => return false,
+        },
     };
 
     // First, push the current register
@@ -901,7 +1192,10 @@ pub fn build_expr_func_call(builder : &mut LtacBuilder, arg : &AstArg, var : &Va
     let mut stmt = ast::create_orphan_stmt(AstStmtType::FuncCall);
     stmt.name = arg.str_val.clone();
     stmt.args = arg.sub_args.clone();
-    build_func_call(builder, &stmt);
+    
+    if !build_func_call(builder, &stmt) {
+        return false;
+    }
            
     //Restore the current register
     store = ld_for_type(&t, &DataType::None);
@@ -920,6 +1214,7 @@ pub fn build_expr_func_call(builder : &mut LtacBuilder, arg : &AstArg, var : &Va
         DataType::UInt64 => instr.arg2 = LtacArg::RetRegU64,
         DataType::Float => instr.arg2 = LtacArg::RetRegF32,
         DataType::Double => instr.arg2 = LtacArg::RetRegF64,
+        DataType::FuncPtr(_, _) => instr.arg2 = LtacArg::RetRegI64,
         
         _ => {
             builder.syntax.ltac_error2("Invalid return.".to_string());
@@ -932,3 +1227,224 @@ pub fn build_expr_func_call(builder : &mut LtacBuilder, arg : &AstArg, var : &Va
     true
 }
 
+// Marks an integer add, subtract, or multiply as checked (--overflow-checks)
+fn check_overflow(builder : &mut LtacBuilder, instr : &mut LtacInstr) {
+    match instr.instr_type {
+        LtacType::F32Add | LtacType::F32Sub | LtacType::F32Mul
+        | LtacType::F64Add | LtacType::F64Sub | LtacType::F64Mul => {},
+        
+        _ if builder.overflow_checks => instr.name = builder.build_check_label("ida_overflow_panic"),
+        _ => {},
+    }
+}
+
+// With division checks, integer division and modulo branch to a panic if the divisor is zero
+fn check_division(builder : &mut LtacBuilder, instr : &mut LtacInstr) {
+    match instr.instr_type {
+        LtacType::F32Div | LtacType::F64Div => {},
+        
+        _ if builder.div_checks => instr.name = builder.build_check_label("ida_div_panic"),
+        _ => {},
+    }
+}
+
+// Returns the smallest or largest value of an integer type
+fn int_limit(data_type : &DataType, is_max : bool) -> LtacArg {
+    match data_type {
+        DataType::Byte if is_max => LtacArg::Byte(i8::MAX),
+        DataType::Byte => LtacArg::Byte(i8::MIN),
+        DataType::UByte => LtacArg::UByte(u8::MAX),
+        DataType::Short if is_max => LtacArg::I16(i16::MAX),
+        DataType::Short => LtacArg::I16(i16::MIN),
+        DataType::UShort => LtacArg::U16(u16::MAX),
+        DataType::Int if is_max => LtacArg::I32(i32::MAX),
+        DataType::Int => LtacArg::I32(i32::MIN),
+        DataType::UInt => LtacArg::U32(u32::MAX),
+        DataType::Int64 if is_max => LtacArg::I64(i64::MAX),
+        DataType::Int64 => LtacArg::I64(i64::MIN),
+        _ => LtacArg::U64(u64::MAX),
+    }
+}
+
+// Returns an integer literal of the given type
+fn int_for_type(data_type : &DataType, val : u64, negate : bool) -> LtacArg {
+    let val = if negate { (val as i64).wrapping_neg() } else { val as i64 };
+    
+    match data_type {
+        DataType::Byte => LtacArg::Byte(val as i8),
+        DataType::UByte => LtacArg::UByte(val as u8),
+        DataType::Short => LtacArg::I16(val as i16),
+        DataType::UShort => LtacArg::U16(val as u16),
+        DataType::Int => LtacArg::I32(val as i32),
+        DataType::UInt => LtacArg::U32(val as u32),
+        DataType::Int64 => LtacArg::I64(val),
+        _ => LtacArg::U64(val as u64),
+    }
+}
+
+// Builds the wrapping_add, checked_add, and saturating_add intrinsics
+// These don't depend on --overflow-checks: wrapping_add never traps, checked_add always
+// does, and saturating_add clamps the result to the limits of the type.
+//
+// The sum is built in the register after the current one and then used like any other operand.
+fn build_arith_intrinsic(builder : &mut LtacBuilder, arg : &AstArg, var : &Var, reg_no : i32, instr : &mut LtacInstr) -> bool {
+    let data_type = match var.data_type {
+        DataType::Ptr => var.sub_type.clone(),
+        _ => var.data_type.clone(),
+    };
+    
+    let (add_type, cmp_type, is_signed) = match data_type {
+        DataType::Byte => (LtacType::I8Add, LtacType::I8Cmp, true),
+        DataType::UByte => (LtacType::U8Add, LtacType::U8Cmp, false),
+        DataType::Short => (LtacType::I16Add, LtacType::I16Cmp, true),
+        DataType::UShort => (LtacType::U16Add, LtacType::U16Cmp, false),
+        DataType::Int => (LtacType::I32Add, LtacType::I32Cmp, true),
+        DataType::UInt => (LtacType::U32Add, LtacType::U32Cmp, false),
+        DataType::Int64 => (LtacType::I64Add, LtacType::I64Cmp, true),
+        DataType::UInt64 => (LtacType::U64Add, LtacType::U64Cmp, false),
+        
+        _ => {
+            builder.syntax.ltac_error2("Arithmetic intrinsics can only be used with integers.".to_string());
+            return false;
+        },
+    };
+    
+    // Each operand is either a literal or a variable of the same type
+    let mut operands : Vec<LtacArg> = Vec::new();
+    let mut negate = false;
+    
+    for sub_arg in arg.sub_args.iter() {
+        match sub_arg.arg_type {
+            AstArgType::OpNeg if is_signed => {
+                negate = true;
+                continue;
+            },
+            
+            AstArgType::IntL => operands.push(int_for_type(&data_type, sub_arg.u64_val, negate)),
+            
+            AstArgType::Id if !negate => match builder.get_var(&sub_arg.str_val) {
+                Ok(v) if v.data_type == data_type => {
+                    let pos = v.pos;
+                    let reg = reg_for_type(&data_type, &DataType::None, reg_no + 1 + operands.len() as i32);
+                    
+                    let mut ld = ld_for_type(&data_type, &DataType::None);
+                    ld.arg1 = reg.clone();
+                    ld.arg2 = LtacArg::Mem(pos);
+                    builder.file.code.push(ld);
+                    
+                    operands.push(reg);
+                },
+                
+                _ => operands.push(LtacArg::Empty),
+            },
+            
+            _ => operands.push(LtacArg::Empty),
+        }
+        
+        negate = false;
+    }
+    
+    if operands.len() != 2 || operands.contains(&LtacArg::Empty) {
+        let mut msg = "Expected two integer literals or variables of the same type: ".to_string();
+        msg.push_str(&arg.str_val);
+        
+        builder.syntax.ltac_error2(msg);
+        return false;
+    }
+    
+    // A literal first operand is moved into the destination
+    let dest = reg_for_type(&data_type, &DataType::None, reg_no + 1);
+    
+    if operands[0] != dest {
+        let mut mov = mov_for_type(&data_type, &DataType::None);
+        mov.arg1 = dest.clone();
+        mov.arg2 = operands[0].clone();
+        builder.file.code.push(mov);
+    }
+    
+    let mut add = ltac::create_instr(add_type);
+    add.arg1 = dest.clone();
+    add.arg2 = operands[1].clone();
+    
+    match arg.str_val.as_str() {
+        "checked_add" => {
+            add.name = builder.build_check_label("ida_overflow_panic");
+            builder.file.code.push(add);
+        },
+        
+        // On overflow, the result is the maximum, or the minimum if a negative number was added
+        //
+        // add dest, b -> SAT
+        // br DONE
+        // SAT:
+        // cmp b, 0
+        // bl NEG
+        // mov dest, MAX
+        // br DONE
+        // NEG:
+        // mov dest, MIN
+        // DONE:
+        "saturating_add" => {
+            let sat_label = create_local_label(builder);
+            let neg_label = create_local_label(builder);
+            let done_label = create_local_label(builder);
+            
+            add.name = sat_label.clone();
+            builder.file.code.push(add);
+            
+            let mut br = ltac::create_instr(LtacType::Br);
+            br.name = done_label.clone();
+            builder.file.code.push(br.clone());
+            
+            let mut lbl = ltac::create_instr(LtacType::Label);
+            lbl.name = sat_label;
+            builder.file.code.push(lbl.clone());
+            
+            if is_signed {
+                let mut cmp = ltac::create_instr(cmp_type);
+                cmp.arg1 = operands[1].clone();
+                cmp.arg2 = int_for_type(&data_type, 0, false);
+                
+                // A literal is compared against zero through the destination
+                if !matches!(cmp.arg1, LtacArg::Reg8(_) | LtacArg::Reg16(_) | LtacArg::Reg32(_) | LtacArg::Reg64(_)) {
+                    let mut mov = mov_for_type(&data_type, &DataType::None);
+                    mov.arg1 = dest.clone();
+                    mov.arg2 = operands[1].clone();
+                    builder.file.code.push(mov);
+                    
+                    cmp.arg1 = dest.clone();
+                }
+                
+                builder.file.code.push(cmp);
+                
+                let mut bl = ltac::create_instr(LtacType::Bl);
+                bl.name = neg_label.clone();
+                builder.file.code.push(bl);
+            }
+            
+            let mut mov = mov_for_type(&data_type, &DataType::None);
+            mov.arg1 = dest.clone();
+            mov.arg2 = int_limit(&data_type, true);
+            builder.file.code.push(mov.clone());
+            
+            if is_signed {
+                builder.file.code.push(br);
+                
+                lbl.name = neg_label;
+                builder.file.code.push(lbl.clone());
+                
+                mov.arg2 = int_limit(&data_type, false);
+                builder.file.code.push(mov);
+            }
+            
+            lbl.name = done_label;
+            builder.file.code.push(lbl);
+        },
+        
+        _ => builder.file.code.push(add),
+    }
+    
+    instr.arg2 = dest;
+    builder.file.code.push(instr.clone());
+    true
+}
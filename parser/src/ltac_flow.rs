@@ -4,7 +4,9 @@
 // Ida is licensed under the BSD-3 license. See the COPYING file for more information.
 //
 
+use crate::ast;
 use crate::ltac_builder::*;
+use crate::ltac_func::*;
 use crate::ltac_utils::*;
 
 use crate::ast::{DataType, AstStmt, AstStmtType, AstArgType};
@@ -128,7 +130,7 @@ fn build_cmp(builder : &mut LtacBuilder, line : &AstStmt) -> Vec<LtacInstr> {
             let mut instr2 = ltac::create_instr(LtacType::PushArg);
             instr2.arg1 = LtacArg::PtrLcl(name);
             instr2.arg2_val = 1;
-            builder.file.code.push(instr2);
+            block.push(instr2);
             
             cmp = ltac::create_instr(LtacType::StrCmp);
         },
@@ -315,7 +317,7 @@ fn build_cmp(builder : &mut LtacBuilder, line : &AstStmt) -> Vec<LtacInstr> {
             let mut instr2 = ltac::create_instr(LtacType::PushArg);
             instr2.arg1 = LtacArg::PtrLcl(name);
             instr2.arg2_val = 2;
-            builder.file.code.push(instr2); 
+            block.push(instr2);
         },
         
         AstArgType::Id => {
@@ -506,6 +508,94 @@ fn build_cmp(builder : &mut LtacBuilder, line : &AstStmt) -> Vec<LtacInstr> {
     block
 }
 
+// Calls one of the core library panic functions with the current location
+// ida_panic(file, line, msg) and ida_assert_fail(file, line, msg) never return
+fn build_panic_call(builder : &mut LtacBuilder, line : &AstStmt, func_name : &str) -> bool {
+    let mut call = line.clone();
+    call.stmt_type = AstStmtType::FuncCall;
+    call.name = func_name.to_string();
+    call.args = vec![
+        ast::create_string(builder.src_path.clone()),
+        ast::create_int(line.line_no as u64),
+        line.args.last().unwrap().clone(),
+    ];
+    
+    build_func_call(builder, &call)
+}
+
+// Builds the panic statement
+pub fn build_panic(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
+    build_panic_call(builder, line, "ida_panic")
+}
+
+// Builds an assert statement
+// Asserts are left out of release builds
+//
+// cmp ...
+// b<cond> OK
+// call ida_assert_fail
+// OK:
+//
+pub fn build_assert(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
+    if builder.release {
+        return true;
+    }
+    
+    // The last argument is the message
+    let mut cond = line.clone();
+    cond.args.pop();
+    
+    if cond.args.len() < 3 {
+        builder.syntax.ltac_error(line, "Invalid condition in assert.".to_string());
+        return false;
+    }
+    
+    let cmp_block = build_cmp(builder, &cond);
+    let cmp_type = cmp_block.last().unwrap().instr_type.clone();
+    let is_float = cmp_type == LtacType::F32Cmp || cmp_type == LtacType::F64Cmp;
+    
+    for ln in cmp_block.iter() {
+        builder.file.code.push(ln.clone());
+    }
+    
+    // Skip the panic if the condition holds
+    let ok_label = create_local_label(builder);
+    
+    let op = &cond.args[1];
+    let mut br = ltac::create_instr(LtacType::Br);
+    br.name = ok_label.clone();
+    
+    match &op.arg_type {
+        AstArgType::OpEq => br.instr_type = LtacType::Be,
+        AstArgType::OpNeq => br.instr_type = LtacType::Bne,
+        AstArgType::OpLt if is_float => br.instr_type = LtacType::Bfl,
+        AstArgType::OpLt => br.instr_type = LtacType::Bl,
+        AstArgType::OpLte if is_float => br.instr_type = LtacType::Bfle,
+        AstArgType::OpLte => br.instr_type = LtacType::Ble,
+        AstArgType::OpGt if is_float => br.instr_type = LtacType::Bfg,
+        AstArgType::OpGt => br.instr_type = LtacType::Bg,
+        AstArgType::OpGte if is_float => br.instr_type = LtacType::Bfge,
+        AstArgType::OpGte => br.instr_type = LtacType::Bge,
+        
+        _ => {
+            builder.syntax.ltac_error(line, "Invalid condition in assert.".to_string());
+            return false;
+        },
+    }
+    
+    builder.file.code.push(br);
+    
+    if !build_panic_call(builder, line, "ida_assert_fail") {
+        return false;
+    }
+    
+    let mut lbl = ltac::create_instr(LtacType::Label);
+    lbl.name = ok_label;
+    builder.file.code.push(lbl);
+    
+    true
+}
+
 // Builds an LTAC conditional block (specific for if-else)
 pub fn build_cond(builder : &mut LtacBuilder, line : &AstStmt) {
     if line.stmt_type == AstStmtType::If {
@@ -626,6 +716,9 @@ pub fn build_while(builder : &mut LtacBuilder, line : &AstStmt) {
     
     // Build the conditional statement
     let block = build_cmp(builder, line);
+    let cmp_type = block.last().unwrap().instr_type.clone();
+    let is_float = cmp_type == LtacType::F32Cmp || cmp_type == LtacType::F64Cmp;
+    
     for ln in block.iter() {
         cmp_block.push(ln.clone());
     }
@@ -638,9 +731,13 @@ pub fn build_while(builder : &mut LtacBuilder, line : &AstStmt) {
     match &op.arg_type {
         AstArgType::OpEq => br.instr_type = LtacType::Be,
         AstArgType::OpNeq => br.instr_type = LtacType::Bne,
+        AstArgType::OpLt if is_float => br.instr_type = LtacType::Bfl,
         AstArgType::OpLt => br.instr_type = LtacType::Bl,
+        AstArgType::OpLte if is_float => br.instr_type = LtacType::Bfle,
         AstArgType::OpLte => br.instr_type = LtacType::Ble,
+        AstArgType::OpGt if is_float => br.instr_type = LtacType::Bfg,
         AstArgType::OpGt => br.instr_type = LtacType::Bg,
+        AstArgType::OpGte if is_float => br.instr_type = LtacType::Bfge,
         AstArgType::OpGte => br.instr_type = LtacType::Bge,
         _ => {},
     }
@@ -5,6 +5,7 @@
 //
 
 use crate::ltac_builder::*;
+use crate::ltac_func::build_str_len;
 use crate::ltac_utils::*;
 
 use crate::ast::{DataType, AstStmt, AstArgType};
@@ -48,6 +49,8 @@ fn build_range_for_loop(builder : &mut LtacBuilder, line : &AstStmt)  {
         data_type : DataType::Int,
         sub_type : DataType::None,
         is_param : false,
+        is_owned : false,
+        dims : Vec::new(),
     };
     
     builder.vars.insert(name, index);
@@ -190,6 +193,8 @@ fn build_foreach_loop(builder : &mut LtacBuilder, line : &AstStmt)  {
         data_type : data_type.clone(),
         sub_type : DataType::None,
         is_param : false,
+        is_owned : false,
+        dims : Vec::new(),
     };
     
     builder.vars.insert(index_name, index);
@@ -226,6 +231,11 @@ fn build_foreach_loop(builder : &mut LtacBuilder, line : &AstStmt)  {
         instr.arg1 = LtacArg::Mem(index_pos);
         instr.arg2 = LtacArg::Reg64(0);
         builder.file.code.push(instr.clone());
+        
+        // The array only holds pointers, so the length has to be found
+        if !builder.c_strings {
+            build_str_len(builder, index_pos);
+        }
     } else {
         let reg = reg_for_type(&data_type, &DataType::None, 0);
         
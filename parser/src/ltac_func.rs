@@ -7,7 +7,7 @@
 use crate::ltac_builder::*;
 use crate::ltac;
 use crate::ltac::{LtacType, LtacArg};
-use crate::ast::{DataType, AstStmt, AstArgType};
+use crate::ast::{DataType, AstStmt, AstArg, AstArgType};
 
 use crate::ltac_array::*;
 use crate::ltac_utils::*;
@@ -25,9 +25,55 @@ pub fn build_func_call(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
     // Represents the current argument position
     let mut arg_no : i32 = 1;
     let mut flt_arg_no : i32 = 1;
+    
+    // Arrays are passed as a pointer and a length, unless we are calling C code
+    // Strings are too, unless we are also calling the core or standard library
+    let is_c_call = call_type == LtacType::Syscall || builder.c_functions.contains(&line.name);
+    let c_strings = is_c_call || builder.c_str_functions.contains(&line.name);
+    
+    // Calls through a function pointer use the type of the pointer, and are checked against it
+    let ptr_params = match builder.vars.get(&line.name) {
+        Some(Var { data_type : DataType::FuncPtr(params, _), .. }) => Some(params.clone()),
+        _ => None,
+    };
+    
+    if let Some(ref ptr_params) = ptr_params {
+        if !check_func_ptr_args(builder, line, ptr_params) {
+            return false;
+        }
+    }
+    
+    let params = match (&ptr_params, builder.get_signature(&line.name)) {
+        (Some(params), _) => params.clone(),
+        (None, Ok(DataType::FuncPtr(params, _))) if !is_c_call => params.clone(),
+        _ => Vec::new(),
+    };
+    
+    // Floats passed as extra arguments to a variadic function are promoted to double, like C does
+    let arg_types = match (&ptr_params, builder.get_signature(&line.name)) {
+        (Some(params), _) => params.clone(),
+        (None, Ok(DataType::FuncPtr(params, _))) => params.clone(),
+        _ => Vec::new(),
+    };
+    let is_variadic = builder.variadic.contains(&line.name);
+    
+    // Byte arrays passed as strings need their length computed before we start loading
+    // the arguments, since it takes a function call
+    let lengths = match build_str_lengths(builder, line, &params, c_strings) {
+        Some(lengths) => lengths,
+        None => return false,
+    };
 
     // Build the arguments
-    for arg in line.args.iter() {
+    for (i, arg) in line.args.iter().enumerate() {
+        // If we don't know the function, arrays still get their size (as they always have),
+        // but strings don't, since it is most likely a C function
+        let takes_size = !is_c_call && match params.get(i) {
+            Some(t) => *t == DataType::Ptr || (*t == DataType::Str && !c_strings),
+            None => params.is_empty(),
+        };
+        let takes_len = takes_size && params.get(i).is_some();
+        
         match &arg.arg_type {
             AstArgType::CharL => {
                 let mut push = ltac::create_instr(arg_type.clone());
@@ -71,9 +117,11 @@ pub fn build_func_call(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
             },
             
             AstArgType::FloatL => {
+                let is_double = arg_types.get(i) == Some(&DataType::Double) || (is_variadic && i >= arg_types.len());
+                let name = builder.build_float(arg.f64_val, is_double, false);
+                
                 let mut push = ltac::create_instr(LtacType::PushArg);
-                let name = builder.build_float(arg.f64_val, false, false);
-                push.arg1 = LtacArg::F32(name);
+                push.arg1 = if is_double { LtacArg::F64(name) } else { LtacArg::F32(name) };
                 push.arg2_val = flt_arg_no;
                 builder.file.code.push(push);
                 
@@ -89,10 +137,32 @@ pub fn build_func_call(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
                 builder.file.code.push(push);
                 
                 arg_no += 1;
+                
+                // The length of a literal is known at compile time
+                if takes_len {
+                    push = ltac::create_instr(arg_type.clone());
+                    push.arg1 = LtacArg::I32(str_literal_len(&arg.str_val));
+                    push.arg2_val = arg_no;
+                    builder.file.code.push(push);
+                    
+                    arg_no += 1;
+                }
             },
             
             AstArgType::AddrOf => {
                 let name_arg = arg.sub_args.first().unwrap();
+                
+                // Function addresses are passed directly
+                if !builder.var_exists(&name_arg.str_val) && builder.function_exists(&name_arg.str_val) {
+                    let mut push = ltac::create_instr(arg_type.clone());
+                    push.arg1 = LtacArg::PtrFunc(name_arg.str_val.clone());
+                    push.arg2_val = arg_no;
+                    builder.file.code.push(push);
+                    
+                    arg_no += 1;
+                    continue;
+                }
+                
                 let ref_var = match builder.get_var(&name_arg.str_val) {
                     Ok(v) => v,
                     
@@ -136,27 +206,36 @@ pub fn build_func_call(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
                         } else if v.data_type == DataType::UShort {
                             push.arg2 = LtacArg::U16(0);
                             
-                        } else if v.data_type == DataType::Ptr {
+                        } else if v.data_type == DataType::Ptr || v.data_type == DataType::Str {
                             push.arg1 = LtacArg::Ptr(v.pos);
                             
-                            // Push the size if we are not making a system call
-                            if arg_type != LtacType::KPushArg {
+                            // Push the size if the function takes it
+                            if (v.data_type == DataType::Ptr && takes_size) || takes_len {
                                 let mut push2 = ltac::create_instr(LtacType::PushArg);
-                                push2.arg1 = LtacArg::Mem(v.pos - 8);
+                                push2.arg1 = LtacArg::Mem(lengths[i].unwrap_or(v.pos - 8));
                                 push2.arg2 = LtacArg::I32(0);
                                 push2.arg2_val = arg_no + 1;
                                 builder.file.code.push(push2);
                             }
                             
-                        } else if v.data_type == DataType::Str {
-                            push.arg1 = LtacArg::Ptr(v.pos);
-                            
                         } else if v.data_type == DataType::Int64 {
                             push.arg2 = LtacArg::I64(0);
                             
                         } else if v.data_type == DataType::UInt64 {
                             push.arg2 = LtacArg::U64(0);
                             
+                        } else if matches!(v.data_type, DataType::FuncPtr(_, _)) {
+                            push.arg2 = LtacArg::I64(0);
+                            
+                        } else if v.data_type == DataType::Float && is_variadic && i >= arg_types.len() {
+                            let mut cvt = ltac::create_instr(LtacType::CvtF32F64);
+                            cvt.arg1 = LtacArg::FltReg64(0);
+                            cvt.arg2 = LtacArg::Mem(v.pos);
+                            builder.file.code.push(cvt);
+                            
+                            push.arg1 = LtacArg::FltReg64(0);
+                            push.arg2 = LtacArg::FltReg64(flt_arg_no);
+                            
                         } else if v.data_type == DataType::Float {
                             push.arg2 = LtacArg::FltReg(flt_arg_no);
                             
@@ -172,7 +251,7 @@ pub fn build_func_call(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
                             push.arg2_val = arg_no;
                             arg_no += 1;
                             
-                            if v.data_type == DataType::Ptr && arg_type != LtacType::KPushArg {
+                            if (v.data_type == DataType::Ptr && takes_size) || (v.data_type == DataType::Str && takes_len) {
                                 arg_no += 1;
                             }
                         }
@@ -198,13 +277,89 @@ pub fn build_func_call(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
     }
     
     // Build the call
+    // If we have a function pointer, we call through the variable
     let mut fc = ltac::create_instr(call_type);
     fc.name = line.name.clone();
+    
+    match builder.vars.get(&line.name) {
+        Some(v) if matches!(v.data_type, DataType::FuncPtr(_, _)) => {
+            fc = ltac::create_instr(LtacType::CallReg);
+            fc.arg1 = LtacArg::Mem(v.pos);
+        },
+        
+        _ => {},
+    }
+    
     builder.file.code.push(fc);
     
     true
 }
 
+// Checks the arguments of a call through a function pointer against its parameters
+// Each value is pushed as its own argument, so that is what we count. The call may be
+// part of an expression, so errors use the line of the current statement.
+fn check_func_ptr_args(builder : &mut LtacBuilder, line : &AstStmt, params : &[DataType]) -> bool {
+    let args : Vec<&AstArg> = line.args.iter().filter(|arg| matches!(arg.arg_type,
+        AstArgType::ByteL | AstArgType::ShortL | AstArgType::IntL | AstArgType::FloatL |
+        AstArgType::CharL | AstArgType::StringL | AstArgType::Id | AstArgType::AddrOf
+    )).collect();
+    
+    if args.len() != params.len() {
+        let mut msg = "Invalid number of arguments for function pointer: ".to_string();
+        msg.push_str(&line.name);
+        
+        builder.syntax.ltac_error2(msg);
+        return false;
+    }
+    
+    for (arg, param) in args.iter().zip(params.iter()) {
+        let is_int = matches!(param,
+            DataType::Byte | DataType::UByte | DataType::Short | DataType::UShort |
+            DataType::Int | DataType::UInt | DataType::Int64 | DataType::UInt64 |
+            DataType::Char | DataType::Enum(_)
+        );
+        let is_ptr = *param == DataType::Ptr || *param == DataType::Str;
+        
+        let valid = match arg.arg_type {
+            AstArgType::ByteL | AstArgType::ShortL | AstArgType::IntL | AstArgType::CharL => is_int,
+            AstArgType::FloatL => *param == DataType::Float || *param == DataType::Double,
+            AstArgType::StringL => is_ptr,
+            
+            // Function addresses have to match exactly, like they do on assignment
+            AstArgType::AddrOf => {
+                let name = &arg.sub_args.first().unwrap().str_val;
+                
+                if !builder.var_exists(name) && builder.function_exists(name) {
+                    builder.get_signature(name) == Ok(param)
+                } else {
+                    is_ptr || *param == DataType::Int64 || *param == DataType::UInt64
+                }
+            },
+            
+            // Byte arrays can be passed as strings, and strings as byte slices
+            _ => match builder.vars.get(&arg.str_val) {
+                Some(v) => match (&v.data_type, param) {
+                    (DataType::Enum(_), DataType::Int) => true,
+                    (DataType::Ptr, DataType::Str) | (DataType::Str, DataType::Ptr) => true,
+                    (data_type, _) => data_type == param,
+                },
+                
+                None => true,
+            },
+        };
+        
+        if !valid {
+            let mut msg = "Invalid argument type for function pointer: ".to_string();
+            msg.push_str(&line.name);
+            
+            builder.syntax.ltac_error2(msg);
+            return false;
+        }
+    }
+    
+    true
+}
+
 // Builds a function return
 pub fn build_return(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
     if line.args.len() > 0 && builder.current_type == DataType::Void {
@@ -275,9 +430,13 @@ pub fn build_return(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
                 mov.arg1 = LtacArg::RetRegF64;
             },
             
-            // TODO: We may want a different move for this
             DataType::Str => {
-                mov = ltac::create_instr(LtacType::Mov);
+                mov = ltac::create_instr(LtacType::MovQ);
+                mov.arg1 = LtacArg::RetRegI64;
+            },
+            
+            DataType::FuncPtr(_, _) => {
+                mov = ltac::create_instr(LtacType::MovQ);
                 mov.arg1 = LtacArg::RetRegI64;
             },
             
@@ -328,10 +487,24 @@ pub fn build_return(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
             
             AstArgType::StringL => {},
             
+            AstArgType::AddrOf => {
+                let name_arg = arg1.sub_args.first().unwrap();
+                
+                if !builder.var_exists(&name_arg.str_val) && builder.function_exists(&name_arg.str_val) {
+                    mov.arg2 = LtacArg::PtrFunc(name_arg.str_val.clone());
+                }
+            },
+            
             AstArgType::Id => {
                 match builder.vars.get(&arg1.str_val) {
                     Some(v) => {
-                        mov.instr_type = ld_for_type(&v.data_type, &v.sub_type).instr_type;
+                        // A string is returned as its address, even if the variable is a byte array
+                        if builder.current_type == DataType::Str {
+                            mov.instr_type = LtacType::LdQ;
+                        } else {
+                            mov.instr_type = ld_for_type(&v.data_type, &v.sub_type).instr_type;
+                        }
+                        
                         mov.arg2 = LtacArg::Mem(v.pos);
                     },
                     
@@ -451,3 +624,80 @@ pub fn build_end(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
     true
 }
 
+
+// Computes the lengths of any byte arrays passed to string parameters
+// The array holds text, so the length comes from strlen rather than the array size.
+// The libraries don't keep string lengths, so this is also needed when they pass a
+// string to an array parameter. Each length is stored in a hidden variable
+fn build_str_lengths(builder : &mut LtacBuilder, line : &AstStmt, params : &[DataType], c_strings : bool) -> Option<Vec<Option<i32>>> {
+    let mut lengths : Vec<Option<i32>> = Vec::new();
+    
+    for (i, arg) in line.args.iter().enumerate() {
+        let pos = match builder.get_var(&arg.str_val) {
+            Ok(v) if !c_strings && arg.arg_type == AstArgType::Id && v.data_type == DataType::Ptr
+                && matches!(v.sub_type, DataType::Byte | DataType::UByte | DataType::Char)
+                && params.get(i) == Some(&DataType::Str) => v.pos,
+            Ok(v) if builder.c_strings && arg.arg_type == AstArgType::Id && v.data_type == DataType::Str
+                && params.get(i) == Some(&DataType::Ptr) => v.pos,
+            _ => {
+                lengths.push(None);
+                continue;
+            },
+        };
+        
+        builder.stack_pos += 12;
+        
+        let mut instr = ltac::create_instr(LtacType::MovQ);
+        instr.arg1 = LtacArg::Reg64(0);
+        instr.arg2 = LtacArg::Mem(pos);
+        builder.file.code.push(instr);
+        
+        instr = ltac::create_instr(LtacType::MovQ);
+        instr.arg1 = LtacArg::Mem(builder.stack_pos);
+        instr.arg2 = LtacArg::Reg64(0);
+        builder.file.code.push(instr);
+        
+        if !build_str_len(builder, builder.stack_pos) {
+            return None;
+        }
+        
+        lengths.push(Some(builder.stack_pos - 8));
+    }
+    
+    Some(lengths)
+}
+
+// Stores the length of a string variable whose length we don't know
+// Instruction syntax:
+// pusharg [pos]
+// call strlen
+// mov r0, ret
+// mov [pos - 8], r0
+//
+pub fn build_str_len(builder : &mut LtacBuilder, pos : i32) -> bool {
+    if !builder.function_exists(&"strlen".to_string()) {
+        builder.syntax.ltac_error2("Strings from function calls and arrays require the core library.".to_string());
+        return false;
+    }
+    
+    let mut push = ltac::create_instr(LtacType::PushArg);
+    push.arg1 = LtacArg::Ptr(pos);
+    push.arg2_val = 1;
+    builder.file.code.push(push);
+    
+    let mut call = ltac::create_instr(LtacType::Call);
+    call.name = "strlen".to_string();
+    builder.file.code.push(call);
+    
+    let mut store = ltac::create_instr(LtacType::Mov);
+    store.arg1 = LtacArg::Reg32(0);
+    store.arg2 = LtacArg::RetRegI32;
+    builder.file.code.push(store);
+    
+    store = ltac::create_instr(LtacType::Mov);
+    store.arg1 = LtacArg::Mem(pos - 8);
+    store.arg2 = LtacArg::Reg32(0);
+    builder.file.code.push(store);
+    
+    true
+}
@@ -0,0 +1,775 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// Reads and writes the LTAC text format
+// See docs/ltac-text.md for the syntax. Anything written by to_text can be read
+// back by parse_text to get the same LtacFile.
+
+use crate::ltac;
+use crate::ltac::{LtacFile, LtacData, LtacDataType, LtacInstr, LtacType, LtacArg};
+use crate::syntax;
+
+// Returns the mnemonic for an instruction
+fn mnemonic(instr_type : &LtacType) -> &'static str {
+    match instr_type {
+        LtacType::None => "none",
+        
+        LtacType::Extern => "extern",
+        LtacType::Label => "lbl",
+        LtacType::Func => "func",
+        LtacType::Ret => "ret",
+        
+        LtacType::MovB => "mov.b",
+        LtacType::MovUB => "mov.ub",
+        LtacType::MovW => "mov.w",
+        LtacType::MovUW => "mov.uw",
+        LtacType::Mov => "mov",
+        LtacType::MovU => "mov.u",
+        LtacType::MovQ => "mov.q",
+        LtacType::MovUQ => "mov.uq",
+        LtacType::MovF32 => "mov.f32",
+        LtacType::MovF64 => "mov.f64",
+        LtacType::MovI32Vec => "mov.i32.vec",
+        
+        LtacType::LdAddr => "ldaddr",
+        LtacType::Push => "push",
+        LtacType::Pop => "pop",
+        
+        LtacType::LdB => "i8.ld",
+        LtacType::LdUB => "u8.ld",
+        LtacType::LdW => "i16.ld",
+        LtacType::LdUW => "u16.ld",
+        LtacType::Ld => "i32.ld",
+        LtacType::LdU => "u32.ld",
+        LtacType::LdQ => "i64.ld",
+        LtacType::LdUQ => "u64.ld",
+        LtacType::LdF32 => "f32.ld",
+        LtacType::LdF64 => "f64.ld",
+        
+        LtacType::StrB => "i8.str",
+        LtacType::StrUB => "u8.str",
+        LtacType::StrW => "i16.str",
+        LtacType::StrUW => "u16.str",
+        LtacType::Str => "i32.str",
+        LtacType::StrU => "u32.str",
+        LtacType::StrQ => "i64.str",
+        LtacType::StrUQ => "u64.str",
+        LtacType::StrF32 => "f32.str",
+        LtacType::StrF64 => "f64.str",
+        LtacType::StrPtr => "ptr.str",
+        
+        LtacType::LdArgI8 => "i8.ldarg",
+        LtacType::LdArgU8 => "u8.ldarg",
+        LtacType::LdArgI16 => "i16.ldarg",
+        LtacType::LdArgU16 => "u16.ldarg",
+        LtacType::LdArgI32 => "i32.ldarg",
+        LtacType::LdArgU32 => "u32.ldarg",
+        LtacType::LdArgI64 => "i64.ldarg",
+        LtacType::LdArgU64 => "u64.ldarg",
+        LtacType::LdArgF32 => "f32.ldarg",
+        LtacType::LdArgF64 => "f64.ldarg",
+        LtacType::LdArgPtr => "ptr.ldarg",
+        
+        LtacType::PushArg => "pusharg",
+        LtacType::KPushArg => "kpusharg",
+        LtacType::Call => "call",
+        LtacType::CallReg => "call.reg",
+        LtacType::Syscall => "syscall",
+        
+        LtacType::Malloc => "malloc",
+        LtacType::Calloc => "calloc",
+        LtacType::Free => "free",
+        LtacType::Resize => "resize",
+        LtacType::Exit => "exit",
+        
+        LtacType::I8Cmp => "i8.cmp",
+        LtacType::U8Cmp => "u8.cmp",
+        LtacType::I16Cmp => "i16.cmp",
+        LtacType::U16Cmp => "u16.cmp",
+        LtacType::I32Cmp => "i32.cmp",
+        LtacType::U32Cmp => "u32.cmp",
+        LtacType::I64Cmp => "i64.cmp",
+        LtacType::U64Cmp => "u64.cmp",
+        LtacType::F32Cmp => "f32.cmp",
+        LtacType::F64Cmp => "f64.cmp",
+        LtacType::StrCmp => "str.cmp",
+        
+        LtacType::Br => "br",
+        LtacType::Be => "be",
+        LtacType::Bne => "bne",
+        LtacType::Bl => "bl",
+        LtacType::Ble => "ble",
+        LtacType::Bfl => "bfl",
+        LtacType::Bfle => "bfle",
+        LtacType::Bg => "bg",
+        LtacType::Bge => "bge",
+        LtacType::Bfg => "bfg",
+        LtacType::Bfge => "bfge",
+        
+        LtacType::I8Add => "i8.add",
+        LtacType::U8Add => "u8.add",
+        LtacType::I8Sub => "i8.sub",
+        LtacType::I8Mul => "i8.mul",
+        LtacType::U8Mul => "u8.mul",
+        LtacType::I8Div => "i8.div",
+        LtacType::U8Div => "u8.div",
+        LtacType::I8Mod => "i8.mod",
+        LtacType::U8Mod => "u8.mod",
+        
+        LtacType::I16Add => "i16.add",
+        LtacType::U16Add => "u16.add",
+        LtacType::I16Sub => "i16.sub",
+        LtacType::I16Mul => "i16.mul",
+        LtacType::U16Mul => "u16.mul",
+        LtacType::I16Div => "i16.div",
+        LtacType::U16Div => "u16.div",
+        LtacType::I16Mod => "i16.mod",
+        LtacType::U16Mod => "u16.mod",
+        
+        LtacType::I32Add => "i32.add",
+        LtacType::U32Add => "u32.add",
+        LtacType::I32Sub => "i32.sub",
+        LtacType::I32Mul => "i32.mul",
+        LtacType::U32Mul => "u32.mul",
+        LtacType::I32Div => "i32.div",
+        LtacType::U32Div => "u32.div",
+        LtacType::I32Mod => "i32.mod",
+        LtacType::U32Mod => "u32.mod",
+        
+        LtacType::I64Add => "i64.add",
+        LtacType::U64Add => "u64.add",
+        LtacType::I64Sub => "i64.sub",
+        LtacType::I64Mul => "i64.mul",
+        LtacType::U64Mul => "u64.mul",
+        LtacType::I64Div => "i64.div",
+        LtacType::U64Div => "u64.div",
+        LtacType::I64Mod => "i64.mod",
+        LtacType::U64Mod => "u64.mod",
+        
+        LtacType::F32Add => "f32.add",
+        LtacType::F32Sub => "f32.sub",
+        LtacType::F32Mul => "f32.mul",
+        LtacType::F32Div => "f32.div",
+        
+        LtacType::F64Add => "f64.add",
+        LtacType::F64Sub => "f64.sub",
+        LtacType::F64Mul => "f64.mul",
+        LtacType::F64Div => "f64.div",
+        
+        LtacType::And => "and",
+        LtacType::Or => "or",
+        LtacType::Xor => "xor",
+        LtacType::Lsh => "lsh",
+        LtacType::Rsh => "rsh",
+        
+        LtacType::I32VAdd => "i32.vadd",
+        
+        LtacType::CvtF32F64 => "cvt.f32.f64",
+        LtacType::CvtF64F32 => "cvt.f64.f32",
+        LtacType::CvtIntF32 => "cvt.i.f32",
+        LtacType::CvtIntF64 => "cvt.i.f64",
+        LtacType::CvtF32Int => "cvt.f32.i",
+        LtacType::CvtF64Int => "cvt.f64.i",
+        LtacType::MovF64Int => "mov.f64.i32",
+    }
+}
+
+// Returns the instruction for a mnemonic
+fn instr_for_mnemonic(name : &str) -> Option<LtacType> {
+    let instr_type = match name {
+        "none" => LtacType::None,
+        
+        "extern" => LtacType::Extern,
+        "lbl" => LtacType::Label,
+        "func" => LtacType::Func,
+        "ret" => LtacType::Ret,
+        
+        "mov.b" => LtacType::MovB,
+        "mov.ub" => LtacType::MovUB,
+        "mov.w" => LtacType::MovW,
+        "mov.uw" => LtacType::MovUW,
+        "mov" => LtacType::Mov,
+        "mov.u" => LtacType::MovU,
+        "mov.q" => LtacType::MovQ,
+        "mov.uq" => LtacType::MovUQ,
+        "mov.f32" => LtacType::MovF32,
+        "mov.f64" => LtacType::MovF64,
+        "mov.i32.vec" => LtacType::MovI32Vec,
+        
+        "ldaddr" => LtacType::LdAddr,
+        "push" => LtacType::Push,
+        "pop" => LtacType::Pop,
+        
+        "i8.ld" => LtacType::LdB,
+        "u8.ld" => LtacType::LdUB,
+        "i16.ld" => LtacType::LdW,
+        "u16.ld" => LtacType::LdUW,
+        "i32.ld" => LtacType::Ld,
+        "u32.ld" => LtacType::LdU,
+        "i64.ld" => LtacType::LdQ,
+        "u64.ld" => LtacType::LdUQ,
+        "f32.ld" => LtacType::LdF32,
+        "f64.ld" => LtacType::LdF64,
+        
+        "i8.str" => LtacType::StrB,
+        "u8.str" => LtacType::StrUB,
+        "i16.str" => LtacType::StrW,
+        "u16.str" => LtacType::StrUW,
+        "i32.str" => LtacType::Str,
+        "u32.str" => LtacType::StrU,
+        "i64.str" => LtacType::StrQ,
+        "u64.str" => LtacType::StrUQ,
+        "f32.str" => LtacType::StrF32,
+        "f64.str" => LtacType::StrF64,
+        "ptr.str" => LtacType::StrPtr,
+        
+        "i8.ldarg" => LtacType::LdArgI8,
+        "u8.ldarg" => LtacType::LdArgU8,
+        "i16.ldarg" => LtacType::LdArgI16,
+        "u16.ldarg" => LtacType::LdArgU16,
+        "i32.ldarg" => LtacType::LdArgI32,
+        "u32.ldarg" => LtacType::LdArgU32,
+        "i64.ldarg" => LtacType::LdArgI64,
+        "u64.ldarg" => LtacType::LdArgU64,
+        "f32.ldarg" => LtacType::LdArgF32,
+        "f64.ldarg" => LtacType::LdArgF64,
+        "ptr.ldarg" => LtacType::LdArgPtr,
+        
+        "pusharg" => LtacType::PushArg,
+        "kpusharg" => LtacType::KPushArg,
+        "call" => LtacType::Call,
+        "call.reg" => LtacType::CallReg,
+        "syscall" => LtacType::Syscall,
+        
+        "malloc" => LtacType::Malloc,
+        "calloc" => LtacType::Calloc,
+        "free" => LtacType::Free,
+        "resize" => LtacType::Resize,
+        "exit" => LtacType::Exit,
+        
+        "i8.cmp" => LtacType::I8Cmp,
+        "u8.cmp" => LtacType::U8Cmp,
+        "i16.cmp" => LtacType::I16Cmp,
+        "u16.cmp" => LtacType::U16Cmp,
+        "i32.cmp" => LtacType::I32Cmp,
+        "u32.cmp" => LtacType::U32Cmp,
+        "i64.cmp" => LtacType::I64Cmp,
+        "u64.cmp" => LtacType::U64Cmp,
+        "f32.cmp" => LtacType::F32Cmp,
+        "f64.cmp" => LtacType::F64Cmp,
+        "str.cmp" => LtacType::StrCmp,
+        
+        "br" => LtacType::Br,
+        "be" => LtacType::Be,
+        "bne" => LtacType::Bne,
+        "bl" => LtacType::Bl,
+        "ble" => LtacType::Ble,
+        "bfl" => LtacType::Bfl,
+        "bfle" => LtacType::Bfle,
+        "bg" => LtacType::Bg,
+        "bge" => LtacType::Bge,
+        "bfg" => LtacType::Bfg,
+        "bfge" => LtacType::Bfge,
+        
+        "i8.add" => LtacType::I8Add,
+        "u8.add" => LtacType::U8Add,
+        "i8.sub" => LtacType::I8Sub,
+        "i8.mul" => LtacType::I8Mul,
+        "u8.mul" => LtacType::U8Mul,
+        "i8.div" => LtacType::I8Div,
+        "u8.div" => LtacType::U8Div,
+        "i8.mod" => LtacType::I8Mod,
+        "u8.mod" => LtacType::U8Mod,
+        
+        "i16.add" => LtacType::I16Add,
+        "u16.add" => LtacType::U16Add,
+        "i16.sub" => LtacType::I16Sub,
+        "i16.mul" => LtacType::I16Mul,
+        "u16.mul" => LtacType::U16Mul,
+        "i16.div" => LtacType::I16Div,
+        "u16.div" => LtacType::U16Div,
+        "i16.mod" => LtacType::I16Mod,
+        "u16.mod" => LtacType::U16Mod,
+        
+        "i32.add" => LtacType::I32Add,
+        "u32.add" => LtacType::U32Add,
+        "i32.sub" => LtacType::I32Sub,
+        "i32.mul" => LtacType::I32Mul,
+        "u32.mul" => LtacType::U32Mul,
+        "i32.div" => LtacType::I32Div,
+        "u32.div" => LtacType::U32Div,
+        "i32.mod" => LtacType::I32Mod,
+        "u32.mod" => LtacType::U32Mod,
+        
+        "i64.add" => LtacType::I64Add,
+        "u64.add" => LtacType::U64Add,
+        "i64.sub" => LtacType::I64Sub,
+        "i64.mul" => LtacType::I64Mul,
+        "u64.mul" => LtacType::U64Mul,
+        "i64.div" => LtacType::I64Div,
+        "u64.div" => LtacType::U64Div,
+        "i64.mod" => LtacType::I64Mod,
+        "u64.mod" => LtacType::U64Mod,
+        
+        "f32.add" => LtacType::F32Add,
+        "f32.sub" => LtacType::F32Sub,
+        "f32.mul" => LtacType::F32Mul,
+        "f32.div" => LtacType::F32Div,
+        
+        "f64.add" => LtacType::F64Add,
+        "f64.sub" => LtacType::F64Sub,
+        "f64.mul" => LtacType::F64Mul,
+        "f64.div" => LtacType::F64Div,
+        
+        "and" => LtacType::And,
+        "or" => LtacType::Or,
+        "xor" => LtacType::Xor,
+        "lsh" => LtacType::Lsh,
+        "rsh" => LtacType::Rsh,
+        
+        "i32.vadd" => LtacType::I32VAdd,
+        
+        "cvt.f32.f64" => LtacType::CvtF32F64,
+        "cvt.f64.f32" => LtacType::CvtF64F32,
+        "cvt.i.f32" => LtacType::CvtIntF32,
+        "cvt.i.f64" => LtacType::CvtIntF64,
+        "cvt.f32.i" => LtacType::CvtF32Int,
+        "cvt.f64.i" => LtacType::CvtF64Int,
+        "mov.f64.i32" => LtacType::MovF64Int,
+        
+        _ => return None,
+    };
+    
+    Some(instr_type)
+}
+
+// The instructions where the name is the main operand
+// The name of any other instruction is written after "->"
+fn name_first(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::Extern | LtacType::Label | LtacType::Func | LtacType::Call
+        | LtacType::Br | LtacType::Be | LtacType::Bne
+        | LtacType::Bl | LtacType::Ble | LtacType::Bfl | LtacType::Bfle
+        | LtacType::Bg | LtacType::Bge | LtacType::Bfg | LtacType::Bfge => true,
+        
+        _ => false,
+    }
+}
+
+//=====================================
+// The writer
+
+// Returns an LTAC file as text
+pub fn to_text(file : &LtacFile) -> String {
+    let mut text = String::new();
+    
+    text.push_str(".data\n");
+    
+    for data in file.data.iter() {
+        text.push_str(&data.name);
+        
+        match &data.data_type {
+            LtacDataType::StringL => {
+                text.push_str(" .string \"");
+                text.push_str(&data.val);
+                text.push_str("\"");
+            },
+            
+            LtacDataType::FloatL => {
+                text.push_str(" .float ");
+                text.push_str(&data.val);
+            },
+            
+            LtacDataType::DoubleL => {
+                text.push_str(" .double ");
+                text.push_str(&data.val);
+            },
+            
+            LtacDataType::ArrayL(size) => {
+                text.push_str(" .array");
+                text.push_str(&size.to_string());
+                text.push_str(" ");
+                text.push_str(&data.val);
+            },
+        }
+        
+        text.push_str("\n");
+    }
+    
+    text.push_str("\n.text\n");
+    
+    for instr in file.code.iter() {
+        match &instr.instr_type {
+            LtacType::Func => text.push_str("\n"),
+            LtacType::Extern | LtacType::Label => {},
+            _ => text.push_str("  "),
+        }
+        
+        text.push_str(&instr_to_text(instr));
+        text.push_str("\n");
+    }
+    
+    text
+}
+
+// Returns a single instruction as text
+pub fn instr_to_text(instr : &LtacInstr) -> String {
+    let mut line = mnemonic(&instr.instr_type).to_string();
+    
+    if name_first(&instr.instr_type) && !instr.name.is_empty() {
+        line.push_str(" ");
+        line.push_str(&instr.name);
+    }
+    
+    if instr.arg1 != LtacArg::Empty || instr.arg2 != LtacArg::Empty {
+        line.push_str(" ");
+        line.push_str(&arg_to_text(&instr.arg1));
+    }
+    
+    if instr.arg2 != LtacArg::Empty {
+        line.push_str(", ");
+        line.push_str(&arg_to_text(&instr.arg2));
+    }
+    
+    if !name_first(&instr.instr_type) && !instr.name.is_empty() {
+        line.push_str(" -> ");
+        line.push_str(&instr.name);
+    }
+    
+    if instr.arg1_val != 0 {
+        line.push_str(" val1=");
+        line.push_str(&instr.arg1_val.to_string());
+    }
+    
+    if instr.arg2_val != 0 {
+        line.push_str(" val2=");
+        line.push_str(&instr.arg2_val.to_string());
+    }
+    
+    line
+}
+
+// Returns an argument as text
+pub fn arg_to_text(arg : &LtacArg) -> String {
+    match arg {
+        LtacArg::Empty => "_".to_string(),
+        
+        LtacArg::Reg8(pos) => format!("i8.r{}", pos),
+        LtacArg::Reg16(pos) => format!("i16.r{}", pos),
+        LtacArg::Reg32(pos) => format!("i32.r{}", pos),
+        LtacArg::Reg64(pos) => format!("i64.r{}", pos),
+        LtacArg::FltReg(pos) => format!("f32.r{}", pos),
+        LtacArg::FltReg64(pos) => format!("f64.r{}", pos),
+        
+        LtacArg::RetRegI8 => "i8.ret".to_string(),
+        LtacArg::RetRegU8 => "u8.ret".to_string(),
+        LtacArg::RetRegI16 => "i16.ret".to_string(),
+        LtacArg::RetRegU16 => "u16.ret".to_string(),
+        LtacArg::RetRegI32 => "i32.ret".to_string(),
+        LtacArg::RetRegU32 => "u32.ret".to_string(),
+        LtacArg::RetRegI64 => "i64.ret".to_string(),
+        LtacArg::RetRegU64 => "u64.ret".to_string(),
+        LtacArg::RetRegF32 => "f32.ret".to_string(),
+        LtacArg::RetRegF64 => "f64.ret".to_string(),
+        
+        LtacArg::Mem(pos) => format!("[bp-{}]", pos),
+        LtacArg::MemOffsetImm(pos, offset) => format!("[bp-{}+{}]", pos, offset),
+        LtacArg::MemOffsetMem(pos, var, size) => format!("[bp-{}+({}*{})]", pos, var, size),
+        LtacArg::MemOffsetReg(pos, reg, size) => format!("[bp-{}+(r{}*{})]", pos, reg, size),
+        LtacArg::MemOffsetStride(pos, row, reg, stride, size) => {
+            format!("[bp-{}+(({}*{}+r{})*{})]", pos, row, stride, reg, size)
+        },
+        
+        LtacArg::Byte(val) => format!("{}i8", val),
+        LtacArg::UByte(val) => format!("{}u8", val),
+        LtacArg::I16(val) => format!("{}i16", val),
+        LtacArg::U16(val) => format!("{}u16", val),
+        LtacArg::I32(val) => val.to_string(),
+        LtacArg::U32(val) => format!("{}u32", val),
+        LtacArg::I64(val) => format!("{}i64", val),
+        LtacArg::U64(val) => format!("{}u64", val),
+        LtacArg::F32(name) => format!("f32:{}", name),
+        LtacArg::F64(name) => format!("f64:{}", name),
+        
+        LtacArg::Ptr(pos) => format!("ptr[bp-{}]", pos),
+        LtacArg::PtrLcl(name) => format!("&{}", name),
+        LtacArg::PtrFunc(name) => format!("@{}", name),
+    }
+}
+
+//=====================================
+// The reader
+
+// Builds an LTAC file from text
+// Any errors are printed, like syntax errors in a source file
+pub fn parse_text(text : &str, name : &String) -> Result<LtacFile, ()> {
+    let mut file = LtacFile {
+        name : name.clone(),
+        data : Vec::new(),
+        code : Vec::new(),
+    };
+    
+    let mut syntax = syntax::create_error_manager();
+    let mut in_data = false;
+    
+    for (i, line) in text.lines().enumerate() {
+        let line_no = (i + 1) as i32;
+        let ln = line.trim();
+        
+        // Blank lines and comments
+        if ln.is_empty() || ln.starts_with('#') {
+            continue;
+        }
+        
+        if ln == ".data" {
+            in_data = true;
+            continue;
+        } else if ln == ".text" {
+            in_data = false;
+            continue;
+        }
+        
+        let result = if in_data {
+            parse_data(ln).map(|data| file.data.push(data))
+        } else {
+            parse_instr(ln).map(|instr| file.code.push(instr))
+        };
+        
+        if let Err(msg) = result {
+            syntax.line_error(line_no, ln, msg);
+        }
+    }
+    
+    if !syntax.errors.is_empty() {
+        syntax.print_errors();
+        return Err(());
+    }
+    
+    Ok(file)
+}
+
+// Parses a line in the data section
+fn parse_data(line : &str) -> Result<LtacData, String> {
+    let mut parts = line.splitn(3, ' ');
+    let name = parts.next().unwrap_or("");
+    let directive = parts.next().unwrap_or("");
+    let val = parts.next().unwrap_or("");
+    
+    let data_type = match directive {
+        ".string" => {
+            if val.len() < 2 || !val.starts_with('"') || !val.ends_with('"') {
+                return Err("Expected a quoted string.".to_string());
+            }
+            
+            LtacDataType::StringL
+        },
+        
+        ".float" => LtacDataType::FloatL,
+        ".double" => LtacDataType::DoubleL,
+        
+        _ if directive.starts_with(".array") => {
+            match directive[6..].parse::<i32>() {
+                Ok(size) => LtacDataType::ArrayL(size),
+                Err(_e) => return Err("Expected the element size after .array".to_string()),
+            }
+        },
+        
+        _ => return Err(format!("Unknown data type: {}", directive)),
+    };
+    
+    let val = if data_type == LtacDataType::StringL {
+        &val[1..val.len()-1]
+    } else {
+        val
+    };
+    
+    Ok(LtacData {
+        data_type : data_type,
+        name : name.to_string(),
+        val : val.to_string(),
+    })
+}
+
+// Parses an instruction
+fn parse_instr(line : &str) -> Result<LtacInstr, String> {
+    let mut tokens = line.split_whitespace();
+    let op = tokens.next().unwrap_or("");
+    
+    let instr_type = match instr_for_mnemonic(op) {
+        Some(instr_type) => instr_type,
+        None => return Err(format!("Unknown instruction: {}", op)),
+    };
+    
+    let mut instr = ltac::create_instr(instr_type);
+    let mut args : Vec<LtacArg> = Vec::new();
+    let mut next_name = false;
+    
+    for token in tokens {
+        if next_name {
+            instr.name = token.to_string();
+            next_name = false;
+        } else if token == "->" {
+            next_name = true;
+        } else if token.starts_with("val1=") {
+            instr.arg1_val = parse_int(&token[5..])?;
+        } else if token.starts_with("val2=") {
+            instr.arg2_val = parse_int(&token[5..])?;
+        } else {
+            let token = token.trim_end_matches(',');
+            
+            match parse_arg(token) {
+                Some(arg) => args.push(arg),
+                
+                // The name can come before the arguments
+                None if args.is_empty() && instr.name.is_empty() => instr.name = token.to_string(),
+                None => return Err(format!("Invalid argument: {}", token)),
+            }
+        }
+    }
+    
+    if next_name {
+        return Err("Expected a name after \"->\"".to_string());
+    }
+    
+    if args.len() > 2 {
+        return Err("Instructions can only have two arguments.".to_string());
+    }
+    
+    let mut args = args.into_iter();
+    instr.arg1 = args.next().unwrap_or(LtacArg::Empty);
+    instr.arg2 = args.next().unwrap_or(LtacArg::Empty);
+    
+    Ok(instr)
+}
+
+fn parse_int(val : &str) -> Result<i32, String> {
+    match val.parse::<i32>() {
+        Ok(val) => Ok(val),
+        Err(_e) => Err(format!("Invalid number: {}", val)),
+    }
+}
+
+// Parses an argument
+// This returns None if the token isn't an argument
+fn parse_arg(token : &str) -> Option<LtacArg> {
+    let arg = match token {
+        "_" => LtacArg::Empty,
+        
+        "i8.ret" => LtacArg::RetRegI8,
+        "u8.ret" => LtacArg::RetRegU8,
+        "i16.ret" => LtacArg::RetRegI16,
+        "u16.ret" => LtacArg::RetRegU16,
+        "i32.ret" => LtacArg::RetRegI32,
+        "u32.ret" => LtacArg::RetRegU32,
+        "i64.ret" => LtacArg::RetRegI64,
+        "u64.ret" => LtacArg::RetRegU64,
+        "f32.ret" => LtacArg::RetRegF32,
+        "f64.ret" => LtacArg::RetRegF64,
+        
+        _ if token.starts_with("ptr[") => {
+            match parse_mem(&token[3..])? {
+                LtacArg::Mem(pos) => LtacArg::Ptr(pos),
+                _ => return None,
+            }
+        },
+        
+        _ if token.starts_with('[') => parse_mem(token)?,
+        _ if token.starts_with("f32:") => LtacArg::F32(token[4..].to_string()),
+        _ if token.starts_with("f64:") => LtacArg::F64(token[4..].to_string()),
+        _ if token.starts_with('&') => LtacArg::PtrLcl(token[1..].to_string()),
+        _ if token.starts_with('@') => LtacArg::PtrFunc(token[1..].to_string()),
+        
+        _ if token.starts_with('-') || token.starts_with(|c : char| c.is_ascii_digit()) => {
+            parse_literal(token)?
+        },
+        
+        _ => parse_reg(token)?,
+    };
+    
+    Some(arg)
+}
+
+// Parses a register, such as i32.r1
+fn parse_reg(token : &str) -> Option<LtacArg> {
+    let pos = token.find(".r")?;
+    let num = token[pos+2..].parse::<i32>().ok()?;
+    
+    match &token[..pos] {
+        "i8" => Some(LtacArg::Reg8(num)),
+        "i16" => Some(LtacArg::Reg16(num)),
+        "i32" => Some(LtacArg::Reg32(num)),
+        "i64" => Some(LtacArg::Reg64(num)),
+        "f32" => Some(LtacArg::FltReg(num)),
+        "f64" => Some(LtacArg::FltReg64(num)),
+        _ => None,
+    }
+}
+
+// Parses an integer literal
+// The type is given by the suffix; a literal without one is an i32
+fn parse_literal(token : &str) -> Option<LtacArg> {
+    let suffixes = ["i16", "u16", "u32", "i64", "u64", "i8", "u8"];
+    
+    for suffix in suffixes.iter() {
+        if let Some(num) = token.strip_suffix(suffix) {
+            let arg = match *suffix {
+                "i8" => LtacArg::Byte(num.parse::<i8>().ok()?),
+                "u8" => LtacArg::UByte(num.parse::<u8>().ok()?),
+                "i16" => LtacArg::I16(num.parse::<i16>().ok()?),
+                "u16" => LtacArg::U16(num.parse::<u16>().ok()?),
+                "u32" => LtacArg::U32(num.parse::<u32>().ok()?),
+                "i64" => LtacArg::I64(num.parse::<i64>().ok()?),
+                _ => LtacArg::U64(num.parse::<u64>().ok()?),
+            };
+            
+            return Some(arg);
+        }
+    }
+    
+    Some(LtacArg::I32(token.parse::<i32>().ok()?))
+}
+
+// Parses a memory reference
+//  [bp-pos]
+//  [bp-pos+offset]
+//  [bp-pos+(var*size)]
+//  [bp-pos+(rN*size)]
+//  [bp-pos+((row*stride+rN)*size)]
+fn parse_mem(token : &str) -> Option<LtacArg> {
+    let inner = token.strip_prefix("[bp-")?.strip_suffix(']')?;
+    
+    // The position can be negative, so skip the first character when looking for the offset
+    let split = match inner.get(1..)?.find('+') {
+        Some(split) => split + 1,
+        None => return Some(LtacArg::Mem(inner.parse::<i32>().ok()?)),
+    };
+    
+    let pos = inner[..split].parse::<i32>().ok()?;
+    let offset = &inner[split+1..];
+    
+    if let Some(stride) = offset.strip_prefix("((") {
+        let (index, size) = stride.rsplit_once(")*")?;
+        let size = size.strip_suffix(')')?.parse::<i32>().ok()?;
+        let (row, reg) = index.split_once("+r")?;
+        let (row, stride) = row.split_once('*')?;
+        
+        return Some(LtacArg::MemOffsetStride(pos, row.parse::<i32>().ok()?, reg.parse::<i32>().ok()?,
+                        stride.parse::<i32>().ok()?, size));
+    }
+    
+    if let Some(index) = offset.strip_prefix('(') {
+        let (var, size) = index.strip_suffix(')')?.split_once('*')?;
+        let size = size.parse::<i32>().ok()?;
+        
+        if let Some(reg) = var.strip_prefix('r') {
+            return Some(LtacArg::MemOffsetReg(pos, reg.parse::<i32>().ok()?, size));
+        }
+        
+        return Some(LtacArg::MemOffsetMem(pos, var.parse::<i32>().ok()?, size));
+    }
+    
+    Some(LtacArg::MemOffsetImm(pos, offset.parse::<i32>().ok()?))
+}
@@ -38,6 +38,17 @@ pub fn create_label(builder : &mut LtacBuilder) -> String {
     name
 }
 
+// Creates a label that isn't tied to a block
+pub fn create_local_label(builder : &mut LtacBuilder) -> String {
+    let lbl_pos = builder.str_pos.to_string();
+    builder.str_pos += 1;
+    
+    let mut name = "L".to_string();
+    name.push_str(&lbl_pos);
+    
+    name
+}
+
 // A utility function to create a top-level label
 pub fn create_top_label(builder : &mut LtacBuilder) {
     let lbl_pos = builder.str_pos.to_string();
@@ -58,6 +69,7 @@ pub fn size_for_type(data_type : &DataType) -> i32 {
         DataType::Int | DataType::UInt => 4,
         DataType::Int64 | DataType::UInt64 => 8,
         DataType::Str | DataType::Ptr => 8,
+        DataType::FuncPtr(_, _) => 8,
         DataType::Float => 4,
         DataType::Double => 8,
         _ => 0,
@@ -109,6 +121,11 @@ pub fn mov_for_type(data_type : &DataType, sub_type : &DataType) -> LtacInstr {
         
         DataType::Ptr if *sub_type == DataType::Str => instr = ltac::create_instr(LtacType::MovQ),
         
+        // Function pointers
+        DataType::FuncPtr(_, _) => instr = ltac::create_instr(LtacType::MovQ),
+        
+        DataType::Ptr if matches!(sub_type, DataType::FuncPtr(_, _)) => instr = ltac::create_instr(LtacType::MovQ),
+        
         _ => {},
     }
     
@@ -160,6 +177,11 @@ pub fn ld_for_type(data_type : &DataType, sub_type : &DataType) -> LtacInstr {
         
         DataType::Ptr if *sub_type == DataType::Str => instr = ltac::create_instr(LtacType::LdQ),
         
+        // Function pointers
+        DataType::FuncPtr(_, _) => instr = ltac::create_instr(LtacType::LdQ),
+        
+        DataType::Ptr if matches!(sub_type, DataType::FuncPtr(_, _)) => instr = ltac::create_instr(LtacType::LdQ),
+        
         _ => {},
     }
     
@@ -211,6 +233,11 @@ pub fn str_for_type(data_type : &DataType, sub_type : &DataType) -> LtacInstr {
         
         DataType::Ptr if *sub_type == DataType::Str => instr = ltac::create_instr(LtacType::StrQ),
         
+        // Function pointers
+        DataType::FuncPtr(_, _) => instr = ltac::create_instr(LtacType::StrQ),
+        
+        DataType::Ptr if matches!(sub_type, DataType::FuncPtr(_, _)) => instr = ltac::create_instr(LtacType::StrQ),
+        
         _ => {},
     }
     
@@ -263,6 +290,12 @@ pub fn reg_for_type(data_type : &DataType, sub_type : &DataType, reg_no : i32) -
         DataType::Ptr
         if *sub_type == DataType::Str => arg = LtacArg::Reg64(reg_no),
         
+        // Function pointers
+        DataType::FuncPtr(_, _) => arg = LtacArg::Reg64(reg_no),
+        
+        DataType::Ptr
+        if matches!(sub_type, DataType::FuncPtr(_, _)) => arg = LtacArg::Reg64(reg_no),
+        
         _ => {},
     }
     
@@ -290,6 +323,7 @@ pub fn ldarg_for_type(data_type : &DataType, dest : LtacArg, pos : i32) -> LtacI
         DataType::Double => arg = ltac::create_instr(LtacType::LdArgF64),
         
         DataType::Ptr | DataType::Str => arg = ltac::create_instr(LtacType::LdArgPtr),
+        DataType::FuncPtr(_, _) => arg = ltac::create_instr(LtacType::LdArgPtr),
         
         _ => return arg,
     }
@@ -300,3 +334,35 @@ pub fn ldarg_for_type(data_type : &DataType, dest : LtacArg, pos : i32) -> LtacI
     arg
 }
 
+// Returns the conversion needed to assign one type to another, if any
+// Only int and int64 convert to and from the floating-point types
+pub fn cvt_for_types(src : &DataType, dest : &DataType) -> Option<LtacType> {
+    match (src, dest) {
+        (DataType::Float, DataType::Double) => Some(LtacType::CvtF32F64),
+        (DataType::Double, DataType::Float) => Some(LtacType::CvtF64F32),
+        
+        (DataType::Int, DataType::Float) | (DataType::Int64, DataType::Float) => Some(LtacType::CvtIntF32),
+        (DataType::Int, DataType::Double) | (DataType::Int64, DataType::Double) => Some(LtacType::CvtIntF64),
+        
+        (DataType::Float, DataType::Int) | (DataType::Float, DataType::Int64) => Some(LtacType::CvtF32Int),
+        (DataType::Double, DataType::Int) | (DataType::Double, DataType::Int64) => Some(LtacType::CvtF64Int),
+        
+        _ => None,
+    }
+}
+
+// Returns the length of a string literal
+// Escape sequences are still in the literal at this point, but they only take one byte
+pub fn str_literal_len(val : &str) -> i32 {
+    let mut length = 0;
+    let mut chars = val.chars();
+    
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        }
+        length += 1;
+    }
+    
+    length
+}
@@ -19,6 +19,8 @@ use crate::ltac_utils::*;
 // Note for array sizes:
 //    Array sizes are 12 bytes long.
 //    The first 8 bytes hold the pointer, and the second 4 hold the size
+//    Strings are laid out the same way, with the length in place of the size
+//    (except in the core library, where they are only a pointer)
 //
 pub fn build_var_dec(builder : &mut LtacBuilder, line : &AstStmt, arg_no_o : i32, flt_arg_no_o : i32) -> (bool, i32, i32) {
     let mut arg_no = arg_no_o;
@@ -41,9 +43,11 @@ pub fn build_var_dec(builder : &mut LtacBuilder, line : &AstStmt, arg_no_o : i32
         DataType::Double => builder.stack_pos += 8,
         
         DataType::Char => builder.stack_pos += 1,
-        DataType::Str => builder.stack_pos += 8,
+        DataType::Str if builder.c_strings => builder.stack_pos += 8,
+        DataType::Str => builder.stack_pos += 12,
         
         DataType::Ptr => builder.stack_pos += 12,
+        DataType::FuncPtr(_, _) => builder.stack_pos += 8,
         
         // TODO: We will need better type detection
         DataType::Enum(_) => builder.stack_pos += 4,
@@ -64,6 +68,17 @@ pub fn build_var_dec(builder : &mut LtacBuilder, line : &AstStmt, arg_no_o : i32
         data_type : data_type,
         sub_type : sub_type,
         is_param : is_param,
+        
+        // Only arrays declared with a size or a literal belong to the function; arrays
+        // from new expressions are managed by the program
+        is_owned : !line.sub_args.is_empty()
+            || (line.args.len() == 1 && line.args[0].arg_type == AstArgType::ArrayL),
+        
+        dims : line.sub_args.iter()
+            .skip_while(|a| a.arg_type != AstArgType::Dim)
+            .filter(|a| a.arg_type == AstArgType::IntL)
+            .map(|a| a.u64_val as i32)
+            .collect(),
     };
     
     builder.vars.insert(name, v);
@@ -81,8 +96,8 @@ pub fn build_var_dec(builder : &mut LtacBuilder, line : &AstStmt, arg_no_o : i32
             ld = ldarg_for_type(&data_type, mem, arg_no);
             arg_no += 1;
             
-            // If we have a pointer, make sure to load the size
-            if data_type == DataType::Ptr {
+            // If we have a pointer or a string, make sure to load the size
+            if data_type == DataType::Ptr || (data_type == DataType::Str && !builder.c_strings) {
                 let mut arg2 = ltac::create_instr(LtacType::LdArgI32);
                 arg2.arg1 = LtacArg::Mem(builder.stack_pos - 8);
                 arg2.arg2_val = arg_no;
@@ -130,6 +145,7 @@ pub fn build_var_assign(builder : &mut LtacBuilder, line : &AstStmt) -> bool {
 // TODO: I want to consider merging this with the rest of the expression builder
 pub fn build_str_assign(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -> bool {
     let mut instr = ltac::create_instr(LtacType::MovQ);
+    let mut length = LtacArg::Empty;
     
     if line.args.len() == 1 {
         let arg = line.args.first().unwrap();
@@ -140,12 +156,23 @@ pub fn build_str_assign(builder : &mut LtacBuilder, line : &AstStmt, var : &Var)
             AstArgType::StringL => {
                 let name = builder.build_string(arg.str_val.clone());
                 instr.arg2 = LtacArg::PtrLcl(name);
+                length = LtacArg::I32(str_literal_len(&arg.str_val));
+            },
+            
+            // Slices become a call to the core library
+            AstArgType::Id if builder.var_exists(&arg.str_val)
+                    && arg.sub_args.iter().any(|a| a.arg_type == AstArgType::Range) => {
+                if !build_str_slice(builder, line, arg) {
+                    return false;
+                }
+                
+                instr.arg2 = LtacArg::RetRegI64;
             },
             
             // Build an ID value based on a variable
             AstArgType::Id if builder.var_exists(&arg.str_val) => {
                 let v = match &builder.get_var(&arg.str_val) {
-                    Ok(v) => v.clone(),
+                    Ok(v) => (*v).clone(),
                     Err(_e) => return false,
                 };
             
@@ -164,29 +191,52 @@ pub fn build_str_assign(builder : &mut LtacBuilder, line : &AstStmt, var : &Var)
                     
                         if arg.sub_args.len() == 1 {
                             if first_arg.arg_type == AstArgType::IntL {
+                                build_index_check(builder, &v, LtacArg::I32(first_arg.u64_val as i32));
+                                
                                 let offset = (first_arg.u64_val as i32) * size;
                                 instr2.arg2 = LtacArg::MemOffsetImm(v.pos, offset);
                             } else if first_arg.arg_type == AstArgType::Id {
-                                match &builder.get_var(&first_arg.str_val) {
-                                    Ok(v2) => instr2.arg2 = LtacArg::MemOffsetMem(v.pos, v2.pos, size),
+                                let index_pos = match &builder.get_var(&first_arg.str_val) {
+                                    Ok(v2) => v2.pos,
                                     Err(_e) => {
                                         builder.syntax.ltac_error2("Invalid offset variable.".to_string());
                                         return false;
                                     },
                                 };
+                                
+                                build_index_check(builder, &v, LtacArg::Mem(index_pos));
+                                instr2.arg2 = LtacArg::MemOffsetMem(v.pos, index_pos, size);
                             }
                         }
                     }
                     
                     builder.file.code.push(instr2);
+                    instr.arg2 = LtacArg::Reg64(0);
+                    
+                // A byte array is copied, so changing the array later can't make the length wrong
+                } else if v.data_type != DataType::Str && !builder.c_strings {
+                    if !build_str_copy(builder, &arg.str_val) {
+                        return false;
+                    }
+                    
+                    instr.arg2 = LtacArg::RetRegI64;
                 } else {
                     let mut instr2 = ltac::create_instr(LtacType::MovQ);
                     instr2.arg1 = LtacArg::Reg64(0);
                     instr2.arg2 = LtacArg::Mem(v.pos);
                     builder.file.code.push(instr2);
+                    
+                    if v.data_type == DataType::Str {
+                        length = LtacArg::Mem(v.pos - 8);
+                    }
+                    
+                    // The string now points to the array, so it escapes the function
+                    if let Some(v) = builder.vars.get_mut(&arg.str_val) {
+                        v.is_owned = false;
+                    }
+                    
+                    instr.arg2 = LtacArg::Reg64(0);
                 }
-                
-                instr.arg2 = LtacArg::Reg64(0);
             },
             
             AstArgType::Id => {
@@ -220,12 +270,245 @@ pub fn build_str_assign(builder : &mut LtacBuilder, line : &AstStmt, var : &Var)
             },
         }
     } else {
-        //TODO
+        return build_str_concat(builder, line, var);
     }
     
     builder.file.code.push(instr);
     
+    if builder.c_strings {
+        return true;
+    }
+    
+    // Copy the length if we know it; otherwise, strlen has to find it
+    // Byte arrays hold text, so their length is not the array size
+    match length {
+        LtacArg::Empty => build_str_len(builder, var.pos),
+        
+        _ => {
+            let mut instr2 = ltac::create_instr(LtacType::Mov);
+            instr2.arg1 = LtacArg::Reg32(0);
+            instr2.arg2 = length;
+            builder.file.code.push(instr2);
+            
+            instr2 = ltac::create_instr(LtacType::Mov);
+            instr2.arg1 = LtacArg::Mem(var.pos - 8);
+            instr2.arg2 = LtacArg::Reg32(0);
+            builder.file.code.push(instr2);
+            
+            true
+        },
+    }
+}
+
+// Copies the text in a byte array to a new string
+// This is a slice of the whole array, which stops at the first zero:
+// pusharg [array]
+// pusharg 0
+// pusharg <largest int>
+// call str_slice
+fn build_str_copy(builder : &mut LtacBuilder, name : &str) -> bool {
+    if !builder.function_exists(&"str_slice".to_string()) {
+        builder.syntax.ltac_error2("Strings from function calls and arrays require the core library.".to_string());
+        return false;
+    }
+    
+    let mut array = ast::create_arg(AstArgType::Id);
+    array.str_val = name.to_string();
+    
+    let mut start = ast::create_arg(AstArgType::IntL);
+    start.u64_val = 0;
+    
+    let mut stop = ast::create_arg(AstArgType::IntL);
+    stop.u64_val = i32::MAX as u64;
+    
+    let mut stmt = ast::create_orphan_stmt(AstStmtType::FuncCall);
+    stmt.name = "str_slice".to_string();
+    stmt.args.push(array);
+    stmt.args.push(start);
+    stmt.args.push(stop);
+    
+    build_func_call(builder, &stmt)
+}
+
+// Builds a string slice (s[start..stop])
+// Instruction syntax:
+// pusharg [s]
+// pusharg start
+// pusharg stop
+// call str_slice
+//
+fn build_str_slice(builder : &mut LtacBuilder, line : &AstStmt, arg : &AstArg) -> bool {
+    match builder.get_var(&arg.str_val) {
+        Ok(v) if v.data_type == DataType::Str => {},
+        _ => {
+            builder.syntax.ltac_error(line, "You can only slice strings.".to_string());
+            return false;
+        },
+    }
+    
+    if arg.sub_args.len() != 3 || arg.sub_args[1].arg_type != AstArgType::Range {
+        builder.syntax.ltac_error(line, "Invalid string slice.".to_string());
+        return false;
+    }
+    
+    let mut s = ast::create_arg(AstArgType::Id);
+    s.str_val = arg.str_val.clone();
+    
+    let mut stmt = ast::create_orphan_stmt(AstStmtType::FuncCall);
+    stmt.name = "str_slice".to_string();
+    stmt.args.push(s);
+    stmt.args.push(arg.sub_args[0].clone());
+    stmt.args.push(arg.sub_args[2].clone());
+    
+    build_func_call(builder, &stmt)
+}
+
+// Builds a string concatenation (s1 + s2 + ...)
+// Neighboring literals are joined at compile time, and everything else is
+// joined at runtime with str_concat. Each runtime join creates a new string.
+fn build_str_concat(builder : &mut LtacBuilder, line : &AstStmt, var : &Var) -> bool {
+    let mut operands : Vec<LtacArg> = Vec::new();
+    let mut literals : Vec<String> = Vec::new();
+    
+    // The length of the result is the sum of the lengths
+    let mut literal_len = 0;
+    let mut var_lens : Vec<i32> = Vec::new();
+    
+    // Joining is associative, so we can ignore any parantheses and
+    // take the operands in the order they appear
+    let args : Vec<&AstArg> = line.args.iter()
+        .filter(|a| a.arg_type != AstArgType::OpLParen && a.arg_type != AstArgType::OpRParen)
+        .collect();
+    
+    if args.len() % 2 != 1 {
+        builder.syntax.ltac_error(line, "Invalid string assignment.".to_string());
+        return false;
+    }
+    
+    for (i, arg) in args.iter().enumerate() {
+        // Every other argument is an operator
+        if i % 2 == 1 {
+            if arg.arg_type != AstArgType::OpAdd {
+                builder.syntax.ltac_error(line, "Strings can only be joined with \'+\'.".to_string());
+                return false;
+            }
+            continue;
+        }
+        
+        if arg.arg_type == AstArgType::StringL {
+            literals.push(arg.str_val.clone());
+            literal_len += str_literal_len(&arg.str_val);
+            continue;
+        }
+        
+        let pos = match builder.get_var(&arg.str_val) {
+            Ok(v) if arg.arg_type == AstArgType::Id && v.data_type == DataType::Str && arg.sub_args.is_empty() => v.pos,
+            _ => {
+                builder.syntax.ltac_error(line, "You can only join strings.".to_string());
+                return false;
+            },
+        };
+        
+        if !literals.is_empty() {
+            let name = builder.build_string_concat(&literals);
+            operands.push(LtacArg::PtrLcl(name));
+            literals.clear();
+        }
+        
+        operands.push(LtacArg::Ptr(pos));
+        var_lens.push(pos - 8);
+    }
+    
+    if !literals.is_empty() {
+        let name = builder.build_string_concat(&literals);
+        operands.push(LtacArg::PtrLcl(name));
+    }
+    
+    // If everything was a literal, we only need the length
+    if operands.len() == 1 {
+        let mut instr = ltac::create_instr(LtacType::MovQ);
+        instr.arg1 = LtacArg::Mem(var.pos);
+        instr.arg2 = operands.pop().unwrap();
+        builder.file.code.push(instr);
+        
+        build_str_concat_len(builder, var, literal_len, &var_lens);
+        return true;
+    }
+    
+    // Partial results are kept in a hidden variable
+    let mut tmp_pos = 0;
+    if operands.len() > 2 {
+        builder.stack_pos += 8;
+        tmp_pos = builder.stack_pos;
+    }
+    
+    for i in 1 .. operands.len() {
+        let mut push = ltac::create_instr(LtacType::PushArg);
+        if i == 1 {
+            push.arg1 = operands[0].clone();
+        } else {
+            push.arg1 = LtacArg::Ptr(tmp_pos);
+        }
+        push.arg2_val = 1;
+        builder.file.code.push(push);
+        
+        push = ltac::create_instr(LtacType::PushArg);
+        push.arg1 = operands[i].clone();
+        push.arg2_val = 2;
+        builder.file.code.push(push);
+        
+        let mut call = ltac::create_instr(LtacType::Call);
+        call.name = "str_concat".to_string();
+        builder.file.code.push(call);
+        
+        let mut store = ltac::create_instr(LtacType::MovQ);
+        if i == operands.len() - 1 {
+            store.arg1 = LtacArg::Mem(var.pos);
+        } else {
+            store.arg1 = LtacArg::Mem(tmp_pos);
+        }
+        store.arg2 = LtacArg::RetRegI64;
+        builder.file.code.push(store);
+    }
+    
+    build_str_concat_len(builder, var, literal_len, &var_lens);
     true
 }
 
+// Stores the length of a joined string
+// Instruction syntax:
+// mov r0, literal_len
+// mov r1, [len1]
+// add r0, r1
+// ...
+// mov [var.pos - 8], r0
+//
+fn build_str_concat_len(builder : &mut LtacBuilder, var : &Var, literal_len : i32, var_lens : &[i32]) {
+    if builder.c_strings {
+        return;
+    }
+    
+    let mut instr = ltac::create_instr(LtacType::Mov);
+    instr.arg1 = LtacArg::Reg32(0);
+    instr.arg2 = LtacArg::I32(literal_len);
+    builder.file.code.push(instr);
+    
+    for pos in var_lens.iter() {
+        instr = ltac::create_instr(LtacType::Mov);
+        instr.arg1 = LtacArg::Reg32(1);
+        instr.arg2 = LtacArg::Mem(*pos);
+        builder.file.code.push(instr);
+        
+        instr = ltac::create_instr(LtacType::I32Add);
+        instr.arg1 = LtacArg::Reg32(0);
+        instr.arg2 = LtacArg::Reg32(1);
+        builder.file.code.push(instr);
+    }
+    
+    instr = ltac::create_instr(LtacType::Mov);
+    instr.arg1 = LtacArg::Mem(var.pos - 8);
+    instr.arg2 = LtacArg::Reg32(0);
+    builder.file.code.push(instr);
+}
+
     
@@ -0,0 +1,408 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// Checks that LTAC is well formed before it goes to a backend
+// The backends trust their input, so malformed LTAC usually turns into a confusing
+// assembler error (or wrong code). This catches it where it is made instead.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::ltac::{LtacFile, LtacDataType, LtacInstr, LtacType, LtacArg};
+use crate::ltac_text::{instr_to_text, arg_to_text};
+
+// The kinds of operand an instruction can take
+#[derive(PartialEq)]
+enum Kind {
+    Reg,        // An integer register (including the return register)
+    FltReg,     // A float register (including the return register)
+    Mem,
+    Imm,        // An integer literal
+    Flt,        // A float literal
+    Ptr,        // An address: a pointer variable, a data symbol, or a function
+    Empty,      // The operand can be left out
+}
+
+// The operands of an instruction, and their width in bits
+// A width of 0 means any width.
+struct Rule {
+    arg1 : &'static [Kind],
+    width1 : i32,
+    arg2 : &'static [Kind],
+    width2 : i32,
+}
+
+fn rule(arg1 : &'static [Kind], width1 : i32, arg2 : &'static [Kind], width2 : i32) -> Rule {
+    Rule {
+        arg1 : arg1,
+        width1 : width1,
+        arg2 : arg2,
+        width2 : width2,
+    }
+}
+
+// Returns the width of an integer register
+fn reg_width(arg : &LtacArg) -> Option<i32> {
+    match arg {
+        LtacArg::Reg8(_) | LtacArg::RetRegI8 | LtacArg::RetRegU8 => Some(8),
+        LtacArg::Reg16(_) | LtacArg::RetRegI16 | LtacArg::RetRegU16 => Some(16),
+        LtacArg::Reg32(_) | LtacArg::RetRegI32 | LtacArg::RetRegU32 => Some(32),
+        LtacArg::Reg64(_) | LtacArg::RetRegI64 | LtacArg::RetRegU64 => Some(64),
+        _ => None,
+    }
+}
+
+fn flt_reg_width(arg : &LtacArg) -> Option<i32> {
+    match arg {
+        LtacArg::FltReg(_) | LtacArg::RetRegF32 => Some(32),
+        LtacArg::FltReg64(_) | LtacArg::RetRegF64 => Some(64),
+        _ => None,
+    }
+}
+
+fn is_mem(arg : &LtacArg) -> bool {
+    match arg {
+        LtacArg::Mem(_) | LtacArg::MemOffsetImm(..) | LtacArg::MemOffsetMem(..)
+        | LtacArg::MemOffsetReg(..) | LtacArg::MemOffsetStride(..) => true,
+        _ => false,
+    }
+}
+
+// Returns true if an operand is one of the kinds, at the width
+fn matches_kind(arg : &LtacArg, kind : &Kind, width : i32) -> bool {
+    let width_ok = |w : i32| width == 0 || w == width;
+    
+    match kind {
+        Kind::Reg => reg_width(arg).is_some_and(width_ok),
+        Kind::FltReg => flt_reg_width(arg).is_some_and(width_ok),
+        Kind::Mem => is_mem(arg),
+        
+        Kind::Imm => match arg {
+            LtacArg::Byte(_) | LtacArg::UByte(_) | LtacArg::I16(_) | LtacArg::U16(_)
+            | LtacArg::I32(_) | LtacArg::U32(_) | LtacArg::I64(_) | LtacArg::U64(_) => true,
+            _ => false,
+        },
+        
+        Kind::Flt => match arg {
+            LtacArg::F32(_) => width_ok(32),
+            LtacArg::F64(_) => width_ok(64),
+            _ => false,
+        },
+        
+        Kind::Ptr => match arg {
+            LtacArg::Ptr(_) | LtacArg::PtrLcl(_) | LtacArg::PtrFunc(_) => true,
+            _ => false,
+        },
+        
+        Kind::Empty => *arg == LtacArg::Empty,
+    }
+}
+
+// Checks one operand against the kinds it can be
+fn check_arg(arg : &LtacArg, kinds : &[Kind], width : i32, pos : i32) -> Result<(), String> {
+    if kinds.is_empty() {
+        if *arg == LtacArg::Empty {
+            return Ok(());
+        }
+        
+        return Err(format!("Operand {} should be empty, but it is {}.", pos, arg_to_text(arg)));
+    }
+    
+    if kinds.iter().any(|kind| matches_kind(arg, kind, width)) {
+        return Ok(());
+    }
+    
+    // A register of the wrong size gets its own message, since it is the most common mistake
+    let actual = match (reg_width(arg), flt_reg_width(arg)) {
+        (Some(w), _) if kinds.contains(&Kind::Reg) => w,
+        (_, Some(w)) if kinds.contains(&Kind::FltReg) => w,
+        _ => 0,
+    };
+    
+    if actual != 0 {
+        return Err(format!("Operand {} is a {}-bit register, but the instruction works on {} bits.", pos, actual, width));
+    }
+    
+    if *arg == LtacArg::Empty {
+        return Err(format!("Operand {} is missing.", pos));
+    }
+    
+    Err(format!("Operand {} can't be {}.", pos, arg_to_text(arg)))
+}
+
+// Returns the operands an instruction takes
+fn get_rule(instr : &LtacInstr) -> Rule {
+    // Parser loads are written like moves (register first), and may load a literal
+    let int_ld = |w : i32| rule(&[Kind::Reg], w, &[Kind::Mem, Kind::Imm], w);
+    let flt_ld = |w : i32| rule(&[Kind::FltReg], w, &[Kind::Mem, Kind::Flt], w);
+    
+    let int_mov = |w : i32| rule(&[Kind::Reg, Kind::Mem], w, &[Kind::Reg, Kind::Mem, Kind::Imm], w);
+    let flt_mov = |w : i32| rule(&[Kind::FltReg, Kind::Mem], w, &[Kind::FltReg, Kind::Mem, Kind::Flt], w);
+    let int_math = |w : i32| rule(&[Kind::Reg], w, &[Kind::Reg, Kind::Mem, Kind::Imm], w);
+    let flt_math = |w : i32| rule(&[Kind::FltReg], w, &[Kind::FltReg, Kind::Mem, Kind::Flt], w);
+    let int_cmp = |w : i32| rule(&[Kind::Reg, Kind::Mem], w, &[Kind::Reg, Kind::Mem, Kind::Imm], w);
+    let flt_cmp = |w : i32| rule(&[Kind::FltReg], w, &[Kind::FltReg, Kind::Mem, Kind::Flt], w);
+    let int_str = |w : i32| rule(&[Kind::Mem], w, &[Kind::Reg], w);
+    let flt_str = |w : i32| rule(&[Kind::Mem], w, &[Kind::FltReg], w);
+    let ldarg = || rule(&[Kind::Mem], 0, &[], 0);
+    let none = || rule(&[], 0, &[], 0);
+    
+    match &instr.instr_type {
+        LtacType::None | LtacType::Extern | LtacType::Label | LtacType::Func | LtacType::Ret => none(),
+        
+        LtacType::MovB | LtacType::MovUB => int_mov(8),
+        LtacType::MovW | LtacType::MovUW => int_mov(16),
+        LtacType::Mov | LtacType::MovU => int_mov(32),
+        LtacType::MovF32 => flt_mov(32),
+        LtacType::MovF64 => flt_mov(64),
+        LtacType::MovI32Vec => rule(&[Kind::Reg, Kind::Mem], 32, &[Kind::Reg, Kind::Mem], 32),
+        
+        // Addresses are 64-bit
+        LtacType::MovQ | LtacType::MovUQ => {
+            rule(&[Kind::Reg, Kind::Mem], 64, &[Kind::Reg, Kind::Mem, Kind::Imm, Kind::Ptr], 64)
+        },
+        
+        LtacType::LdAddr => rule(&[Kind::Reg], 64, &[Kind::Mem], 0),
+        LtacType::Push | LtacType::Pop => rule(&[Kind::Reg], 0, &[], 0),
+        
+        LtacType::LdB | LtacType::LdUB => int_ld(8),
+        LtacType::LdW | LtacType::LdUW => int_ld(16),
+        LtacType::Ld | LtacType::LdU => int_ld(32),
+        LtacType::LdQ | LtacType::LdUQ => int_ld(64),
+        LtacType::LdF32 => flt_ld(32),
+        LtacType::LdF64 => flt_ld(64),
+        
+        LtacType::StrB | LtacType::StrUB => int_str(8),
+        LtacType::StrW | LtacType::StrUW => int_str(16),
+        LtacType::Str | LtacType::StrU => int_str(32),
+        LtacType::StrQ | LtacType::StrUQ | LtacType::StrPtr => int_str(64),
+        LtacType::StrF32 => flt_str(32),
+        LtacType::StrF64 => flt_str(64),
+        
+        LtacType::LdArgI8 | LtacType::LdArgU8
+        | LtacType::LdArgI16 | LtacType::LdArgU16
+        | LtacType::LdArgI32 | LtacType::LdArgU32
+        | LtacType::LdArgI64 | LtacType::LdArgU64
+        | LtacType::LdArgF32 | LtacType::LdArgF64
+        | LtacType::LdArgPtr => ldarg(),
+        
+        // The second operand of an argument is a hint for the type of a memory operand
+        LtacType::PushArg | LtacType::KPushArg => {
+            rule(&[Kind::Reg, Kind::FltReg, Kind::Mem, Kind::Imm, Kind::Flt, Kind::Ptr], 0,
+                &[Kind::Imm, Kind::FltReg, Kind::Empty], 0)
+        },
+        
+        LtacType::Call | LtacType::Syscall | LtacType::StrCmp => none(),
+        LtacType::CallReg => rule(&[Kind::Reg, Kind::Mem], 64, &[], 0),
+        
+        LtacType::Malloc | LtacType::Calloc | LtacType::Free => none(),
+        LtacType::Resize => rule(&[Kind::Ptr], 0, &[], 0),
+        LtacType::Exit => rule(&[Kind::Reg, Kind::Mem, Kind::Imm], 0, &[], 0),
+        
+        LtacType::I8Cmp | LtacType::U8Cmp => int_cmp(8),
+        LtacType::I16Cmp | LtacType::U16Cmp => int_cmp(16),
+        LtacType::I32Cmp | LtacType::U32Cmp => int_cmp(32),
+        LtacType::I64Cmp | LtacType::U64Cmp => int_cmp(64),
+        LtacType::F32Cmp => flt_cmp(32),
+        LtacType::F64Cmp => flt_cmp(64),
+        
+        LtacType::Br | LtacType::Be | LtacType::Bne
+        | LtacType::Bl | LtacType::Ble | LtacType::Bfl | LtacType::Bfle
+        | LtacType::Bg | LtacType::Bge | LtacType::Bfg | LtacType::Bfge => none(),
+        
+        LtacType::I8Add | LtacType::U8Add | LtacType::I8Sub | LtacType::I8Mul | LtacType::U8Mul
+        | LtacType::I8Div | LtacType::U8Div | LtacType::I8Mod | LtacType::U8Mod => int_math(8),
+        
+        LtacType::I16Add | LtacType::U16Add | LtacType::I16Sub | LtacType::I16Mul | LtacType::U16Mul
+        | LtacType::I16Div | LtacType::U16Div | LtacType::I16Mod | LtacType::U16Mod => int_math(16),
+        
+        LtacType::I32Add | LtacType::U32Add | LtacType::I32Sub | LtacType::I32Mul | LtacType::U32Mul
+        | LtacType::I32Div | LtacType::U32Div | LtacType::I32Mod | LtacType::U32Mod => int_math(32),
+        
+        LtacType::I64Add | LtacType::U64Add | LtacType::I64Sub | LtacType::I64Mul | LtacType::U64Mul
+        | LtacType::I64Div | LtacType::U64Div | LtacType::I64Mod | LtacType::U64Mod => int_math(64),
+        
+        LtacType::F32Add | LtacType::F32Sub | LtacType::F32Mul | LtacType::F32Div => flt_math(32),
+        LtacType::F64Add | LtacType::F64Sub | LtacType::F64Mul | LtacType::F64Div => flt_math(64),
+        
+        // Bitwise instructions take their width from the destination
+        LtacType::And | LtacType::Or | LtacType::Xor | LtacType::Lsh | LtacType::Rsh => {
+            let width = reg_width(&instr.arg1).unwrap_or(0);
+            rule(&[Kind::Reg], 0, &[Kind::Reg, Kind::Mem, Kind::Imm], width)
+        },
+        
+        LtacType::I32VAdd => rule(&[Kind::Reg], 32, &[Kind::Reg, Kind::Mem], 32),
+        
+        LtacType::CvtF32F64 => rule(&[Kind::FltReg], 64, &[Kind::FltReg, Kind::Mem], 32),
+        LtacType::CvtF64F32 => rule(&[Kind::FltReg], 32, &[Kind::FltReg, Kind::Mem], 64),
+        LtacType::CvtIntF32 => rule(&[Kind::FltReg], 32, &[Kind::Reg, Kind::Mem], 0),
+        LtacType::CvtIntF64 => rule(&[Kind::FltReg], 64, &[Kind::Reg, Kind::Mem], 0),
+        LtacType::CvtF32Int => rule(&[Kind::Reg], 0, &[Kind::FltReg, Kind::Mem], 32),
+        LtacType::CvtF64Int | LtacType::MovF64Int => rule(&[Kind::Reg], 0, &[Kind::FltReg, Kind::Mem], 64),
+    }
+}
+
+fn is_ldarg(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::LdArgI8 | LtacType::LdArgU8
+        | LtacType::LdArgI16 | LtacType::LdArgU16
+        | LtacType::LdArgI32 | LtacType::LdArgU32
+        | LtacType::LdArgI64 | LtacType::LdArgU64
+        | LtacType::LdArgF32 | LtacType::LdArgF64
+        | LtacType::LdArgPtr => true,
+        _ => false,
+    }
+}
+
+fn is_load(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::LdB | LtacType::LdUB
+        | LtacType::LdW | LtacType::LdUW
+        | LtacType::Ld | LtacType::LdU
+        | LtacType::LdQ | LtacType::LdUQ
+        | LtacType::LdF32 | LtacType::LdF64 => true,
+        _ => false,
+    }
+}
+
+// Returns true if an instruction needs a label
+// Integer math only does if it is checked (it has a name).
+fn needs_label(instr : &LtacInstr) -> bool {
+    match instr.instr_type {
+        LtacType::Br | LtacType::Be | LtacType::Bne
+        | LtacType::Bl | LtacType::Ble | LtacType::Bfl | LtacType::Bfle
+        | LtacType::Bg | LtacType::Bge | LtacType::Bfg | LtacType::Bfge => true,
+        
+        LtacType::Extern | LtacType::Label | LtacType::Func
+        | LtacType::Call | LtacType::Syscall => false,
+        
+        _ => !instr.name.is_empty(),
+    }
+}
+
+// Checks the operands of one instruction
+fn check_instr(instr : &LtacInstr, data : &HashMap<String, LtacDataType>) -> Result<(), String> {
+    let rule = get_rule(instr);
+    
+    // RISC loads put the memory first
+    let (arg1, arg2) = if is_load(&instr.instr_type) && is_mem(&instr.arg1) {
+        (&instr.arg2, &instr.arg1)
+    } else {
+        (&instr.arg1, &instr.arg2)
+    };
+    
+    check_arg(arg1, rule.arg1, rule.width1, 1)?;
+    check_arg(arg2, rule.arg2, rule.width2, 2)?;
+    
+    if is_mem(arg1) && is_mem(arg2) {
+        return Err("Only one operand can be in memory.".to_string());
+    }
+    
+    match &instr.instr_type {
+        LtacType::Extern | LtacType::Label | LtacType::Func | LtacType::Call if instr.name.is_empty() => {
+            return Err("The instruction needs a name.".to_string());
+        },
+        
+        _ => {},
+    }
+    
+    // Data symbols have to exist, and float literals have to be the right size
+    for arg in [arg1, arg2].iter() {
+        let (name, expected) = match arg {
+            LtacArg::PtrLcl(name) => (name, None),
+            LtacArg::F32(name) => (name, Some(LtacDataType::FloatL)),
+            LtacArg::F64(name) => (name, Some(LtacDataType::DoubleL)),
+            _ => continue,
+        };
+        
+        match (data.get(name), expected) {
+            (None, _) => return Err(format!("The data symbol {} is not defined.", name)),
+            (Some(data_type), Some(expected)) if *data_type != expected => {
+                return Err(format!("The data symbol {} is the wrong type for {}.", name, arg_to_text(arg)));
+            },
+            _ => {},
+        }
+    }
+    
+    Ok(())
+}
+
+// Checks an LTAC file
+// This checks the operands of each instruction (including the sizes of registers),
+// that branches go to labels that exist, that data symbols exist, that ret is
+// inside a function, and that arguments are only loaded at the start of a function.
+// Any errors are printed.
+pub fn verify(file : &LtacFile) -> Result<(), ()> {
+    let mut errors : Vec<(String, String, String)> = Vec::new();
+    
+    let mut data : HashMap<String, LtacDataType> = HashMap::new();
+    for entry in file.data.iter() {
+        data.insert(entry.name.clone(), entry.data_type.clone());
+    }
+    
+    // Labels can be used before they are defined
+    let mut labels : HashSet<String> = HashSet::new();
+    for instr in file.code.iter() {
+        if instr.instr_type == LtacType::Label && !labels.insert(instr.name.clone()) {
+            errors.push((String::new(), instr_to_text(instr), format!("The label {} is defined more than once.", instr.name)));
+        }
+    }
+    
+    let mut func_name = String::new();
+    let mut in_func = false;
+    let mut in_prologue = false;
+    
+    for instr in file.code.iter() {
+        let mut result = check_instr(instr, &data);
+        
+        match &instr.instr_type {
+            LtacType::Func => {
+                func_name = instr.name.clone();
+                in_func = true;
+                in_prologue = true;
+            },
+            
+            LtacType::Ret if !in_func => {
+                result = Err("ret is outside of a function.".to_string());
+            },
+            
+            instr_type if is_ldarg(instr_type) => {
+                if !in_prologue {
+                    result = Err("Arguments can only be loaded at the start of a function.".to_string());
+                }
+            },
+            
+            LtacType::None => {},
+            _ => in_prologue = false,
+        }
+        
+        if result.is_ok() && needs_label(instr) && !labels.contains(&instr.name) {
+            result = Err(format!("The label {} is not defined.", instr.name));
+        }
+        
+        if let Err(msg) = result {
+            errors.push((func_name.clone(), instr_to_text(instr), msg));
+        }
+    }
+    
+    if errors.is_empty() {
+        return Ok(());
+    }
+    
+    for (func, line, msg) in errors.iter() {
+        println!("LTAC Error: {}", msg);
+        
+        if func.is_empty() {
+            println!(" -> {}", line);
+        } else {
+            println!(" -> [{}] {}", func, line);
+        }
+        
+        println!("");
+    }
+    
+    Err(())
+}
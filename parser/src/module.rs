@@ -33,7 +33,15 @@ pub fn build_module(builder : &mut AstBuilder) -> bool {
         },
     }
     
-    if builder.get_token() != Token::Semicolon {
+    let mut token = builder.get_token();
+    
+    // Modules that stand in for C functions keep plain C strings
+    if token == Token::Id("c_strings".to_string()) {
+        builder.tree.c_strings = true;
+        token = builder.get_token();
+    }
+    
+    if token != Token::Semicolon {
         builder.syntax_error("Expecting terminator".to_string());
         return false;
     }
@@ -162,6 +170,12 @@ pub fn generate_module(tree : &AstTree) -> io::Result<()> {
     line.push_str("# DO NOT MODIFY. This will be rewritten each time you compile.");
     line.push_str("\n\n");
     
+    if tree.c_strings {
+        line.push_str("module ");
+        line.push_str(&tree.module);
+        line.push_str(" c_strings;\n\n");
+    }
+    
     // Iterate through all the constants
     for c in tree.constants.iter() {
         if c.line.len() == 0 {
@@ -17,6 +17,7 @@ pub struct SyntaxError {
 #[derive(Clone)]
 pub struct ErrorManager {
     pub errors : Vec<SyntaxError>,
+    pub warnings : Vec<SyntaxError>,
     pub current_ln : String,
     pub current_ln_no : i32,
 }
@@ -24,6 +25,7 @@ pub struct ErrorManager {
 pub fn create_error_manager() -> ErrorManager {
     ErrorManager {
         errors : Vec::new(),
+        warnings : Vec::new(),
         current_ln : String::new(),
         current_ln_no : 0,
     }
@@ -53,6 +55,17 @@ impl ErrorManager {
         self.errors.push(error);
     }
     
+    // Warnings don't stop the build
+    pub fn ltac_warning(&mut self, stmt : &AstStmt, msg : String) {
+        let warning = SyntaxError {
+            line_no : stmt.line_no,
+            line : stmt.line.clone(),
+            message : msg,
+        };
+        
+        self.warnings.push(warning);
+    }
+    
     pub fn ltac_error2(&mut self, msg : String) {
         let error = SyntaxError {
             line_no : self.current_ln_no,
@@ -63,6 +76,17 @@ impl ErrorManager {
         self.errors.push(error);
     }
     
+    // Called when reading LTAC text
+    pub fn line_error(&mut self, line_no : i32, line : &str, msg : String) {
+        let error = SyntaxError {
+            line_no : line_no,
+            line : line.to_string(),
+            message : msg,
+        };
+        
+        self.errors.push(error);
+    }
+    
     // Set the current line to make it easier to call LTAC errors
     pub fn set_data(&mut self, stmt : &AstStmt) {
         self.current_ln = stmt.line.clone();
@@ -77,5 +101,14 @@ impl ErrorManager {
             println!("");
         }
     }
+    
+    // Warnings go to stderr so they don't mix with the output of --run
+    pub fn print_warnings(&mut self) {
+        for warning in self.warnings.iter() {
+            eprintln!("Warning: {}", warning.message);
+            eprintln!(" -> [{}] {}", warning.line_no, warning.line);
+            eprintln!("");
+        }
+    }
 }
 
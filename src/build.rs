@@ -4,100 +4,214 @@
 // Ida is licensed under the BSD-3 license. See the COPYING file for more information.
 //
 
-// Eventually, I want all backends to use these functions
+// All the backends assemble and link through these functions
 
 use std::io;
 use std::io::prelude::*;
+use std::fs;
+use std::env;
+use std::process;
 use std::process::Command;
+use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
-// Assemble a file
-pub fn assemble(name : &String, no_link : bool) {
-    // Create all the names
-    let mut asm_name = "/tmp/".to_string();
-    asm_name.push_str(name);
-    asm_name.push_str(".asm");
+use crate::target::Target;
+
+// Holds the assembly and object files while we build
+// Unless we were given a directory (--build-dir), this is a new temporary directory
+// for each run, so parallel builds don't clobber each other. It is removed when
+// we are done, unless it needs to be kept (--save-temps, or a failed build).
+pub struct BuildDir {
+    pub path : PathBuf,
+    pub keep : bool,
+}
+
+impl BuildDir {
+    // Returns the path of an intermediate file
+    pub fn file(&self, name : &String, ext : &str) -> String {
+        let mut path = self.path.join(name);
+        path.set_extension(ext);
+        path.to_string_lossy().to_string()
+    }
     
-    let mut obj_name = "/tmp/".to_string();
-    if no_link {
-        obj_name = "./".to_string();
+    // Keeps the files after a failed build, so they can be looked at
+    pub fn keep_files(&mut self) {
+        if !self.keep {
+            println!("Note: The intermediate files are in {}", self.path.display());
+        }
+        
+        self.keep = true;
+    }
+}
+
+impl Drop for BuildDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.path);
+        }
     }
+}
+
+// Creates the build directory
+pub fn create_build_dir(dir : &String, save_temps : bool) -> io::Result<BuildDir> {
+    if !dir.is_empty() {
+        fs::create_dir_all(dir)?;
+        
+        return Ok(BuildDir {
+            path : PathBuf::from(dir),
+            keep : true,
+        });
+    }
+    
+    // create_dir fails if the directory exists, so this is safe against other runs
+    let pid = process::id();
+    let mut count = 0;
     
-    obj_name.push_str(name);
-    obj_name.push_str(".o");
+    loop {
+        let path = env::temp_dir().join(format!("ida-{}-{}", pid, count));
+        
+        match fs::create_dir(&path) {
+            Ok(()) => return Ok(BuildDir {
+                path : path,
+                keep : save_temps,
+            }),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => count += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-    // Assemble
-    let asm = Command::new("as")
-        .args(&[&asm_name, "-o", &obj_name])
-        .output()
-        .expect("Fatal: Assembly failed.");
+// Returns a unique name for the intermediate files of an input
+// Inputs with the same name in different directories get different hashes
+pub fn unique_name(input : &String, stem : &String) -> String {
+    let full_path = match fs::canonicalize(input) {
+        Ok(path) => path,
+        Err(_e) => PathBuf::from(input),
+    };
+    
+    let mut hasher = DefaultHasher::new();
+    full_path.hash(&mut hasher);
+    
+    format!("{}-{:016x}", stem, hasher.finish())
+}
+
+// Assemble a file
+// Returns false if the assembler failed
+pub fn assemble(asm_name : &String, obj_name : &String, target : &Target) -> bool {
+    let asm = match Command::new(&target.assembler).args(&[asm_name, "-o", obj_name]).output() {
+        Ok(asm) => asm,
+        Err(e) => {
+            tool_error("assembler", &target.assembler, "--as", e);
+            return false;
+        },
+    };
         
     if !asm.status.success() {
         io::stdout().write_all(&asm.stdout).unwrap();
         io::stderr().write_all(&asm.stderr).unwrap();
+        return false;
     }
+    
+    true
 }
  
 // Link everything
-pub fn link(all_names : &Vec<String>, output : &String, use_corelib : bool, is_lib : bool, inc_start : bool) {
+// Returns false if the linker failed
+pub fn link(all_names : &Vec<String>, output : &String, use_corelib : bool, use_c : bool, is_lib : bool, inc_start : bool, target : &Target) -> bool {
     let mut names : Vec<String> = Vec::new();
     let mut libs : Vec<String> = Vec::new();
     
     for name in all_names.iter() {
-        if name.ends_with(".o") {
-            names.push(name.clone());
-        } else if name.starts_with("-l") {
+        if name.starts_with("-l") {
             libs.push(name.clone());
         } else {
-            let mut obj_name = "/tmp/".to_string();
-            obj_name.push_str(&name);
-            obj_name.push_str(".o");
-            names.push(obj_name);
+            names.push(name.clone());
         }
     }
     
+    // Targets without an Ida runtime only get the C libraries
+    let has_runtime = !target.ida_dir.is_empty();
+    
     // Link
-    //let ld : Output;
-    let mut args : Vec<&str> = Vec::new();
-    args.push("-L./");
+    let mut args : Vec<String> = Vec::new();
+    args.push("-L./".to_string());
+    
+    if !target.sysroot.is_empty() {
+        args.push(format!("--sysroot={}", target.sysroot));
+    }
     
-    if !is_lib && inc_start {
-        args.push("/usr/lib/ida/irt.o");
+    // The C start-up files replace our own
+    if use_c && !is_lib {
+        for crt in target.crt_start.iter() {
+            args.push(target.crt_path(crt));
+        }
+    } else if !is_lib && inc_start && has_runtime {
+        args.push(format!("{}/irt.o", target.ida_dir));
     }
     
-    args.push("-dynamic-linker");
-    args.push("/lib64/ld-linux-x86-64.so.2");
+    args.push("-dynamic-linker".to_string());
+    args.push(target.dynamic_linker.clone());
         
     for name in names.iter() {
-        args.push(&name);
+        args.push(name.clone());
     }
         
     if is_lib {
-        args.push("-shared");
+        args.push("-shared".to_string());
     }
     
     for lib in libs.iter() {
-        args.push(lib);
+        args.push(lib.clone());
     }
-        
-    args.push("-o");
-    args.push(output);
     
-    if use_corelib {
-        args.push("-lida");
+    if use_c {
+        // When cross-linking, libc isn't in the default search path
+        for dir in target.lib_paths().iter() {
+            args.push(format!("-L{}", dir));
+        }
         
-        if use_corelib {
-            args.push("-lida_core");
+        args.push("-lc".to_string());
+        
+        if !is_lib {
+            for crt in target.crt_end.iter() {
+                args.push(target.crt_path(crt));
+            }
         }
     }
+        
+    args.push("-o".to_string());
+    args.push(output.clone());
+    
+    if use_corelib && has_runtime {
+        args.push(format!("-L{}", target.ida_dir));
+        args.push("-lida".to_string());
+        args.push("-lida_core".to_string());
+    }
     
-    let ld = Command::new("ld")
-        .args(args.as_slice())
-        .output()
-        .expect("Fatal: Linking failed.");
+    let ld = match Command::new(&target.linker).args(args.as_slice()).output() {
+        Ok(ld) => ld,
+        Err(e) => {
+            tool_error("linker", &target.linker, "--ld", e);
+            return false;
+        },
+    };
     
     if !ld.status.success() {
         io::stdout().write_all(&ld.stdout).unwrap();
         io::stderr().write_all(&ld.stderr).unwrap();
+        return false;
     }
+    
+    true
 }
 
+// Reports a tool that couldn't be run
+fn tool_error(kind : &str, tool : &String, flag : &str, e : io::Error) {
+    if e.kind() == io::ErrorKind::NotFound {
+        println!("Fatal: The {} ({}) was not found.", kind, tool);
+        println!("Make sure it is installed, or use {} to pick a different one.", flag);
+    } else {
+        println!("Fatal: Unable to run the {} ({}): {}", kind, tool, e);
+    }
+}
@@ -5,13 +5,18 @@
 //
 
 use std::env;
+use std::fs;
 use std::process;
+use std::path::Path;
 
 use parser;
 use parser::Arch;
+use parser::ltac::LtacFile;
 use transform;
+use transform::OptLevel;
 
 mod build;
+mod target;
 
 #[cfg(target_arch = "x86_64")]
 fn get_arch() -> Arch {
@@ -43,41 +48,92 @@ fn run() -> i32 {
         return 2;
     }
     
-    let mut print_ast = false;
-    let mut print_ltac = false;
+    let mut emit_ast = false;
+    let mut emit_ltac = false;
+    let mut dump_cfg = false;
+    let mut emit_asm = false;
+    let mut emit_obj = false;
+    let mut emit_link = false;
     let mut use_c = false;
     let mut use_corelib = true;
     let mut link_lib = false;
-    let mut no_link = false;
     let mut inc_start = true;
     let mut pic = false;
+    let mut omit_fp = false;
+    let mut bounds_check = false;
+    let mut overflow_checks = false;
+    let mut div_checks = false;
+    let mut release = false;
+    let mut opt_level = OptLevel::O0;
+    let mut passes : Option<Vec<String>> = None;
+    let mut print_after : Vec<String> = Vec::new();
+    let mut time_passes = false;
     let mut risc_mode = false;      // This is a dev feature to allow us to work on the RISC optimizer on x86
     let mut arch = get_arch();
     let mut inputs : Vec<String> = Vec::new();
-    let mut output : String = "a.out".to_string();
+    let mut output = String::new();
+    let mut target_name = String::new();
+    let mut assembler = String::new();
+    let mut linker = String::new();
+    let mut sysroot = String::new();
+    let mut build_dir_name = String::new();
+    let mut save_temps = false;
+    let mut run = false;
+    let mut run_args : Vec<String> = Vec::new();
+    let mut in_run_args = false;
     
-    let mut next_output = false;
+    // The option waiting for a value
+    let mut next_arg = String::new();
     
     for arg in args {
-        if next_output {
-            output = arg.clone();
-            next_output = false;
+        // Everything after "--" is passed to the program with --run
+        if in_run_args {
+            run_args.push(arg);
+            continue;
+        }
+        
+        if !next_arg.is_empty() {
+            match next_arg.as_ref() {
+                "--target" => target_name = arg.clone(),
+                "--as" => assembler = arg.clone(),
+                "--ld" => linker = arg.clone(),
+                "--sysroot" => sysroot = arg.clone(),
+                "--build-dir" => build_dir_name = arg.clone(),
+                _ => output = arg.clone(),
+            }
+            
+            next_arg.clear();
             continue;
         }
     
         match arg.as_ref() {
-            "--ast" => print_ast = true,
-            "--ltac" => print_ltac = true,
+            "--ast" => emit_ast = true,
+            "--ltac" => emit_ltac = true,
+            "--dump-cfg" => dump_cfg = true,
+            "-S" => emit_asm = true,
+            "-c" | "--no-link" => emit_obj = true,
             "--use-c" => use_c = true,
             "--lib" => {
                 link_lib = true;
                 pic = true;
             },
             "--pic" => pic = true,
+            "--omit-frame-pointer" => omit_fp = true,
+            "--bounds-check" => bounds_check = true,
+            "--overflow-checks" => overflow_checks = true,
+            "--div-checks" => div_checks = true,
+            "--release" => release = true,
             "--risc" => risc_mode = true,
-            "--no-link" => no_link = true,
+            "-O0" => opt_level = OptLevel::O0,
+            "-O1" => opt_level = OptLevel::O1,
+            "-O2" => opt_level = OptLevel::O2,
+            "-Os" => opt_level = OptLevel::Os,
+            "--time-passes" => time_passes = true,
             "--no-start" => inc_start = false,
-            "-o" => next_output = true,
+            "-o" | "--target" | "--as" | "--ld" | "--sysroot" | "--build-dir" => next_arg = arg.clone(),
+            "--save-temps" => save_temps = true,
+            "--run" => run = true,
+            "--" => in_run_args = true,
             
             "--no-corelib" => {
                 use_corelib = false;
@@ -95,87 +151,318 @@ fn run() -> i32 {
                 return 0;
             },
             
+            _ if arg.starts_with("--passes=") => {
+                let list = &arg["--passes=".len()..];
+                passes = Some(list.split(',').filter(|p| !p.is_empty()).map(|p| p.to_string()).collect());
+            },
+            
+            _ if arg.starts_with("--print-after=") => {
+                let list = &arg["--print-after=".len()..];
+                print_after.extend(list.split(',').filter(|p| !p.is_empty()).map(|p| p.to_string()));
+            },
+            
+            _ if arg.starts_with("--emit=") => {
+                for kind in arg["--emit=".len()..].split(',') {
+                    match kind {
+                        "ast" => emit_ast = true,
+                        "ltac" => emit_ltac = true,
+                        "asm" => emit_asm = true,
+                        "obj" => emit_obj = true,
+                        "link" => emit_link = true,
+                        _ => {
+                            println!("Fatal: Unknown --emit kind: {}", kind);
+                            println!("The kinds are ast, ltac, asm, obj, and link.");
+                            return 2;
+                        },
+                    }
+                }
+            },
+            
             _ => inputs.push(arg.clone()),
         }
     }
     
-    if print_ast {
-        let input = inputs.last().unwrap();
-        let ast = match parser::get_ast(&input, arch, use_corelib, true) {
-            Ok(ast) => ast,
-            Err(_e) => return 1,
-        };
-        
-        ast.print();
-        return 0;
+    if !next_arg.is_empty() {
+        println!("Fatal: {} needs a value.", next_arg);
+        return 2;
+    }
+    
+    // Work out the toolchain
+    // A target overrides -march
+    let target = if target_name.ends_with(".toml") {
+        target::from_file(&target_name)
+    } else if !target_name.is_empty() {
+        target::from_triple(&target_name)
+    } else {
+        Ok(target::for_arch(arch))
+    };
+    
+    let mut target = match target {
+        Ok(target) => target,
+        Err(e) => {
+            println!("Fatal: {}", e);
+            return 2;
+        },
+    };
+    
+    arch = target.arch;
+    
+    if !assembler.is_empty() {
+        target.assembler = assembler;
+    }
+    
+    if !linker.is_empty() {
+        target.linker = linker;
+    }
+    
+    if !sysroot.is_empty() {
+        target.sysroot = sysroot;
+    }
+    
+    // The passes are checked now, so a bad name doesn't show up after the first input
+    let mut options = transform::create_options(arch);
+    options.use_c = use_c;
+    options.risc_mode = risc_mode;
+    options.opt_level = opt_level;
+    options.passes = passes;
+    options.print_after = print_after;
+    options.time_passes = time_passes;
+    
+    if transform::check_options(&options).is_err() {
+        return 2;
+    }
+    
+    // Release builds leave out runtime checks
+    if release {
+        bounds_check = false;
+        overflow_checks = false;
+        div_checks = false;
+    }
+    
+    // Without -S, -c, --emit, or --run, we build an executable (or library)
+    if !emit_ast && !emit_ltac && !dump_cfg && !emit_asm && !emit_obj && !run {
+        emit_link = true;
+    }
+    
+    // The interpreter only needs the LTAC
+    if run && (emit_asm || emit_obj || emit_link) {
+        println!("Fatal: --run can't be used with -S, -c, or --emit=asm, obj, or link.");
+        return 2;
+    }
+    
+    if run && inputs.iter().any(|i| i.starts_with("-l") || i.ends_with(".o")) {
+        println!("Fatal: --run only works with .ida and .ltac files.");
+        return 2;
+    }
+    
+    // When we aren't linking, -o names the one file we are making
+    let sources = inputs.iter().filter(|i| !i.starts_with("-l") && !i.ends_with(".o")).count();
+    let file_kinds = [emit_ltac, emit_asm, emit_obj].iter().filter(|k| **k).count();
+    
+    if inputs.is_empty() {
+        println!("Fatal: No input file specified.");
+        return 2;
+    }
+    
+    if !output.is_empty() && !emit_link && sources > 1 {
+        println!("Fatal: -o can't be used with -S, -c, or --emit when there are multiple inputs.");
+        return 2;
+    }
+    
+    let emit_output = if emit_link {
+        String::new()
+    } else {
+        output.clone()
+    };
+    
+    if output.is_empty() {
+        output = "a.out".to_string();
     }
     
+    let mut build_dir = match build::create_build_dir(&build_dir_name, save_temps) {
+        Ok(build_dir) => build_dir,
+        Err(e) => {
+            println!("Fatal: Unable to create the build directory: {}", e);
+            return 2;
+        },
+    };
+    
     let mut all_names : Vec<String> = Vec::new();
+    let mut run_files : Vec<LtacFile> = Vec::new();
+    
+    // The program name comes first in its arguments
+    if run {
+        run_args.insert(0, inputs[0].clone());
+    }
     
     for input in inputs {
         if input.starts_with("-l") || input.ends_with(".o") {
             all_names.push(input);
             continue;
         }
+        
+        let stem = parser::get_name(&input);
+        let is_ltac = input.ends_with(".ltac");
+        
+        if emit_ast && !is_ltac {
+            let ast = match parser::get_ast(&input, arch, use_corelib, true) {
+                Ok(ast) => ast,
+                Err(_e) => return 1,
+            };
+            
+            ast.print();
+        }
+        
+        if !emit_ltac && !dump_cfg && !emit_asm && !emit_obj && !emit_link && !run {
+            continue;
+        }
+        
+        // The intermediate files are named after the full path of the input
+        let name = build::unique_name(&input, &stem);
+        
+        let asm_name = if emit_asm {
+            emit_name(&emit_output, &stem, "asm", file_kinds)
+        } else {
+            build_dir.file(&name, "asm")
+        };
+        
+        let obj_name = if emit_obj {
+            emit_name(&emit_output, &stem, "o", file_kinds)
+        } else {
+            build_dir.file(&name, "o")
+        };
     
         // Build the LTAC portion
-        let mut ltac = match parser::parse(input, arch, use_corelib) {
+        // LTAC files are read straight in
+        let ltac = if is_ltac {
+            parser::parse_ltac(&input)
+        } else {
+            parser::parse(input, arch, use_corelib, bounds_check, overflow_checks, div_checks, release)
+        };
+        
+        let mut ltac = match ltac {
             Ok(ltac) => ltac,
             Err(_e) => return 1,
         };
         
         // Do any needed transformations or optimizations
-        ltac = match transform::run(&ltac, arch, use_c, risc_mode) {
+        ltac = match transform::run(&ltac, &options) {
             Ok(ltac) => ltac,
             Err(_e) => return 1,
         };
         
-        all_names.push(ltac.name.clone());
+        if emit_ltac {
+            let ltac_name = emit_name(&emit_output, &stem, "ltac", file_kinds);
+            if let Err(e) = ltac_printer::compile(&ltac, &ltac_name) {
+                println!("Fatal: Unable to write {}: {}", ltac_name, e);
+                return 1;
+            }
+        }
+        
+        if dump_cfg {
+            let dot_name = format!("{}.dot", stem);
+            let cfg = transform::cfg::build(&ltac);
+            
+            if let Err(e) = fs::write(&dot_name, cfg.to_dot(&stem)) {
+                println!("Fatal: Unable to write {}: {}", dot_name, e);
+                return 1;
+            }
+        }
+        
+        if run {
+            run_files.push(ltac);
+            continue;
+        }
+        
+        if !emit_asm && !emit_obj && !emit_link {
+            continue;
+        }
         
         // Now compile
-        if print_ltac {
-            ltac_printer::compile(&ltac).expect("LTAC Codegen failed with unknown error."); 
-        } else if arch == Arch::X86_64 {
-            x86_64::compile(&ltac, pic).expect("Codegen failed with unknown error.");
-            build::assemble(&ltac.name, no_link);
-        } else if arch == Arch::AArch64 {
-            aarch64::compile(&ltac).expect("Codegen failed with unknown error.");
-            //build::assemble(&ltac.name, no_link);
-        } else if arch == Arch::Riscv64 {
-            riscv64::compile(&ltac).expect("Codegen failed with unknown error.");
-            riscv64::build_asm(&ltac.name, no_link);
-        } else {
-            // TODO
+        let result = match arch {
+            Arch::X86_64 => x86_64::compile(&ltac, &asm_name, pic, omit_fp),
+            Arch::AArch64 => aarch64::compile(&ltac, &asm_name, omit_fp),
+            Arch::Riscv64 => riscv64::compile(&ltac, &asm_name),
+        };
+        
+        if let Err(e) = result {
+            println!("Fatal: Unable to write {}: {}", asm_name, e);
+            return 1;
+        }
+        
+        if !emit_obj && !emit_link {
+            continue;
         }
+        
+        if !build::assemble(&asm_name, &obj_name, &target) {
+            build_dir.keep_files();
+            return 1;
+        }
+        
+        all_names.push(obj_name);
+    }
+    
+    if run {
+        return ltac_interp::run(&run_files, arch, &run_args);
     }
     
     // Link
-    if !no_link && !print_ltac {
-        if arch == Arch::X86_64 {
-            build::link(&all_names, &output, use_corelib, link_lib, inc_start);
-        } else if arch == Arch::AArch64 {
-            //build::link(&all_names, &output, use_corelib, link_lib, inc_start);
-        } else if arch == Arch::Riscv64 {
-            riscv64::link(&all_names, &output, use_c, link_lib);
-        }
+    if emit_link && !build::link(&all_names, &output, use_corelib, use_c, link_lib, inc_start, &target) {
+        build_dir.keep_files();
+        return 1;
+    }
+    
+    if save_temps && build_dir_name.is_empty() {
+        println!("Note: The intermediate files are in {}", build_dir.path.display());
     }
     
     0
 }
 
+// Returns the name of a file made with -S, -c, or --emit
+// -o names the file if there's only one; otherwise, it gives the name for each kind
+fn emit_name(output : &String, stem : &String, ext : &str, file_kinds : usize) -> String {
+    if output.is_empty() {
+        return format!("{}.{}", stem, ext);
+    } else if file_kinds == 1 {
+        return output.clone();
+    }
+    
+    Path::new(output).with_extension(ext).to_string_lossy().to_string()
+}
+
 // Displays compiler help
 fn help() {
     println!("idac version 0.1");
     println!("");
-    println!("--ast \t\t Print a textual representation of the AST");
-    println!("--ltac \t\t Save the LTAC IR to a file.");
+    println!("--ast \t\t Print a textual representation of the AST (the same as --emit=ast)");
+    println!("--ltac \t\t Save the LTAC IR to a file (the same as --emit=ltac).");
+    println!("--dump-cfg \t Save the control-flow graph of each function to <name>.dot, for Graphviz.");
+    println!("-S \t\t Only generate assembly (the same as --emit=asm).");
+    println!("-c \t\t Only generate an object file (the same as --emit=obj).");
+    println!("--emit=<kinds> \t Generate any of ast, ltac, asm, obj, and link (the executable), separated by commas.");
+    println!("--run \t\t Run the program with the LTAC interpreter instead of building it. Arguments after -- are passed to it.");
     println!("--use-c \t Link to C start-up files and the C standard library.");
     println!("--lib \t\t Generate a dynamic library.");
     println!("--pic \t\t Generate position independent code (x86 only- you need this if you are building a library)");
-    println!("--no-link \t Only generate an object file.");
+    println!("--omit-frame-pointer \t Do not set up a frame pointer (x86-64 and AArch64 only)");
+    println!("--bounds-check \t Check array indices at runtime.");
+    println!("--overflow-checks \t Check integer add, subtract, and multiply for overflow at runtime.");
+    println!("--div-checks \t Check integer division and modulo for a zero divisor at runtime.");
+    println!("--release \t Leave out asserts and runtime checks.");
+    println!("-O0, -O1, -O2, -Os \t Set the optimization level (the default is -O0).");
+    println!("--passes=<passes> \t Run these optimization passes, separated by commas, instead of the ones for the level.");
+    println!("--print-after=<passes> \t Print the LTAC after these passes, separated by commas (or \"all\").");
+    println!("--time-passes \t Print how long each pass took.");
     println!("--no-start \t Do not link with start files.");
     println!("-l<lib> \t Link to a certain library.");
     println!("-o <name> \t Specify the output name.");
+    println!("--target <triple or file> \t Build for a target triple (such as aarch64-linux-gnu), or a TOML target file.");
+    println!("--as <path> \t Use a different assembler.");
+    println!("--ld <path> \t Use a different linker.");
+    println!("--sysroot <dir> \t Look for the C start-up files and libraries in this directory.");
+    println!("--build-dir <dir> \t Write the assembly and object files to this directory, and keep them.");
+    println!("--save-temps \t Keep the assembly and object files (they are deleted by default).");
     println!("-h, --help \t Display this message and exit.");
     println!("");
 }
@@ -0,0 +1,253 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// Describes the toolchain used to assemble and link for a target
+// A target comes from the host defaults, a triple (--target aarch64-linux-gnu),
+// or a TOML file (--target my-board.toml)
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use parser::Arch;
+
+pub struct Target {
+    pub arch : Arch,
+    pub assembler : String,
+    pub linker : String,
+    pub sysroot : String,
+    
+    // The first of these that has crt1.o in it holds the C start-up files
+    pub crt_dirs : Vec<String>,
+    pub crt_start : Vec<String>,
+    pub crt_end : Vec<String>,
+    
+    pub dynamic_linker : String,
+    pub lib_dirs : Vec<String>,
+    
+    // Where the Ida core library, standard library, and irt.o live
+    // This is empty if they haven't been ported to the target
+    pub ida_dir : String,
+}
+
+impl Target {
+    // Returns a path inside the sysroot
+    pub fn in_sysroot(&self, path : &str) -> String {
+        if self.sysroot.is_empty() || !path.starts_with('/') {
+            return path.to_string();
+        }
+        
+        format!("{}{}", self.sysroot.trim_end_matches('/'), path)
+    }
+    
+    // Finds the directory with the C start-up files
+    // Distributions put these in different places
+    pub fn crt_dir(&self) -> String {
+        for dir in self.crt_dirs.iter() {
+            let dir = self.in_sysroot(dir);
+            if Path::new(&dir).join("crt1.o").exists() {
+                return dir;
+            }
+        }
+        
+        match self.crt_dirs.first() {
+            Some(dir) => self.in_sysroot(dir),
+            None => String::new(),
+        }
+    }
+    
+    // Returns the full path of a C start-up file
+    pub fn crt_path(&self, name : &str) -> String {
+        if name.starts_with('/') {
+            return self.in_sysroot(name);
+        }
+        
+        format!("{}/{}", self.crt_dir(), name)
+    }
+    
+    // Returns the library search paths, with the C start-up directory first
+    pub fn lib_paths(&self) -> Vec<String> {
+        let mut paths = vec![self.crt_dir()];
+        
+        for dir in self.lib_dirs.iter() {
+            let dir = self.in_sysroot(dir);
+            if !paths.contains(&dir) {
+                paths.push(dir);
+            }
+        }
+        
+        paths
+    }
+}
+
+// Returns the default target for an architecture
+// When cross-compiling, the <arch>-linux-gnu- assembler and linker are used
+pub fn for_arch(arch : Arch) -> Target {
+    let prefix = match arch {
+        _ if is_host(arch) => "",
+        Arch::X86_64 => "x86_64-linux-gnu-",
+        Arch::AArch64 => "aarch64-linux-gnu-",
+        Arch::Riscv64 => "riscv64-linux-gnu-",
+    };
+    
+    let crt_dirs : &[&str] = match arch {
+        Arch::X86_64 => &["/usr/lib64", "/usr/lib/x86_64-linux-gnu", "/usr/x86_64-linux-gnu/lib"],
+        Arch::AArch64 => &["/usr/lib/aarch64-linux-gnu", "/usr/aarch64-linux-gnu/lib", "/usr/lib64", "/usr/lib"],
+        Arch::Riscv64 => &["/usr/lib64", "/usr/lib/riscv64-linux-gnu", "/usr/riscv64-linux-gnu/lib", "/usr/lib"],
+    };
+    
+    let dynamic_linker = match arch {
+        Arch::X86_64 => "/lib64/ld-linux-x86-64.so.2",
+        Arch::AArch64 => "/lib/ld-linux-aarch64.so.1",
+        Arch::Riscv64 => "/lib64/ld-linux-riscv64-lp64d.so.1",
+    };
+    
+    // The AArch64 libraries are static, and live in their own directory
+    // There is no RISC-V core library yet
+    let ida_dir = match arch {
+        Arch::X86_64 => "/usr/lib/ida",
+        Arch::AArch64 => "/usr/lib/ida/aarch64",
+        Arch::Riscv64 => "",
+    };
+    
+    Target {
+        arch : arch,
+        assembler : format!("{}as", prefix),
+        linker : format!("{}ld", prefix),
+        sysroot : String::new(),
+        crt_dirs : crt_dirs.iter().map(|d| d.to_string()).collect(),
+        crt_start : vec!["crt1.o".to_string(), "crti.o".to_string()],
+        crt_end : vec!["crtn.o".to_string()],
+        dynamic_linker : dynamic_linker.to_string(),
+        lib_dirs : Vec::new(),
+        ida_dir : ida_dir.to_string(),
+    }
+}
+
+// Returns true if we are running on an architecture
+fn is_host(arch : Arch) -> bool {
+    match arch {
+        Arch::X86_64 => cfg!(target_arch = "x86_64"),
+        Arch::AArch64 => cfg!(target_arch = "aarch64"),
+        Arch::Riscv64 => cfg!(target_arch = "riscv64"),
+    }
+}
+
+// Returns the architecture named by the first part of a triple
+fn arch_for_triple(triple : &str) -> Result<Arch, String> {
+    let name = triple.split('-').next().unwrap_or("");
+    
+    match name {
+        "x86_64" | "amd64" => Ok(Arch::X86_64),
+        "aarch64" | "arm64" => Ok(Arch::AArch64),
+        "riscv64" | "riscv64gc" => Ok(Arch::Riscv64),
+        _ => Err(format!("Unknown target: {}", triple)),
+    }
+}
+
+// Returns the target for a triple, such as aarch64-linux-gnu
+// The tools are <triple>-as and <triple>-ld, unless the triple is for the host
+pub fn from_triple(triple : &str) -> Result<Target, String> {
+    let arch = arch_for_triple(triple)?;
+    let mut target = for_arch(arch);
+    
+    if !is_host(arch) {
+        target.assembler = format!("{}-as", triple);
+        target.linker = format!("{}-ld", triple);
+    }
+    
+    Ok(target)
+}
+
+// Loads a target from a TOML file
+// Only simple "key = value" lines are supported, where the value is a string
+// or an array of strings. Any key that is left out keeps the default for the
+// architecture.
+pub fn from_file(path : &str) -> Result<Target, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("Unable to read {}: {}", path, e)),
+    };
+    
+    let values = parse_toml(&contents).map_err(|e| format!("{}: {}", path, e))?;
+    
+    let mut target = match values.get("triple") {
+        Some(triple) => from_triple(&triple[0])?,
+        None => match values.get("arch") {
+            Some(arch) => for_arch(arch_for_triple(&arch[0])?),
+            None => return Err(format!("{}: The target needs an arch or a triple.", path)),
+        },
+    };
+    
+    for (key, value) in values.iter() {
+        let is_list = key == "crt_dir" || key == "crt_start" || key == "crt_end" || key == "lib_dirs";
+        if !is_list && value.len() != 1 {
+            return Err(format!("{}: Expected a single string for {}.", path, key));
+        }
+        
+        match key.as_ref() {
+            "arch" | "triple" => {},
+            "as" => target.assembler = value[0].clone(),
+            "ld" => target.linker = value[0].clone(),
+            "sysroot" => target.sysroot = value[0].clone(),
+            "crt_dir" => target.crt_dirs = value.clone(),
+            "crt_start" => target.crt_start = value.clone(),
+            "crt_end" => target.crt_end = value.clone(),
+            "dynamic_linker" => target.dynamic_linker = value[0].clone(),
+            "lib_dirs" => target.lib_dirs = value.clone(),
+            "ida_dir" => target.ida_dir = value[0].clone(),
+            _ => return Err(format!("{}: Unknown key: {}", path, key)),
+        }
+    }
+    
+    Ok(target)
+}
+
+// Parses the TOML subset used by target files
+// A single string is returned as an array with one item
+fn parse_toml(contents : &str) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut values : HashMap<String, Vec<String>> = HashMap::new();
+    
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+            continue;
+        }
+        
+        let (key, value) = match line.find('=') {
+            Some(pos) => (line[..pos].trim(), line[pos+1..].trim()),
+            None => return Err(format!("Line {}: Expected \"key = value\".", i + 1)),
+        };
+        
+        let items = if value.starts_with('[') && value.ends_with(']') {
+            let inner = value[1..value.len()-1].trim();
+            let mut items = Vec::new();
+            
+            for item in inner.split(',') {
+                let item = item.trim();
+                if !item.is_empty() {
+                    items.push(parse_string(item, i + 1)?);
+                }
+            }
+            
+            items
+        } else {
+            vec![parse_string(value, i + 1)?]
+        };
+        
+        values.insert(key.to_string(), items);
+    }
+    
+    Ok(values)
+}
+
+fn parse_string(value : &str, line_no : usize) -> Result<String, String> {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(format!("Line {}: Expected a string.", line_no));
+    }
+    
+    Ok(value[1..value.len()-1].to_string())
+}
@@ -0,0 +1,16 @@
+// For now, we just call the main function and
+// pass parameters
+
+.text
+.globl _start
+.extern main
+
+_start:
+    mov x29, 0
+    mov x30, 0
+    ldr w0, [sp]
+    add x1, sp, 8
+    bl main
+
+    mov x8, 93
+    svc 0
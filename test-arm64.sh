@@ -2,10 +2,17 @@
 
 test_count=0
 
+# Off an AArch64 machine, the tests run under qemu-user
+runner=""
+if [[ `uname -m` != "aarch64" ]] ; then
+    runner="qemu-aarch64 -L /usr/aarch64-linux-gnu"
+fi
+
 function run_test() {
     for entry in $1
     do
     	name=`basename $entry .ida`
+    	name=`basename $name .ltac`
         
         if [[ $3 == "error" ]] ; then
             if [ -f ./ERROR_TEST.sh ] ; then
@@ -13,7 +20,7 @@ function run_test() {
             fi
             
             echo "#!/bin/bash" >> ERROR_TEST.sh
-            echo "cargo run $entry --use-c" >> ERROR_TEST.sh
+            echo "cargo run $entry -march=aarch64 --use-c" >> ERROR_TEST.sh
             chmod 777 ERROR_TEST.sh
             ./test.py $entry  ./ERROR_TEST.sh "error"
             
@@ -23,25 +30,39 @@ function run_test() {
             fi
             
             rm ERROR_TEST.sh
+        elif [[ $3 == "warning" ]] ; then
+            # The warnings are printed while building, so the build is part of the test
+            echo "#!/bin/bash" > WARN_TEST.sh
+            echo "./target/debug/idac $entry -march=aarch64 --use-c -o $name && $runner ./$name" >> WARN_TEST.sh
+            chmod 777 WARN_TEST.sh
+            ./test.py $entry ./WARN_TEST.sh ""
+            
+            if [[ $? != 0 ]] ; then
+                rm WARN_TEST.sh
+                exit 1
+            fi
+            
+            rm WARN_TEST.sh ./$name
         else
             if [[ $2 == "sys" ]] ; then
-                cargo run $entry $3 -o $name
+                cargo run $entry -march=aarch64 $3 -o $name
             elif [[ $2 == "clib" ]] ; then
-                cargo run $entry --no-corelib $3 -o $name
-                mv /tmp/$name.asm /tmp/$name.s
-                gcc /tmp/$name.s -o $name
+                cargo run $entry -march=aarch64 --use-c $3 -o $name
             fi
+            
+            echo "#!/bin/bash" > RUN_TEST.sh
+            echo "$runner ./$name" >> RUN_TEST.sh
+            chmod 777 RUN_TEST.sh
         
-    	    ./test.py $entry ./$name ""
+    	    ./test.py $entry ./RUN_TEST.sh ""
     	    
     	    if [[ $? != 0 ]] ; then
+    	        rm RUN_TEST.sh
         		exit 1
         	fi
         	
+        	rm RUN_TEST.sh
         	rm ./$name
-        	rm /tmp/$name.o
-        	rm /tmp/$name.asm
-        	rm /tmp/$name.s
     	fi
     	
     	test_count=$((test_count+1))
@@ -53,29 +74,44 @@ flags=""
 echo "Running all tests..."
 echo ""
 
-run_test 'test/basic/*.ida' 'clib' $flags
+run_test 'test/basic/*.ida' 'sys' $flags
+run_test 'test/int/*.ida' 'sys' $flags
+run_test 'test/int64/*.ida' 'sys' $flags
+run_test 'test/byte/*.ida' 'sys' $flags
+run_test 'test/short/*.ida' 'sys' $flags
+run_test 'test/float/*.ida' 'clib' $flags
+run_test 'test/char/*.ida' 'sys' $flags
+run_test 'test/string/*.ida' 'sys' $flags
 
-#run_test 'test/int/*.ida' 'clib' $flags
-#run_test 'test/int64/*.ida' 'clib' $flags
-#run_test 'test/byte/*.ida' 'clib' $flags
-#run_test 'test/short/*.ida' 'clib' $flags
-#run_test 'test/float/*.ida' 'clib' $flags
-#run_test 'test/char/*.ida' 'clib' $flags
-#run_test 'test/string/*.ida' 'clib' $flags
+run_test 'test/assign/*.ida' 'sys' $flags
+run_test 'test/ooop/*.ida' 'sys' $flags
+run_test 'test/loop/*.ida' 'sys' $flags
+run_test 'test/mem/*.ida' 'sys' $flags
+run_test 'test/const/*.ida' 'sys' $flags
+run_test 'test/func/*.ida' 'sys' $flags
+run_test 'test/recursion/*.ida' 'sys' $flags
+run_test 'test/recursion/*.ida' 'sys' "--omit-frame-pointer"
+run_test 'test/enum/*.ida' 'sys' $flags
+run_test 'test/assert/*.ida' 'sys' $flags
+run_test 'test/bounds/*.ida' 'sys' "--bounds-check"
+run_test 'test/overflow/*.ida' 'sys' "--overflow-checks"
+run_test 'test/div/*.ida' 'sys' "--div-checks"
+run_test 'test/release/*.ida' 'sys' "--release"
+run_test 'test/opt/*.ida' 'clib' "-O1"
+run_test 'test/opt/*.ida' 'clib' "-O2"
+run_test 'test/overflow/*.ida' 'sys' "--overflow-checks -O1"
+run_test 'test/div/*.ida' 'sys' "--div-checks -O1"
+run_test 'test/ltac/*.ltac' 'clib' $flags
 
-#run_test 'test/ooop/*.ida' 'clib' $flags
-#run_test 'test/loop/*.ida' 'clib' $flags
-#run_test 'test/ldarg/*.ida' 'clib' $flags
-#run_test 'test/const/*.ida' 'clib' $flags
-#run_test 'test/func/*.ida' 'clib' $flags
-
-#run_test 'test/errors/*.ida' 'clib' "error"
-#run_test 'test/errors/ltac/*.ida' "clib" "error"
+run_test 'test/warnings/*.ida' 'clib' "warning"
+run_test 'test/errors/*.ida' 'sys' "error"
+run_test 'test/errors/ltac/*.ida' "sys" "error"
+run_test 'test/errors/verify/*.ltac' "sys" "error"
 
 #run_test 'test/vector/*.ida' 'clib'
-#run_test 'test/syscall/x86-64/*.ida' 'sys'
+# The system call tests use the x86-64 numbers
+#run_test 'test/syscall/x86-64/*.ida' 'sys2'
 
 echo ""
 echo "$test_count tests passed successfully."
 echo "Done"
-
@@ -0,0 +1,78 @@
+#!/bin/bash
+
+# Runs the tests with the LTAC interpreter (idac --run)
+# This doesn't need an assembler or linker, so it works on any host.
+
+test_count=0
+
+cargo build
+idac=./target/debug/idac
+
+function run_test() {
+    for entry in $1
+    do
+        if [ -f ./RUN_TEST.sh ] ; then
+            rm RUN_TEST.sh
+        fi
+        
+        echo "#!/bin/bash" >> RUN_TEST.sh
+        echo "$idac $entry --run $3" >> RUN_TEST.sh
+        chmod 777 RUN_TEST.sh
+        ./test.py $entry ./RUN_TEST.sh "$2"
+        
+        if [[ $? != 0 ]] ; then
+            rm RUN_TEST.sh
+            exit 1
+        fi
+        
+        rm RUN_TEST.sh
+        test_count=$((test_count+1))
+    done
+}
+
+flags=""
+
+echo "Running all tests..."
+echo ""
+
+run_test 'test/basic/*.ida' '' $flags
+run_test 'test/int/*.ida' '' $flags
+run_test 'test/int64/*.ida' '' $flags
+run_test 'test/byte/*.ida' '' $flags
+run_test 'test/short/*.ida' '' $flags
+run_test 'test/float/*.ida' '' "--use-c"
+run_test 'test/char/*.ida' '' $flags
+run_test 'test/string/*.ida' '' $flags
+
+run_test 'test/assign/*.ida' '' $flags
+run_test 'test/ooop/*.ida' '' $flags
+run_test 'test/loop/*.ida' '' $flags
+run_test 'test/mem/*.ida' '' $flags
+run_test 'test/const/*.ida' '' $flags
+run_test 'test/func/*.ida' '' $flags
+run_test 'test/recursion/*.ida' '' $flags
+run_test 'test/enum/*.ida' '' $flags
+run_test 'test/assert/*.ida' '' $flags
+run_test 'test/bounds/*.ida' '' "--bounds-check"
+run_test 'test/overflow/*.ida' '' "--overflow-checks"
+run_test 'test/div/*.ida' '' "--div-checks"
+run_test 'test/release/*.ida' '' "--release"
+run_test 'test/opt/*.ida' '' "--use-c -O1"
+run_test 'test/opt/*.ida' '' "--use-c -O2"
+run_test 'test/opt/*.ida' '' "--use-c --passes=fold"
+run_test 'test/overflow/*.ida' '' "--overflow-checks -O1"
+run_test 'test/div/*.ida' '' "--div-checks -O1"
+run_test 'test/ltac/*.ltac' '' "--use-c"
+
+run_test 'test/warnings/*.ida' '' "--use-c"
+run_test 'test/errors/*.ida' 'error' "--use-c"
+run_test 'test/errors/ltac/*.ida' 'error' "--use-c"
+run_test 'test/errors/verify/*.ltac' 'error' "--use-c"
+
+# These use the x86-64 system call numbers
+run_test 'test/syscall/x86-64/*.ida' '' "--target x86_64-linux-gnu"
+
+echo ""
+echo "$test_count tests passed successfully."
+echo "Done"
+
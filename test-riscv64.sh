@@ -37,8 +37,6 @@ function run_test() {
         	fi
         	
         	rm ./$name
-        	rm /tmp/$name.o
-        	rm /tmp/$name.asm
     	fi
     	
     	test_count=$((test_count+1))
@@ -57,6 +55,7 @@ run_test 'test/int64/*.ida' 'clib' $flags
 run_test 'test/byte/*.ida' 'clib' $flags
 run_test 'test/short/*.ida' 'clib' $flags
 #run_test 'test/float/*.ida' 'clib' $flags
+run_test 'test/float/float_move1.ida' 'clib' $flags
 #run_test 'test/char/*.ida' 'clib' $flags
 #run_test 'test/string/*.ida' 'clib' $flags
 
@@ -65,9 +64,12 @@ run_test 'test/loop/*.ida' 'clib' $flags
 #run_test 'test/ldarg/*.ida' 'clib' $flags
 run_test 'test/const/*.ida' 'clib' $flags
 #run_test 'test/func/*.ida' 'clib' $flags
+run_test 'test/recursion/*.ida' 'clib' $flags
+run_test 'test/opt/*.ida' 'clib' "-O1"
 
 run_test 'test/errors/*.ida' 'clib' "error"
 run_test 'test/errors/ltac/*.ida' "clib" "error"
+run_test 'test/errors/verify/*.ltac' "clib" "error"
 
 #run_test 'test/vector/*.ida' 'clib'
 #run_test 'test/syscall/x86-64/*.ida' 'sys'
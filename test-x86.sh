@@ -6,6 +6,7 @@ function run_test() {
     for entry in $1
     do
     	name=`basename $entry .ida`
+    	name=`basename $name .ltac`
         
         if [[ $3 == "error" ]] ; then
             if [ -f ./ERROR_TEST.sh ] ; then
@@ -23,6 +24,19 @@ function run_test() {
             fi
             
             rm ERROR_TEST.sh
+        elif [[ $3 == "warning" ]] ; then
+            # The warnings are printed while building, so the build is part of the test
+            echo "#!/bin/bash" > WARN_TEST.sh
+            echo "./target/debug/idac $entry --use-c -o $name && ./$name" >> WARN_TEST.sh
+            chmod 777 WARN_TEST.sh
+            ./test.py $entry ./WARN_TEST.sh ""
+            
+            if [[ $? != 0 ]] ; then
+                rm WARN_TEST.sh
+                exit 1
+            fi
+            
+            rm WARN_TEST.sh ./$name
         else
             if [[ $2 == "sys" ]] ; then
                 cargo run $entry $3 -o $name
@@ -39,8 +53,6 @@ function run_test() {
         	fi
         	
         	rm ./$name
-        	rm /tmp/$name.o
-        	rm /tmp/$name.asm
     	fi
     	
     	test_count=$((test_count+1))
@@ -57,7 +69,7 @@ run_test 'test/int/*.ida' 'sys' $flags
 run_test 'test/int64/*.ida' 'sys' $flags
 run_test 'test/byte/*.ida' 'sys' $flags
 run_test 'test/short/*.ida' 'sys' $flags
-#run_test 'test/float/*.ida' 'clib' $flags
+run_test 'test/float/*.ida' 'clib' $flags
 run_test 'test/char/*.ida' 'sys' $flags
 run_test 'test/string/*.ida' 'sys' $flags
 
@@ -67,10 +79,24 @@ run_test 'test/loop/*.ida' 'sys' $flags
 run_test 'test/mem/*.ida' 'sys' $flags
 run_test 'test/const/*.ida' 'sys' $flags
 run_test 'test/func/*.ida' 'sys' $flags
+run_test 'test/recursion/*.ida' 'sys' $flags
+run_test 'test/recursion/*.ida' 'sys' "--omit-frame-pointer"
 run_test 'test/enum/*.ida' 'sys' $flags
+run_test 'test/assert/*.ida' 'sys' $flags
+run_test 'test/bounds/*.ida' 'sys' "--bounds-check"
+run_test 'test/overflow/*.ida' 'sys' "--overflow-checks"
+run_test 'test/div/*.ida' 'sys' "--div-checks"
+run_test 'test/release/*.ida' 'sys' "--release"
+run_test 'test/opt/*.ida' 'clib' "-O1"
+run_test 'test/opt/*.ida' 'clib' "-O2"
+run_test 'test/overflow/*.ida' 'sys' "--overflow-checks -O1"
+run_test 'test/div/*.ida' 'sys' "--div-checks -O1"
+run_test 'test/ltac/*.ltac' 'clib' $flags
 
+run_test 'test/warnings/*.ida' 'clib' "warning"
 run_test 'test/errors/*.ida' 'sys' "error"
 run_test 'test/errors/ltac/*.ida' "sys" "error"
+run_test 'test/errors/verify/*.ltac' "sys" "error"
 
 #run_test 'test/vector/*.ida' 'clib'
 run_test 'test/syscall/x86-64/*.ida' 'sys2'
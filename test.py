@@ -17,7 +17,9 @@ basename = os.path.basename(test_file)
 print("[TEST] " + basename)
 
 output = []
+errors = None
 in_output = False
+in_errors = False
 ret = 0
 
 with open(test_file) as reader:
@@ -28,14 +30,24 @@ with open(test_file) as reader:
 			in_output = True
 		elif ln == "#END" and in_output:
 			in_output = False
+		elif ln == "#STDERR":
+			in_errors = True
+			errors = []
+		elif ln == "#END" and in_errors:
+			in_errors = False
 		elif ln.startswith("#RET"):
 			ret = int(ln.split()[1])
 		elif in_output:
 			output.append(ln[1:])
+		elif in_errors:
+			errors.append(ln[1:])
 			
-result = subprocess.run([bin_file], stdout=subprocess.PIPE)
+result = subprocess.run([bin_file], stdout=subprocess.PIPE, stderr=subprocess.PIPE)
 cmd_output = result.stdout.decode('utf-8').split('\n')
 cmd_output.remove('')
+cmd_errors = result.stderr.decode('utf-8').split('\n')
+cmd_errors.pop()
+sys.stderr.write(result.stderr.decode('utf-8'))
 rc = result.returncode
 
 # Check output
@@ -50,16 +62,26 @@ if is_output:
 			is_output = False
 			break
 			
+# Only tests with a #STDERR section check stderr
+is_errors = True
+
+if errors != None and errors != cmd_errors:
+	is_errors = False
+
 is_ret = True
 if test_type != "error":
 	if rc != ret:
 		is_ret = False
 
 # Print results if wrong
-if (not is_ret) or (not is_output):
+if (not is_ret) or (not is_output) or (not is_errors):
 	print("Expected Output: " + str(output))
 	print("CMD Output: " + str(cmd_output))
 	print("")
+	if errors != None:
+		print("Expected Stderr: " + str(errors))
+		print("CMD Stderr: " + str(cmd_errors))
+		print("")
 	if test_type != "error":
 		print("Expected Return: " + str(ret))
 		print("Actual Return: " + str(rc))
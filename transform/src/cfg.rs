@@ -0,0 +1,453 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The control-flow graph
+// This splits the flat LTAC code into functions and basic blocks. The blocks keep
+// their instructions in the original order, so the graph can be turned back into code.
+
+use std::collections::HashMap;
+
+use parser::ltac;
+use parser::ltac::{LtacFile, LtacInstr, LtacType};
+
+// Represents a basic block
+// If the block starts with a label, the label is the first instruction
+#[derive(Clone)]
+pub struct BasicBlock {
+    pub code : Vec<LtacInstr>,
+    pub succ : Vec<usize>,
+    pub pred : Vec<usize>,
+}
+
+// Represents a natural loop
+// The blocks include the header and the latches (the blocks that branch back to it)
+#[derive(Clone)]
+pub struct Loop {
+    pub header : usize,
+    pub latches : Vec<usize>,
+    pub blocks : Vec<usize>,
+}
+
+// Represents a function
+// The first block is the entry, and starts with the func instruction. A block
+// that can't be reached from the entry has no immediate dominator.
+#[derive(Clone)]
+pub struct CfgFunc {
+    pub name : String,
+    pub blocks : Vec<BasicBlock>,
+    pub idom : Vec<Option<usize>>,
+    pub loops : Vec<Loop>,
+}
+
+// Represents the graph for a file
+// Anything before the first function (such as the externs) goes in the header
+#[derive(Clone)]
+pub struct Cfg {
+    pub header : Vec<LtacInstr>,
+    pub funcs : Vec<CfgFunc>,
+}
+
+impl BasicBlock {
+    // Returns the label that starts the block, if there is one
+    pub fn label(&self) -> Option<&String> {
+        match self.code.first() {
+            Some(instr) if instr.instr_type == LtacType::Label => Some(&instr.name),
+            _ => None,
+        }
+    }
+}
+
+impl CfgFunc {
+    // Returns true if a dominates b
+    pub fn dominates(&self, a : usize, b : usize) -> bool {
+        if a == b {
+            return true;
+        }
+        
+        let mut current = b;
+        while let Some(parent) = self.idom[current] {
+            if parent == a {
+                return true;
+            }
+            
+            current = parent;
+        }
+        
+        false
+    }
+    
+    // Returns true if a block can be reached from the entry
+    pub fn is_reachable(&self, block : usize) -> bool {
+        block == 0 || self.idom[block].is_some()
+    }
+    
+    // Returns the number of loops a block is in
+    pub fn loop_depth(&self, block : usize) -> usize {
+        self.loops.iter().filter(|l| l.blocks.contains(&block)).count()
+    }
+    
+    // Works out the edges, dominators, and loops again
+    // This should be called after a pass changes the branches or the blocks
+    pub fn update(&mut self) {
+        build_edges(self);
+        build_dominators(self);
+        build_loops(self);
+    }
+}
+
+impl Cfg {
+    // Turns the graph back into flat code
+    pub fn to_code(&self) -> Vec<LtacInstr> {
+        let mut code = self.header.clone();
+        
+        for func in self.funcs.iter() {
+            for block in func.blocks.iter() {
+                code.extend(block.code.iter().cloned());
+            }
+        }
+        
+        code
+    }
+    
+    // Returns the graph in the Graphviz format
+    // Loop headers have a double border, back edges are dashed, and blocks that
+    // can't be reached are grey.
+    pub fn to_dot(&self, name : &String) -> String {
+        let mut dot = format!("digraph \"{}\" {{\n", escape(name));
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        
+        for func in self.funcs.iter() {
+            dot.push_str(&format!("    subgraph \"cluster_{}\" {{\n", escape(&func.name)));
+            dot.push_str(&format!("        label=\"{}\";\n", escape(&func.name)));
+            
+            for (i, block) in func.blocks.iter().enumerate() {
+                let mut text = format!("B{}", i);
+                if func.loops.iter().any(|l| l.header == i) {
+                    text.push_str(&format!(" (loop depth {})", func.loop_depth(i)));
+                }
+                
+                text.push_str("\\l");
+                
+                for instr in block.code.iter() {
+                    text.push_str(&escape(&ltac::instr_to_text(instr)));
+                    text.push_str("\\l");
+                }
+                
+                let mut attrs = format!("label=\"{}\"", text);
+                if func.loops.iter().any(|l| l.header == i) {
+                    attrs.push_str(", peripheries=2");
+                }
+                
+                if !func.is_reachable(i) {
+                    attrs.push_str(", color=grey, fontcolor=grey");
+                }
+                
+                dot.push_str(&format!("        \"{}.{}\" [{}];\n", escape(&func.name), i, attrs));
+            }
+            
+            for (i, block) in func.blocks.iter().enumerate() {
+                for succ in block.succ.iter() {
+                    let style = if func.is_reachable(i) && func.dominates(*succ, i) {
+                        " [style=dashed]"
+                    } else {
+                        ""
+                    };
+                    
+                    dot.push_str(&format!("        \"{}.{}\" -> \"{}.{}\"{};\n",
+                        escape(&func.name), i, escape(&func.name), succ, style));
+                }
+            }
+            
+            dot.push_str("    }\n");
+        }
+        
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Builds the graph for a file
+pub fn build(file : &LtacFile) -> Cfg {
+    let mut cfg = Cfg {
+        header : Vec::new(),
+        funcs : Vec::new(),
+    };
+    
+    let mut current : Vec<LtacInstr> = Vec::new();
+    
+    for instr in file.code.iter() {
+        match instr.instr_type {
+            LtacType::Func => {
+                end_block(&mut cfg, &mut current);
+                
+                cfg.funcs.push(CfgFunc {
+                    name : instr.name.clone(),
+                    blocks : Vec::new(),
+                    idom : Vec::new(),
+                    loops : Vec::new(),
+                });
+                
+                current.push(instr.clone());
+            },
+            
+            _ if cfg.funcs.is_empty() => cfg.header.push(instr.clone()),
+            
+            LtacType::Label => {
+                end_block(&mut cfg, &mut current);
+                current.push(instr.clone());
+            },
+            
+            
+            _ => {
+                current.push(instr.clone());
+                
+                if is_terminator(instr) || is_branch(instr) {
+                    end_block(&mut cfg, &mut current);
+                }
+            },
+        }
+    }
+    
+    end_block(&mut cfg, &mut current);
+    
+    for func in cfg.funcs.iter_mut() {
+        func.update();
+    }
+    
+    cfg
+}
+
+// Adds the current block to the last function
+// Blocks only start once we are in a function
+fn end_block(cfg : &mut Cfg, current : &mut Vec<LtacInstr>) {
+    if current.is_empty() {
+        return;
+    }
+    
+    let block = BasicBlock {
+        code : current.clone(),
+        succ : Vec::new(),
+        pred : Vec::new(),
+    };
+    
+    if let Some(func) = cfg.funcs.last_mut() {
+        func.blocks.push(block);
+    }
+    
+    current.clear();
+}
+
+// Returns true if control never goes on to the next instruction
+fn is_terminator(instr : &LtacInstr) -> bool {
+    match instr.instr_type {
+        LtacType::Br | LtacType::Ret | LtacType::Exit => true,
+        _ => false,
+    }
+}
+
+// Returns true if an instruction can branch to a label
+// Besides the branches, checked math branches to its panic label.
+fn is_branch(instr : &LtacInstr) -> bool {
+    match instr.instr_type {
+        LtacType::Br | LtacType::Be | LtacType::Bne
+        | LtacType::Bl | LtacType::Ble | LtacType::Bfl | LtacType::Bfle
+        | LtacType::Bg | LtacType::Bge | LtacType::Bfg | LtacType::Bfge => true,
+        
+        LtacType::Extern | LtacType::Label | LtacType::Func
+        | LtacType::Call | LtacType::Syscall => false,
+        
+        _ => !instr.name.is_empty(),
+    }
+}
+
+// Works out the successors and predecessors of each block
+fn build_edges(func : &mut CfgFunc) {
+    let mut labels : HashMap<String, usize> = HashMap::new();
+    for (i, block) in func.blocks.iter().enumerate() {
+        if let Some(label) = block.label() {
+            labels.insert(label.clone(), i);
+        }
+    }
+    
+    let count = func.blocks.len();
+    
+    for i in 0 .. count {
+        let mut succ : Vec<usize> = Vec::new();
+        
+        match func.blocks[i].code.last() {
+            Some(instr) => {
+                if !is_terminator(instr) && i + 1 < count {
+                    succ.push(i + 1);
+                }
+                
+                // A label in another function isn't an edge
+                if is_branch(instr) {
+                    if let Some(target) = labels.get(&instr.name) {
+                        if !succ.contains(target) {
+                            succ.push(*target);
+                        }
+                    }
+                }
+            },
+            
+            None if i + 1 < count => succ.push(i + 1),
+            None => {},
+        }
+        
+        func.blocks[i].succ = succ;
+        func.blocks[i].pred.clear();
+    }
+    
+    for i in 0 .. count {
+        for s in func.blocks[i].succ.clone() {
+            func.blocks[s].pred.push(i);
+        }
+    }
+}
+
+// Works out the immediate dominators
+// This is the iterative algorithm from "A Simple, Fast Dominance Algorithm"
+// by Cooper, Harvey, and Kennedy.
+fn build_dominators(func : &mut CfgFunc) {
+    let count = func.blocks.len();
+    func.idom = vec![None; count];
+    
+    if count == 0 {
+        return;
+    }
+    
+    // Number the blocks in reverse postorder
+    let mut postorder : Vec<usize> = Vec::new();
+    let mut visited = vec![false; count];
+    let mut stack : Vec<(usize, usize)> = vec![(0, 0)];
+    visited[0] = true;
+    
+    while let Some((block, next)) = stack.pop() {
+        if next < func.blocks[block].succ.len() {
+            stack.push((block, next + 1));
+            
+            let succ = func.blocks[block].succ[next];
+            if !visited[succ] {
+                visited[succ] = true;
+                stack.push((succ, 0));
+            }
+        } else {
+            postorder.push(block);
+        }
+    }
+    
+    let mut order = vec![usize::MAX; count];
+    for (i, block) in postorder.iter().enumerate() {
+        order[*block] = i;
+    }
+    
+    let mut idom : Vec<Option<usize>> = vec![None; count];
+    idom[0] = Some(0);
+    
+    let mut changed = true;
+    while changed {
+        changed = false;
+        
+        for block in postorder.iter().rev().skip(1) {
+            let mut new_idom : Option<usize> = None;
+            
+            for pred in func.blocks[*block].pred.iter() {
+                if idom[*pred].is_none() {
+                    continue;
+                }
+                
+                new_idom = match new_idom {
+                    None => Some(*pred),
+                    Some(current) => Some(intersect(&idom, &order, *pred, current)),
+                };
+            }
+            
+            if new_idom.is_some() && idom[*block] != new_idom {
+                idom[*block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    
+    // The entry doesn't have an immediate dominator
+    idom[0] = None;
+    func.idom = idom;
+}
+
+// Finds the closest common dominator of two blocks
+fn intersect(idom : &[Option<usize>], order : &[usize], a : usize, b : usize) -> usize {
+    let mut a = a;
+    let mut b = b;
+    
+    while a != b {
+        while order[a] < order[b] {
+            a = idom[a].unwrap();
+        }
+        
+        while order[b] < order[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    
+    a
+}
+
+// Finds the natural loops
+// Each edge to a block that dominates its source is a back edge. Back edges
+// to the same header make up one loop.
+fn build_loops(func : &mut CfgFunc) {
+    let mut loops : Vec<Loop> = Vec::new();
+    
+    for latch in 0 .. func.blocks.len() {
+        if !func.is_reachable(latch) {
+            continue;
+        }
+        
+        for header in func.blocks[latch].succ.iter() {
+            if !func.dominates(*header, latch) {
+                continue;
+            }
+            
+            let index = match loops.iter().position(|l| l.header == *header) {
+                Some(index) => index,
+                None => {
+                    loops.push(Loop {
+                        header : *header,
+                        latches : Vec::new(),
+                        blocks : vec![*header],
+                    });
+                    
+                    loops.len() - 1
+                },
+            };
+            
+            loops[index].latches.push(latch);
+            
+            // Walk backwards from the latch until we reach the header
+            let mut stack = vec![latch];
+            while let Some(block) = stack.pop() {
+                if loops[index].blocks.contains(&block) {
+                    continue;
+                }
+                
+                loops[index].blocks.push(block);
+                stack.extend(func.blocks[block].pred.iter().filter(|p| func.is_reachable(**p)));
+            }
+        }
+    }
+    
+    for l in loops.iter_mut() {
+        l.blocks.sort();
+    }
+    
+    loops.sort_by_key(|l| l.header);
+    func.loops = loops;
+}
+
+// Escapes a string for a Graphviz label
+fn escape(text : &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,272 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The dead code eliminator
+// This removes blocks that can't be reached, branches to the label right after
+// them, labels nothing branches to, and stores to local variables that are never
+// read again. Each of these can open up more of the others, so we go until
+// nothing changes.
+
+use std::collections::HashSet;
+
+use crate::cfg;
+use crate::cfg::CfgFunc;
+use crate::ltac::{LtacFile, LtacInstr, LtacType, LtacArg};
+
+// This also returns if anything was removed
+pub fn eliminate_dead_code(file : &LtacFile) -> Result<(LtacFile, bool), ()> {
+    let mut file2 = file.clone();
+    let mut changed = true;
+    let mut any_changed = false;
+    
+    while changed {
+        changed = false;
+        
+        let mut graph = cfg::build(&file2);
+        
+        for func in graph.funcs.iter_mut() {
+            if remove_unreachable(func) {
+                changed = true;
+            }
+            
+            if remove_dead_stores(func) {
+                changed = true;
+            }
+        }
+        
+        file2.code = graph.to_code();
+        
+        if remove_branches(&mut file2.code) {
+            changed = true;
+        }
+        
+        if remove_labels(&mut file2.code) {
+            changed = true;
+        }
+        
+        any_changed = any_changed || changed;
+    }
+    
+    Ok((file2, any_changed))
+}
+
+// Removes the blocks that can't be reached from the entry
+fn remove_unreachable(func : &mut CfgFunc) -> bool {
+    let keep : Vec<bool> = (0 .. func.blocks.len()).map(|i| func.is_reachable(i)).collect();
+    if keep.iter().all(|k| *k) {
+        return false;
+    }
+    
+    let mut index = 0;
+    func.blocks.retain(|_| {
+        index += 1;
+        keep[index - 1]
+    });
+    
+    func.update();
+    true
+}
+
+// Removes unconditional branches to the label that comes right after them
+// If there are a few labels in a row, any of them counts.
+fn remove_branches(code : &mut Vec<LtacInstr>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    
+    while i < code.len() {
+        if code[i].instr_type == LtacType::Br {
+            let target = code[i + 1 ..].iter()
+                .take_while(|instr| instr.instr_type == LtacType::Label)
+                .any(|instr| instr.name == code[i].name);
+            
+            if target {
+                code.remove(i);
+                changed = true;
+                continue;
+            }
+        }
+        
+        i += 1;
+    }
+    
+    changed
+}
+
+// Removes the labels that nothing refers to
+fn remove_labels(code : &mut Vec<LtacInstr>) -> bool {
+    let mut used : HashSet<String> = HashSet::new();
+    
+    for instr in code.iter() {
+        if instr.instr_type != LtacType::Label && !instr.name.is_empty() {
+            used.insert(instr.name.clone());
+        }
+        
+        for arg in [&instr.arg1, &instr.arg2].iter() {
+            match arg {
+                LtacArg::PtrLcl(name) | LtacArg::PtrFunc(name) => { used.insert(name.clone()); },
+                _ => {},
+            }
+        }
+    }
+    
+    let count = code.len();
+    code.retain(|instr| instr.instr_type != LtacType::Label || used.contains(&instr.name));
+    
+    code.len() != count
+}
+
+// Removes stores to local variables that are never read
+// The liveness is worked out for each byte of the stack frame, since variables can
+// be read with a different width than they were written with. If the address of
+// a variable is taken, anything could read it, so we leave the function alone.
+fn remove_dead_stores(func : &mut CfgFunc) -> bool {
+    let addr_taken = func.blocks.iter()
+        .flat_map(|b| b.code.iter())
+        .any(|i| i.instr_type == LtacType::LdAddr || is_ptr(&i.arg1) || is_ptr(&i.arg2));
+    
+    if addr_taken {
+        return false;
+    }
+    
+    // Work out what is live at the start of each block
+    // Nothing is live when the function returns.
+    let count = func.blocks.len();
+    let mut live_in : Vec<HashSet<i32>> = vec![HashSet::new(); count];
+    let mut changed = true;
+    
+    while changed {
+        changed = false;
+        
+        for i in (0 .. count).rev() {
+            let mut live = live_out(func, &live_in, i);
+            
+            for instr in func.blocks[i].code.iter().rev() {
+                update_live(instr, &mut live);
+            }
+            
+            if live != live_in[i] {
+                live_in[i] = live;
+                changed = true;
+            }
+        }
+    }
+    
+    // Now remove the stores
+    let mut removed = false;
+    
+    for i in 0 .. count {
+        let mut live = live_out(func, &live_in, i);
+        let mut code : Vec<LtacInstr> = Vec::new();
+        
+        for instr in func.blocks[i].code.iter().rev() {
+            if let Some((pos, size)) = stack_store(instr) {
+                if !(0 .. size).any(|b| live.contains(&(b - pos))) {
+                    removed = true;
+                    continue;
+                }
+            }
+            
+            update_live(instr, &mut live);
+            code.push(instr.clone());
+        }
+        
+        code.reverse();
+        func.blocks[i].code = code;
+    }
+    
+    removed
+}
+
+fn live_out(func : &CfgFunc, live_in : &[HashSet<i32>], block : usize) -> HashSet<i32> {
+    let mut live : HashSet<i32> = HashSet::new();
+    
+    for succ in func.blocks[block].succ.iter() {
+        live.extend(live_in[*succ].iter());
+    }
+    
+    live
+}
+
+// Updates the live bytes going backwards over an instruction
+// A byte is stored as its offset from the frame pointer.
+fn update_live(instr : &LtacInstr, live : &mut HashSet<i32>) {
+    if let Some((pos, size)) = stack_store(instr) {
+        for b in 0 .. size {
+            live.remove(&(b - pos));
+        }
+        
+        add_reads(&instr.arg2, read_size(&instr.instr_type), live);
+        return;
+    }
+    
+    let size = read_size(&instr.instr_type);
+    add_reads(&instr.arg1, size, live);
+    add_reads(&instr.arg2, size, live);
+}
+
+fn add_reads(arg : &LtacArg, size : i32, live : &mut HashSet<i32>) {
+    let mut read = |pos : i32, size : i32| {
+        for b in 0 .. size {
+            live.insert(b - pos);
+        }
+    };
+    
+    // The offset forms read the pointer, and the variable with the index
+    match arg {
+        LtacArg::Mem(pos) => read(*pos, size),
+        LtacArg::MemOffsetImm(pos, _) => read(*pos, 8),
+        LtacArg::MemOffsetMem(pos, var, _) => {
+            read(*pos, 8);
+            read(*var, 4);
+        },
+        LtacArg::MemOffsetReg(pos, _, _) => read(*pos, 8),
+        LtacArg::MemOffsetStride(pos, row, _, _, _) => {
+            read(*pos, 8);
+            read(*row, 4);
+        },
+        _ => {},
+    }
+}
+
+// Returns the position and size of a store to a local variable
+fn stack_store(instr : &LtacInstr) -> Option<(i32, i32)> {
+    let pos = match instr.arg1 {
+        LtacArg::Mem(pos) => pos,
+        _ => return None,
+    };
+    
+    let size = match instr.instr_type {
+        LtacType::MovB | LtacType::MovUB | LtacType::StrB | LtacType::StrUB => 1,
+        LtacType::MovW | LtacType::MovUW | LtacType::StrW | LtacType::StrUW => 2,
+        LtacType::Mov | LtacType::MovU | LtacType::Str | LtacType::StrU
+        | LtacType::MovF32 | LtacType::StrF32 => 4,
+        LtacType::MovQ | LtacType::MovUQ | LtacType::StrQ | LtacType::StrUQ
+        | LtacType::MovF64 | LtacType::StrF64 | LtacType::StrPtr => 8,
+        _ => return None,
+    };
+    
+    Some((pos, size))
+}
+
+// Returns how many bytes an instruction reads from a variable
+// Anything we aren't sure of reads the most it could.
+fn read_size(instr_type : &LtacType) -> i32 {
+    match instr_type {
+        LtacType::LdB | LtacType::LdUB | LtacType::MovB | LtacType::MovUB => 1,
+        LtacType::LdW | LtacType::LdUW | LtacType::MovW | LtacType::MovUW => 2,
+        LtacType::Ld | LtacType::LdU | LtacType::Mov | LtacType::MovU
+        | LtacType::LdF32 | LtacType::MovF32 => 4,
+        LtacType::MovI32Vec | LtacType::I32VAdd => 16,
+        _ => 8,
+    }
+}
+
+fn is_ptr(arg : &LtacArg) -> bool {
+    match arg {
+        LtacArg::Ptr(_) => true,
+        _ => false,
+    }
+}
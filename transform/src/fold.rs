@@ -0,0 +1,564 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The constant folder
+// This works out integer math on values that are known at compile time, and
+// replaces it with a move. Values are followed through registers and local
+// variables in straight-line code, and a branch with a known outcome is either
+// made unconditional or dropped. Constants are already literals by the time
+// they get here, so they fold like any other number.
+
+use std::collections::HashMap;
+
+use crate::cfg;
+use crate::ltac;
+use crate::ltac::{LtacFile, LtacInstr, LtacType, LtacArg};
+
+// What we know at a point in a block
+// Registers and memory hold their bits, masked to the width. A register is only
+// known at the width it was written with.
+#[derive(Clone)]
+struct State {
+    regs : HashMap<i32, (u32, u64)>,
+    mem : Vec<(i32, u32, u64)>,     // Position, width, bits
+    flags : Option<(u64, u64, u32, bool)>,  // The two values, width, and if it was signed
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            regs : HashMap::new(),
+            mem : Vec::new(),
+            flags : None,
+        }
+    }
+    
+    fn clear(&mut self) {
+        self.regs.clear();
+        self.mem.clear();
+        self.flags = None;
+    }
+    
+    // Returns the value of an operand, if it is known
+    fn get(&self, arg : &LtacArg, width : u32) -> Option<u64> {
+        if let Some(val) = imm_val(arg) {
+            return Some(val as u64 & mask(width));
+        }
+        
+        match arg {
+            LtacArg::Mem(pos) => {
+                self.mem.iter()
+                    .find(|(p, w, _)| *p == *pos && *w == width)
+                    .map(|(_, _, bits)| *bits)
+            },
+            
+            _ => match reg_of(arg) {
+                Some((reg, reg_width)) if reg_width == width => {
+                    match self.regs.get(&reg) {
+                        Some((w, bits)) if *w == width => Some(*bits),
+                        _ => None,
+                    }
+                },
+                
+                _ => None,
+            },
+        }
+    }
+    
+    // Records a value written to an operand
+    fn set(&mut self, arg : &LtacArg, width : u32, val : Option<u64>) {
+        if let Some((reg, _)) = reg_of(arg) {
+            match val {
+                Some(bits) => self.regs.insert(reg, (width, bits)),
+                None => self.regs.remove(&reg),
+            };
+            
+            return;
+        }
+        
+        match arg {
+            LtacArg::Mem(pos) => {
+                // Drop anything the store overlaps
+                let start = -pos;
+                let end = start + (width / 8) as i32;
+                self.mem.retain(|(p, w, _)| -p + (*w / 8) as i32 <= start || -p >= end);
+                
+                if let Some(bits) = val {
+                    self.mem.push((*pos, width, bits));
+                }
+            },
+            
+            // Writes through a pointer can change any variable
+            LtacArg::MemOffsetImm(..) | LtacArg::MemOffsetMem(..)
+            | LtacArg::MemOffsetReg(..) | LtacArg::MemOffsetStride(..)
+            | LtacArg::Ptr(_) => self.mem.clear(),
+            
+            _ => {},
+        }
+    }
+}
+
+// Folds the constants in each function
+// This also returns if any instruction was changed or dropped
+pub fn fold_constants(file : &LtacFile) -> Result<(LtacFile, bool), ()> {
+    let mut graph = cfg::build(file);
+    let mut changed = false;
+    
+    for func in graph.funcs.iter_mut() {
+        let mut states : Vec<State> = Vec::new();
+        
+        // If the address of a local is taken, a call could change it
+        let addr_taken = func.blocks.iter()
+            .flat_map(|b| b.code.iter())
+            .any(|i| i.instr_type == LtacType::LdAddr || is_ptr(&i.arg1) || is_ptr(&i.arg2));
+        
+        for i in 0 .. func.blocks.len() {
+            // A block with only one way in starts with what its predecessor ended with
+            let mut state = match func.blocks[i].pred[..] {
+                [pred] if pred < i => states[pred].clone(),
+                _ => State::new(),
+            };
+            
+            let mut code : Vec<LtacInstr> = Vec::new();
+            
+            for instr in func.blocks[i].code.iter() {
+                match fold_instr(instr, &mut state, addr_taken) {
+                    Some(instr2) => {
+                        if instr2 != *instr {
+                            changed = true;
+                        }
+                        
+                        code.push(instr2);
+                    },
+                    
+                    None => changed = true,
+                }
+            }
+            
+            func.blocks[i].code = code;
+            states.push(state);
+        }
+    }
+    
+    let mut file2 = file.clone();
+    file2.code = graph.to_code();
+    
+    Ok((file2, changed))
+}
+
+// Folds one instruction
+// This returns None if the instruction should be dropped
+fn fold_instr(instr : &LtacInstr, state : &mut State, addr_taken : bool) -> Option<LtacInstr> {
+    let mut instr2 = instr.clone();
+    
+    // The flags only last from a comparison to its branches
+    if !is_cmp(&instr.instr_type) && !is_branch(&instr.instr_type) {
+        state.flags = None;
+    }
+    
+    match &instr.instr_type {
+        LtacType::Label | LtacType::Func | LtacType::Ret | LtacType::Exit
+        | LtacType::Br | LtacType::Bfl | LtacType::Bfle
+        | LtacType::Bfg | LtacType::Bfge => {},
+        
+        // Moves
+        // A move to memory is a store
+        t if mov_width(t).is_some() => {
+            let (width, _) = mov_width(t).unwrap();
+            let dest_width = reg_of(&instr.arg1).map(|(_, w)| w).unwrap_or(width);
+            let val = state.get(&instr.arg2, dest_width);
+            
+            // The register already has the value
+            if val.is_some() && reg_of(&instr.arg1).is_some() && state.get(&instr.arg1, dest_width) == val {
+                return None;
+            }
+            
+            if let Some(bits) = val {
+                if !is_mem_arg(&instr.arg1) && (reg_of(&instr.arg2).is_some() || is_mem_arg(&instr.arg2)) {
+                    instr2.arg2 = imm_for(dest_width, is_signed_mov(t), bits);
+                }
+            }
+            
+            state.set(&instr.arg1, dest_width, val);
+        },
+        
+        // Loads
+        // The parser puts the register first, and the RISC form puts the memory first
+        t if load_width(t).is_some() => {
+            let (width, signed) = load_width(t).unwrap();
+            let (dest, src) = if is_mem_arg(&instr.arg1) {
+                (&instr.arg2, &instr.arg1)
+            } else {
+                (&instr.arg1, &instr.arg2)
+            };
+            
+            let val = match reg_of(dest) {
+                Some((_, reg_width)) if reg_width == width => state.get(src, width),
+                _ => None,
+            };
+            
+            if let Some(bits) = val {
+                instr2 = ltac::create_instr(mov_for(width, signed));
+                instr2.arg1 = dest.clone();
+                instr2.arg2 = imm_for(width, signed, bits);
+            }
+            
+            state.set(dest, width, val);
+        },
+        
+        // Stores
+        t if store_width(t).is_some() => {
+            let width = store_width(t).unwrap();
+            let val = state.get(&instr.arg2, width);
+            state.set(&instr.arg1, width, val);
+        },
+        
+        // Math
+        _ if math_width(instr).is_some() => {
+            let (width, signed) = math_width(instr).unwrap();
+            let a = state.get(&instr.arg1, width);
+            let b = state.get(&instr.arg2, width);
+            let mut val : Option<u64> = None;
+            
+            if let (Some(a), Some(b)) = (a, b) {
+                val = calc(instr, width, signed, a, b);
+            }
+            
+            match val {
+                Some(bits) if reg_of(&instr.arg1).is_some() => {
+                    instr2 = ltac::create_instr(mov_for(width, signed));
+                    instr2.arg1 = instr.arg1.clone();
+                    instr2.arg2 = imm_for(width, signed, bits);
+                },
+                
+                // The result isn't known, but the second operand may be
+                _ => {
+                    val = None;
+                    
+                    if let Some(b) = b {
+                        if reg_of(&instr.arg1).is_some() && reg_of(&instr.arg2).is_some() && fits_imm(width, b) {
+                            instr2.arg2 = imm_for(width, signed, b);
+                        }
+                    }
+                },
+            }
+            
+            state.set(&instr.arg1, width, val);
+        },
+        
+        // Comparisons
+        t if cmp_width(t).is_some() => {
+            let (width, signed) = cmp_width(t).unwrap();
+            let a = state.get(&instr.arg1, width);
+            let b = state.get(&instr.arg2, width);
+            
+            state.flags = match (a, b) {
+                (Some(a), Some(b)) => Some((a, b, width, signed)),
+                _ => None,
+            };
+            
+            if let Some(b) = b {
+                if reg_of(&instr.arg2).is_some() && fits_imm(width, b) {
+                    instr2.arg2 = imm_for(width, signed, b);
+                }
+            }
+        },
+        
+        // Branches with a known outcome
+        t if is_branch(t) => {
+            if let Some((a, b, width, signed)) = state.flags {
+                match branch_taken(t, a, b, width, signed) {
+                    Some(true) => instr2.instr_type = LtacType::Br,
+                    Some(false) => return None,
+                    None => {},
+                }
+            }
+        },
+        
+        // Calls change the registers, but they can only change a local through a pointer
+        LtacType::PushArg | LtacType::KPushArg | LtacType::Call
+        | LtacType::CallReg | LtacType::Syscall => {
+            state.regs.clear();
+            
+            if addr_taken {
+                state.mem.clear();
+            }
+        },
+        
+        // We don't know what anything else does, so we forget everything
+        _ => state.clear(),
+    }
+    
+    Some(instr2)
+}
+
+// Works out integer math
+// This returns None if the result should be left to run time: a division by zero,
+// the smallest value divided by -1, a checked operation that overflows, or a shift
+// by the width or more.
+fn calc(instr : &LtacInstr, width : u32, signed : bool, a : u64, b : u64) -> Option<u64> {
+    let (x, y) = if signed {
+        (sign_extend(a, width) as i128, sign_extend(b, width) as i128)
+    } else {
+        (a as i128, b as i128)
+    };
+    
+    // Two large 64-bit values can overflow even an i128 when multiplied, but the low bits
+    // are still right for the wrapped result
+    let (result, wrapped) = match &instr.instr_type {
+        LtacType::And => (x & y, false),
+        LtacType::Or => (x | y, false),
+        LtacType::Xor => (x ^ y, false),
+        LtacType::Lsh if b < width as u64 => (((a << b) & mask(width)) as i128, false),
+        LtacType::Rsh if b < width as u64 => ((a >> b) as i128, false),
+        LtacType::Lsh | LtacType::Rsh => return None,
+        
+        t => match math_op(t) {
+            '+' => x.overflowing_add(y),
+            '-' => x.overflowing_sub(y),
+            '*' => x.overflowing_mul(y),
+            '/' | '%' if y == 0 => return None,
+            '/' | '%' if signed && y == -1 && x == sign_extend(1 << (width - 1), width) as i128 => return None,
+            '/' => (x / y, false),
+            _ => (x % y, false),
+        },
+    };
+    
+    let overflow = wrapped || if signed {
+        result != sign_extend(result as u64, width) as i128
+    } else {
+        result < 0 || result > mask(width) as i128
+    };
+    
+    if overflow && !instr.name.is_empty() {
+        return None;
+    }
+    
+    Some(result as u64 & mask(width))
+}
+
+// Returns if a branch is taken, if it compares integers
+fn branch_taken(instr_type : &LtacType, a : u64, b : u64, width : u32, signed : bool) -> Option<bool> {
+    let lt = if signed {
+        sign_extend(a, width) < sign_extend(b, width)
+    } else {
+        a < b
+    };
+    
+    match instr_type {
+        LtacType::Be => Some(a == b),
+        LtacType::Bne => Some(a != b),
+        LtacType::Bl => Some(lt),
+        LtacType::Ble => Some(lt || a == b),
+        LtacType::Bg => Some(!lt && a != b),
+        LtacType::Bge => Some(!lt),
+        _ => None,
+    }
+}
+
+fn mask(width : u32) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+fn sign_extend(bits : u64, width : u32) -> i64 {
+    let shift = 64 - width;
+    ((bits << shift) as i64) >> shift
+}
+
+// The x86 backend can only take 32-bit literals in math and comparisons
+fn fits_imm(width : u32, bits : u64) -> bool {
+    if width < 64 {
+        return true;
+    }
+    
+    let val = bits as i64;
+    val >= i32::MIN as i64 && val <= i32::MAX as i64
+}
+
+fn reg_of(arg : &LtacArg) -> Option<(i32, u32)> {
+    match arg {
+        LtacArg::Reg8(pos) => Some((*pos, 8)),
+        LtacArg::Reg16(pos) => Some((*pos, 16)),
+        LtacArg::Reg32(pos) => Some((*pos, 32)),
+        LtacArg::Reg64(pos) => Some((*pos, 64)),
+        _ => None,
+    }
+}
+
+fn is_mem_arg(arg : &LtacArg) -> bool {
+    match arg {
+        LtacArg::Mem(_) | LtacArg::MemOffsetImm(..) | LtacArg::MemOffsetMem(..)
+        | LtacArg::MemOffsetReg(..) | LtacArg::MemOffsetStride(..) => true,
+        _ => false,
+    }
+}
+
+fn is_ptr(arg : &LtacArg) -> bool {
+    match arg {
+        LtacArg::Ptr(_) => true,
+        _ => false,
+    }
+}
+
+fn imm_val(arg : &LtacArg) -> Option<i64> {
+    match arg {
+        LtacArg::Byte(val) => Some(*val as i64),
+        LtacArg::UByte(val) => Some(*val as i64),
+        LtacArg::I16(val) => Some(*val as i64),
+        LtacArg::U16(val) => Some(*val as i64),
+        LtacArg::I32(val) => Some(*val as i64),
+        LtacArg::U32(val) => Some(*val as i64),
+        LtacArg::I64(val) => Some(*val),
+        LtacArg::U64(val) => Some(*val as i64),
+        _ => None,
+    }
+}
+
+// Returns a literal of the right type for a width
+fn imm_for(width : u32, signed : bool, bits : u64) -> LtacArg {
+    match (width, signed) {
+        (8, true) => LtacArg::Byte(bits as u8 as i8),
+        (8, false) => LtacArg::UByte(bits as u8),
+        (16, true) => LtacArg::I16(bits as u16 as i16),
+        (16, false) => LtacArg::U16(bits as u16),
+        (32, true) => LtacArg::I32(bits as u32 as i32),
+        (32, false) => LtacArg::U32(bits as u32),
+        (_, true) => LtacArg::I64(bits as i64),
+        (_, false) => LtacArg::U64(bits),
+    }
+}
+
+fn mov_for(width : u32, signed : bool) -> LtacType {
+    match (width, signed) {
+        (8, true) => LtacType::MovB,
+        (8, false) => LtacType::MovUB,
+        (16, true) => LtacType::MovW,
+        (16, false) => LtacType::MovUW,
+        (32, true) => LtacType::Mov,
+        (32, false) => LtacType::MovU,
+        (_, true) => LtacType::MovQ,
+        (_, false) => LtacType::MovUQ,
+    }
+}
+
+fn is_signed_mov(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::MovUB | LtacType::MovUW | LtacType::MovU | LtacType::MovUQ => false,
+        _ => true,
+    }
+}
+
+// These return the width and signedness of each kind of instruction
+fn mov_width(instr_type : &LtacType) -> Option<(u32, bool)> {
+    match instr_type {
+        LtacType::MovB => Some((8, true)),
+        LtacType::MovUB => Some((8, false)),
+        LtacType::MovW => Some((16, true)),
+        LtacType::MovUW => Some((16, false)),
+        LtacType::Mov => Some((32, true)),
+        LtacType::MovU => Some((32, false)),
+        LtacType::MovQ => Some((64, true)),
+        LtacType::MovUQ => Some((64, false)),
+        _ => None,
+    }
+}
+
+fn load_width(instr_type : &LtacType) -> Option<(u32, bool)> {
+    match instr_type {
+        LtacType::LdB => Some((8, true)),
+        LtacType::LdUB => Some((8, false)),
+        LtacType::LdW => Some((16, true)),
+        LtacType::LdUW => Some((16, false)),
+        LtacType::Ld => Some((32, true)),
+        LtacType::LdU => Some((32, false)),
+        LtacType::LdQ => Some((64, true)),
+        LtacType::LdUQ => Some((64, false)),
+        _ => None,
+    }
+}
+
+fn store_width(instr_type : &LtacType) -> Option<u32> {
+    match instr_type {
+        LtacType::StrB | LtacType::StrUB => Some(8),
+        LtacType::StrW | LtacType::StrUW => Some(16),
+        LtacType::Str | LtacType::StrU => Some(32),
+        LtacType::StrQ | LtacType::StrUQ => Some(64),
+        _ => None,
+    }
+}
+
+// The bitwise instructions take their width from the register
+fn math_width(instr : &LtacInstr) -> Option<(u32, bool)> {
+    match &instr.instr_type {
+        LtacType::I8Add | LtacType::I8Sub | LtacType::I8Mul
+        | LtacType::I8Div | LtacType::I8Mod => Some((8, true)),
+        LtacType::U8Add | LtacType::U8Mul | LtacType::U8Div | LtacType::U8Mod => Some((8, false)),
+        
+        LtacType::I16Add | LtacType::I16Sub | LtacType::I16Mul
+        | LtacType::I16Div | LtacType::I16Mod => Some((16, true)),
+        LtacType::U16Add | LtacType::U16Mul | LtacType::U16Div | LtacType::U16Mod => Some((16, false)),
+        
+        LtacType::I32Add | LtacType::I32Sub | LtacType::I32Mul
+        | LtacType::I32Div | LtacType::I32Mod => Some((32, true)),
+        LtacType::U32Add | LtacType::U32Mul | LtacType::U32Div | LtacType::U32Mod => Some((32, false)),
+        
+        LtacType::I64Add | LtacType::I64Sub | LtacType::I64Mul
+        | LtacType::I64Div | LtacType::I64Mod => Some((64, true)),
+        LtacType::U64Add | LtacType::U64Mul | LtacType::U64Div | LtacType::U64Mod => Some((64, false)),
+        
+        LtacType::And | LtacType::Or | LtacType::Xor
+        | LtacType::Lsh | LtacType::Rsh => reg_of(&instr.arg1).map(|(_, w)| (w, true)),
+        
+        _ => None,
+    }
+}
+
+fn math_op(instr_type : &LtacType) -> char {
+    match instr_type {
+        LtacType::I8Add | LtacType::U8Add | LtacType::I16Add | LtacType::U16Add
+        | LtacType::I32Add | LtacType::U32Add | LtacType::I64Add | LtacType::U64Add => '+',
+        
+        LtacType::I8Sub | LtacType::I16Sub | LtacType::I32Sub | LtacType::I64Sub => '-',
+        
+        LtacType::I8Mul | LtacType::U8Mul | LtacType::I16Mul | LtacType::U16Mul
+        | LtacType::I32Mul | LtacType::U32Mul | LtacType::I64Mul | LtacType::U64Mul => '*',
+        
+        LtacType::I8Div | LtacType::U8Div | LtacType::I16Div | LtacType::U16Div
+        | LtacType::I32Div | LtacType::U32Div | LtacType::I64Div | LtacType::U64Div => '/',
+        
+        _ => '%',
+    }
+}
+
+fn cmp_width(instr_type : &LtacType) -> Option<(u32, bool)> {
+    match instr_type {
+        LtacType::I8Cmp => Some((8, true)),
+        LtacType::U8Cmp => Some((8, false)),
+        LtacType::I16Cmp => Some((16, true)),
+        LtacType::U16Cmp => Some((16, false)),
+        LtacType::I32Cmp => Some((32, true)),
+        LtacType::U32Cmp => Some((32, false)),
+        LtacType::I64Cmp => Some((64, true)),
+        LtacType::U64Cmp => Some((64, false)),
+        _ => None,
+    }
+}
+
+fn is_cmp(instr_type : &LtacType) -> bool {
+    cmp_width(instr_type).is_some()
+}
+
+fn is_branch(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::Be | LtacType::Bne | LtacType::Bl | LtacType::Ble
+        | LtacType::Bg | LtacType::Bge => true,
+        _ => false,
+    }
+}
@@ -4,51 +4,125 @@
 // Ida is licensed under the BSD-3 license. See the COPYING file for more information.
 //
 
-use std::collections::HashMap;
-
 use parser::ltac;
-use parser::ltac::{LtacFile, LtacType, LtacArg};
+use parser::ltac::{LtacFile, LtacType};
 use parser::Arch;
 
+// The control-flow graph is public, since the driver can dump it
+pub mod cfg;
+pub mod pass;
+
+pub use pass::{Options, OptLevel, Pass, PassManager, Stage, create_options, check_options};
+
 // Import any local modules
+mod dce;
+mod fold;
+mod regalloc;
 mod risc;
 mod riscv;
 
+use dce::*;
+use fold::*;
+use regalloc::*;
 use risc::*;
 use riscv::*;
 
-// Architectures
-// 1-> x86-64
-// 2-> AArch64
-// 3-> Riscv64
-
 // The main transformation function
-pub fn run(file : &LtacFile, arch : Arch, use_c : bool, risc_mode : bool) -> Result<LtacFile, ()> {
-    let mut file2 = match check_builtins(file, use_c) {
-        Ok(ltac) => ltac,
-        Err(_e) => return Err(()),
-    };
-    
-    if risc_mode || /*arch == Arch::AArch64 ||*/ arch == Arch::Riscv64 {
-        file2 = match risc_optimize(&file2) {
-            Ok(ltac) => ltac,
-            Err(_e) => return Err(()),
-        }
-    }
+pub fn run(file : &LtacFile, options : &Options) -> Result<LtacFile, ()> {
+    let mut manager = PassManager::new(options);
+    manager.run(file, options)
+}
+
+// Returns all the passes, in the order they run in each stage
+// To add a pass, add it here; the levels it runs at go in enabled.
+pub fn get_passes() -> Vec<Pass> {
+    vec![
+        Pass {
+            name : "builtins",
+            about : "Turns malloc, calloc, free, resize, and exit into calls",
+            stage : Stage::Lower,
+            optional : false,
+            enabled : |_| true,
+            run : |file, options| check_builtins(file, options.use_c),
+        },
+        
+        Pass {
+            name : "fold",
+            about : "Folds constants and branches with a known outcome (-O1)",
+            stage : Stage::Optimize,
+            optional : true,
+            enabled : |options| options.opt_level != OptLevel::O0,
+            run : |file, _| fold_constants(file).map(|(file2, _)| file2),
+        },
+        
+        Pass {
+            name : "dce",
+            about : "Removes code that can't be reached and stores that are never read (-O1)",
+            stage : Stage::Optimize,
+            optional : true,
+            enabled : |options| options.opt_level != OptLevel::O0,
+            run : |file, _| eliminate_dead_code(file).map(|(file2, _)| file2),
+        },
+        
+        Pass {
+            name : "repeat",
+            about : "Runs fold and dce again until the code stops changing (-O2)",
+            stage : Stage::Optimize,
+            optional : true,
+            enabled : |options| options.opt_level == OptLevel::O2 || options.opt_level == OptLevel::Os,
+            run : |file, _| repeat_folding(file),
+        },
+        
+        Pass {
+            name : "risc",
+            about : "Moves memory operands to separate loads and stores (AArch64, RISC-V, and --risc)",
+            stage : Stage::Target,
+            optional : false,
+            enabled : |options| options.risc_mode || options.arch == Arch::AArch64 || options.arch == Arch::Riscv64,
+            run : |file, _| risc_optimize(file),
+        },
+        
+        Pass {
+            name : "riscv",
+            about : "Fixes up the code for RISC-V",
+            stage : Stage::Target,
+            optional : false,
+            enabled : |options| options.arch == Arch::Riscv64,
+            run : |file, _| riscv_optimize(file),
+        },
+        
+        Pass {
+            name : "regalloc",
+            about : "Keeps variables and temporaries in registers on x86-64, AArch64, and RISC-V (-O1)",
+            stage : Stage::Target,
+            optional : true,
+            enabled : |options| options.opt_level != OptLevel::O0,
+            run : |file, options| allocate_registers(file, options),
+        },
+    ]
+}
+
+// Runs the folder and the dead code eliminator until neither finds anything
+// Removing dead code joins blocks, so values can be followed further on the next
+// round. We stop after a few rounds in case they keep undoing each other.
+fn repeat_folding(file : &LtacFile) -> Result<LtacFile, ()> {
+    let mut file2 = file.clone();
     
-    if arch == Arch::Riscv64 {
-        file2 = match riscv_optimize(&file2) {
-            Ok(ltac) => ltac,
-            Err(_e) => return Err(()),
+    for _ in 0 .. 8 {
+        let (file3, folded) = fold_constants(&file2)?;
+        let (file3, removed) = eliminate_dead_code(&file3)?;
+        file2 = file3;
+        
+        if !folded && !removed {
+            break;
         }
     }
     
     Ok(file2)
 }
 
-// Scans the code for malloc, free, and exit instructions
-// If we are using the C libraries, these are simply transforms to a function call
-// Otherwise, we must transform them to a system call
+// Scans the code for malloc, calloc, free, resize, and exit instructions
+// These are transformed to calls to either the C library or the core library
 fn check_builtins(file : &LtacFile, use_c : bool) -> Result<LtacFile, ()> {
     let mut file2 = LtacFile {
         name : file.name.clone(),
@@ -57,9 +131,6 @@ fn check_builtins(file : &LtacFile, use_c : bool) -> Result<LtacFile, ()> {
     };
     
     let code = file.code.clone();
-    let mut index = 0;
-    
-    let mut mm_map : HashMap<i32, i32> = HashMap::new();
     
     for line in code.iter() {
         match &line.instr_type {
@@ -84,82 +155,34 @@ fn check_builtins(file : &LtacFile, use_c : bool) -> Result<LtacFile, ()> {
                 }
             },
         
+            // The corelib allocator has the same interface as the C library,
+            // so these are plain calls either way
             LtacType::Malloc => {
-                if use_c {
-                    let mut instr = ltac::create_instr(LtacType::Call);
-                    instr.name = "malloc".to_string();
-                    file2.code.push(instr);
-                } else {
-                    let size_instr = code.iter().nth(index-1).unwrap();
-                    let pos_instr = code.iter().nth(index+1).unwrap();
-                    file2.code.pop();
-                    
-                    // Push the memory location and size to the hash map
-                    let pos = match &pos_instr.arg1 {
-                        LtacArg::Mem(pos) => *pos,
-                        LtacArg::Ptr(pos) => *pos,
-                        _ => 0,
-                    };
-                    
-                    let size = match &size_instr.arg1 {
-                        LtacArg::I32(val) => *val,
-                        _ => 0,
-                    };
-                    
-                    mm_map.insert(pos, size);
-                    
-                    // Make the call
-                    let mut instr = ltac::create_instr(LtacType::PushArg);
-                    instr.arg1 = size_instr.arg1.clone();
-                    instr.arg2_val = 1;
-                    file2.code.push(instr.clone());
-                    
-                    instr = ltac::create_instr(LtacType::Call);
-                    instr.name = "malloc".to_string();
-                    file2.code.push(instr);
-                }
+                let mut instr = ltac::create_instr(LtacType::Call);
+                instr.name = "malloc".to_string();
+                file2.code.push(instr);
+            },
+            
+            LtacType::Calloc => {
+                let mut instr = ltac::create_instr(LtacType::Call);
+                instr.name = "calloc".to_string();
+                file2.code.push(instr);
             },
             
             LtacType::Free => {
-                if use_c {
-                    let mut instr = ltac::create_instr(LtacType::Call);
-                    instr.name = "free".to_string();
-                    file2.code.push(instr);
-                } else {
-                    let addr_instr = code.iter().nth(index-1).unwrap();
-                    file2.code.pop();
-                    
-                    // Address
-                    let mut instr = ltac::create_instr(LtacType::PushArg);
-                    instr.arg1 = addr_instr.arg1.clone();
-                    instr.arg2_val = 1;
-                    file2.code.push(instr.clone());
-                    
-                    // Memory segment size
-                    let pos = match &addr_instr.arg1 {
-                        LtacArg::Ptr(pos) => *pos,
-                        _ => 0,
-                    };
-                    
-                    match &mm_map.get(&pos) {
-                        Some(size) => instr.arg1 = LtacArg::I32(**size),
-                        None => instr.arg1 = LtacArg::I32(0),
-                    }
-                    
-                    instr.arg2_val = 2;
-                    file2.code.push(instr.clone());
-                    
-                    // The system call
-                    instr = ltac::create_instr(LtacType::Call);
-                    instr.name = "free".to_string();
-                    file2.code.push(instr.clone());
-                }
+                let mut instr = ltac::create_instr(LtacType::Call);
+                instr.name = "free".to_string();
+                file2.code.push(instr);
+            },
+            
+            LtacType::Resize => {
+                let mut instr = ltac::create_instr(LtacType::Call);
+                instr.name = "realloc".to_string();
+                file2.code.push(instr);
             },
             
             _ => file2.code.push(line.clone()),
         }
-        
-        index += 1;
     }
     
     Ok(file2)
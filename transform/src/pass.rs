@@ -0,0 +1,211 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The pass manager
+// The passes themselves are listed in get_passes() in lib.rs. The manager picks
+// the ones to run from the options, runs them in order, and checks, prints, and
+// times them as it goes.
+
+use std::time::{Duration, Instant};
+
+use parser::ltac;
+use parser::ltac::LtacFile;
+use parser::Arch;
+
+use crate::get_passes;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    Os,         // The same as O2, since none of the passes make the code bigger
+}
+
+// Where a pass runs
+// The optimizations run on the code after the built-in instructions are lowered,
+// but before it is turned into the form the target needs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Lower,
+    Optimize,
+    Target,
+}
+
+// Represents a pass
+// Passes that aren't optional always run when they are enabled; the others can
+// also be picked with --passes.
+#[derive(Clone)]
+pub struct Pass {
+    pub name : &'static str,
+    pub about : &'static str,
+    pub stage : Stage,
+    pub optional : bool,
+    pub enabled : fn(&Options) -> bool,
+    pub run : fn(&LtacFile, &Options) -> Result<LtacFile, ()>,
+}
+
+// The options for the transform layer
+pub struct Options {
+    pub arch : Arch,
+    pub use_c : bool,
+    pub risc_mode : bool,
+    pub opt_level : OptLevel,
+    pub passes : Option<Vec<String>>,   // Replaces the optional passes for the level
+    pub print_after : Vec<String>,      // Pass names, or "all"
+    pub time_passes : bool,
+}
+
+pub fn create_options(arch : Arch) -> Options {
+    Options {
+        arch : arch,
+        use_c : false,
+        risc_mode : false,
+        opt_level : OptLevel::O0,
+        passes : None,
+        print_after : Vec::new(),
+        time_passes : false,
+    }
+}
+
+// Checks the pass names in the options
+pub fn check_options(options : &Options) -> Result<(), ()> {
+    let passes = get_passes();
+    
+    if let Some(ref names) = options.passes {
+        for name in names.iter() {
+            match passes.iter().find(|p| p.name == name) {
+                Some(pass) if !pass.optional => {
+                    println!("Error: The {} pass always runs, so it can't be given to --passes.", name);
+                    return Err(());
+                },
+                
+                Some(_) => {},
+                
+                None => {
+                    unknown_pass(name);
+                    return Err(());
+                },
+            }
+        }
+    }
+    
+    for name in options.print_after.iter() {
+        if name != "all" && !passes.iter().any(|p| p.name == name) {
+            unknown_pass(name);
+            return Err(());
+        }
+    }
+    
+    Ok(())
+}
+
+fn unknown_pass(name : &String) {
+    println!("Error: Unknown pass: {}", name);
+    println!("The passes are:");
+    
+    for pass in get_passes().iter() {
+        println!("  {:<10} {}", pass.name, pass.about);
+    }
+}
+
+pub struct PassManager {
+    pub passes : Vec<Pass>,
+    pub times : Vec<(&'static str, Duration)>,
+}
+
+impl PassManager {
+    // Picks the passes to run
+    // With --passes, the optional passes run in the order they were given, but
+    // each one still runs in its own stage.
+    pub fn new(options : &Options) -> PassManager {
+        let mut passes : Vec<Pass> = Vec::new();
+        let all = get_passes();
+        
+        for stage in [Stage::Lower, Stage::Optimize, Stage::Target].iter() {
+            let in_stage : Vec<&Pass> = all.iter().filter(|p| p.stage == *stage).collect();
+            
+            // The required passes come first in each stage
+            for pass in in_stage.iter() {
+                if !pass.optional && (pass.enabled)(options) {
+                    passes.push((*pass).clone());
+                }
+            }
+            
+            match options.passes {
+                Some(ref names) => {
+                    for name in names.iter() {
+                        if let Some(pass) = in_stage.iter().find(|p| p.name == name && p.optional) {
+                            passes.push((*pass).clone());
+                        }
+                    }
+                },
+                
+                None => {
+                    for pass in in_stage.iter() {
+                        if pass.optional && (pass.enabled)(options) {
+                            passes.push((*pass).clone());
+                        }
+                    }
+                },
+            }
+        }
+        
+        PassManager {
+            passes : passes,
+            times : Vec::new(),
+        }
+    }
+    
+    // Runs the passes
+    pub fn run(&mut self, file : &LtacFile, options : &Options) -> Result<LtacFile, ()> {
+        let mut file2 = file.clone();
+        self.times.clear();
+        
+        for pass in self.passes.iter() {
+            let start = Instant::now();
+            file2 = (pass.run)(&file2, options)?;
+            self.times.push((pass.name, start.elapsed()));
+            
+            if options.print_after.iter().any(|p| p == "all" || p == pass.name) {
+                println!("# After {} ({})", pass.name, file2.name);
+                print!("{}", ltac::to_text(&file2));
+                println!("");
+            }
+            
+            verify_pass(&file2, pass.name)?;
+        }
+        
+        if options.time_passes {
+            self.print_times(&file2.name);
+        }
+        
+        Ok(file2)
+    }
+    
+    pub fn print_times(&self, name : &String) {
+        let mut total = Duration::new(0, 0);
+        
+        println!("Pass times for {}:", name);
+        
+        for (pass, time) in self.times.iter() {
+            println!("  {:<10} {:>10.3} ms", pass, time.as_secs_f64() * 1000.0);
+            total += *time;
+        }
+        
+        println!("  {:<10} {:>10.3} ms", "total", total.as_secs_f64() * 1000.0);
+    }
+}
+
+// Checks the output of a pass in debug builds
+fn verify_pass(file : &LtacFile, pass : &str) -> Result<(), ()> {
+    if cfg!(debug_assertions) && ltac::verify(file).is_err() {
+        println!("Error: The {} pass generated invalid code.", pass);
+        return Err(());
+    }
+    
+    Ok(())
+}
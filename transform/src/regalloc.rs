@@ -0,0 +1,1394 @@
+//
+// Copyright 2021 Patrick Flynn
+// This file is part of the Ida compiler.
+// Ida is licensed under the BSD-3 license. See the COPYING file for more information.
+//
+
+// The register allocator
+// The builder keeps every variable in a stack slot, and uses a few numbered registers
+// for each expression. Here, the variables that are only ever read and written whole
+// are moved to registers, and every register is treated as a virtual one. The virtual
+// registers are then given real ones by linear scan, and anything that doesn't fit is
+// spilled to a new stack slot.
+//
+// This runs after the other target passes, so it sees the code the backend gets.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use parser::Arch;
+
+use crate::cfg;
+use crate::ltac;
+use crate::ltac::{LtacFile, LtacInstr, LtacType, LtacArg};
+use crate::pass::Options;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Kind {
+    Int,
+    Flt,
+}
+
+// How an instruction uses a register
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Use,
+    Def,
+    UseDef,
+}
+
+// A register in an instruction
+// The register in a memory offset is only read. The width is in bits.
+struct Operand {
+    arg : usize,
+    kind : Kind,
+    reg : i32,
+    width : i32,
+    role : Role,
+}
+
+impl Operand {
+    fn is_use(&self) -> bool {
+        self.role != Role::Def
+    }
+    
+    fn is_def(&self) -> bool {
+        self.role != Role::Use
+    }
+    
+    // Writing part of a register keeps the rest of it
+    fn is_partial(&self) -> bool {
+        match self.kind {
+            Kind::Int => self.width < 32,
+            Kind::Flt => self.width < 64,
+        }
+    }
+}
+
+// The registers a backend gives us
+// Each one is its number in the LTAC, and whether it is kept across calls. The sizes
+// (in bytes) are the variables that can live in a register and still wrap the same way.
+struct RegClass {
+    int_regs : Vec<(i32, bool)>,
+    flt_regs : Vec<(i32, bool)>,
+    int_sizes : Vec<i32>,
+    flt_sizes : Vec<i32>,
+}
+
+// What we know about the virtual registers in a function
+struct Regs {
+    next : i32,
+    partial : HashSet<i32>,     // A write to part of the register has to keep the rest
+    undefined : HashSet<i32>,   // Read before anything is written to it
+    fixed : HashSet<i32>,       // Holds a spilled value, so it can't be spilled again
+}
+
+// A live range
+// The instruction at index i reads its registers at 2i and writes them at 2i + 1.
+struct Interval {
+    reg : i32,
+    kind : Kind,
+    start : usize,
+    end : usize,
+    weight : u64,
+    calls : bool,
+}
+
+// A basic block, as a range of the function's code
+struct Block {
+    start : usize,
+    end : usize,
+    succ : Vec<usize>,
+    depth : usize,
+}
+
+fn reg_class(arch : &Arch) -> RegClass {
+    match arch {
+        // r11, rbx, r12, and r13, of which rbx, r12, and r13 are callee-saved; rcx and r10 hold arguments
+        // All the vector registers are lost on a call.
+        Arch::X86_64 => RegClass {
+            int_regs : vec![(3, false), (0, true), (4, true), (5, true)],
+            flt_regs : (0 .. 6).map(|r| (r, false)).collect(),
+            int_sizes : vec![1, 2, 4, 8],
+            flt_sizes : vec![4, 8],
+        },
+        
+        // x9-x15 and x19-x28 (callee-saved), and v16-v23
+        // Bytes and shorts are extended after math, but not by a move, so they stay in memory.
+        Arch::AArch64 => RegClass {
+            int_regs : (0 .. 17).map(|r| (r, r >= 7)).collect(),
+            flt_regs : (0 .. 8).map(|r| (r, false)).collect(),
+            int_sizes : vec![4, 8],
+            flt_sizes : vec![4, 8],
+        },
+        
+        // s4-s11 and fs4-fs11, which are all callee-saved
+        // Doubles can't be loaded or stored on RISC-V yet, so they stay in memory too.
+        Arch::Riscv64 => RegClass {
+            int_regs : (0 .. 8).map(|r| (r, true)).collect(),
+            flt_regs : (0 .. 8).map(|r| (r, true)).collect(),
+            int_sizes : vec![4, 8],
+            flt_sizes : vec![4],
+        },
+    }
+}
+
+// The main register allocator
+pub fn allocate_registers(file : &LtacFile, options : &Options) -> Result<LtacFile, ()> {
+    let mut file2 = LtacFile {
+        name : file.name.clone(),
+        data : file.data.clone(),
+        code : Vec::new(),
+    };
+    
+    let class = reg_class(&options.arch);
+    let risc = options.risc_mode || options.arch != Arch::X86_64;
+    let mut func : Vec<LtacInstr> = Vec::new();
+    
+    for instr in file.code.iter() {
+        if instr.instr_type == LtacType::Func && !func.is_empty() {
+            let code = allocate_func(&func, &class, risc)?;
+            file2.code.extend(code);
+            func.clear();
+        }
+        
+        if func.is_empty() && instr.instr_type != LtacType::Func {
+            file2.code.push(instr.clone());
+        } else {
+            func.push(instr.clone());
+        }
+    }
+    
+    if !func.is_empty() {
+        let code = allocate_func(&func, &class, risc)?;
+        file2.code.extend(code);
+    }
+    
+    Ok(file2)
+}
+
+// Allocates the registers for one function
+// The first instruction is the func instruction.
+fn allocate_func(func : &[LtacInstr], class : &RegClass, risc : bool) -> Result<Vec<LtacInstr>, ()> {
+    let start_top = frame_top(func);
+    let mut top = start_top;
+    
+    let mut code = promote(func, class, risc);
+    let mut regs = build_webs(&mut code);
+    
+    while remove_dead(&mut code, &regs) {}
+    
+    loop {
+        let blocks = build_blocks(&code);
+        let live_in = liveness(&code, &blocks, &regs);
+        let intervals = build_intervals(&code, &blocks, &live_in, &regs);
+        
+        let (assigned, spilled) = match linear_scan(&intervals, &build_hints(&code), class, &regs) {
+            Some(result) => result,
+            None => {
+                println!("Error: Ran out of registers in {}.", func[0].name);
+                return Err(());
+            },
+        };
+        
+        if spilled.is_empty() {
+            assign(&mut code, &assigned);
+            break;
+        }
+        
+        spill(&mut code, &spilled, &mut regs, &mut top, risc);
+    }
+    
+    // The spill slots go above the other variables
+    if top > start_top {
+        let mut stack_size = 0;
+        while stack_size < top + 8 {
+            stack_size += 16;
+        }
+        
+        code[0].arg1_val = code[0].arg1_val.max(stack_size);
+        code[0].arg2_val = top;
+    }
+    
+    Ok(code)
+}
+
+//=====================================
+// Instruction info
+
+fn is_load(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::LdB | LtacType::LdUB | LtacType::LdW | LtacType::LdUW
+        | LtacType::Ld | LtacType::LdU | LtacType::LdQ | LtacType::LdUQ
+        | LtacType::LdF32 | LtacType::LdF64 => true,
+        _ => false,
+    }
+}
+
+fn is_store(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::StrB | LtacType::StrUB | LtacType::StrW | LtacType::StrUW
+        | LtacType::Str | LtacType::StrU | LtacType::StrQ | LtacType::StrUQ
+        | LtacType::StrF32 | LtacType::StrF64 | LtacType::StrPtr => true,
+        _ => false,
+    }
+}
+
+fn is_move(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::MovB | LtacType::MovUB | LtacType::MovW | LtacType::MovUW
+        | LtacType::Mov | LtacType::MovU | LtacType::MovQ | LtacType::MovUQ
+        | LtacType::MovF32 | LtacType::MovF64 => true,
+        _ => false,
+    }
+}
+
+fn is_ldarg(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::LdArgI8 | LtacType::LdArgU8 | LtacType::LdArgI16 | LtacType::LdArgU16
+        | LtacType::LdArgI32 | LtacType::LdArgU32 | LtacType::LdArgI64 | LtacType::LdArgU64
+        | LtacType::LdArgF32 | LtacType::LdArgF64 | LtacType::LdArgPtr => true,
+        _ => false,
+    }
+}
+
+fn is_cmp(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::I8Cmp | LtacType::U8Cmp | LtacType::I16Cmp | LtacType::U16Cmp
+        | LtacType::I32Cmp | LtacType::U32Cmp | LtacType::I64Cmp | LtacType::U64Cmp
+        | LtacType::F32Cmp | LtacType::F64Cmp => true,
+        _ => false,
+    }
+}
+
+fn is_bitwise(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::And | LtacType::Or | LtacType::Xor | LtacType::Lsh | LtacType::Rsh => true,
+        _ => false,
+    }
+}
+
+fn is_cvt(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::CvtF32F64 | LtacType::CvtF64F32 | LtacType::CvtIntF32 | LtacType::CvtIntF64
+        | LtacType::CvtF32Int | LtacType::CvtF64Int | LtacType::MovF64Int => true,
+        _ => false,
+    }
+}
+
+// Returns true if registers other than ours might be lost
+fn is_call(instr_type : &LtacType) -> bool {
+    match instr_type {
+        LtacType::Call | LtacType::CallReg | LtacType::Syscall | LtacType::StrCmp
+        | LtacType::Malloc | LtacType::Calloc | LtacType::Free | LtacType::Resize
+        | LtacType::Exit => true,
+        _ => false,
+    }
+}
+
+fn is_pusharg(instr_type : &LtacType) -> bool {
+    *instr_type == LtacType::PushArg || *instr_type == LtacType::KPushArg
+}
+
+fn is_mem(arg : &LtacArg) -> bool {
+    match arg {
+        LtacArg::Mem(_) | LtacArg::MemOffsetImm(..) | LtacArg::MemOffsetMem(..)
+        | LtacArg::MemOffsetReg(..) | LtacArg::MemOffsetStride(..) | LtacArg::Ptr(_) => true,
+        _ => false,
+    }
+}
+
+// Returns the kind and size of a typed math or compare instruction
+fn op_size(instr_type : &LtacType) -> Option<(Kind, i32)> {
+    match instr_type {
+        LtacType::I8Add | LtacType::U8Add | LtacType::I8Sub | LtacType::I8Mul | LtacType::U8Mul
+        | LtacType::I8Div | LtacType::U8Div | LtacType::I8Mod | LtacType::U8Mod
+        | LtacType::I8Cmp | LtacType::U8Cmp => Some((Kind::Int, 1)),
+        
+        LtacType::I16Add | LtacType::U16Add | LtacType::I16Sub | LtacType::I16Mul | LtacType::U16Mul
+        | LtacType::I16Div | LtacType::U16Div | LtacType::I16Mod | LtacType::U16Mod
+        | LtacType::I16Cmp | LtacType::U16Cmp => Some((Kind::Int, 2)),
+        
+        LtacType::I32Add | LtacType::U32Add | LtacType::I32Sub | LtacType::I32Mul | LtacType::U32Mul
+        | LtacType::I32Div | LtacType::U32Div | LtacType::I32Mod | LtacType::U32Mod
+        | LtacType::I32Cmp | LtacType::U32Cmp => Some((Kind::Int, 4)),
+        
+        LtacType::I64Add | LtacType::U64Add | LtacType::I64Sub | LtacType::I64Mul | LtacType::U64Mul
+        | LtacType::I64Div | LtacType::U64Div | LtacType::I64Mod | LtacType::U64Mod
+        | LtacType::I64Cmp | LtacType::U64Cmp => Some((Kind::Int, 8)),
+        
+        LtacType::F32Add | LtacType::F32Sub | LtacType::F32Mul | LtacType::F32Div
+        | LtacType::F32Cmp => Some((Kind::Flt, 4)),
+        
+        LtacType::F64Add | LtacType::F64Sub | LtacType::F64Mul | LtacType::F64Div
+        | LtacType::F64Cmp => Some((Kind::Flt, 8)),
+        
+        _ => None,
+    }
+}
+
+// Returns the kind and size of the memory a load, store, or move uses
+fn mem_size(instr_type : &LtacType) -> Option<(Kind, i32)> {
+    match instr_type {
+        LtacType::LdB | LtacType::LdUB | LtacType::StrB | LtacType::StrUB
+        | LtacType::MovB | LtacType::MovUB | LtacType::LdArgI8 | LtacType::LdArgU8 => Some((Kind::Int, 1)),
+        
+        LtacType::LdW | LtacType::LdUW | LtacType::StrW | LtacType::StrUW
+        | LtacType::MovW | LtacType::MovUW | LtacType::LdArgI16 | LtacType::LdArgU16 => Some((Kind::Int, 2)),
+        
+        LtacType::Ld | LtacType::LdU | LtacType::Str | LtacType::StrU
+        | LtacType::Mov | LtacType::MovU | LtacType::LdArgI32 | LtacType::LdArgU32 => Some((Kind::Int, 4)),
+        
+        LtacType::LdQ | LtacType::LdUQ | LtacType::StrQ | LtacType::StrUQ | LtacType::StrPtr
+        | LtacType::MovQ | LtacType::MovUQ | LtacType::LdArgI64 | LtacType::LdArgU64
+        | LtacType::LdArgPtr => Some((Kind::Int, 8)),
+        
+        LtacType::LdF32 | LtacType::StrF32 | LtacType::MovF32 | LtacType::LdArgF32 => Some((Kind::Flt, 4)),
+        LtacType::LdF64 | LtacType::StrF64 | LtacType::MovF64 | LtacType::LdArgF64 => Some((Kind::Flt, 8)),
+        
+        _ => None,
+    }
+}
+
+// Returns the kind and size of a register, literal, or address
+fn arg_size(arg : &LtacArg) -> Option<(Kind, i32)> {
+    match arg {
+        LtacArg::Reg8(_) | LtacArg::RetRegI8 | LtacArg::RetRegU8
+        | LtacArg::Byte(_) | LtacArg::UByte(_) => Some((Kind::Int, 1)),
+        
+        LtacArg::Reg16(_) | LtacArg::RetRegI16 | LtacArg::RetRegU16
+        | LtacArg::I16(_) | LtacArg::U16(_) => Some((Kind::Int, 2)),
+        
+        LtacArg::Reg32(_) | LtacArg::RetRegI32 | LtacArg::RetRegU32
+        | LtacArg::I32(_) | LtacArg::U32(_) => Some((Kind::Int, 4)),
+        
+        LtacArg::Reg64(_) | LtacArg::RetRegI64 | LtacArg::RetRegU64
+        | LtacArg::I64(_) | LtacArg::U64(_)
+        | LtacArg::PtrLcl(_) | LtacArg::PtrFunc(_) => Some((Kind::Int, 8)),
+        
+        LtacArg::FltReg(_) | LtacArg::RetRegF32 | LtacArg::F32(_) => Some((Kind::Flt, 4)),
+        LtacArg::FltReg64(_) | LtacArg::RetRegF64 | LtacArg::F64(_) => Some((Kind::Flt, 8)),
+        
+        _ => None,
+    }
+}
+
+// Returns how an instruction uses its two arguments
+fn roles(instr : &LtacInstr) -> (Role, Role) {
+    let instr_type = &instr.instr_type;
+    
+    if is_load(instr_type) && is_mem(&instr.arg1) {
+        (Role::Use, Role::Def)
+    } else if is_load(instr_type) || is_move(instr_type) || is_ldarg(instr_type) || is_cvt(instr_type)
+        || *instr_type == LtacType::LdAddr || *instr_type == LtacType::Pop {
+        (Role::Def, Role::Use)
+    } else if is_cmp(instr_type) || is_store(instr_type) || is_pusharg(instr_type)
+        || *instr_type == LtacType::CallReg || *instr_type == LtacType::Push {
+        (Role::Use, Role::Use)
+    } else if op_size(instr_type).is_some() || is_bitwise(instr_type) || *instr_type == LtacType::I32VAdd {
+        (Role::UseDef, Role::Use)
+    } else {
+        (Role::UseDef, Role::UseDef)
+    }
+}
+
+// Returns the registers in an instruction
+// The second argument of pusharg is only a hint about the type.
+fn operands(instr : &LtacInstr) -> Vec<Operand> {
+    let (role1, role2) = roles(instr);
+    let mut args = vec![(1, &instr.arg1, role1)];
+    if !is_pusharg(&instr.instr_type) {
+        args.push((2, &instr.arg2, role2));
+    }
+    
+    let mut ops : Vec<Operand> = Vec::new();
+    
+    for (n, arg, role) in args {
+        let (kind, reg, width) = match arg {
+            LtacArg::Reg8(reg) => (Kind::Int, *reg, 8),
+            LtacArg::Reg16(reg) => (Kind::Int, *reg, 16),
+            LtacArg::Reg32(reg) => (Kind::Int, *reg, 32),
+            LtacArg::Reg64(reg) => (Kind::Int, *reg, 64),
+            LtacArg::FltReg(reg) => (Kind::Flt, *reg, 32),
+            LtacArg::FltReg64(reg) => (Kind::Flt, *reg, 64),
+            
+            LtacArg::MemOffsetReg(_, reg, _)
+            | LtacArg::MemOffsetStride(_, _, reg, _, _) => {
+                ops.push(Operand { arg : n, kind : Kind::Int, reg : *reg, width : 64, role : Role::Use });
+                continue;
+            },
+            
+            _ => continue,
+        };
+        
+        ops.push(Operand { arg : n, kind : kind, reg : reg, width : width, role : role });
+    }
+    
+    ops
+}
+
+// Changes the register in an argument
+fn set_reg(instr : &mut LtacInstr, n : usize, reg : i32) {
+    let arg = if n == 1 { &mut instr.arg1 } else { &mut instr.arg2 };
+    
+    *arg = match arg {
+        LtacArg::Reg8(_) => LtacArg::Reg8(reg),
+        LtacArg::Reg16(_) => LtacArg::Reg16(reg),
+        LtacArg::Reg32(_) => LtacArg::Reg32(reg),
+        LtacArg::Reg64(_) => LtacArg::Reg64(reg),
+        LtacArg::FltReg(_) => LtacArg::FltReg(reg),
+        LtacArg::FltReg64(_) => LtacArg::FltReg64(reg),
+        LtacArg::MemOffsetReg(pos, _, size) => LtacArg::MemOffsetReg(*pos, reg, *size),
+        LtacArg::MemOffsetStride(pos, row, _, stride, size) => LtacArg::MemOffsetStride(*pos, *row, reg, *stride, *size),
+        _ => return,
+    };
+}
+
+fn reg_arg(kind : Kind, size : i32, reg : i32) -> LtacArg {
+    match (kind, size) {
+        (Kind::Int, 1) => LtacArg::Reg8(reg),
+        (Kind::Int, 2) => LtacArg::Reg16(reg),
+        (Kind::Int, 4) => LtacArg::Reg32(reg),
+        (Kind::Int, _) => LtacArg::Reg64(reg),
+        (Kind::Flt, 4) => LtacArg::FltReg(reg),
+        (Kind::Flt, _) => LtacArg::FltReg64(reg),
+    }
+}
+
+// Builds a load
+// Before the RISC pass, loads put the register first.
+fn build_load(instr_type : LtacType, reg : LtacArg, pos : i32, risc : bool) -> LtacInstr {
+    let mut load = ltac::create_instr(instr_type);
+    
+    if risc {
+        load.arg1 = LtacArg::Mem(pos);
+        load.arg2 = reg;
+    } else {
+        load.arg1 = reg;
+        load.arg2 = LtacArg::Mem(pos);
+    }
+    
+    load
+}
+
+//=====================================
+// Moving variables to registers
+
+// Returns the highest stack position a function uses
+fn frame_top(code : &[LtacInstr]) -> i32 {
+    let mut top = code[0].arg2_val;
+    
+    for instr in code.iter() {
+        for arg in [&instr.arg1, &instr.arg2].iter() {
+            match arg {
+                LtacArg::Mem(pos) | LtacArg::Ptr(pos)
+                | LtacArg::MemOffsetImm(pos, _) | LtacArg::MemOffsetReg(pos, _, _) => top = top.max(*pos),
+                LtacArg::MemOffsetMem(pos, var, _) => top = top.max(*pos).max(*var),
+                LtacArg::MemOffsetStride(pos, row, _, _, _) => top = top.max(*pos).max(*row),
+                _ => {},
+            }
+        }
+    }
+    
+    (top + 7) / 8 * 8
+}
+
+// Works out how an instruction uses a variable in one of its arguments
+// This is the kind and size it reads or writes it as, or None if the variable has to
+// stay in memory.
+fn slot_access(instr : &LtacInstr, n : usize) -> Option<(Kind, i32)> {
+    let other = if n == 1 { &instr.arg2 } else { &instr.arg1 };
+    let instr_type = &instr.instr_type;
+    
+    if is_load(instr_type) || is_store(instr_type) || is_move(instr_type) {
+        let access = mem_size(instr_type)?;
+        if arg_size(other) != Some(access) {
+            return None;
+        }
+        
+        return Some(access);
+    }
+    
+    if let Some(access) = op_size(instr_type) {
+        if arg_size(other) != Some(access) {
+            return None;
+        }
+        
+        return Some(access);
+    }
+    
+    match instr_type {
+        // The shift count has to be in a register or a literal
+        LtacType::And | LtacType::Or | LtacType::Xor => {
+            match arg_size(other) {
+                Some((Kind::Int, size)) => Some((Kind::Int, size)),
+                _ => None,
+            }
+        },
+        
+        LtacType::Lsh | LtacType::Rsh if n == 1 => {
+            match other {
+                LtacArg::Byte(_) | LtacArg::UByte(_) | LtacArg::I16(_) | LtacArg::U16(_)
+                | LtacArg::I32(_) | LtacArg::U32(_) | LtacArg::I64(_) | LtacArg::U64(_) => arg_size(other),
+                _ => None,
+            }
+        },
+        
+        LtacType::CvtF32F64 | LtacType::CvtF32Int if n == 2 => Some((Kind::Flt, 4)),
+        LtacType::CvtF64F32 | LtacType::CvtF64Int if n == 2 => Some((Kind::Flt, 8)),
+        
+        // The size of an argument comes from the type hint
+        LtacType::PushArg | LtacType::KPushArg if n == 1 => {
+            match instr.arg2 {
+                LtacArg::FltReg(_) => Some((Kind::Flt, 4)),
+                LtacArg::FltReg64(_) => Some((Kind::Flt, 8)),
+                LtacArg::Byte(_) | LtacArg::UByte(_) => Some((Kind::Int, 1)),
+                LtacArg::I16(_) | LtacArg::U16(_) => Some((Kind::Int, 2)),
+                LtacArg::I64(_) | LtacArg::U64(_) => Some((Kind::Int, 8)),
+                _ => Some((Kind::Int, 4)),
+            }
+        },
+        
+        LtacType::CallReg if n == 1 => Some((Kind::Int, 8)),
+        
+        _ if is_ldarg(instr_type) && n == 1 => mem_size(instr_type),
+        
+        _ => None,
+    }
+}
+
+// Moves the variables that can live in registers out of memory
+// A variable can if its address is never taken, and every instruction that uses it
+// reads or writes the whole thing the same way. Arguments are still written to their
+// slot, and loaded once after that.
+fn promote(code : &[LtacInstr], class : &RegClass, risc : bool) -> Vec<LtacInstr> {
+    if code.iter().any(|instr| instr.instr_type == LtacType::LdAddr) {
+        return code.to_vec();
+    }
+    
+    // Find how each slot is used, and the memory everything touches
+    let mut slots : BTreeMap<i32, Option<(Kind, i32)>> = BTreeMap::new();
+    let mut ranges : Vec<(i32, i32)> = Vec::new();
+    
+    for instr in code.iter() {
+        for n in 1 .. 3 {
+            let arg = if n == 1 { &instr.arg1 } else { &instr.arg2 };
+            
+            // The pointers and the row of a 2D array stay in memory
+            // The variable with the index of an array is read as an int.
+            let accesses = match arg {
+                LtacArg::Mem(pos) => {
+                    let access = slot_access(instr, n);
+                    let size = match (access, mem_size(&instr.instr_type)) {
+                        (Some((_, size)), _) | (None, Some((_, size))) => size,
+                        (None, None) => 8,
+                    };
+                    
+                    vec![(*pos, access, size)]
+                },
+                
+                LtacArg::MemOffsetMem(pos, var, _) => vec![(*pos, None, 8), (*var, Some((Kind::Int, 4)), 4)],
+                LtacArg::MemOffsetStride(pos, row, _, _, _) => vec![(*pos, None, 8), (*row, None, 4)],
+                
+                LtacArg::MemOffsetImm(pos, _) | LtacArg::MemOffsetReg(pos, _, _)
+                | LtacArg::Ptr(pos) => vec![(*pos, None, 8)],
+                
+                _ => continue,
+            };
+            
+            for (pos, access, size) in accesses {
+                ranges.push((pos, size));
+                
+                let slot = slots.entry(pos).or_insert(access);
+                if *slot != access {
+                    *slot = None;
+                }
+            }
+        }
+    }
+    
+    // Give the ones we can keep a virtual register
+    let mut next = code.iter()
+        .flat_map(operands)
+        .map(|op| op.reg)
+        .max()
+        .unwrap_or(0) + 1;
+    
+    let mut vregs : HashMap<i32, LtacArg> = HashMap::new();
+    
+    for (pos, access) in slots.iter() {
+        let (kind, size) = match access {
+            Some(access) => *access,
+            None => continue,
+        };
+        
+        let sizes = if kind == Kind::Int { &class.int_sizes } else { &class.flt_sizes };
+        if !sizes.contains(&size) {
+            continue;
+        }
+        
+        // Nothing else can touch the same memory
+        let overlaps = ranges.iter()
+            .any(|(p, s)| *p != *pos && -*p < -*pos + size && -*pos < -*p + *s);
+        
+        if overlaps {
+            continue;
+        }
+        
+        vregs.insert(*pos, reg_arg(kind, size, next));
+        next += 1;
+    }
+    
+    if vregs.is_empty() {
+        return code.to_vec();
+    }
+    
+    // Rewrite the code
+    let mut code2 : Vec<LtacInstr> = Vec::new();
+    let mut loads : Vec<LtacInstr> = Vec::new();
+    
+    for instr in code.iter() {
+        let mut instr2 = instr.clone();
+        let instr_type = instr.instr_type.clone();
+        
+        if is_ldarg(&instr_type) {
+            if let LtacArg::Mem(pos) = instr.arg1 {
+                if let Some(reg) = vregs.get(&pos) {
+                    loads.push(build_load(load_for_arg(&instr_type), reg.clone(), pos, risc));
+                }
+            }
+            
+            code2.push(instr2);
+            continue;
+        }
+        
+        if instr_type != LtacType::Func {
+            code2.append(&mut loads);
+        }
+        
+        let vreg = |arg : &LtacArg| -> Option<LtacArg> {
+            match arg {
+                LtacArg::Mem(pos) => vregs.get(pos).cloned(),
+                _ => None,
+            }
+        };
+        
+        // The index of an array can come from a register instead
+        for n in 1 .. 3 {
+            let arg = if n == 1 { &mut instr2.arg1 } else { &mut instr2.arg2 };
+            
+            if let LtacArg::MemOffsetMem(pos, var, size) = arg.clone() {
+                if let Some(LtacArg::Reg32(reg)) = vregs.get(&var) {
+                    *arg = LtacArg::MemOffsetReg(pos, *reg, size);
+                }
+            }
+        }
+        
+        if is_load(&instr_type) {
+            let (mem, reg) = if is_mem(&instr2.arg1) { (&instr2.arg1, &instr2.arg2) } else { (&instr2.arg2, &instr2.arg1) };
+            
+            if let Some(vreg) = vreg(mem) {
+                let reg = reg.clone();
+                instr2.instr_type = mov_for_load(&instr_type);
+                instr2.arg1 = reg;
+                instr2.arg2 = vreg;
+            }
+        } else if is_store(&instr_type) {
+            if let Some(vreg) = vreg(&instr2.arg1) {
+                instr2.instr_type = mov_for_store(&instr_type);
+                instr2.arg1 = vreg;
+            }
+        } else {
+            for n in 1 .. 3 {
+                let arg = if n == 1 { &mut instr2.arg1 } else { &mut instr2.arg2 };
+                
+                if let Some(vreg) = vreg(arg) {
+                    *arg = vreg;
+                }
+            }
+        }
+        
+        code2.push(instr2);
+    }
+    
+    code2.append(&mut loads);
+    code2
+}
+
+fn mov_for_load(instr_type : &LtacType) -> LtacType {
+    match instr_type {
+        LtacType::LdB => LtacType::MovB,
+        LtacType::LdUB => LtacType::MovUB,
+        LtacType::LdW => LtacType::MovW,
+        LtacType::LdUW => LtacType::MovUW,
+        LtacType::LdU => LtacType::MovU,
+        LtacType::LdQ => LtacType::MovQ,
+        LtacType::LdUQ => LtacType::MovUQ,
+        LtacType::LdF32 => LtacType::MovF32,
+        LtacType::LdF64 => LtacType::MovF64,
+        _ => LtacType::Mov,
+    }
+}
+
+fn mov_for_store(instr_type : &LtacType) -> LtacType {
+    match instr_type {
+        LtacType::StrB => LtacType::MovB,
+        LtacType::StrUB => LtacType::MovUB,
+        LtacType::StrW => LtacType::MovW,
+        LtacType::StrUW => LtacType::MovUW,
+        LtacType::StrU => LtacType::MovU,
+        LtacType::StrQ | LtacType::StrPtr => LtacType::MovQ,
+        LtacType::StrUQ => LtacType::MovUQ,
+        LtacType::StrF32 => LtacType::MovF32,
+        LtacType::StrF64 => LtacType::MovF64,
+        _ => LtacType::Mov,
+    }
+}
+
+fn load_for_arg(instr_type : &LtacType) -> LtacType {
+    match instr_type {
+        LtacType::LdArgI8 => LtacType::LdB,
+        LtacType::LdArgU8 => LtacType::LdUB,
+        LtacType::LdArgI16 => LtacType::LdW,
+        LtacType::LdArgU16 => LtacType::LdUW,
+        LtacType::LdArgU32 => LtacType::LdU,
+        LtacType::LdArgI64 | LtacType::LdArgPtr => LtacType::LdQ,
+        LtacType::LdArgU64 => LtacType::LdUQ,
+        LtacType::LdArgF32 => LtacType::LdF32,
+        LtacType::LdArgF64 => LtacType::LdF64,
+        _ => LtacType::Ld,
+    }
+}
+
+//=====================================
+// Webs and liveness
+
+// Splits a function into blocks
+fn build_blocks(code : &[LtacInstr]) -> Vec<Block> {
+    let file = LtacFile {
+        name : String::new(),
+        data : Vec::new(),
+        code : code.to_vec(),
+    };
+    
+    let graph = cfg::build(&file);
+    let mut blocks : Vec<Block> = Vec::new();
+    let mut start = 0;
+    
+    if let Some(func) = graph.funcs.first() {
+        for (i, block) in func.blocks.iter().enumerate() {
+            blocks.push(Block {
+                start : start,
+                end : start + block.code.len(),
+                succ : block.succ.clone(),
+                depth : func.loop_depth(i),
+            });
+            
+            start += block.code.len();
+        }
+    }
+    
+    blocks
+}
+
+// Gives each value its own virtual register
+// Each write to a register is joined with the reads it reaches, and writes that reach
+// the same read are joined, so each web can go in any register. Writing part of a
+// register keeps the rest, so a read that is wider than the write it reaches also
+// reaches the writes before that.
+fn build_webs(code : &mut [LtacInstr]) -> Regs {
+    type Defs = BTreeMap<(Kind, i32), BTreeSet<usize>>;
+    
+    let blocks = build_blocks(code);
+    let ops : Vec<Vec<Operand>> = code.iter().map(operands).collect();
+    
+    // Number the operands
+    // The entry has a definition of each register, for the ones read before they are written.
+    let mut first : Vec<usize> = Vec::new();
+    let mut count = 0;
+    
+    for instr_ops in ops.iter() {
+        first.push(count);
+        count += instr_ops.len();
+    }
+    
+    let mut entry : Defs = Defs::new();
+    for op in ops.iter().flatten() {
+        entry.entry((op.kind, op.reg)).or_insert_with(|| {
+            count += 1;
+            [count - 1].iter().cloned().collect()
+        });
+    }
+    
+    // Find the definitions that reach each block
+    let mut preds : Vec<Vec<usize>> = vec![Vec::new(); blocks.len()];
+    for (i, block) in blocks.iter().enumerate() {
+        for succ in block.succ.iter() {
+            preds[*succ].push(i);
+        }
+    }
+    
+    let mut reach_in : Vec<Defs> = vec![Defs::new(); blocks.len()];
+    let mut reach_out : Vec<Defs> = vec![Defs::new(); blocks.len()];
+    let mut changed = true;
+    
+    while changed {
+        changed = false;
+        
+        for (i, block) in blocks.iter().enumerate() {
+            let mut defs = if i == 0 { entry.clone() } else { Defs::new() };
+            for pred in preds[i].iter() {
+                for (key, set) in reach_out[*pred].iter() {
+                    defs.entry(*key).or_insert_with(BTreeSet::new).extend(set.iter().cloned());
+                }
+            }
+            
+            reach_in[i] = defs.clone();
+            
+            for k in block.start .. block.end {
+                for (j, op) in ops[k].iter().enumerate() {
+                    if op.is_def() {
+                        defs.insert((op.kind, op.reg), [first[k] + j].iter().cloned().collect());
+                    }
+                }
+            }
+            
+            if defs != reach_out[i] {
+                reach_out[i] = defs;
+                changed = true;
+            }
+        }
+    }
+    
+    // Find the definitions that reach each read, and the ones each definition hides
+    let mut uses : Vec<(usize, Vec<usize>, i32)> = Vec::new();
+    let mut prior : Vec<Vec<usize>> = vec![Vec::new(); count];
+    let mut partial : Vec<Option<i32>> = vec![None; count];
+    let mut is_def = vec![false; count];
+    
+    for (i, block) in blocks.iter().enumerate() {
+        let mut defs = reach_in[i].clone();
+        
+        for k in block.start .. block.end {
+            for (j, op) in ops[k].iter().enumerate() {
+                if op.is_use() {
+                    let reaching = match defs.get(&(op.kind, op.reg)) {
+                        Some(set) => set.iter().cloned().collect(),
+                        None => Vec::new(),
+                    };
+                    
+                    uses.push((first[k] + j, reaching, op.width));
+                }
+            }
+            
+            for (j, op) in ops[k].iter().enumerate() {
+                if op.is_def() {
+                    let e = first[k] + j;
+                    
+                    if let Some(set) = defs.get(&(op.kind, op.reg)) {
+                        prior[e] = set.iter().cloned().collect();
+                    }
+                    
+                    if op.is_partial() {
+                        partial[e] = Some(op.width);
+                    }
+                    
+                    is_def[e] = true;
+                    defs.insert((op.kind, op.reg), [e].iter().cloned().collect());
+                }
+            }
+        }
+    }
+    
+    // Join them
+    let mut parent : Vec<usize> = (0 .. count).collect();
+    let mut chained = vec![false; count];
+    
+    for (e, reaching, width) in uses.iter() {
+        let mut stack = reaching.clone();
+        let mut seen : HashSet<usize> = HashSet::new();
+        
+        while let Some(d) = stack.pop() {
+            if !seen.insert(d) {
+                continue;
+            }
+            
+            union(&mut parent, *e, d);
+            
+            if let Some(def_width) = partial[d] {
+                if def_width < *width {
+                    chained[d] = true;
+                    stack.extend(prior[d].iter().cloned());
+                }
+            }
+        }
+    }
+    
+    let mut has_def = vec![false; count];
+    let mut has_chain = vec![false; count];
+    
+    for e in 0 .. count {
+        let root = find(&mut parent, e);
+        has_def[root] |= is_def[e];
+        has_chain[root] |= chained[e];
+    }
+    
+    // Number the webs
+    // A web that is never written can read anything, so each read gets its own register.
+    let mut regs = Regs {
+        next : 0,
+        partial : HashSet::new(),
+        undefined : HashSet::new(),
+        fixed : HashSet::new(),
+    };
+    
+    let mut names : HashMap<usize, i32> = HashMap::new();
+    
+    for k in 0 .. code.len() {
+        let mut fresh : HashMap<usize, i32> = HashMap::new();
+        
+        for (j, op) in ops[k].iter().enumerate() {
+            let root = find(&mut parent, first[k] + j);
+            
+            let reg = if has_def[root] {
+                match names.get(&root) {
+                    Some(reg) => *reg,
+                    None => {
+                        names.insert(root, regs.next);
+                        regs.next += 1;
+                        regs.next - 1
+                    },
+                }
+            } else {
+                match fresh.get(&root) {
+                    Some(reg) => *reg,
+                    None => {
+                        fresh.insert(root, regs.next);
+                        regs.undefined.insert(regs.next);
+                        regs.next += 1;
+                        regs.next - 1
+                    },
+                }
+            };
+            
+            if has_chain[root] {
+                regs.partial.insert(reg);
+            }
+            
+            set_reg(&mut code[k], op.arg, reg);
+        }
+    }
+    
+    regs
+}
+
+fn find(parent : &mut [usize], e : usize) -> usize {
+    let mut root = e;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    
+    let mut current = e;
+    while parent[current] != root {
+        let next = parent[current];
+        parent[current] = root;
+        current = next;
+    }
+    
+    root
+}
+
+fn union(parent : &mut [usize], a : usize, b : usize) {
+    let a = find(parent, a);
+    let b = find(parent, b);
+    
+    if a != b {
+        parent[a.max(b)] = a.min(b);
+    }
+}
+
+// Returns the registers an instruction reads and writes, for the liveness
+// A register that is never written doesn't need to live anywhere but where it is read.
+fn uses_defs(instr : &LtacInstr, regs : &Regs) -> (Vec<i32>, Vec<i32>) {
+    let mut uses : Vec<i32> = Vec::new();
+    let mut defs : Vec<i32> = Vec::new();
+    
+    for op in operands(instr) {
+        if regs.undefined.contains(&op.reg) {
+            continue;
+        }
+        
+        if op.is_use() || (op.is_partial() && regs.partial.contains(&op.reg)) {
+            uses.push(op.reg);
+        }
+        
+        if op.is_def() {
+            defs.push(op.reg);
+        }
+    }
+    
+    (uses, defs)
+}
+
+// Updates the live registers going backwards over an instruction
+fn update_live(instr : &LtacInstr, regs : &Regs, live : &mut HashSet<i32>) {
+    let (uses, defs) = uses_defs(instr, regs);
+    
+    for reg in defs.iter() {
+        live.remove(reg);
+    }
+    
+    live.extend(uses.iter());
+}
+
+fn live_out(blocks : &[Block], live_in : &[HashSet<i32>], block : usize) -> HashSet<i32> {
+    let mut live : HashSet<i32> = HashSet::new();
+    
+    for succ in blocks[block].succ.iter() {
+        live.extend(live_in[*succ].iter());
+    }
+    
+    live
+}
+
+// Works out the registers that are live at the start of each block
+fn liveness(code : &[LtacInstr], blocks : &[Block], regs : &Regs) -> Vec<HashSet<i32>> {
+    let mut live_in : Vec<HashSet<i32>> = vec![HashSet::new(); blocks.len()];
+    let mut changed = true;
+    
+    while changed {
+        changed = false;
+        
+        for i in (0 .. blocks.len()).rev() {
+            let mut live = live_out(blocks, &live_in, i);
+            
+            for k in (blocks[i].start .. blocks[i].end).rev() {
+                update_live(&code[k], regs, &mut live);
+            }
+            
+            if live != live_in[i] {
+                live_in[i] = live;
+                changed = true;
+            }
+        }
+    }
+    
+    live_in
+}
+
+// Returns true if an instruction does nothing but write its registers
+fn is_pure(instr : &LtacInstr) -> bool {
+    if !instr.name.is_empty() {
+        return false;
+    }
+    
+    match instr.instr_type {
+        LtacType::I8Add | LtacType::U8Add | LtacType::I8Sub | LtacType::I8Mul | LtacType::U8Mul
+        | LtacType::I16Add | LtacType::U16Add | LtacType::I16Sub | LtacType::I16Mul | LtacType::U16Mul
+        | LtacType::I32Add | LtacType::U32Add | LtacType::I32Sub | LtacType::I32Mul | LtacType::U32Mul
+        | LtacType::I64Add | LtacType::U64Add | LtacType::I64Sub | LtacType::I64Mul | LtacType::U64Mul
+        | LtacType::F32Add | LtacType::F32Sub | LtacType::F32Mul | LtacType::F32Div
+        | LtacType::F64Add | LtacType::F64Sub | LtacType::F64Mul | LtacType::F64Div
+        | LtacType::LdAddr => true,
+        
+        ref instr_type => is_load(instr_type) || is_move(instr_type) || is_bitwise(instr_type) || is_cvt(instr_type),
+    }
+}
+
+// Removes the instructions that write a register nobody reads
+// The builder loads things it never uses, such as the register it saves around a call.
+fn remove_dead(code : &mut Vec<LtacInstr>, regs : &Regs) -> bool {
+    let blocks = build_blocks(code);
+    let live_in = liveness(code, &blocks, regs);
+    let mut dead = vec![false; code.len()];
+    
+    for i in 0 .. blocks.len() {
+        let mut live = live_out(&blocks, &live_in, i);
+        
+        for k in (blocks[i].start .. blocks[i].end).rev() {
+            if is_pure(&code[k]) {
+                let (_, defs) = uses_defs(&code[k], regs);
+                
+                if !defs.is_empty() && defs.iter().all(|reg| !live.contains(reg)) {
+                    dead[k] = true;
+                    continue;
+                }
+            }
+            
+            update_live(&code[k], regs, &mut live);
+        }
+    }
+    
+    if !dead.iter().any(|d| *d) {
+        return false;
+    }
+    
+    let mut index = 0;
+    code.retain(|_| {
+        index += 1;
+        !dead[index - 1]
+    });
+    
+    true
+}
+
+//=====================================
+// Allocation
+
+// Works out the live range of each register
+// The ranges don't have holes; a register is live from the first point it is live to the last.
+fn build_intervals(code : &[LtacInstr], blocks : &[Block], live_in : &[HashSet<i32>], regs : &Regs) -> Vec<Interval> {
+    let mut intervals : BTreeMap<i32, Interval> = BTreeMap::new();
+    
+    for block in blocks.iter() {
+        let weight = 10u64.pow(block.depth.min(6) as u32);
+        
+        for k in block.start .. block.end {
+            for op in operands(&code[k]) {
+                let iv = intervals.entry(op.reg).or_insert(Interval {
+                    reg : op.reg,
+                    kind : op.kind,
+                    start : usize::MAX,
+                    end : 0,
+                    weight : 0,
+                    calls : false,
+                });
+                
+                if op.is_use() || (op.is_partial() && regs.partial.contains(&op.reg)) {
+                    iv.start = iv.start.min(2 * k);
+                    iv.end = iv.end.max(2 * k);
+                }
+                
+                if op.is_def() {
+                    iv.start = iv.start.min(2 * k + 1);
+                    iv.end = iv.end.max(2 * k + 1);
+                }
+                
+                iv.weight += weight;
+            }
+        }
+    }
+    
+    for (i, block) in blocks.iter().enumerate() {
+        if block.start == block.end {
+            continue;
+        }
+        
+        for reg in live_in[i].iter() {
+            if let Some(iv) = intervals.get_mut(reg) {
+                iv.start = iv.start.min(2 * block.start);
+            }
+        }
+        
+        for reg in live_out(blocks, live_in, i).iter() {
+            if let Some(iv) = intervals.get_mut(reg) {
+                iv.end = iv.end.max(2 * block.end - 1);
+            }
+        }
+    }
+    
+    // A register that is live across a call needs one the call keeps
+    for (k, instr) in code.iter().enumerate() {
+        if is_call(&instr.instr_type) {
+            for iv in intervals.values_mut() {
+                if iv.start <= 2 * k && iv.end > 2 * k {
+                    iv.calls = true;
+                }
+            }
+        }
+    }
+    
+    let mut list : Vec<Interval> = intervals.into_values().collect();
+    list.sort_by_key(|iv| (iv.start, iv.reg));
+    list
+}
+
+// Returns the registers each register is moved to or from
+// If one of them gets a register first, we try to give the other the same one so the
+// move goes away.
+fn build_hints(code : &[LtacInstr]) -> HashMap<i32, Vec<i32>> {
+    let mut hints : HashMap<i32, Vec<i32>> = HashMap::new();
+    
+    for instr in code.iter() {
+        if !is_move(&instr.instr_type) {
+            continue;
+        }
+        
+        let ops = operands(instr);
+        if ops.len() == 2 && ops[0].kind == ops[1].kind {
+            hints.entry(ops[0].reg).or_default().push(ops[1].reg);
+            hints.entry(ops[1].reg).or_default().push(ops[0].reg);
+        }
+    }
+    
+    hints
+}
+
+// Gives each live range a register, in the order they start
+// When we run out, the range that is used the least (counting loops as ten times the
+// uses) is spilled. Returns the registers, and the ones that were spilled; if we can't
+// spill anything, returns None.
+fn linear_scan(intervals : &[Interval], hints : &HashMap<i32, Vec<i32>>, class : &RegClass, regs : &Regs)
+        -> Option<(HashMap<i32, i32>, Vec<i32>)> {
+    let mut assigned : HashMap<i32, i32> = HashMap::new();
+    let mut spilled : Vec<i32> = Vec::new();
+    let mut active : Vec<usize> = Vec::new();
+    
+    for (i, iv) in intervals.iter().enumerate() {
+        active.retain(|a| intervals[*a].end >= iv.start);
+        
+        // Caller-saved registers go first, so the others are left for the ranges that need them
+        let pool = if iv.kind == Kind::Int { &class.int_regs } else { &class.flt_regs };
+        let mut allowed : Vec<(i32, bool)> = pool.iter()
+            .filter(|(_, saved)| *saved || !iv.calls)
+            .cloned()
+            .collect();
+        
+        allowed.sort_by_key(|(_, saved)| *saved);
+        
+        let busy : HashSet<i32> = active.iter()
+            .filter(|a| intervals[**a].kind == iv.kind)
+            .map(|a| assigned[&intervals[*a].reg])
+            .collect();
+        
+        let free = |reg : &i32| allowed.iter().any(|(r, _)| r == reg) && !busy.contains(reg);
+        
+        let hint = match hints.get(&iv.reg) {
+            Some(list) => list.iter().filter_map(|h| assigned.get(h)).cloned().find(|reg| free(reg)),
+            None => None,
+        };
+        
+        let reg = match hint {
+            Some(reg) => Some(reg),
+            None => allowed.iter().map(|(r, _)| *r).find(|reg| free(reg)),
+        };
+        
+        if let Some(reg) = reg {
+            assigned.insert(iv.reg, reg);
+            active.push(i);
+            continue;
+        }
+        
+        // Spill the cheapest range; if they cost the same, the one that ends last
+        let cost = |iv : &Interval| (iv.weight, usize::MAX - iv.end, iv.reg);
+        
+        let victim = active.iter()
+            .cloned()
+            .filter(|a| intervals[*a].kind == iv.kind && !regs.fixed.contains(&intervals[*a].reg))
+            .filter(|a| allowed.iter().any(|(r, _)| *r == assigned[&intervals[*a].reg]))
+            .min_by_key(|a| cost(&intervals[*a]));
+        
+        let spill_current = !regs.fixed.contains(&iv.reg) && match victim {
+            Some(v) => cost(iv) <= cost(&intervals[v]),
+            None => true,
+        };
+        
+        if spill_current {
+            spilled.push(iv.reg);
+            continue;
+        }
+        
+        let v = victim?;
+        let reg = assigned.remove(&intervals[v].reg).unwrap();
+        spilled.push(intervals[v].reg);
+        active.retain(|a| *a != v);
+        
+        assigned.insert(iv.reg, reg);
+        active.push(i);
+    }
+    
+    Some((assigned, spilled))
+}
+
+// Moves the spilled registers to new stack slots
+// Each instruction that uses one gets a new register instead, which is loaded before
+// it and stored after it. The whole register is saved, so nothing is lost.
+fn spill(code : &mut Vec<LtacInstr>, spilled : &[i32], regs : &mut Regs, top : &mut i32, risc : bool) {
+    let mut slots : HashMap<i32, i32> = HashMap::new();
+    for reg in spilled.iter() {
+        *top += 8;
+        slots.insert(*reg, *top);
+    }
+    
+    let doubles : HashSet<i32> = code.iter()
+        .flat_map(|instr| [&instr.arg1, &instr.arg2].iter().cloned().cloned().collect::<Vec<LtacArg>>())
+        .filter_map(|arg| match arg {
+            LtacArg::FltReg64(reg) => Some(reg),
+            _ => None,
+        })
+        .collect();
+    
+    let mut code2 : Vec<LtacInstr> = Vec::with_capacity(code.len());
+    
+    for instr in code.iter() {
+        let ops = operands(instr);
+        let mut instr2 = instr.clone();
+        let mut temps : BTreeMap<i32, (i32, Kind)> = BTreeMap::new();
+        
+        for op in ops.iter() {
+            if !slots.contains_key(&op.reg) {
+                continue;
+            }
+            
+            let temp = match temps.get(&op.reg) {
+                Some((temp, _)) => *temp,
+                None => {
+                    let temp = regs.next;
+                    regs.next += 1;
+                    regs.fixed.insert(temp);
+                    
+                    if regs.partial.contains(&op.reg) {
+                        regs.partial.insert(temp);
+                    }
+                    
+                    temps.insert(op.reg, (temp, op.kind));
+                    temp
+                },
+            };
+            
+            set_reg(&mut instr2, op.arg, temp);
+        }
+        
+        let mut after : Vec<LtacInstr> = Vec::new();
+        
+        for (reg, (temp, kind)) in temps.iter() {
+            let pos = slots[reg];
+            
+            let (load_type, store_type, arg) = match kind {
+                Kind::Int => (LtacType::LdQ, LtacType::StrQ, LtacArg::Reg64(*temp)),
+                Kind::Flt if doubles.contains(reg) => (LtacType::LdF64, LtacType::StrF64, LtacArg::FltReg64(*temp)),
+                Kind::Flt => (LtacType::LdF32, LtacType::StrF32, LtacArg::FltReg(*temp)),
+            };
+            
+            let reads = ops.iter()
+                .any(|op| op.reg == *reg && (op.is_use() || (op.is_partial() && regs.partial.contains(reg))));
+            
+            if reads {
+                code2.push(build_load(load_type, arg.clone(), pos, risc));
+            }
+            
+            if ops.iter().any(|op| op.reg == *reg && op.is_def()) {
+                let mut store = ltac::create_instr(store_type);
+                store.arg1 = LtacArg::Mem(pos);
+                store.arg2 = arg;
+                after.push(store);
+            }
+        }
+        
+        code2.push(instr2);
+        code2.append(&mut after);
+    }
+    
+    *code = code2;
+}
+
+// Gives the registers their real numbers
+// Moves between the same register go away.
+fn assign(code : &mut Vec<LtacInstr>, assigned : &HashMap<i32, i32>) {
+    for instr in code.iter_mut() {
+        for op in operands(instr) {
+            set_reg(instr, op.arg, assigned[&op.reg]);
+        }
+    }
+    
+    code.retain(|instr| !is_move(&instr.instr_type) || instr.arg1 != instr.arg2 || operands(instr).is_empty());
+}
@@ -26,11 +26,41 @@ fn has_mem(arg : &LtacArg) -> bool {
         LtacArg::MemOffsetImm(_n1, _n2) => return true,
         LtacArg::MemOffsetMem(_n1, _n2, _n3) |
         LtacArg::MemOffsetReg(_n1, _n2, _n3) => return true,
+        LtacArg::MemOffsetStride(..) => return true,
         
         _ => return false,
     }
 }
 
+fn is_load(instr : &LtacType) -> bool {
+    match instr {
+        LtacType::LdB | LtacType::LdUB |
+        LtacType::LdW | LtacType::LdUW |
+        LtacType::Ld | LtacType::LdU |
+        LtacType::LdQ | LtacType::LdUQ |
+        LtacType::LdF32 | LtacType::LdF64
+            => return true,
+            
+        _ => return false,
+    }
+}
+
+// Returns the move for a load that doesn't actually read memory
+fn mov_for_load(instr : &LtacType) -> LtacType {
+    match instr {
+        LtacType::LdB => return LtacType::MovB,
+        LtacType::LdUB => return LtacType::MovUB,
+        LtacType::LdW => return LtacType::MovW,
+        LtacType::LdUW => return LtacType::MovUW,
+        LtacType::LdU => return LtacType::MovU,
+        LtacType::LdQ => return LtacType::MovQ,
+        LtacType::LdUQ => return LtacType::MovUQ,
+        LtacType::LdF32 => return LtacType::MovF32,
+        LtacType::LdF64 => return LtacType::MovF64,
+        _ => return LtacType::Mov,
+    }
+}
+
 // Returns the proper load instruction for a given move
 fn load_for_mov(instr : &LtacType) -> LtacType {
     match instr {
@@ -65,6 +95,9 @@ fn load_for_mov(instr : &LtacType) -> LtacType {
         LtacType::MovF32 => return LtacType::LdF32,
         LtacType::MovF64 => return LtacType::LdF64,
         
+        LtacType::CvtF32F64 | LtacType::CvtF32Int => return LtacType::LdF32,
+        LtacType::CvtF64F32 | LtacType::CvtF64Int => return LtacType::LdF64,
+        
         _ => return LtacType::Ld,
     }
 }
@@ -113,7 +146,18 @@ pub fn risc_optimize(file : &LtacFile) -> Result<LtacFile, ()> {
     for line in code.iter() {
         let mut instr2 = line.clone();
         
-        if is_move(&line.instr_type) {
+        // The parser writes loads with the register first, like a move
+        // RISC loads put the memory first, and anything else is really a move
+        if is_load(&line.instr_type) && !has_mem(&line.arg1) {
+            if has_mem(&line.arg2) {
+                instr2.arg1 = line.arg2.clone();
+                instr2.arg2 = line.arg1.clone();
+            } else {
+                instr2.instr_type = mov_for_load(&line.instr_type);
+            }
+            
+            file2.code.push(instr2);
+        } else if is_move(&line.instr_type) {
             if has_mem(&line.arg1) {
                 let instr_type = store_for_mov(&line.instr_type);
                 let mut store = ltac::create_instr(instr_type);
@@ -138,7 +182,8 @@ pub fn risc_optimize(file : &LtacFile) -> Result<LtacFile, ()> {
                 file2.code.push(instr2);
             }
         } else {
-            if has_mem(&line.arg2) && line.instr_type != LtacType::PushArg {
+            // LdAddr only takes the address of its memory operand
+            if has_mem(&line.arg2) && line.instr_type != LtacType::PushArg && line.instr_type != LtacType::LdAddr {
                 let instr_type = load_for_mov(&line.instr_type);
                 let mut load = ltac::create_instr(instr_type.clone());
                 load.arg1 = instr2.arg2.clone();
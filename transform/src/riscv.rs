@@ -19,31 +19,21 @@ pub fn riscv_optimize(file : &LtacFile) -> Result<LtacFile, ()> {
     };
     
     let code = file.code.clone();
-    let mut skip_next = false;
     
     for index in 0 .. code.len() {
-        if skip_next {
-            skip_next = false;
-            continue;
-        }
-        
         let line = code.iter().nth(index).unwrap();
         
-        let mut instr2 = line.clone();
+        let instr2 = line.clone();
         let mut arg_count = 1;
         
         // If we are making a printf call with a float argument, we have to do some
-        // special conversions.
+        // special conversions. The float has already been promoted to a double.
         if instr2.instr_type == LtacType::PushArg {
             let flt_arg : bool;
             
-            match &instr2.arg2 {
-                LtacArg::FltReg(_) => flt_arg = true,
-                LtacArg::FltReg64(_) => flt_arg = false,
-                _ => {
-                    arg_count += 1;
-                    flt_arg = false;
-                },
+            match &instr2.arg1 {
+                LtacArg::FltReg64(_) => flt_arg = true,
+                _ => flt_arg = false,
             }
             
             if !flt_arg {
@@ -73,55 +63,18 @@ pub fn riscv_optimize(file : &LtacFile) -> Result<LtacFile, ()> {
             }
             
             // If we make it this far, we have found a printf call
-            // First, convert the pusharg to a regular f32.ld
-            instr2.instr_type = LtacType::LdF32;
-            let pos = match &instr2.arg2 {
-                LtacArg::FltReg(pos) => *pos,
-                _ => 0,
-            };
-            
-            file2.code.push(instr2);
-            
-            // Now, convert to a double
-            let mut cvt_instr = ltac::create_instr(LtacType::CvtF32F64);
-            cvt_instr.arg1 = LtacArg::FltReg64(pos);
-            cvt_instr.arg2 = LtacArg::FltReg(pos);
-            file2.code.push(cvt_instr);
-            
-            // Now, move it to an integer register
+            // Variadic arguments go in the integer registers, so move it over
+            // The register has to be 64 bits, or the allocator would only keep half of the double
             let mut mv_instr = ltac::create_instr(LtacType::MovF64Int);
-            mv_instr.arg1 = LtacArg::Reg32(0);
-            mv_instr.arg2 = LtacArg::FltReg64(pos);
+            mv_instr.arg1 = LtacArg::Reg64(0);
+            mv_instr.arg2 = instr2.arg1.clone();
             file2.code.push(mv_instr);
             
             // Finally, create the pusharg
             let mut pusharg = ltac::create_instr(LtacType::PushArg);
-            pusharg.arg1 = LtacArg::Reg32(0);
+            pusharg.arg1 = LtacArg::Reg64(0);
             pusharg.arg2_val = arg_count + 1;
             file2.code.push(pusharg);
-            
-        // For some odd reason, you cannot move between float registers on RISC-V
-        } else if instr2.instr_type == LtacType::MovF32 {
-            match &instr2.arg2 {
-                LtacArg::FltReg(_p) | LtacArg::FltReg64(_p) => {},
-                
-                _ => {
-                    file2.code.push(instr2);
-                    continue;
-                },
-            }
-            
-            let mut next_instr = code.iter().nth(index + 1).unwrap().clone();
-            
-            if next_instr.instr_type == LtacType::StrF32 || next_instr.instr_type == LtacType::StrF64 {
-                next_instr.arg2 = instr2.arg1.clone();
-            } else {
-                next_instr.arg1 = instr2.arg2.clone();
-            }
-            
-            file2.code.push(next_instr);
-            
-            skip_next = true;
         
         // Otherwise, just add the current line
         } else {